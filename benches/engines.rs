@@ -0,0 +1,106 @@
+#[cfg(feature = "nan-boxing")]
+use bailey::bytecode::nan_box::NanBoxedValue;
+use bailey::bytecode::vm::VirtualMachine;
+use bailey::driver::{compile, compile_bytecode, CompileOptions};
+use bailey::ir_cps::compiler::cps_convert;
+use bailey::ir_cps::interpreter::eval;
+use bailey::ir_flat::interpreter::WordInterpreter;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::{list_sum_test, map_fold_test};
+use bailey::lang::test::mutation::counter_test;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+// Runs the same program on every execution engine: the interpreter for the
+// let intermediate language, the tree-walking interpreter for CPS terms, the
+// interpreter for the flat intermediate language and the bytecode virtual
+// machine. Compilation is not part of the measurement.
+// With the nan-boxing feature, the virtual machine also runs with NaN-boxed
+// values, to compare them with the enum representation.
+fn bench_engines(c: &mut Criterion, name: &str, e: &Expr) {
+    let options = CompileOptions::default();
+    let compiled = compile(e, &options).expect("expected program");
+    let let_program = compiled.let_program;
+    let flat_program = compiled.flat_program.expect("expected flat program");
+    let cps_program = cps_convert(e).expect("expected program");
+    let bytecode = compile_bytecode(e, &options).expect("expected program");
+
+    let mut group = c.benchmark_group(name);
+    group.bench_function("let", |b| {
+        b.iter_batched(
+            || ProgramEvaluator::new(let_program.clone()),
+            |mut evaluator| evaluator.run().expect("program should not fail"),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("cps", |b| {
+        b.iter(|| eval(&cps_program).expect("program should not fail"))
+    });
+    group.bench_function("flat", |b| {
+        b.iter_batched(
+            || WordInterpreter::new(&flat_program),
+            |mut interpreter| interpreter.run().expect("program should not fail"),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("bytecode", |b| {
+        b.iter_batched(
+            || VirtualMachine::new(bytecode.clone()),
+            |mut vm| vm.run(),
+            BatchSize::SmallInput,
+        )
+    });
+    #[cfg(feature = "nan-boxing")]
+    group.bench_function("bytecode_nan_boxed", |b| {
+        b.iter_batched(
+            || VirtualMachine::<NanBoxedValue>::with_representation(bytecode.clone()),
+            |mut vm| vm.run(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn fib(c: &mut Criterion) {
+    bench_engines(c, "fib", &fib_test(30));
+}
+
+fn ackermann(c: &mut Criterion) {
+    bench_engines(c, "ackermann", &ackermann_test(2, 3));
+}
+
+fn list_sum(c: &mut Criterion) {
+    bench_engines(c, "list_sum", &list_sum_test(1000));
+}
+
+fn map_fold(c: &mut Criterion) {
+    bench_engines(c, "map_fold", &map_fold_test(1000));
+}
+
+fn even_odd(c: &mut Criterion) {
+    bench_engines(c, "even_odd", &even_odd_test(1000));
+}
+
+fn closure_capture(c: &mut Criterion) {
+    bench_engines(c, "closure_capture", &closure_capture_test(1000));
+}
+
+fn counter(c: &mut Criterion) {
+    bench_engines(c, "counter", &counter_test(1000));
+}
+
+criterion_group!(
+    benches,
+    fib,
+    ackermann,
+    list_sum,
+    map_fold,
+    even_odd,
+    closure_capture,
+    counter
+);
+criterion_main!(benches);
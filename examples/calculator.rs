@@ -0,0 +1,84 @@
+// A line-based calculator service. Every line on standard input is a request
+// containing an expression in reverse Polish notation (e.g. `1 2 + 4 ==`), and
+// every request is answered with a single line starting with `ok` or `error`.
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+
+use bailey::ir_let::compiler::let_normalize;
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::{BinOp, Constant, Expr};
+
+// Requests are untrusted, so evaluation is bounded in memory.
+const MAX_HEAP_OBJECTS: usize = 10_000;
+
+fn parse_request(request: &str) -> Result<Expr, String> {
+    let mut stack = Vec::new();
+
+    for token in request.split_whitespace() {
+        let op = match token {
+            "+" => Some(BinOp::Add),
+            "-" => Some(BinOp::Sub),
+            "==" => Some(BinOp::Eq),
+            _ => None,
+        };
+
+        let expr = match op {
+            Some(op) => {
+                let rhs = stack.pop().ok_or("missing operand")?;
+                let lhs = stack.pop().ok_or("missing operand")?;
+                Expr::BinOp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                }
+            }
+            None => match token {
+                "true" => Expr::Literal(Constant::Bool { value: true }),
+                "false" => Expr::Literal(Constant::Bool { value: false }),
+                _ => {
+                    let value = token
+                        .parse()
+                        .map_err(|_| format!("invalid token `{}`", token))?;
+                    Expr::Literal(Constant::Int { value })
+                }
+            },
+        };
+
+        stack.push(expr);
+    }
+
+    let result = stack.pop().ok_or("empty request")?;
+    if !stack.is_empty() {
+        return Err("too many operands".to_owned());
+    }
+
+    Ok(result)
+}
+
+fn handle_request(request: &str) -> Result<String, Box<dyn Error>> {
+    let expr = parse_request(request)?;
+    let program = let_normalize(&expr)?;
+    let mut evaluator = ProgramEvaluator::with_heap_limit(program, MAX_HEAP_OBJECTS);
+
+    match evaluator.run()? {
+        Value::Int(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Pointer(_) => Err("unexpected result".into()),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        match handle_request(&line) {
+            Ok(response) => writeln!(stdout, "ok {}", response)?,
+            Err(error) => writeln!(stdout, "error {}", error)?,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+// A host application that lets plugins register builtin functions, which are
+// then available to every guest program the host runs. The builtins are
+// native functions written in Rust, which guest programs call like any other
+// function.
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::Heap;
+use bailey::ir_let::interpreter::heap_value::{HeapValue, Tuple, Value};
+use bailey::ir_let::interpreter::native::{NativeCode, Natives};
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use std::error::Error;
+
+trait Plugin {
+    fn name(&self) -> &str;
+
+    fn register(&self, host: &mut Host);
+}
+
+struct Host {
+    natives: Natives,
+}
+
+impl Host {
+    fn new() -> Self {
+        Host {
+            natives: Natives::new(),
+        }
+    }
+
+    fn load(&mut self, plugin: &dyn Plugin) {
+        println!("loading plugin {}", plugin.name());
+        plugin.register(self);
+    }
+
+    fn register_builtin(&mut self, name: &str, arity: usize, code: NativeCode) {
+        self.natives.register(name, arity, code);
+    }
+
+    // The builtins are globals of the program, which are bound to the natives
+    // before it runs.
+    fn run(&self, program: &Expr) -> Result<String, Box<dyn Error>> {
+        let options = CompileOptions {
+            globals: self.natives.names(),
+            ..CompileOptions::default()
+        };
+        let compiled = compile(program, &options)?;
+
+        let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+        evaluator.bind_natives(&self.natives)?;
+        let value = evaluator.run()?;
+        Ok(render(value, evaluator.heap()))
+    }
+}
+
+fn double(_heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    let x = args[0].check_int()?;
+    Ok(Value::Int(x.wrapping_add(x)))
+}
+
+fn negate(_heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Int(args[0].check_int()?.wrapping_neg()))
+}
+
+struct ArithmeticPlugin;
+
+impl Plugin for ArithmeticPlugin {
+    fn name(&self) -> &str {
+        "arithmetic"
+    }
+
+    fn register(&self, host: &mut Host) {
+        host.register_builtin("double", 1, double);
+        host.register_builtin("negate", 1, negate);
+    }
+}
+
+// The tuple holds a reference to each of its fields.
+fn pair(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    let address = heap.alloc(HeapValue::Tuple(Tuple::new(args.to_vec())))?;
+    for field in args {
+        if let Some(field_address) = field.address() {
+            heap.inc_refcount(field_address);
+        }
+    }
+    Ok(Value::Pointer(address))
+}
+
+fn field(heap: &dyn Heap, value: Value, index: usize) -> Result<Value, RuntimeError> {
+    let fields = &heap
+        .deref(value.check_pointer("tuple")?)
+        .check_tuple()?
+        .field_values;
+    fields
+        .get(index)
+        .copied()
+        .ok_or(RuntimeError::IndexOutOfRange {
+            index: index as i64,
+            size: fields.len(),
+        })
+}
+
+fn fst(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    field(heap, args[0], 0)
+}
+
+fn snd(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    field(heap, args[0], 1)
+}
+
+struct PairPlugin;
+
+impl Plugin for PairPlugin {
+    fn name(&self) -> &str {
+        "pairs"
+    }
+
+    fn register(&self, host: &mut Host) {
+        host.register_builtin("pair", 2, pair);
+        host.register_builtin("fst", 1, fst);
+        host.register_builtin("snd", 1, snd);
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut host = Host::new();
+    host.load(&ArithmeticPlugin);
+    host.load(&PairPlugin);
+
+    let program = expr! {
+        let p = pair(1, double(negate(21)));
+        (fst(p), snd(p), p)
+    };
+    println!("{}", host.run(&program)?);
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+// An interactive read-eval-print loop for a small infix expression language:
+//
+//   > let x = 1 + 2
+//   > (x - 3) == 0
+//   true
+//
+// Definitions are remembered for the rest of the session by wrapping every
+// evaluated expression in let bindings for all earlier definitions.
+use std::io::{self, BufRead, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use bailey::ir_let::compiler::let_normalize;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::{BinOp, Constant, Expr};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Ident(String),
+    Plus,
+    Minus,
+    EqEq,
+    Assign,
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<Chars> = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            tokens.push(Token::Int(digits.parse().map_err(|_| "integer too large")?));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_alphanumeric() || **d == '_') {
+                ident.push(d);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else {
+            chars.next();
+            tokens.push(match c {
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                '=' if chars.peek() == Some(&'=') => {
+                    chars.next();
+                    Token::EqEq
+                }
+                '=' => Token::Assign,
+                _ => return Err(format!("unexpected character `{}`", c)),
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("expected {:?}", expected)),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        let lhs = self.parse_sum()?;
+
+        if self.peek() == Some(&Token::EqEq) {
+            self.next();
+            let rhs = self.parse_sum()?;
+            return Ok(binop(BinOp::Eq, lhs, rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, String> {
+        let mut result = self.parse_atom()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinOp::Add,
+                Some(Token::Minus) => BinOp::Sub,
+                _ => return Ok(result),
+            };
+            self.next();
+            result = binop(op, result, self.parse_atom()?);
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Int(value)) => Ok(Expr::Literal(Constant::Int { value })),
+            Some(Token::Ident(name)) if name == "true" || name == "false" => {
+                Ok(Expr::Literal(Constant::Bool {
+                    value: name == "true",
+                }))
+            }
+            Some(Token::Ident(var_name)) => Ok(Expr::Var { var_name }),
+            Some(Token::LParen) => {
+                let first = self.parse_expr()?;
+                if self.peek() != Some(&Token::Comma) {
+                    self.expect(Token::RParen)?;
+                    return Ok(first);
+                }
+
+                let mut values = vec![first];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    values.push(self.parse_expr()?);
+                }
+                self.expect(Token::RParen)?;
+                Ok(Expr::Tuple { values })
+            }
+            _ => Err("expected expression".to_owned()),
+        }
+    }
+}
+
+fn binop(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::BinOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }
+}
+
+enum Command {
+    Define(String, Expr),
+    Evaluate(Expr),
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut parser = Parser {
+        tokens: tokenize(line)?,
+        position: 0,
+    };
+
+    let command = match parser.tokens.as_slice() {
+        [Token::Ident(keyword), Token::Ident(name), Token::Assign, ..] if keyword == "let" => {
+            let name = name.clone();
+            parser.position = 3;
+            Command::Define(name, parser.parse_expr()?)
+        }
+        _ => Command::Evaluate(parser.parse_expr()?),
+    };
+
+    if parser.peek().is_some() {
+        return Err("unexpected input after expression".to_owned());
+    }
+
+    Ok(command)
+}
+
+fn evaluate(definitions: &[(String, Expr)], expr: Expr) -> Result<String, String> {
+    let program = definitions
+        .iter()
+        .rev()
+        .fold(expr, |body, (name, definition)| Expr::Let {
+            name: name.clone(),
+            definition: Box::new(definition.clone()),
+            body: Box::new(body),
+        });
+
+    let compiled = let_normalize(&program).map_err(|e| e.to_string())?;
+    let mut evaluator = ProgramEvaluator::new(compiled);
+
+    let value = evaluator.run().map_err(|e| e.to_string())?;
+    Ok(render(value, evaluator.heap()))
+}
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut definitions = Vec::new();
+
+    write!(stdout, "> ")?;
+    stdout.flush()?;
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+
+        if !line.trim().is_empty() {
+            match parse_command(&line) {
+                Ok(Command::Define(name, expr)) => {
+                    // Only remember definitions that evaluate successfully.
+                    let mut candidate = definitions.clone();
+                    candidate.push((name.clone(), expr));
+                    match evaluate(&candidate, Expr::Var { var_name: name }) {
+                        Ok(_) => definitions = candidate,
+                        Err(error) => writeln!(stdout, "error: {}", error)?,
+                    }
+                }
+                Ok(Command::Evaluate(expr)) => match evaluate(&definitions, expr) {
+                    Ok(value) => writeln!(stdout, "{}", value)?,
+                    Err(error) => writeln!(stdout, "error: {}", error)?,
+                },
+                Err(error) => writeln!(stdout, "error: {}", error)?,
+            }
+        }
+
+        write!(stdout, "> ")?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
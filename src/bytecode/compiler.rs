@@ -0,0 +1,226 @@
+use crate::bytecode::syntax as target;
+use crate::ir_flat::syntax as source;
+use crate::lang::syntax::Constant;
+
+use std::collections::HashMap;
+
+// Jumps whose target is not known yet are emitted with this placeholder and
+// patched once the target has been emitted.
+const UNRESOLVED_TARGET: usize = usize::MAX;
+
+struct FunctionCompiler<'a> {
+    function: &'a source::Function,
+    code: Vec<target::Instruction>,
+    // The code offsets of the loop headers that have been emitted so far.
+    // Jumps only go to enclosing loop headers, which precede them.
+    loop_headers: HashMap<usize, usize>,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(function: &'a source::Function) -> Self {
+        FunctionCompiler {
+            function,
+            code: Vec::new(),
+            loop_headers: HashMap::new(),
+        }
+    }
+
+    fn emit(&mut self, instruction: target::Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    fn patch_jump(&mut self, jump_index: usize) {
+        let here = self.code.len();
+        match &mut self.code[jump_index] {
+            target::Instruction::Jump(t) | target::Instruction::JumpIfFalse(t) => *t = here,
+            _ => panic!("attempt to patch non-jump instruction"),
+        }
+    }
+
+    fn load(&mut self, reference: source::Reference) {
+        self.emit(target::Instruction::Load(reference));
+    }
+
+    // Blocks are inlined at the place where they are entered. Returns the
+    // jumps out of the block, which are patched by the If that entered it.
+    fn compile_block(&mut self, block_index: usize) -> Vec<usize> {
+        let block = &self.function.blocks[block_index];
+        let mut exits = Vec::new();
+
+        if block.is_loop_header {
+            self.loop_headers.insert(block_index, self.code.len());
+        }
+
+        for instruction in &block.instructions {
+            match instruction {
+                source::Instruction::Jump(_) => {
+                    exits.push(self.emit(target::Instruction::Jump(UNRESOLVED_TARGET)));
+                }
+                source::Instruction::Return(reference) => {
+                    self.load(*reference);
+                    self.emit(target::Instruction::Return);
+                }
+                source::Instruction::Assignment(source::Assignment { name, definition }) => {
+                    self.compile_definition(definition);
+
+                    // The branches of an If store its result themselves.
+                    if !matches!(
+                        definition,
+                        source::Definition::Step(source::Step::Control(source::Control::If { .. }))
+                    ) {
+                        self.emit(target::Instruction::Store(*name));
+                    }
+                }
+            }
+        }
+
+        exits
+    }
+
+    fn compile_definition(&mut self, definition: &source::Definition) {
+        match definition {
+            source::Definition::Var(reference) => self.load(*reference),
+            source::Definition::Step(source::Step::Simple(simple)) => self.compile_simple(simple),
+            source::Definition::Step(source::Step::Control(control)) => {
+                self.compile_control(control)
+            }
+        }
+    }
+
+    fn compile_simple(&mut self, simple: &source::Simple) {
+        match simple {
+            source::Simple::Literal(Constant::Int { value }) => {
+                self.emit(target::Instruction::PushInt(*value));
+            }
+            source::Simple::Literal(Constant::Bool { value }) => {
+                self.emit(target::Instruction::PushBool(*value));
+            }
+            source::Simple::Fun(source::AllocClosure {
+                free_vars, body, ..
+            }) => {
+                for free_var in free_vars {
+                    self.load(*free_var);
+                }
+                self.emit(target::Instruction::MakeClosure {
+                    function_index: body.function_index,
+                    env_size: free_vars.len(),
+                });
+            }
+            source::Simple::BinOp { op, lhs, rhs } => {
+                self.load(*lhs);
+                self.load(*rhs);
+                self.emit(target::Instruction::BinOp(*op));
+            }
+            source::Simple::Tuple { args } => {
+                for arg in args {
+                    self.load(*arg);
+                }
+                self.emit(target::Instruction::MakeTuple(args.len()));
+            }
+            source::Simple::Set {
+                tuple,
+                index,
+                new_value,
+            } => {
+                self.load(*tuple);
+                self.load(*new_value);
+                self.emit(target::Instruction::SetField(*index));
+            }
+        }
+    }
+
+    fn compile_control(&mut self, control: &source::Control) {
+        match control {
+            source::Control::Call { func, args } => {
+                self.load(*func);
+                for arg in args {
+                    self.load(*arg);
+                }
+                self.emit(target::Instruction::Call(args.len()));
+            }
+            source::Control::TailCall { func, args } => {
+                self.load(*func);
+                for arg in args {
+                    self.load(*arg);
+                }
+                self.emit(target::Instruction::TailCall(args.len()));
+            }
+            source::Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => {
+                self.load(*condition);
+                let jump_to_failure =
+                    self.emit(target::Instruction::JumpIfFalse(UNRESOLVED_TARGET));
+                let success_exits = self.compile_block(branch_success.block_index);
+                self.patch_jump(jump_to_failure);
+                let failure_exits = self.compile_block(branch_failure.block_index);
+                for exit in success_exits.into_iter().chain(failure_exits) {
+                    self.patch_jump(exit);
+                }
+            }
+            source::Control::Jump { target, args } => {
+                for arg in args {
+                    self.load(*arg);
+                }
+                // All arguments are loaded before any of them is rebound, since
+                // the new values can depend on the old ones.
+                for offset in (0..args.len()).rev() {
+                    self.emit(target::Instruction::StoreArgument(offset));
+                }
+                let header = *self
+                    .loop_headers
+                    .get(&target.block_index)
+                    .expect("jump target should be an enclosing loop header");
+                self.emit(target::Instruction::Jump(header));
+            }
+        }
+    }
+}
+
+pub fn function_names(program: &source::Program) -> HashMap<usize, String> {
+    let mut names = HashMap::new();
+    names.insert(0, "toplevel".to_owned());
+
+    for function in &program.functions {
+        for block in &function.blocks {
+            for instruction in &block.instructions {
+                if let source::Instruction::Assignment(source::Assignment {
+                    definition:
+                        source::Definition::Step(source::Step::Simple(source::Simple::Fun(closure))),
+                    ..
+                }) = instruction
+                {
+                    if let Some(name) = closure.name {
+                        names.insert(closure.body.function_index, program.name(name).to_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    names
+}
+
+pub fn compile_program(program: &source::Program) -> target::Program {
+    let mut names = function_names(program);
+    let mut functions = Vec::new();
+
+    for (i, function) in program.functions.iter().enumerate() {
+        let mut compiler = FunctionCompiler::new(function);
+        // The body of every function starts at its first block.
+        compiler.compile_block(0);
+
+        functions.push(target::Function {
+            name: names.remove(&i).unwrap_or_default(),
+            args_size: function.args_size,
+            closure_env_size: function.closure_env_size,
+            frame_size: function.frame_size,
+            code: compiler.code,
+        });
+    }
+
+    target::Program { functions }
+}
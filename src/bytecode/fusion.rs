@@ -0,0 +1,182 @@
+use crate::bytecode::syntax::{Instruction, Program};
+use crate::ir_flat::syntax::Reference;
+use crate::lang::syntax::BinOp;
+
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fusion {
+    // Load followed by BinOp.
+    LoadBinOp,
+    // Store followed by a Load of the same local.
+    StoreKeep,
+    // An equality test whose result is stored and then branched on.
+    CompareBranch,
+}
+
+impl Fusion {
+    const ALL: [Fusion; 3] = [Fusion::LoadBinOp, Fusion::StoreKeep, Fusion::CompareBranch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Fusion::LoadBinOp => "load + binop",
+            Fusion::StoreKeep => "store + load",
+            Fusion::CompareBranch => "compare + branch",
+        }
+    }
+}
+
+// How often each superinstruction occurs: the fusion pass counts the pairs it
+// fused, and the virtual machine the superinstructions it executed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FusionStats {
+    counts: [usize; 3],
+}
+
+impl FusionStats {
+    pub fn record(&mut self, fusion: Fusion) {
+        self.counts[fusion as usize] += 1;
+    }
+
+    pub fn count(&self, fusion: Fusion) -> usize {
+        self.counts[fusion as usize]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    // The fusions that occurred, most frequent first.
+    pub fn ranking(&self) -> Vec<(Fusion, usize)> {
+        let mut ranking: Vec<(Fusion, usize)> = Fusion::ALL
+            .iter()
+            .map(|fusion| (*fusion, self.count(*fusion)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        ranking.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        ranking
+    }
+}
+
+impl fmt::Display for FusionStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (fusion, count) in self.ranking() {
+            writeln!(f, "{}: {}", fusion.name(), count)?;
+        }
+        Ok(())
+    }
+}
+
+fn jump_target(instruction: &mut Instruction) -> Option<&mut usize> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::JumpIfFalse(target)
+        | Instruction::CompareBranch { target, .. } => Some(target),
+        _ => None,
+    }
+}
+
+// The rule decides whether the instructions at the start of the slice form a
+// superinstruction, and if so how many of them it replaces.
+type Rule = fn(&[Instruction]) -> Option<(Instruction, Fusion, usize)>;
+
+// Replaces every sequence of instructions that the rule fuses by a single
+// instruction, and moves the jump targets accordingly. A sequence is not fused
+// if a jump goes into the middle of it.
+fn fuse_sequences(code: &mut Vec<Instruction>, stats: &mut FusionStats, rule: Rule) {
+    let targets: HashSet<usize> = code
+        .iter_mut()
+        .filter_map(|instruction| jump_target(instruction).copied())
+        .collect();
+
+    let mut fused = Vec::new();
+    // Jumps may go to the end of the code.
+    let mut new_index = vec![0; code.len() + 1];
+    let mut i = 0;
+    while i < code.len() {
+        new_index[i] = fused.len();
+        let superinstruction = rule(&code[i..])
+            .filter(|(_, _, length)| (i + 1..i + length).all(|j| !targets.contains(&j)));
+
+        match superinstruction {
+            Some((instruction, fusion, length)) => {
+                stats.record(fusion);
+                new_index[i + 1..i + length].fill(fused.len());
+                fused.push(instruction);
+                i += length;
+            }
+            None => {
+                fused.push(code[i].clone());
+                i += 1;
+            }
+        }
+    }
+    new_index[code.len()] = fused.len();
+
+    for instruction in &mut fused {
+        if let Some(target) = jump_target(instruction) {
+            *target = new_index[*target];
+        }
+    }
+    *code = fused;
+}
+
+// The condition of an If is always a local, so a comparison is stored before
+// it is branched on.
+fn compare_branch(code: &[Instruction]) -> Option<(Instruction, Fusion, usize)> {
+    let [comparison, store, load, branch, ..] = code else {
+        return None;
+    };
+    match (comparison, store, load, branch) {
+        (
+            Instruction::BinOp(BinOp::Eq),
+            Instruction::Store(local),
+            Instruction::Load(Reference::Local(loaded)),
+            Instruction::JumpIfFalse(target),
+        ) if local == loaded => {
+            let instruction = Instruction::CompareBranch {
+                local: *local,
+                target: *target,
+            };
+            Some((instruction, Fusion::CompareBranch, 4))
+        }
+        _ => None,
+    }
+}
+
+fn load_binop(code: &[Instruction]) -> Option<(Instruction, Fusion, usize)> {
+    match code {
+        [Instruction::Load(reference), Instruction::BinOp(op), ..] => Some((
+            Instruction::LoadBinOp(*reference, *op),
+            Fusion::LoadBinOp,
+            2,
+        )),
+        _ => None,
+    }
+}
+
+fn store_keep(code: &[Instruction]) -> Option<(Instruction, Fusion, usize)> {
+    match code {
+        [Instruction::Store(local), Instruction::Load(Reference::Local(loaded)), ..]
+            if local == loaded =>
+        {
+            Some((Instruction::StoreKeep(*local), Fusion::StoreKeep, 2))
+        }
+        _ => None,
+    }
+}
+
+// Fuses common sequences of instructions into superinstructions, so that the
+// virtual machine dispatches fewer instructions. The longest sequences are
+// fused first, since their instructions would otherwise be taken by the
+// shorter ones.
+pub fn fuse_superinstructions(program: &mut Program) -> FusionStats {
+    let mut stats = FusionStats::default();
+    for function in &mut program.functions {
+        fuse_sequences(&mut function.code, &mut stats, compare_branch);
+        fuse_sequences(&mut function.code, &mut stats, load_binop);
+        fuse_sequences(&mut function.code, &mut stats, store_keep);
+    }
+    stats
+}
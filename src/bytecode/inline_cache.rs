@@ -0,0 +1,64 @@
+use std::fmt;
+
+// What an instruction saw the last time it ran. Calls remember the function
+// they entered, whose arity has been checked already, and field accesses the
+// size of the tuple they read from together with the index, which was in
+// range. A call site or field access that keeps seeing the same function or
+// tuple size skips the checks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InlineCache {
+    Empty,
+    Call {
+        function_index: usize,
+        frame_size: usize,
+    },
+    Field {
+        tuple_size: usize,
+        index: usize,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub call_hits: usize,
+    pub call_misses: usize,
+    pub field_hits: usize,
+    pub field_misses: usize,
+}
+
+fn hit_rate(hits: usize, misses: usize) -> f64 {
+    if hits + misses == 0 {
+        0.0
+    } else {
+        hits as f64 / (hits + misses) as f64
+    }
+}
+
+impl CacheStats {
+    pub fn call_hit_rate(&self) -> f64 {
+        hit_rate(self.call_hits, self.call_misses)
+    }
+
+    pub fn field_hit_rate(&self) -> f64 {
+        hit_rate(self.field_hits, self.field_misses)
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "call cache: {} hits, {} misses ({:.1}%)",
+            self.call_hits,
+            self.call_misses,
+            100.0 * self.call_hit_rate()
+        )?;
+        writeln!(
+            f,
+            "field cache: {} hits, {} misses ({:.1}%)",
+            self.field_hits,
+            self.field_misses,
+            100.0 * self.field_hit_rate()
+        )
+    }
+}
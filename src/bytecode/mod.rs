@@ -0,0 +1,7 @@
+pub mod compiler;
+pub mod fusion;
+pub mod inline_cache;
+#[cfg(feature = "nan-boxing")]
+pub mod nan_box;
+pub mod syntax;
+pub mod vm;
@@ -0,0 +1,250 @@
+use crate::bytecode::vm::{Closure, ValueRepr};
+use crate::ir_let::interpreter::error::RuntimeError;
+
+use std::cell::{Ref, RefCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+// Every value fits in a single 64-bit word. A word that is not a negative
+// quiet NaN is a float. The negative quiet NaNs carry a tag in bits 48-50 and
+// a 48-bit payload: an immediate int or bool, or a pointer obtained from
+// Rc::into_raw for values on the heap. Ints that do not fit in 48 bits are
+// boxed on the heap, and floats that are NaN are stored as the positive
+// canonical NaN so that they cannot be mistaken for a tagged word.
+const TAGGED: u64 = 0xfff8_0000_0000_0000;
+const TAG_SHIFT: u32 = 48;
+const TAG_MASK: u64 = 0x7;
+const PAYLOAD_MASK: u64 = 0x0000_ffff_ffff_ffff;
+const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+const TAG_INT: u64 = 1;
+const TAG_BOOL: u64 = 2;
+const TAG_BIG_INT: u64 = 3;
+const TAG_TUPLE: u64 = 4;
+const TAG_CLOSURE: u64 = 5;
+
+const MIN_IMMEDIATE: i64 = -(1 << 47);
+const MAX_IMMEDIATE: i64 = (1 << 47) - 1;
+
+type Tuple = RefCell<Vec<NanBoxedValue>>;
+
+pub struct NanBoxedValue {
+    bits: u64,
+    // The word may own a reference count of an Rc, which is not thread-safe.
+    _rc: PhantomData<Rc<()>>,
+}
+
+impl NanBoxedValue {
+    fn from_bits(bits: u64) -> Self {
+        NanBoxedValue {
+            bits,
+            _rc: PhantomData,
+        }
+    }
+
+    fn tagged(tag: u64, payload: u64) -> Self {
+        debug_assert!(payload & !PAYLOAD_MASK == 0);
+        Self::from_bits(TAGGED | tag << TAG_SHIFT | payload)
+    }
+
+    fn pointer<T>(tag: u64, rc: Rc<T>) -> Self {
+        let pointer = Rc::into_raw(rc) as usize as u64;
+        assert!(
+            pointer & !PAYLOAD_MASK == 0,
+            "heap pointer does not fit in 48 bits"
+        );
+        Self::tagged(tag, pointer)
+    }
+
+    fn tag(&self) -> Option<u64> {
+        if self.bits & TAGGED == TAGGED {
+            Some(self.bits >> TAG_SHIFT & TAG_MASK)
+        } else {
+            None
+        }
+    }
+
+    fn payload(&self) -> u64 {
+        self.bits & PAYLOAD_MASK
+    }
+
+    // The value behind the pointer lives at least as long as the word, since
+    // the word owns a reference count.
+    fn deref<T>(&self) -> &T {
+        unsafe { &*(self.payload() as usize as *const T) }
+    }
+
+    pub fn float(value: f64) -> Self {
+        if value.is_nan() {
+            Self::from_bits(CANONICAL_NAN)
+        } else {
+            Self::from_bits(value.to_bits())
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self.tag() {
+            None => Some(f64::from_bits(self.bits)),
+            Some(_) => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self.tag() {
+            // Sign-extends the 48-bit payload.
+            Some(TAG_INT) => Some(((self.payload() << 16) as i64) >> 16),
+            Some(TAG_BIG_INT) => Some(*self.deref::<i64>()),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.tag() {
+            Some(TAG_BOOL) => Some(self.payload() != 0),
+            _ => None,
+        }
+    }
+
+    pub fn fields(&self) -> Option<Vec<NanBoxedValue>> {
+        match self.tag() {
+            Some(TAG_TUPLE) => Some(self.deref::<Tuple>().borrow().clone()),
+            _ => None,
+        }
+    }
+
+    pub fn function_index(&self) -> Option<usize> {
+        match self.tag() {
+            Some(TAG_CLOSURE) => Some(self.deref::<Closure<Self>>().function_index),
+            _ => None,
+        }
+    }
+
+    // Whether the value is an int that is stored in the word itself.
+    pub fn is_immediate_int(&self) -> bool {
+        self.tag() == Some(TAG_INT)
+    }
+
+    fn check_tuple(&self) -> Result<&Tuple, RuntimeError> {
+        match self.tag() {
+            Some(TAG_TUPLE) => Ok(self.deref()),
+            _ => Err(RuntimeError::TypeMismatch { expected: "tuple" }),
+        }
+    }
+}
+
+impl Clone for NanBoxedValue {
+    fn clone(&self) -> Self {
+        let pointer = self.payload() as usize;
+        unsafe {
+            match self.tag() {
+                Some(TAG_BIG_INT) => Rc::increment_strong_count(pointer as *const i64),
+                Some(TAG_TUPLE) => Rc::increment_strong_count(pointer as *const Tuple),
+                Some(TAG_CLOSURE) => Rc::increment_strong_count(pointer as *const Closure<Self>),
+                _ => {}
+            }
+        }
+        Self::from_bits(self.bits)
+    }
+}
+
+impl Drop for NanBoxedValue {
+    fn drop(&mut self) {
+        let pointer = self.payload() as usize;
+        unsafe {
+            match self.tag() {
+                Some(TAG_BIG_INT) => Rc::decrement_strong_count(pointer as *const i64),
+                Some(TAG_TUPLE) => Rc::decrement_strong_count(pointer as *const Tuple),
+                Some(TAG_CLOSURE) => Rc::decrement_strong_count(pointer as *const Closure<Self>),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Debug for NanBoxedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tag() {
+            None => write!(f, "Float({:?})", f64::from_bits(self.bits)),
+            Some(TAG_INT | TAG_BIG_INT) => write!(f, "Int({})", self.as_int().unwrap_or_default()),
+            Some(TAG_BOOL) => write!(f, "Bool({})", self.as_bool().unwrap_or_default()),
+            Some(TAG_TUPLE) => f
+                .debug_tuple("Tuple")
+                .field(&self.deref::<Tuple>().borrow())
+                .finish(),
+            Some(TAG_CLOSURE) => f
+                .debug_tuple("Closure")
+                .field(self.deref::<Closure<Self>>())
+                .finish(),
+            Some(tag) => write!(f, "Invalid({}, {:#x})", tag, self.payload()),
+        }
+    }
+}
+
+impl ValueRepr for NanBoxedValue {
+    fn int(value: i64) -> Self {
+        if (MIN_IMMEDIATE..=MAX_IMMEDIATE).contains(&value) {
+            Self::tagged(TAG_INT, value as u64 & PAYLOAD_MASK)
+        } else {
+            Self::pointer(TAG_BIG_INT, Rc::new(value))
+        }
+    }
+
+    fn bool(value: bool) -> Self {
+        Self::tagged(TAG_BOOL, value as u64)
+    }
+
+    fn tuple(fields: Vec<Self>) -> Self {
+        Self::pointer(TAG_TUPLE, Rc::new(RefCell::new(fields)))
+    }
+
+    fn closure(closure: Rc<Closure<Self>>) -> Self {
+        Self::pointer(TAG_CLOSURE, closure)
+    }
+
+    fn check_int(&self) -> Result<i64, RuntimeError> {
+        self.as_int()
+            .ok_or(RuntimeError::TypeMismatch { expected: "int" })
+    }
+
+    fn check_bool(&self) -> Result<bool, RuntimeError> {
+        self.as_bool()
+            .ok_or(RuntimeError::TypeMismatch { expected: "bool" })
+    }
+
+    fn check_closure(&self) -> Result<Rc<Closure<Self>>, RuntimeError> {
+        match self.tag() {
+            Some(TAG_CLOSURE) => {
+                let pointer = self.payload() as usize as *const Closure<Self>;
+                // Takes a reference count of its own, next to the one of the
+                // word.
+                unsafe {
+                    Rc::increment_strong_count(pointer);
+                    Ok(Rc::from_raw(pointer))
+                }
+            }
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "closure",
+            }),
+        }
+    }
+
+    fn borrow_fields(&self) -> Result<Ref<'_, Vec<Self>>, RuntimeError> {
+        Ok(self.check_tuple()?.borrow())
+    }
+
+    fn set_field(&self, index: u32, new_value: Self) -> Result<(), RuntimeError> {
+        let mut fields = self.check_tuple()?.borrow_mut();
+        let size = fields.len();
+        match fields.get_mut(index as usize) {
+            Some(field) => *field = new_value,
+            None => {
+                return Err(RuntimeError::IndexOutOfRange {
+                    index: index as i64,
+                    size,
+                })
+            }
+        }
+        Ok(())
+    }
+}
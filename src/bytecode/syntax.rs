@@ -0,0 +1,62 @@
+use crate::ir_flat::syntax::{LocalReference, Reference};
+use crate::lang::syntax::BinOp;
+
+// A linear, stack-based instruction set. Every instruction pops its operands
+// from the operand stack and pushes its result (if any) back onto it. Jump
+// targets are indices into the code of the current function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    PushInt(i64),
+    PushBool(bool),
+    Load(Reference),
+    Store(LocalReference),
+    // Pops a value and rebinds the argument with the given offset to it, for
+    // the next iteration of a loop.
+    StoreArgument(usize),
+    BinOp(BinOp),
+    // Pops the given number of values and pushes a tuple containing them.
+    MakeTuple(usize),
+    // Pops the new value and the tuple, and pushes the empty tuple.
+    SetField(u32),
+    // Pops the captured values, in the order of the closure offsets of the
+    // target function.
+    MakeClosure {
+        function_index: usize,
+        env_size: usize,
+    },
+    // Pops the arguments and then the closure to call.
+    Call(usize),
+    // Like Call, but replaces the frame of the current function, so the callee
+    // returns directly to the caller of the current function.
+    TailCall(usize),
+    Jump(usize),
+    JumpIfFalse(usize),
+    Return,
+    // Superinstructions, which `fusion` makes out of sequences of the
+    // instructions above. LoadBinOp takes the right operand from the reference
+    // instead of the operand stack.
+    LoadBinOp(Reference, BinOp),
+    // Stores a copy of the value on top of the stack in the local.
+    StoreKeep(LocalReference),
+    // Pops two ints, stores whether they are equal in the local, and jumps if
+    // they differ.
+    CompareBranch {
+        local: LocalReference,
+        target: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub args_size: usize,
+    pub closure_env_size: usize,
+    pub frame_size: usize,
+    pub code: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    // The first function is the entry point of the program.
+    pub functions: Vec<Function>,
+}
@@ -0,0 +1,441 @@
+use crate::bytecode::fusion::{Fusion, FusionStats};
+use crate::bytecode::inline_cache::{CacheStats, InlineCache};
+use crate::bytecode::syntax::{Instruction, Program};
+use crate::ir_flat::syntax::{ArgumentReference, ClosureReference, LocalReference, Reference};
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::lang::syntax::BinOp;
+
+use std::cell::{Ref, RefCell};
+use std::fmt::Debug;
+use std::rc::Rc;
+
+// How the virtual machine represents values. The enum `Value` is the default,
+// and the `nan-boxing` feature adds `nan_box::NanBoxedValue`, which fits every
+// value in a single 64-bit word.
+pub trait ValueRepr: Clone + Debug + Sized {
+    fn int(value: i64) -> Self;
+    fn bool(value: bool) -> Self;
+    fn tuple(fields: Vec<Self>) -> Self;
+    fn closure(closure: Rc<Closure<Self>>) -> Self;
+
+    fn check_int(&self) -> Result<i64, RuntimeError>;
+    fn check_bool(&self) -> Result<bool, RuntimeError>;
+    fn check_closure(&self) -> Result<Rc<Closure<Self>>, RuntimeError>;
+    fn borrow_fields(&self) -> Result<Ref<'_, Vec<Self>>, RuntimeError>;
+
+    fn get_field(&self, index: i64) -> Result<Self, RuntimeError> {
+        let fields = self.borrow_fields()?;
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| fields.get(i))
+            .cloned()
+            .ok_or(RuntimeError::IndexOutOfRange {
+                index,
+                size: fields.len(),
+            })
+    }
+
+    fn set_field(&self, index: u32, new_value: Self) -> Result<(), RuntimeError>;
+}
+
+// Values are reference counted using Rc, which gives the same memory
+// management behavior as the reference-counted heap of the let interpreter.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Tuple(Rc<RefCell<Vec<Value>>>),
+    Closure(Rc<Closure>),
+}
+
+#[derive(Debug)]
+pub struct Closure<V = Value> {
+    pub function_index: usize,
+    pub environment: Vec<V>,
+}
+
+impl Value {
+    fn check_tuple(&self) -> Result<&Rc<RefCell<Vec<Value>>>, RuntimeError> {
+        match self {
+            Value::Tuple(tuple) => Ok(tuple),
+            _ => Err(RuntimeError::TypeMismatch { expected: "tuple" }),
+        }
+    }
+}
+
+impl ValueRepr for Value {
+    fn int(value: i64) -> Self {
+        Value::Int(value)
+    }
+
+    fn bool(value: bool) -> Self {
+        Value::Bool(value)
+    }
+
+    fn tuple(fields: Vec<Self>) -> Self {
+        Value::Tuple(Rc::new(RefCell::new(fields)))
+    }
+
+    fn closure(closure: Rc<Closure<Self>>) -> Self {
+        Value::Closure(closure)
+    }
+
+    fn check_int(&self) -> Result<i64, RuntimeError> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "int" }),
+        }
+    }
+
+    fn check_bool(&self) -> Result<bool, RuntimeError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "bool" }),
+        }
+    }
+
+    fn check_closure(&self) -> Result<Rc<Closure<Self>>, RuntimeError> {
+        match self {
+            Value::Closure(closure) => Ok(closure.clone()),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "closure",
+            }),
+        }
+    }
+
+    fn borrow_fields(&self) -> Result<Ref<'_, Vec<Self>>, RuntimeError> {
+        Ok(self.check_tuple()?.borrow())
+    }
+
+    fn set_field(&self, index: u32, new_value: Self) -> Result<(), RuntimeError> {
+        let mut fields = self.check_tuple()?.borrow_mut();
+        let size = fields.len();
+        match fields.get_mut(index as usize) {
+            Some(field) => *field = new_value,
+            None => {
+                return Err(RuntimeError::IndexOutOfRange {
+                    index: index as i64,
+                    size,
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct CallFrame<V> {
+    closure: Rc<Closure<V>>,
+    arguments: Vec<V>,
+    locals: Vec<Option<V>>,
+    // Index of the next instruction to execute in the code of the function.
+    program_counter: usize,
+    // Height of the operand stack when the function was entered.
+    stack_base: usize,
+}
+
+#[derive(Debug)]
+pub struct VirtualMachine<V = Value> {
+    program: Program,
+    operand_stack: Vec<V>,
+    call_stack: Vec<CallFrame<V>>,
+    // An inline cache for every instruction of every function, although only
+    // calls and field accesses use theirs.
+    caches: Vec<Vec<InlineCache>>,
+    cache_stats: CacheStats,
+    // The superinstructions that have been executed.
+    fusion_stats: FusionStats,
+}
+
+impl VirtualMachine {
+    pub fn new(program: Program) -> Self {
+        Self::with_representation(program)
+    }
+}
+
+impl<V: ValueRepr> VirtualMachine<V> {
+    pub fn with_representation(program: Program) -> Self {
+        let caches = program
+            .functions
+            .iter()
+            .map(|function| vec![InlineCache::Empty; function.code.len()])
+            .collect();
+        VirtualMachine {
+            program,
+            operand_stack: Vec::new(),
+            call_stack: Vec::new(),
+            caches,
+            cache_stats: CacheStats::default(),
+            fusion_stats: FusionStats::default(),
+        }
+    }
+
+    pub fn cache_stats(&self) -> &CacheStats {
+        &self.cache_stats
+    }
+
+    pub fn fusion_stats(&self) -> &FusionStats {
+        &self.fusion_stats
+    }
+
+    fn pop(&mut self) -> V {
+        self.operand_stack.pop().expect("operand stack underflow")
+    }
+
+    fn pop_many(&mut self, count: usize) -> Vec<V> {
+        let start = self
+            .operand_stack
+            .len()
+            .checked_sub(count)
+            .expect("operand stack underflow");
+        self.operand_stack.split_off(start)
+    }
+
+    fn current_frame(&self) -> &CallFrame<V> {
+        self.call_stack.last().expect("no active call frame")
+    }
+
+    fn current_frame_mut(&mut self) -> &mut CallFrame<V> {
+        self.call_stack.last_mut().expect("no active call frame")
+    }
+
+    fn enter_function(
+        &mut self,
+        closure: Rc<Closure<V>>,
+        arguments: Vec<V>,
+    ) -> Result<(), RuntimeError> {
+        let function = &self.program.functions[closure.function_index];
+
+        if function.args_size != arguments.len() {
+            return Err(RuntimeError::ArityMismatch {
+                expected: function.args_size,
+                actual: arguments.len(),
+            });
+        }
+
+        self.push_frame(closure, arguments, function.frame_size);
+        Ok(())
+    }
+
+    // A call site always passes the same number of arguments, so the arity
+    // only has to be checked when it calls a function it has not cached.
+    fn call(
+        &mut self,
+        site: (usize, usize),
+        closure: Rc<Closure<V>>,
+        arguments: Vec<V>,
+    ) -> Result<(), RuntimeError> {
+        let cache = &mut self.caches[site.0][site.1];
+        match *cache {
+            InlineCache::Call {
+                function_index,
+                frame_size,
+            } if function_index == closure.function_index => {
+                self.cache_stats.call_hits += 1;
+                self.push_frame(closure, arguments, frame_size);
+                Ok(())
+            }
+            _ => {
+                self.cache_stats.call_misses += 1;
+                let function = &self.program.functions[closure.function_index];
+                *cache = InlineCache::Call {
+                    function_index: closure.function_index,
+                    frame_size: function.frame_size,
+                };
+                self.enter_function(closure, arguments)
+            }
+        }
+    }
+
+    fn get_field(&mut self, site: (usize, usize), tuple: V, index: i64) -> Result<V, RuntimeError> {
+        let cache = &mut self.caches[site.0][site.1];
+        let fields = tuple.borrow_fields()?;
+        if let InlineCache::Field {
+            tuple_size,
+            index: cached_index,
+        } = *cache
+        {
+            if tuple_size == fields.len() && cached_index as i64 == index {
+                self.cache_stats.field_hits += 1;
+                return Ok(fields[cached_index].clone());
+            }
+        }
+
+        self.cache_stats.field_misses += 1;
+        let tuple_size = fields.len();
+        drop(fields);
+        let value = tuple.get_field(index)?;
+        *cache = InlineCache::Field {
+            tuple_size,
+            index: index as usize,
+        };
+        Ok(value)
+    }
+
+    fn push_frame(&mut self, closure: Rc<Closure<V>>, arguments: Vec<V>, frame_size: usize) {
+        self.call_stack.push(CallFrame {
+            locals: vec![None; frame_size],
+            closure,
+            arguments,
+            program_counter: 0,
+            stack_base: self.operand_stack.len(),
+        });
+    }
+
+    fn load(&self, reference: Reference) -> V {
+        let frame = self.current_frame();
+
+        match reference {
+            Reference::Local(LocalReference(offset)) => frame.locals[offset]
+                .clone()
+                .expect("use of uninitialized local"),
+            Reference::Argument(ArgumentReference(offset)) => frame.arguments[offset].clone(),
+            Reference::Closure(ClosureReference(offset)) => {
+                frame.closure.environment[offset].clone()
+            }
+            Reference::This => V::closure(frame.closure.clone()),
+        }
+    }
+
+    fn eval_binop(
+        &mut self,
+        site: (usize, usize),
+        op: BinOp,
+        lhs: V,
+        rhs: V,
+    ) -> Result<V, RuntimeError> {
+        Ok(match op {
+            BinOp::Add => V::int(lhs.check_int()?.wrapping_add(rhs.check_int()?)),
+            BinOp::Sub => V::int(lhs.check_int()?.wrapping_sub(rhs.check_int()?)),
+            BinOp::Eq => V::bool(lhs.check_int()? == rhs.check_int()?),
+            BinOp::Get => self.get_field(site, lhs, rhs.check_int()?)?,
+        })
+    }
+
+    // Errors of the guest program, such as a type mismatch, end the run.
+    pub fn run(&mut self) -> Result<V, RuntimeError> {
+        let entry = Rc::new(Closure {
+            function_index: 0,
+            environment: Vec::new(),
+        });
+        self.enter_function(entry, Vec::new())?;
+
+        loop {
+            if let Some(result) = self.step()? {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<Option<V>, RuntimeError> {
+        let frame = self.current_frame();
+        let site = (frame.closure.function_index, frame.program_counter);
+        let instruction = self.program.functions[site.0].code[site.1].clone();
+        self.current_frame_mut().program_counter += 1;
+
+        match instruction {
+            Instruction::PushInt(value) => self.operand_stack.push(V::int(value)),
+            Instruction::PushBool(value) => self.operand_stack.push(V::bool(value)),
+            Instruction::Load(reference) => {
+                let value = self.load(reference);
+                self.operand_stack.push(value);
+            }
+            Instruction::Store(LocalReference(offset)) => {
+                let value = self.pop();
+                self.current_frame_mut().locals[offset] = Some(value);
+            }
+            Instruction::StoreArgument(offset) => {
+                let value = self.pop();
+                self.current_frame_mut().arguments[offset] = value;
+            }
+            Instruction::BinOp(op) => {
+                let rhs = self.pop();
+                let lhs = self.pop();
+                let value = self.eval_binop(site, op, lhs, rhs)?;
+                self.operand_stack.push(value);
+            }
+            Instruction::MakeTuple(size) => {
+                let fields = self.pop_many(size);
+                self.operand_stack.push(V::tuple(fields));
+            }
+            Instruction::SetField(index) => {
+                let new_value = self.pop();
+                let tuple = self.pop();
+
+                tuple.set_field(index, new_value)?;
+
+                self.operand_stack.push(V::tuple(Vec::new()));
+            }
+            Instruction::MakeClosure {
+                function_index,
+                env_size,
+            } => {
+                let environment = self.pop_many(env_size);
+                self.operand_stack.push(V::closure(Rc::new(Closure {
+                    function_index,
+                    environment,
+                })));
+            }
+            Instruction::Call(args_size) => {
+                let arguments = self.pop_many(args_size);
+                let closure = self.pop().check_closure()?;
+                self.call(site, closure, arguments)?;
+            }
+            Instruction::TailCall(args_size) => {
+                let arguments = self.pop_many(args_size);
+                let closure = self.pop().check_closure()?;
+                let frame = self.call_stack.pop().expect("no active call frame");
+                assert!(self.operand_stack.len() == frame.stack_base);
+                self.call(site, closure, arguments)?;
+            }
+            Instruction::Jump(target) => self.current_frame_mut().program_counter = target,
+            Instruction::JumpIfFalse(target) => {
+                if !self.pop().check_bool()? {
+                    self.current_frame_mut().program_counter = target;
+                }
+            }
+            Instruction::Return => {
+                let result = self.pop();
+                let frame = self.call_stack.pop().expect("no active call frame");
+                assert!(self.operand_stack.len() == frame.stack_base);
+
+                if self.call_stack.is_empty() {
+                    return Ok(Some(result));
+                }
+
+                self.operand_stack.push(result);
+            }
+            Instruction::LoadBinOp(reference, op) => {
+                self.fusion_stats.record(Fusion::LoadBinOp);
+                let rhs = self.load(reference);
+                let lhs = self.pop();
+                let value = self.eval_binop(site, op, lhs, rhs)?;
+                self.operand_stack.push(value);
+            }
+            Instruction::StoreKeep(LocalReference(offset)) => {
+                self.fusion_stats.record(Fusion::StoreKeep);
+                let value = self
+                    .operand_stack
+                    .last()
+                    .expect("operand stack underflow")
+                    .clone();
+                self.current_frame_mut().locals[offset] = Some(value);
+            }
+            Instruction::CompareBranch {
+                local: LocalReference(offset),
+                target,
+            } => {
+                self.fusion_stats.record(Fusion::CompareBranch);
+                let rhs = self.pop().check_int()?;
+                let lhs = self.pop().check_int()?;
+                let frame = self.current_frame_mut();
+                frame.locals[offset] = Some(V::bool(lhs == rhs));
+                if lhs != rhs {
+                    frame.program_counter = target;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
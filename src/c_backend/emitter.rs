@@ -0,0 +1,276 @@
+use crate::ir_flat::syntax::{
+    AllocClosure, Assignment, Control, Definition, Function, Instruction, Program, Reference,
+    Simple, Step,
+};
+use crate::lang::syntax::{BinOp, Constant};
+use crate::result::Result;
+use std::fmt::Write;
+use std::path::Path;
+
+pub const RUNTIME_HEADER: &str = include_str!("runtime.h");
+pub const RUNTIME_HEADER_NAME: &str = "bailey_runtime.h";
+
+// Every function of the flat IR becomes a C function taking the closure being
+// called and its arguments. Blocks are inlined at the If that enters them,
+// like in the bytecode compiler, so that the branches become the arms of an
+// if statement and a branch continues after the If by falling through.
+//
+// The arguments are copied into locals so that jumps to loop headers can
+// rebind them. Locals own their values, which are released when the local is
+// overwritten or the function returns. Tail calls are emitted as ordinary
+// calls, since C does not guarantee that they reuse the stack frame.
+struct FunctionEmitter<'a> {
+    program: &'a Program,
+    function: &'a Function,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> FunctionEmitter<'a> {
+    fn new(program: &'a Program, function: &'a Function) -> Self {
+        FunctionEmitter {
+            program,
+            function,
+            out: String::new(),
+            indent: 1,
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        // Writing to a String cannot fail.
+        writeln!(self.out, "{}{}", "    ".repeat(self.indent), text).unwrap();
+    }
+
+    fn emit_function(mut self, function_index: usize) -> String {
+        let function = self.function;
+
+        self.indent = 0;
+        self.line(&format!("{} {{", signature(function_index)));
+        self.indent = 1;
+        self.line("(void)self;");
+        self.line("(void)arguments;");
+
+        for i in 0..function.args_size {
+            self.line(&format!("bly_value a{} = bly_retain(arguments[{}]);", i, i));
+        }
+        for i in 0..function.frame_size {
+            self.line(&format!("bly_value l{} = NULL;", i));
+        }
+        self.line("bly_value result;");
+
+        // The body of every function starts at its first block.
+        self.emit_block(0);
+
+        self.indent = 0;
+        self.line("exit:");
+        self.indent = 1;
+        for i in 0..function.args_size {
+            self.line(&format!("bly_release(a{});", i));
+        }
+        for i in 0..function.frame_size {
+            self.line(&format!("bly_release(l{});", i));
+        }
+        self.line("return result;");
+
+        self.indent = 0;
+        self.line("}");
+        self.out
+    }
+
+    fn emit_block(&mut self, block_index: usize) {
+        let block = &self.function.blocks[block_index];
+
+        if block.is_loop_header {
+            self.indent -= 1;
+            self.line(&format!("loop_{}:;", block_index));
+            self.indent += 1;
+        }
+
+        for instruction in &block.instructions {
+            match instruction {
+                // The branch ends at the end of the arm of the if statement.
+                Instruction::Jump(_) => {}
+                Instruction::Return(result) => {
+                    self.line(&format!("result = bly_retain({});", reference(*result)));
+                    self.line("goto exit;");
+                }
+                Instruction::Assignment(Assignment { name, definition }) => {
+                    let value = match definition {
+                        Definition::Var(var) => format!("bly_retain({})", reference(*var)),
+                        Definition::Step(Step::Simple(simple)) => self.simple(simple),
+                        Definition::Step(Step::Control(control)) => match self.control(control) {
+                            Some(value) => value,
+                            None => continue,
+                        },
+                    };
+
+                    self.line(&format!("bly_assign(&l{}, {});", name.0, value));
+                }
+            }
+        }
+    }
+
+    fn simple(&self, simple: &Simple) -> String {
+        match simple {
+            Simple::Literal(Constant::Int { value }) => format!("bly_int({})", value),
+            Simple::Literal(Constant::Bool { value }) => format!("bly_bool({})", value),
+            Simple::Fun(AllocClosure {
+                free_vars, body, ..
+            }) => format!(
+                "bly_closure({}, {}, {}, {})",
+                function_name(body.function_index),
+                self.program.functions[body.function_index].args_size,
+                free_vars.len(),
+                references(free_vars)
+            ),
+            Simple::BinOp { op, lhs, rhs } => {
+                let helper = match op {
+                    BinOp::Add => "bly_add",
+                    BinOp::Sub => "bly_sub",
+                    BinOp::Eq => "bly_eq",
+                    BinOp::Get => "bly_get",
+                };
+                format!("{}({}, {})", helper, reference(*lhs), reference(*rhs))
+            }
+            Simple::Tuple { args } => format!("bly_tuple({}, {})", args.len(), references(args)),
+            Simple::Set {
+                tuple,
+                index,
+                new_value,
+            } => format!(
+                "bly_set({}, {}, {})",
+                reference(*tuple),
+                index,
+                reference(*new_value)
+            ),
+        }
+    }
+
+    // Returns the value of the control instruction, or None if it assigns its
+    // local itself (an If) or does not continue (a jump).
+    fn control(&mut self, control: &Control) -> Option<String> {
+        match control {
+            Control::Call { func, args } | Control::TailCall { func, args } => Some(format!(
+                "bly_call({}, {}, {})",
+                reference(*func),
+                args.len(),
+                references(args)
+            )),
+            Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => {
+                self.line(&format!(
+                    "if (bly_check_bool({})) {{",
+                    reference(*condition)
+                ));
+                self.indent += 1;
+                self.emit_block(branch_success.block_index);
+                self.indent -= 1;
+                self.line("} else {");
+                self.indent += 1;
+                self.emit_block(branch_failure.block_index);
+                self.indent -= 1;
+                self.line("}");
+                None
+            }
+            Control::Jump { target, args } => {
+                // All arguments are retained before any of them is rebound,
+                // since the new values can depend on the old ones.
+                self.line("{");
+                self.indent += 1;
+                for (i, arg) in args.iter().enumerate() {
+                    self.line(&format!(
+                        "bly_value t{} = bly_retain({});",
+                        i,
+                        reference(*arg)
+                    ));
+                }
+                for i in 0..args.len() {
+                    self.line(&format!("bly_release(a{});", i));
+                    self.line(&format!("a{} = t{};", i, i));
+                }
+                self.line(&format!("goto loop_{};", target.block_index));
+                self.indent -= 1;
+                self.line("}");
+                None
+            }
+        }
+    }
+}
+
+fn function_name(function_index: usize) -> String {
+    format!("bly_function_{}", function_index)
+}
+
+fn signature(function_index: usize) -> String {
+    format!(
+        "static bly_value {}(bly_value self, bly_value *arguments)",
+        function_name(function_index)
+    )
+}
+
+fn reference(reference: Reference) -> String {
+    match reference {
+        Reference::Local(local) => format!("l{}", local.0),
+        Reference::Argument(argument) => format!("a{}", argument.0),
+        Reference::Closure(closure) => format!("self->fields[{}]", closure.0),
+        Reference::This => "self".to_owned(),
+    }
+}
+
+// An array of values as a compound literal, which cannot be empty in C.
+fn references(references: &[Reference]) -> String {
+    if references.is_empty() {
+        return "NULL".to_owned();
+    }
+
+    let values: Vec<String> = references.iter().map(|r| reference(*r)).collect();
+    format!("(bly_value[]){{{}}}", values.join(", "))
+}
+
+// Emits a C translation unit that includes the runtime header and prints the
+// result of the program when it is run.
+pub fn emit_program(program: &Program) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "#include \"{}\"", RUNTIME_HEADER_NAME).unwrap();
+    writeln!(out).unwrap();
+
+    // Functions can refer to each other in any order.
+    for i in 0..program.functions.len() {
+        writeln!(out, "{};", signature(i)).unwrap();
+    }
+
+    for (i, function) in program.functions.iter().enumerate() {
+        writeln!(out).unwrap();
+        out += &FunctionEmitter::new(program, function).emit_function(i);
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "int main(void) {{").unwrap();
+    writeln!(
+        out,
+        "    bly_value result = {}(NULL, NULL);",
+        function_name(0)
+    )
+    .unwrap();
+    writeln!(out, "    bly_print(result);").unwrap();
+    writeln!(out, "    printf(\"\\n\");").unwrap();
+    writeln!(out, "    bly_release(result);").unwrap();
+    writeln!(out, "    return 0;").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+// Writes the program and the runtime header to the directory, from where they
+// can be compiled with for example `cc -O2 program.c`.
+pub fn save_c_files(program: &Program, directory: &Path) -> Result<()> {
+    std::fs::create_dir_all(directory)?;
+    std::fs::write(directory.join(RUNTIME_HEADER_NAME), RUNTIME_HEADER)?;
+    std::fs::write(directory.join("program.c"), emit_program(program))?;
+
+    Ok(())
+}
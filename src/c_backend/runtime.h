@@ -0,0 +1,231 @@
+// Runtime for C code generated from the flat intermediate language. Every
+// value is boxed in a reference-counted heap object. Functions return owned
+// references and borrow their arguments.
+#ifndef BAILEY_RUNTIME_H
+#define BAILEY_RUNTIME_H
+
+#include <inttypes.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef struct bly_object *bly_value;
+typedef bly_value (*bly_code)(bly_value self, bly_value *arguments);
+
+enum bly_kind { BLY_INT, BLY_BOOL, BLY_TUPLE, BLY_CLOSURE };
+
+struct bly_object {
+    size_t refcount;
+    enum bly_kind kind;
+    union {
+        int64_t int_value;
+        bool bool_value;
+        // Links the objects whose fields are waiting to be released, once
+        // their own value is no longer needed.
+        struct bly_object *next_dead;
+        struct {
+            bly_code code;
+            size_t args_size;
+        } closure;
+    } as;
+    // The fields of a tuple or the environment of a closure.
+    size_t size;
+    bly_value fields[];
+};
+
+static inline void bly_panic(const char *message) {
+    fprintf(stderr, "runtime error: %s\n", message);
+    exit(1);
+}
+
+static inline bly_value bly_alloc(enum bly_kind kind, size_t size) {
+    bly_value object = malloc(sizeof(struct bly_object) + size * sizeof(bly_value));
+    if (object == NULL) {
+        bly_panic("out of memory");
+    }
+    object->refcount = 1;
+    object->kind = kind;
+    object->size = size;
+    return object;
+}
+
+// The reference count of objects that are never freed, such as the cached
+// small integers and booleans.
+#define BLY_IMMORTAL SIZE_MAX
+
+static inline bly_value bly_retain(bly_value value) {
+    if (value->refcount != BLY_IMMORTAL) {
+        value->refcount++;
+    }
+    return value;
+}
+
+// Freeing an object releases its fields, which can free them in turn. The
+// objects that are to be freed are kept in a list instead of recursing, so that
+// freeing a long list does not overflow the stack.
+static inline void bly_release(bly_value value) {
+    if (value == NULL || value->refcount == BLY_IMMORTAL || --value->refcount > 0) {
+        return;
+    }
+    value->as.next_dead = NULL;
+    while (value != NULL) {
+        bly_value next = value->as.next_dead;
+        for (size_t i = 0; i < value->size; i++) {
+            bly_value field = value->fields[i];
+            if (field != NULL && field->refcount != BLY_IMMORTAL && --field->refcount == 0) {
+                field->as.next_dead = next;
+                next = field;
+            }
+        }
+        free(value);
+        value = next;
+    }
+}
+
+// Stores an owned value in a local, releasing its previous value.
+static inline void bly_assign(bly_value *local, bly_value value) {
+    bly_release(*local);
+    *local = value;
+}
+
+// Small integers and the booleans are allocated once, the first time they are
+// needed, and shared from then on, so that counters and comparisons do not
+// allocate.
+#define BLY_SMALL_INT_MIN (-128)
+#define BLY_SMALL_INT_MAX 1024
+
+static bly_value bly_small_ints[BLY_SMALL_INT_MAX - BLY_SMALL_INT_MIN + 1];
+static bly_value bly_bools[2];
+
+static inline bly_value bly_int(int64_t value) {
+    bool is_small = value >= BLY_SMALL_INT_MIN && value <= BLY_SMALL_INT_MAX;
+    if (is_small && bly_small_ints[value - BLY_SMALL_INT_MIN] != NULL) {
+        return bly_small_ints[value - BLY_SMALL_INT_MIN];
+    }
+
+    bly_value object = bly_alloc(BLY_INT, 0);
+    object->as.int_value = value;
+    if (is_small) {
+        object->refcount = BLY_IMMORTAL;
+        bly_small_ints[value - BLY_SMALL_INT_MIN] = object;
+    }
+    return object;
+}
+
+static inline bly_value bly_bool(bool value) {
+    if (bly_bools[value] == NULL) {
+        bly_value object = bly_alloc(BLY_BOOL, 0);
+        object->as.bool_value = value;
+        object->refcount = BLY_IMMORTAL;
+        bly_bools[value] = object;
+    }
+    return bly_bools[value];
+}
+
+static inline int64_t bly_check_int(bly_value value) {
+    if (value->kind != BLY_INT) {
+        bly_panic("expected int");
+    }
+    return value->as.int_value;
+}
+
+static inline bool bly_check_bool(bly_value value) {
+    if (value->kind != BLY_BOOL) {
+        bly_panic("expected bool");
+    }
+    return value->as.bool_value;
+}
+
+static inline bly_value bly_tuple(size_t size, bly_value *fields) {
+    bly_value object = bly_alloc(BLY_TUPLE, size);
+    for (size_t i = 0; i < size; i++) {
+        object->fields[i] = bly_retain(fields[i]);
+    }
+    return object;
+}
+
+static inline bly_value bly_closure(bly_code code, size_t args_size, size_t size, bly_value *environment) {
+    bly_value object = bly_alloc(BLY_CLOSURE, size);
+    object->as.closure.code = code;
+    object->as.closure.args_size = args_size;
+    for (size_t i = 0; i < size; i++) {
+        object->fields[i] = bly_retain(environment[i]);
+    }
+    return object;
+}
+
+// Integers wrap around on overflow, as in the interpreters. Signed overflow is
+// undefined in C, so the arithmetic is done on unsigned integers.
+static inline bly_value bly_add(bly_value lhs, bly_value rhs) {
+    return bly_int((int64_t)((uint64_t)bly_check_int(lhs) + (uint64_t)bly_check_int(rhs)));
+}
+
+static inline bly_value bly_sub(bly_value lhs, bly_value rhs) {
+    return bly_int((int64_t)((uint64_t)bly_check_int(lhs) - (uint64_t)bly_check_int(rhs)));
+}
+
+static inline bly_value bly_eq(bly_value lhs, bly_value rhs) {
+    return bly_bool(bly_check_int(lhs) == bly_check_int(rhs));
+}
+
+static inline bly_value bly_get(bly_value tuple, bly_value index) {
+    int64_t i = bly_check_int(index);
+    if (tuple->kind != BLY_TUPLE) {
+        bly_panic("expected tuple");
+    }
+    if (i < 0 || (size_t)i >= tuple->size) {
+        bly_panic("field index out of range");
+    }
+    return bly_retain(tuple->fields[i]);
+}
+
+static inline bly_value bly_set(bly_value tuple, uint64_t index, bly_value new_value) {
+    if (tuple->kind != BLY_TUPLE) {
+        bly_panic("expected tuple");
+    }
+    if (index >= tuple->size) {
+        bly_panic("tuple index out of range during mutation");
+    }
+    // The new value is retained first, in case it is the same as the old one.
+    bly_retain(new_value);
+    bly_release(tuple->fields[index]);
+    tuple->fields[index] = new_value;
+    return bly_tuple(0, NULL);
+}
+
+static inline bly_value bly_call(bly_value func, size_t args_size, bly_value *arguments) {
+    if (func->kind != BLY_CLOSURE) {
+        bly_panic("expected closure");
+    }
+    if (func->as.closure.args_size != args_size) {
+        bly_panic("incorrect number of arguments");
+    }
+    return func->as.closure.code(func, arguments);
+}
+
+static inline void bly_print(bly_value value) {
+    switch (value->kind) {
+    case BLY_INT:
+        printf("%" PRId64, value->as.int_value);
+        break;
+    case BLY_BOOL:
+        printf(value->as.bool_value ? "true" : "false");
+        break;
+    case BLY_TUPLE:
+        printf("(");
+        for (size_t i = 0; i < value->size; i++) {
+            if (i > 0) {
+                printf(", ");
+            }
+            bly_print(value->fields[i]);
+        }
+        printf(")");
+        break;
+    case BLY_CLOSURE:
+        printf("<closure>");
+        break;
+    }
+}
+
+#endif
@@ -0,0 +1,410 @@
+use crate::bytecode::compiler::function_names;
+use crate::bytecode::syntax as bytecode;
+use crate::ir_flat::syntax as flat;
+use crate::ir_flat::syntax::Reference;
+use crate::ir_let::let_expr as source;
+use crate::lang::syntax::{BinOp, Constant};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+// The column at which source annotations start.
+const ANNOTATION_COLUMN: usize = 48;
+
+fn binop(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Eq => "==",
+        BinOp::Get => "!!",
+    }
+}
+
+fn constant(c: Constant) -> String {
+    match c {
+        Constant::Int { value } => value.to_string(),
+        Constant::Bool { value } => value.to_string(),
+    }
+}
+
+fn line(output: &mut String, indent: &str, text: &str, annotation: Option<&str>) {
+    match annotation {
+        Some(annotation) => {
+            let width = ANNOTATION_COLUMN.saturating_sub(indent.len());
+            writeln!(
+                output,
+                "{}{:<width$} ; {}",
+                indent,
+                text,
+                annotation,
+                width = width
+            )
+        }
+        None => writeln!(output, "{}{}", indent, text),
+    }
+    .unwrap();
+}
+
+// Prints programs of the flat intermediate language and bytecode in a readable
+// form. Jump targets are shown as labels, and references by their kind and
+// offset. With the let program that the flat program was compiled from, the
+// names of arguments and captured variables are shown as well, and every flat
+// instruction is annotated with the instruction it was compiled from.
+#[derive(Default)]
+pub struct Disassembler<'a> {
+    source: Option<&'a source::Program>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new() -> Self {
+        Disassembler { source: None }
+    }
+
+    pub fn with_source(source: &'a source::Program) -> Self {
+        Disassembler {
+            source: Some(source),
+        }
+    }
+
+    fn source_function(&self, function_index: usize) -> Option<&'a source::Function> {
+        self.source
+            .and_then(|program| program.functions.get(function_index))
+    }
+
+    fn reference(&self, function_index: usize, reference: Reference) -> String {
+        let function = self.source_function(function_index);
+        match reference {
+            Reference::Local(local) => format!("local{}", local.0),
+            Reference::Argument(argument) => {
+                match function.and_then(|f| f.arg_names.get(argument.0)) {
+                    Some(name) => format!("arg{}<{}>", argument.0, name),
+                    None => format!("arg{}", argument.0),
+                }
+            }
+            Reference::Closure(closure) => {
+                let free_names = function.and_then(|f| f.free_names.as_ref());
+                match free_names.and_then(|names| names.get(closure.0)) {
+                    Some(name) => format!("env{}<{}>", closure.0, name),
+                    None => format!("env{}", closure.0),
+                }
+            }
+            Reference::This => "this".to_owned(),
+        }
+    }
+
+    fn references(&self, function_index: usize, references: &[Reference]) -> String {
+        let references: Vec<String> = references
+            .iter()
+            .map(|r| self.reference(function_index, *r))
+            .collect();
+        references.join(", ")
+    }
+
+    fn header(
+        &self,
+        output: &mut String,
+        function_index: usize,
+        name: &str,
+        args_size: usize,
+        closure_env_size: usize,
+        frame_size: usize,
+    ) {
+        writeln!(
+            output,
+            "function {} <{}>: args {}, captures {}, frame {}",
+            function_index, name, args_size, closure_env_size, frame_size
+        )
+        .unwrap();
+    }
+
+    pub fn flat(&self, program: &flat::Program) -> String {
+        let names = function_names(program);
+
+        let mut output = String::new();
+        for (function_index, function) in program.functions.iter().enumerate() {
+            self.header(
+                &mut output,
+                function_index,
+                names.get(&function_index).map_or("", |n| n.as_str()),
+                function.args_size,
+                function.closure_env_size,
+                function.frame_size,
+            );
+            let labels = flat_labels(function);
+            let label = |address: flat::TargetAddress| format!("L{}", labels[&address]);
+
+            for (block_index, block) in function.blocks.iter().enumerate() {
+                if block.is_loop_header {
+                    writeln!(output, "block {} (loop header)", block_index).unwrap();
+                } else {
+                    writeln!(output, "block {}", block_index).unwrap();
+                }
+                let annotations = self.flat_annotations(function_index, block_index, block);
+
+                for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                    let address = flat::TargetAddress {
+                        function_index,
+                        block_index,
+                        instruction_index,
+                    };
+                    if labels.contains_key(&address) {
+                        writeln!(output, "{}:", label(address)).unwrap();
+                    }
+
+                    let reference = |r: &Reference| self.reference(function_index, *r);
+                    let text = match instruction {
+                        flat::Instruction::Return(r) => format!("return {}", reference(r)),
+                        flat::Instruction::Jump(target) => format!("goto {}", label(*target)),
+                        flat::Instruction::Assignment(flat::Assignment { name, definition }) => {
+                            let definition = match definition {
+                                flat::Definition::Var(r) => reference(r),
+                                flat::Definition::Step(flat::Step::Simple(simple)) => {
+                                    self.flat_simple(function_index, simple, &names)
+                                }
+                                flat::Definition::Step(flat::Step::Control(control)) => {
+                                    self.flat_control(function_index, control, &label)
+                                }
+                            };
+                            format!("local{} = {}", name.0, definition)
+                        }
+                    };
+                    let annotation = annotations.get(instruction_index).cloned().flatten();
+                    line(&mut output, "    ", &text, annotation.as_deref());
+                }
+            }
+            writeln!(output).unwrap();
+        }
+        output
+    }
+
+    fn flat_simple(
+        &self,
+        function_index: usize,
+        simple: &flat::Simple,
+        names: &HashMap<usize, String>,
+    ) -> String {
+        let reference = |r: &Reference| self.reference(function_index, *r);
+        match simple {
+            flat::Simple::Literal(c) => constant(*c),
+            flat::Simple::Fun(closure) => format!(
+                "closure function {} <{}> [{}]",
+                closure.body.function_index,
+                names
+                    .get(&closure.body.function_index)
+                    .map_or("", |n| n.as_str()),
+                self.references(function_index, &closure.free_vars)
+            ),
+            flat::Simple::BinOp { op, lhs, rhs } => {
+                format!("{} {} {}", reference(lhs), binop(*op), reference(rhs))
+            }
+            flat::Simple::Tuple { args } => {
+                format!("({})", self.references(function_index, args))
+            }
+            flat::Simple::Set {
+                tuple,
+                index,
+                new_value,
+            } => format!("{}.{} := {}", reference(tuple), index, reference(new_value)),
+        }
+    }
+
+    fn flat_control(
+        &self,
+        function_index: usize,
+        control: &flat::Control,
+        label: &dyn Fn(flat::TargetAddress) -> String,
+    ) -> String {
+        let reference = |r: &Reference| self.reference(function_index, *r);
+        match control {
+            flat::Control::Call { func, args } => format!(
+                "call {}({})",
+                reference(func),
+                self.references(function_index, args)
+            ),
+            flat::Control::TailCall { func, args } => format!(
+                "tailcall {}({})",
+                reference(func),
+                self.references(function_index, args)
+            ),
+            flat::Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => format!(
+                "if {} then {} else {}",
+                reference(condition),
+                label(*branch_success),
+                label(*branch_failure)
+            ),
+            flat::Control::Jump { target, args } => format!(
+                "jump {}({})",
+                label(*target),
+                self.references(function_index, args)
+            ),
+        }
+    }
+
+    // The let instruction that every flat instruction of the block was
+    // compiled from. EnterBlock is dropped by the compiler, and ExitBlock
+    // becomes either a Return or an assignment to the local of the If followed
+    // by a jump past it.
+    fn flat_annotations(
+        &self,
+        function_index: usize,
+        block_index: usize,
+        block: &flat::Block,
+    ) -> Vec<Option<String>> {
+        let mut annotations = vec![None; block.instructions.len()];
+        let Some(source_block) = self
+            .source_function(function_index)
+            .and_then(|function| function.blocks.get(block_index))
+        else {
+            return annotations;
+        };
+
+        let mut index = 0;
+        for instruction in &source_block.instructions {
+            if index >= annotations.len() {
+                break;
+            }
+            match instruction {
+                source::Instruction::EnterBlock => {}
+                source::Instruction::Assignment(_) => {
+                    annotations[index] = Some(instruction.to_string());
+                    index += 1;
+                }
+                source::Instruction::ExitBlock(_) => {
+                    annotations[index] = Some(instruction.to_string());
+                    if let flat::Instruction::Assignment(_) = block.instructions[index] {
+                        index += 1;
+                    }
+                    index += 1;
+                }
+            }
+        }
+        annotations
+    }
+
+    pub fn bytecode(&self, program: &bytecode::Program) -> String {
+        let mut output = String::new();
+        for (function_index, function) in program.functions.iter().enumerate() {
+            self.header(
+                &mut output,
+                function_index,
+                &function.name,
+                function.args_size,
+                function.closure_env_size,
+                function.frame_size,
+            );
+
+            let targets: BTreeSet<usize> =
+                function.code.iter().filter_map(bytecode_target).collect();
+            let labels: HashMap<usize, usize> = targets
+                .into_iter()
+                .enumerate()
+                .map(|(label, target)| (target, label))
+                .collect();
+            let label = |target: usize| format!("L{}", labels[&target]);
+
+            for (pc, instruction) in function.code.iter().enumerate() {
+                if labels.contains_key(&pc) {
+                    writeln!(output, "{}:", label(pc)).unwrap();
+                }
+
+                let reference = |r: &Reference| self.reference(function_index, *r);
+                let text = match instruction {
+                    bytecode::Instruction::PushInt(value) => format!("push_int {}", value),
+                    bytecode::Instruction::PushBool(value) => format!("push_bool {}", value),
+                    bytecode::Instruction::Load(r) => format!("load {}", reference(r)),
+                    bytecode::Instruction::Store(local) => format!("store local{}", local.0),
+                    bytecode::Instruction::StoreArgument(offset) => {
+                        let argument = Reference::Argument(flat::ArgumentReference(*offset));
+                        format!("store_arg {}", reference(&argument))
+                    }
+                    bytecode::Instruction::BinOp(op) => format!("binop {}", binop(*op)),
+                    bytecode::Instruction::MakeTuple(size) => format!("make_tuple {}", size),
+                    bytecode::Instruction::SetField(index) => format!("set_field {}", index),
+                    bytecode::Instruction::MakeClosure {
+                        function_index,
+                        env_size,
+                    } => format!(
+                        "make_closure function {} <{}>, captures {}",
+                        function_index,
+                        program
+                            .functions
+                            .get(*function_index)
+                            .map_or("", |f| f.name.as_str()),
+                        env_size
+                    ),
+                    bytecode::Instruction::Call(args) => format!("call {}", args),
+                    bytecode::Instruction::TailCall(args) => format!("tailcall {}", args),
+                    bytecode::Instruction::Jump(target) => format!("jump {}", label(*target)),
+                    bytecode::Instruction::JumpIfFalse(target) => {
+                        format!("jump_if_false {}", label(*target))
+                    }
+                    bytecode::Instruction::Return => "return".to_owned(),
+                    bytecode::Instruction::LoadBinOp(r, op) => {
+                        format!("load_binop {} {}", reference(r), binop(*op))
+                    }
+                    bytecode::Instruction::StoreKeep(local) => {
+                        format!("store_keep local{}", local.0)
+                    }
+                    bytecode::Instruction::CompareBranch { local, target } => {
+                        format!("compare_branch local{} {}", local.0, label(*target))
+                    }
+                };
+                writeln!(output, "{:>6}  {}", pc, text).unwrap();
+            }
+            writeln!(output).unwrap();
+        }
+        output
+    }
+}
+
+fn bytecode_target(instruction: &bytecode::Instruction) -> Option<usize> {
+    match instruction {
+        bytecode::Instruction::Jump(target)
+        | bytecode::Instruction::JumpIfFalse(target)
+        | bytecode::Instruction::CompareBranch { target, .. } => Some(*target),
+        _ => None,
+    }
+}
+
+// Numbers the addresses that the instructions of the function jump to, in the
+// order in which they appear in the function.
+fn flat_labels(function: &flat::Function) -> HashMap<flat::TargetAddress, usize> {
+    let mut targets = BTreeSet::new();
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            match instruction {
+                flat::Instruction::Jump(target) => {
+                    targets.insert(*target);
+                }
+                flat::Instruction::Assignment(flat::Assignment {
+                    definition: flat::Definition::Step(flat::Step::Control(control)),
+                    ..
+                }) => match control {
+                    flat::Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    } => {
+                        targets.insert(*branch_success);
+                        targets.insert(*branch_failure);
+                    }
+                    flat::Control::Jump { target, .. } => {
+                        targets.insert(*target);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    targets
+        .into_iter()
+        .enumerate()
+        .map(|(label, target)| (target, label))
+        .collect()
+}
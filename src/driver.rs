@@ -0,0 +1,298 @@
+use crate::bytecode;
+use crate::bytecode::fusion::fuse_superinstructions;
+use crate::ir_flat::compiler::compile_program;
+use crate::ir_flat::frame_layout::coalesce_slots;
+use crate::ir_flat::object::ObjectFile;
+use crate::ir_flat::syntax as flat;
+use crate::ir_let::compiler::{
+    let_normalize_module, let_normalize_with_globals, let_normalize_with_open_modules,
+    module_interface,
+};
+use crate::ir_let::let_expr::Program;
+use crate::ir_let::linker::{link, qualified_name, CompiledModule};
+use crate::ir_let::passes::constant_folding::ConstantFolding;
+use crate::ir_let::passes::copy_propagation::CopyPropagation;
+use crate::ir_let::passes::hoist_invariants::HoistInvariants;
+use crate::ir_let::passes::known_calls::KnownCalls;
+use crate::ir_let::passes::lambda_lifting::LambdaLifting;
+use crate::ir_let::passes::manager::PassManager;
+use crate::ir_let::passes::prune_captures::PruneCaptures;
+use crate::ir_let::passes::range_propagation::RangePropagation;
+use crate::ir_let::passes::reuse::ReuseTuples;
+use crate::ir_let::passes::simplify::Simplify;
+use crate::ir_let::passes::tail_recursion::TailRecursionToLoop;
+use crate::ir_let::resolve::resolve_variables;
+use crate::lang::prelude::{prelude, PRELUDE_NATIVES};
+use crate::lang::syntax::{Expr, Module};
+use crate::result::Result;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptimizationLevel {
+    O0,
+    O1,
+    O2,
+}
+
+impl OptimizationLevel {
+    // Parses command line flags of the form -O0, -O1 and -O2.
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "-O0" => Some(OptimizationLevel::O0),
+            "-O1" => Some(OptimizationLevel::O1),
+            "-O2" => Some(OptimizationLevel::O2),
+            _ => None,
+        }
+    }
+
+    pub fn pass_manager(self) -> PassManager {
+        let mut manager = PassManager::new();
+
+        if self >= OptimizationLevel::O1 {
+            manager.add_pass(ConstantFolding);
+            manager.add_pass(Simplify::new());
+        }
+
+        if self >= OptimizationLevel::O2 {
+            manager.add_pass(RangePropagation);
+            manager.add_pass(CopyPropagation);
+            manager.add_pass(TailRecursionToLoop);
+            manager.add_pass(HoistInvariants);
+        }
+
+        if self >= OptimizationLevel::O1 {
+            manager.add_pass(PruneCaptures);
+        }
+
+        if self >= OptimizationLevel::O2 {
+            manager.add_pass(KnownCalls);
+            manager.add_pass(LambdaLifting);
+            manager.add_pass(ReuseTuples);
+        }
+
+        manager
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub optimization_level: OptimizationLevel,
+    pub generate_flat_ir: bool,
+    // Without debug info, the names of closures are removed from the flat IR.
+    pub debug_info: bool,
+    // Names that the program can use without binding them, such as native
+    // functions. Only the interpreter of the let intermediate language can
+    // provide them.
+    pub globals: Vec<String>,
+    // Whether programs can use the definitions of `lang::prelude`.
+    pub prelude: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            optimization_level: OptimizationLevel::O2,
+            generate_flat_ir: true,
+            debug_info: true,
+            globals: Vec::new(),
+            prelude: true,
+        }
+    }
+}
+
+impl CompileOptions {
+    // Applies a command line flag to the options, returning false if the flag
+    // is not a compiler option.
+    pub fn parse_flag(&mut self, flag: &str) -> bool {
+        if let Some(optimization_level) = OptimizationLevel::from_flag(flag) {
+            self.optimization_level = optimization_level;
+            return true;
+        }
+
+        match flag {
+            "--no-flat" => self.generate_flat_ir = false,
+            "-g" => self.debug_info = true,
+            "--no-debug-info" => self.debug_info = false,
+            "--no-prelude" => self.prelude = false,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    pub let_program: Program,
+    pub flat_program: Option<flat::Program>,
+}
+
+pub fn compile(e: &Expr, options: &CompileOptions) -> Result<CompiledProgram> {
+    let let_program = if options.prelude {
+        normalize_with_prelude(e, options)?
+    } else {
+        let_normalize_with_globals(e, &options.globals)?
+    };
+    compile_let_program(let_program, options)
+}
+
+// The prelude is only linked if the program uses it, so that other programs
+// are compiled as if there was no prelude.
+fn normalize_with_prelude(e: &Expr, options: &CompileOptions) -> Result<Program> {
+    let mut prelude_globals = options.globals.clone();
+    prelude_globals.extend(PRELUDE_NATIVES.iter().map(|name| name.to_string()));
+    let prelude = let_normalize_module(&prelude(), &HashMap::new(), &prelude_globals)?;
+
+    let program = let_normalize_with_open_modules(e, &options.globals, &[&prelude])?;
+    let free_names = program.functions[0].free_names.iter().flatten();
+    let uses_prelude = free_names.map(|name| name.as_str()).any(|name| {
+        prelude
+            .exports
+            .iter()
+            .any(|e| qualified_name(&prelude.name, e) == name)
+    });
+    if !uses_prelude {
+        return Ok(program);
+    }
+
+    check_prelude_natives(options)?;
+    let main = CompiledModule {
+        name: "main".to_owned(),
+        imports: vec![prelude.name.clone()],
+        exports: vec![],
+        program,
+    };
+    link(&[prelude, main], "main")
+}
+
+fn check_prelude_natives(options: &CompileOptions) -> Result<()> {
+    for native in PRELUDE_NATIVES {
+        if !options.globals.iter().any(|global| global == native) {
+            return Err(format!(
+                "the prelude requires the global {}, see Natives::register_prelude",
+                native
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+// Compiles every module on its own and links the ones that the main module
+// depends on into a single program. Unless one of the modules is called
+// prelude, the prelude can be imported like any other module.
+pub fn compile_modules(
+    modules: &[Module],
+    main: &str,
+    options: &CompileOptions,
+) -> Result<CompiledProgram> {
+    let mut modules = modules.to_vec();
+    let imports_prelude = modules
+        .iter()
+        .flat_map(|module| &module.imports)
+        .any(|import| import.module == "prelude");
+    if options.prelude && imports_prelude && modules.iter().all(|m| m.name != "prelude") {
+        check_prelude_natives(options)?;
+        modules.push(prelude());
+    }
+
+    let mut interfaces = HashMap::new();
+    for module in &modules {
+        interfaces.insert(module.name.clone(), module_interface(module)?);
+    }
+
+    let compiled_modules = modules
+        .iter()
+        .map(|module| let_normalize_module(module, &interfaces, &options.globals))
+        .collect::<Result<Vec<_>>>()?;
+    compile_let_program(link(&compiled_modules, main)?, options)
+}
+
+// Compiles a module to an object file on its own. Only the interfaces of the
+// modules that it imports are needed, which are the exports of their object
+// files.
+pub fn compile_object(
+    module: &Module,
+    interfaces: &HashMap<String, Vec<String>>,
+    options: &CompileOptions,
+) -> Result<ObjectFile> {
+    let compiled_module = let_normalize_module(module, interfaces, &options.globals)?;
+    let compiled = compile_let_program(compiled_module.program, options)?;
+
+    let free_names = compiled.let_program.functions[0]
+        .free_names
+        .iter()
+        .flatten()
+        .map(|name| name.to_string())
+        .collect();
+    Ok(ObjectFile {
+        name: compiled_module.name,
+        imports: compiled_module.imports,
+        exports: compiled_module.exports,
+        free_names,
+        program: compiled
+            .flat_program
+            .ok_or("object files require the flat IR to be generated")?,
+    })
+}
+
+fn compile_let_program(
+    mut let_program: Program,
+    options: &CompileOptions,
+) -> Result<CompiledProgram> {
+    options
+        .optimization_level
+        .pass_manager()
+        .run(&mut let_program);
+    resolve_variables(&mut let_program);
+
+    let flat_program = if options.generate_flat_ir {
+        let mut flat_program = compile_program(&let_program);
+        if options.optimization_level >= OptimizationLevel::O1 {
+            coalesce_slots(&mut flat_program);
+        }
+        if !options.debug_info {
+            strip_debug_info(&mut flat_program);
+        }
+        Some(flat_program)
+    } else {
+        None
+    };
+
+    Ok(CompiledProgram {
+        let_program,
+        flat_program,
+    })
+}
+
+// Compiles the program all the way to bytecode for the virtual machine, which
+// runs on the flat intermediate language. From -O1, pairs of instructions are
+// fused into superinstructions.
+pub fn compile_bytecode(e: &Expr, options: &CompileOptions) -> Result<bytecode::syntax::Program> {
+    let flat_program = compile(e, options)?
+        .flat_program
+        .ok_or("the VM requires the flat IR to be generated")?;
+    let mut program = bytecode::compiler::compile_program(&flat_program);
+    if options.optimization_level >= OptimizationLevel::O1 {
+        fuse_superinstructions(&mut program);
+    }
+    Ok(program)
+}
+
+fn strip_debug_info(program: &mut flat::Program) {
+    for function in &mut program.functions {
+        for block in &mut function.blocks {
+            for instruction in &mut block.instructions {
+                if let flat::Instruction::Assignment(flat::Assignment {
+                    definition:
+                        flat::Definition::Step(flat::Step::Simple(flat::Simple::Fun(closure))),
+                    ..
+                }) = instruction
+                {
+                    closure.name = None;
+                }
+            }
+        }
+    }
+    program.names.clear();
+}
@@ -0,0 +1,214 @@
+use crate::driver::{compile, CompileOptions};
+use crate::ir_let::interpreter::config::EvaluatorConfig;
+use crate::ir_let::interpreter::heap::Heap;
+use crate::ir_let::interpreter::heap_value::{self, HeapAddress, HeapValue};
+use crate::ir_let::interpreter::native::{Natives, TypeTag};
+use crate::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use crate::lang::parser::parse_expr;
+use crate::result::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+// The result of `eval_str`, which the host can inspect after the heap it came
+// from is gone. Tuples that were shared on the heap are shared here as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Tuple(Arc<Vec<Value>>),
+    // Other heap values, such as closures, only mean something on their own
+    // heap, so only their type is kept.
+    Opaque(TypeTag),
+}
+
+impl Value {
+    pub fn tuple(fields: Vec<Value>) -> Self {
+        Value::Tuple(Arc::new(fields))
+    }
+
+    // Copies a value out of the heap. Results can be nested as deeply as the
+    // heap allows, such as long lists, so the tuples are visited with an
+    // explicit stack, and each is copied once however many paths reach it. A
+    // tuple that contains itself has no such copy, so cycles give `None`.
+    pub fn from_heap(value: heap_value::Value, heap: &dyn Heap) -> Option<Self> {
+        let root = match value {
+            heap_value::Value::Pointer(address) if tuple_fields(heap, address).is_some() => address,
+            _ => return Some(Self::from_field(value, heap, &HashMap::new())),
+        };
+
+        let mut copied: HashMap<HeapAddress, Value> = HashMap::new();
+        // The tuples that are being copied, each with the number of its
+        // fields that have been visited.
+        let mut stack = vec![(root, 0)];
+        let mut enclosing = HashSet::from([root]);
+        while let Some((address, next)) = stack.last_mut() {
+            let fields = tuple_fields(heap, *address).expect("expected tuple");
+            if let Some(field) = fields.get(*next) {
+                *next += 1;
+                if let heap_value::Value::Pointer(field_address) = *field {
+                    if tuple_fields(heap, field_address).is_some()
+                        && !copied.contains_key(&field_address)
+                    {
+                        if !enclosing.insert(field_address) {
+                            return None;
+                        }
+                        stack.push((field_address, 0));
+                    }
+                }
+                continue;
+            }
+
+            let address = *address;
+            stack.pop();
+            enclosing.remove(&address);
+            let copy = fields
+                .iter()
+                .map(|field| Self::from_field(*field, heap, &copied))
+                .collect();
+            copied.insert(address, Value::tuple(copy));
+        }
+        copied.remove(&root)
+    }
+
+    // Tuples among the fields have been copied already.
+    fn from_field(
+        value: heap_value::Value,
+        heap: &dyn Heap,
+        copied: &HashMap<HeapAddress, Value>,
+    ) -> Self {
+        match value {
+            heap_value::Value::Int(value) => Value::Int(value),
+            heap_value::Value::Bool(value) => Value::Bool(value),
+            heap_value::Value::Pointer(address) => match copied.get(&address) {
+                Some(copy) => copy.clone(),
+                None => Value::Opaque(TypeTag::of(heap, value)),
+            },
+        }
+    }
+
+    // Whether the value is made of integers, booleans and tuples only, so
+    // that it can be rebuilt on another heap.
+    pub fn is_data(&self) -> bool {
+        let mut visited = HashSet::new();
+        let mut worklist = vec![self];
+        while let Some(value) = worklist.pop() {
+            match value {
+                Value::Int(_) | Value::Bool(_) => {}
+                Value::Tuple(fields) => {
+                    if visited.insert(Arc::as_ptr(fields)) {
+                        worklist.extend(fields.iter());
+                    }
+                }
+                Value::Opaque(_) => return false,
+            }
+        }
+        true
+    }
+}
+
+fn tuple_fields(heap: &dyn Heap, address: HeapAddress) -> Option<&[heap_value::Value]> {
+    match heap.deref(address) {
+        HeapValue::Tuple(tuple) => Some(&tuple.field_values),
+        _ => None,
+    }
+}
+
+// Dropping a long list one nested tuple at a time would recurse once per
+// element, so the fields of tuples that are not shared are moved to a
+// worklist instead.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let Value::Tuple(fields) = self else {
+            return;
+        };
+        let Some(fields) = Arc::get_mut(fields) else {
+            return;
+        };
+        let mut worklist = std::mem::take(fields);
+        while let Some(mut value) = worklist.pop() {
+            if let Value::Tuple(fields) = &mut value {
+                if let Some(fields) = Arc::get_mut(fields) {
+                    worklist.append(fields);
+                }
+            }
+        }
+    }
+}
+
+// Written without recursion for the same reason. Shared tuples are written
+// out in full wherever they occur.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The tuples that are being written, each with the number of its
+        // fields that have been written.
+        let mut stack: Vec<(&[Value], usize)> = Vec::new();
+        let mut next = Some(self);
+        loop {
+            match next.take() {
+                Some(Value::Int(value)) => write!(f, "{}", value)?,
+                Some(Value::Bool(value)) => write!(f, "{}", value)?,
+                Some(Value::Tuple(fields)) => {
+                    write!(f, "(")?;
+                    stack.push((fields, 0));
+                }
+                Some(Value::Opaque(tag)) => write!(f, "<{}>", tag.name())?,
+                None => {}
+            }
+
+            let Some((fields, written)) = stack.last_mut() else {
+                return Ok(());
+            };
+            if let Some(field) = fields.get(*written) {
+                if *written > 0 {
+                    write!(f, ", ")?;
+                }
+                *written += 1;
+                next = Some(field);
+            } else {
+                if fields.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")?;
+                stack.pop();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    pub compile: CompileOptions,
+    // The natives are added to the globals of the compile options.
+    pub natives: Natives,
+    pub config: EvaluatorConfig,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        let mut natives = Natives::new();
+        natives.register_prelude();
+        EvalOptions {
+            compile: CompileOptions::default(),
+            natives,
+            config: EvaluatorConfig::default(),
+        }
+    }
+}
+
+// Parses, compiles and runs a program written as an s-expression (see
+// `lang::parser`) in one go. The flat IR is only generated if the compile
+// options ask for it; the program is always run by the interpreter of the let
+// intermediate language, since only that one provides natives.
+pub fn eval_str(source: &str, options: &EvalOptions) -> Result<Value> {
+    let e = parse_expr(source)?;
+
+    let mut compile_options = options.compile.clone();
+    compile_options.globals.extend(options.natives.names());
+    let compiled = compile(&e, &compile_options)?;
+
+    let mut evaluator = ProgramEvaluator::with_config(compiled.let_program, options.config.clone());
+    evaluator.bind_natives(&options.natives)?;
+    let value = evaluator.run()?;
+    Value::from_heap(value, evaluator.heap()).ok_or_else(|| "the result contains a cycle".into())
+}
@@ -0,0 +1,349 @@
+use crate::ir_cps::syntax::{Primitive, Term, HALT_CONTINUATION};
+use crate::lang::syntax::{BinOp, Constant, Expr};
+use crate::result::Result;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Maps the variable names of the source program to their unique names.
+type Substitution = HashMap<String, String>;
+
+type MetaContinuation<'a, T> = Box<dyn FnOnce(&mut CpsConverter, T) -> Result<Term> + 'a>;
+
+// What to do with the value of an expression once it has been converted. A
+// meta continuation builds the rest of the term at compile time, while an
+// object continuation is a continuation variable of the converted program.
+enum Continuation<'a> {
+    Meta(MetaContinuation<'a, String>),
+    Object(String),
+}
+
+impl<'a> Continuation<'a> {
+    fn meta<F>(f: F) -> Self
+    where
+        F: FnOnce(&mut CpsConverter, String) -> Result<Term> + 'a,
+    {
+        Continuation::Meta(Box::new(f))
+    }
+
+    fn apply(self, converter: &mut CpsConverter, value: String) -> Result<Term> {
+        match self {
+            Continuation::Meta(f) => f(converter, value),
+            Continuation::Object(continuation) => Ok(Term::AppCont {
+                continuation,
+                arg: value,
+            }),
+        }
+    }
+
+    // Turns the continuation into a continuation variable, binding a new
+    // continuation around the term produced by `f` if necessary.
+    fn reify<F>(self, converter: &mut CpsConverter, f: F) -> Result<Term>
+    where
+        F: FnOnce(&mut CpsConverter, String) -> Result<Term>,
+    {
+        match self {
+            Continuation::Object(continuation) => f(converter, continuation),
+            Continuation::Meta(k) => {
+                let name = converter.fresh("__k");
+                let arg_name = converter.fresh("__gen");
+                let continuation_body = k(converter, arg_name.clone())?;
+                let body = f(converter, name.clone())?;
+                Ok(Term::LetCont {
+                    name,
+                    arg_name,
+                    continuation_body: Rc::new(continuation_body),
+                    body: Rc::new(body),
+                })
+            }
+        }
+    }
+}
+
+// Converts expressions to continuation-passing style in a single pass, in the
+// style of "Compiling with Continuations, Continued" (Kennedy, 2007). Calls in
+// tail position pass on the continuation of the enclosing function instead of
+// binding a new one.
+struct CpsConverter {
+    var_counter: u64,
+}
+
+impl CpsConverter {
+    fn new() -> Self {
+        CpsConverter { var_counter: 0 }
+    }
+
+    fn fresh(&mut self, base_name: &str) -> String {
+        let count = self.var_counter;
+        self.var_counter += 1;
+        base_name.to_owned() + "__" + &count.to_string()
+    }
+
+    fn convert<'a>(
+        &mut self,
+        e: &'a Expr,
+        substitution: &Substitution,
+        k: Continuation<'a>,
+    ) -> Result<Term> {
+        match e {
+            Expr::Literal(c) => self.bind_primitive(Primitive::Literal(*c), k),
+            Expr::Var { var_name } => {
+                let unique_name = substitution
+                    .get(var_name)
+                    .ok_or_else(|| format!("unbound variable {}", var_name))?
+                    .clone();
+                k.apply(self, unique_name)
+            }
+            Expr::Fun {
+                name: original_name,
+                arg_names: original_arg_names,
+                body,
+            } => {
+                let unique_name = self.fresh(original_name);
+                let continuation = self.fresh("__k");
+
+                let mut body_substitution = substitution.clone();
+                body_substitution.insert(original_name.clone(), unique_name.clone());
+                let mut unique_arg_names = Vec::new();
+                for original_arg_name in original_arg_names {
+                    let unique_arg_name = self.fresh(original_arg_name);
+                    body_substitution.insert(original_arg_name.clone(), unique_arg_name.clone());
+                    unique_arg_names.push(unique_arg_name);
+                }
+
+                let function_body = self.convert(
+                    body,
+                    &body_substitution,
+                    Continuation::Object(continuation.clone()),
+                )?;
+                let rest = k.apply(self, unique_name.clone())?;
+
+                Ok(Term::LetFun {
+                    name: unique_name,
+                    arg_names: unique_arg_names,
+                    continuation,
+                    function_body: Rc::new(function_body),
+                    body: Rc::new(rest),
+                })
+            }
+            Expr::Call { func, args } => {
+                let substitution_args = substitution.clone();
+                self.convert(
+                    func,
+                    substitution,
+                    Continuation::meta(move |comp, func| {
+                        comp.convert_many(
+                            args,
+                            &substitution_args,
+                            Vec::new(),
+                            Box::new(move |comp, args| {
+                                k.reify(comp, |_, continuation| {
+                                    Ok(Term::AppFun {
+                                        func,
+                                        args,
+                                        continuation,
+                                    })
+                                })
+                            }),
+                        )
+                    }),
+                )
+            }
+            Expr::Let {
+                name: original_name,
+                definition,
+                body,
+            } => {
+                let original_name = original_name.clone();
+                let mut body_substitution = substitution.clone();
+                self.convert(
+                    definition,
+                    substitution,
+                    Continuation::meta(move |comp, value| {
+                        body_substitution.insert(original_name, value);
+                        comp.convert(body, &body_substitution, k)
+                    }),
+                )
+            }
+            Expr::LetTuple {
+                names,
+                definition,
+                body,
+            } => {
+                let body_substitution = substitution.clone();
+                self.convert(
+                    definition,
+                    substitution,
+                    Continuation::meta(move |comp, tuple| {
+                        comp.bind_fields(names, tuple, 0, body_substitution, body, k)
+                    }),
+                )
+            }
+            Expr::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => {
+                let substitution_branches = substitution.clone();
+                self.convert(
+                    condition,
+                    substitution,
+                    Continuation::meta(move |comp, condition| {
+                        // Both branches continue with the same (join)
+                        // continuation, so the rest of the program is only
+                        // generated once.
+                        k.reify(comp, |comp, join| {
+                            let branch_success = comp.convert(
+                                branch_success,
+                                &substitution_branches,
+                                Continuation::Object(join.clone()),
+                            )?;
+                            let branch_failure = comp.convert(
+                                branch_failure,
+                                &substitution_branches,
+                                Continuation::Object(join),
+                            )?;
+                            Ok(Term::If {
+                                condition,
+                                branch_success: Rc::new(branch_success),
+                                branch_failure: Rc::new(branch_failure),
+                            })
+                        })
+                    }),
+                )
+            }
+            Expr::BinOp { op, lhs, rhs } => {
+                let op = *op;
+                let substitution_rhs = substitution.clone();
+                self.convert(
+                    lhs,
+                    substitution,
+                    Continuation::meta(move |comp, lhs| {
+                        comp.convert(
+                            rhs,
+                            &substitution_rhs,
+                            Continuation::meta(move |comp, rhs| {
+                                comp.bind_primitive(Primitive::BinOp { op, lhs, rhs }, k)
+                            }),
+                        )
+                    }),
+                )
+            }
+            Expr::Tuple { values } => self.convert_many(
+                values,
+                substitution,
+                Vec::new(),
+                Box::new(move |comp, values| comp.bind_primitive(Primitive::Tuple { values }, k)),
+            ),
+            Expr::Set {
+                tuple,
+                index,
+                new_expr,
+            } => {
+                let index = *index;
+                let substitution_new = substitution.clone();
+                self.convert(
+                    tuple,
+                    substitution,
+                    Continuation::meta(move |comp, tuple| {
+                        comp.convert(
+                            new_expr,
+                            &substitution_new,
+                            Continuation::meta(move |comp, new_value| {
+                                comp.bind_primitive(
+                                    Primitive::Set {
+                                        tuple,
+                                        index,
+                                        new_value,
+                                    },
+                                    k,
+                                )
+                            }),
+                        )
+                    }),
+                )
+            }
+            Expr::Delay { .. } | Expr::Force { .. } => {
+                Err("thunks are only supported by the let interpreter".into())
+            }
+        }
+    }
+
+    // Converts the expressions from left to right, collecting the variables
+    // holding their values.
+    fn convert_many<'a>(
+        &mut self,
+        es: &'a [Expr],
+        substitution: &Substitution,
+        mut done: Vec<String>,
+        k: MetaContinuation<'a, Vec<String>>,
+    ) -> Result<Term> {
+        match es.split_first() {
+            None => k(self, done),
+            Some((first, rest)) => {
+                let substitution_rest = substitution.clone();
+                self.convert(
+                    first,
+                    substitution,
+                    Continuation::meta(move |comp, value| {
+                        done.push(value);
+                        comp.convert_many(rest, &substitution_rest, done, k)
+                    }),
+                )
+            }
+        }
+    }
+
+    // Binds the names to the fields of the tuple from the given index on, and
+    // converts the body in their scope.
+    fn bind_fields<'a>(
+        &mut self,
+        names: &'a [String],
+        tuple: String,
+        index: i64,
+        mut substitution: Substitution,
+        body: &'a Expr,
+        k: Continuation<'a>,
+    ) -> Result<Term> {
+        let Some((original_name, rest)) = names.split_first() else {
+            return self.convert(body, &substitution, k);
+        };
+
+        let index_name = self.fresh("__gen");
+        let unique_name = self.fresh(original_name);
+        substitution.insert(original_name.clone(), unique_name.clone());
+        let rest = self.bind_fields(rest, tuple.clone(), index + 1, substitution, body, k)?;
+
+        Ok(Term::LetPrim {
+            name: index_name.clone(),
+            primitive: Primitive::Literal(Constant::Int { value: index }),
+            body: Rc::new(Term::LetPrim {
+                name: unique_name,
+                primitive: Primitive::BinOp {
+                    op: BinOp::Get,
+                    lhs: tuple,
+                    rhs: index_name,
+                },
+                body: Rc::new(rest),
+            }),
+        })
+    }
+
+    fn bind_primitive(&mut self, primitive: Primitive, k: Continuation<'_>) -> Result<Term> {
+        let name = self.fresh("__gen");
+        let body = k.apply(self, name.clone())?;
+        Ok(Term::LetPrim {
+            name,
+            primitive,
+            body: Rc::new(body),
+        })
+    }
+}
+
+pub fn cps_convert(e: &Expr) -> Result<Term> {
+    let mut converter = CpsConverter::new();
+    converter.convert(
+        e,
+        &Substitution::new(),
+        Continuation::Object(HALT_CONTINUATION.to_owned()),
+    )
+}
@@ -0,0 +1,335 @@
+use crate::ir_cps::syntax::{Primitive, Term, HALT_CONTINUATION};
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::lang::syntax::{BinOp, Constant};
+use crate::symbol::Symbol;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// Values are reference counted using Rc, like in the bytecode VM.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Tuple(Rc<RefCell<Vec<Value>>>),
+    Closure(Rc<Closure>),
+    Continuation(Rc<Continuation>),
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    name: String,
+    arg_names: Vec<String>,
+    continuation: String,
+    body: Rc<Term>,
+    environment: Environment,
+}
+
+#[derive(Debug)]
+pub enum Continuation {
+    Halt,
+    Local {
+        arg_name: String,
+        body: Rc<Term>,
+        environment: Environment,
+    },
+}
+
+impl Value {
+    fn check_int(&self) -> Result<i64, RuntimeError> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "int" }),
+        }
+    }
+
+    fn check_bool(&self) -> Result<bool, RuntimeError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "bool" }),
+        }
+    }
+
+    fn check_tuple(&self) -> Result<&Rc<RefCell<Vec<Value>>>, RuntimeError> {
+        match self {
+            Value::Tuple(tuple) => Ok(tuple),
+            _ => Err(RuntimeError::TypeMismatch { expected: "tuple" }),
+        }
+    }
+
+    fn check_closure(&self) -> Result<&Rc<Closure>, RuntimeError> {
+        match self {
+            Value::Closure(closure) => Ok(closure),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "closure",
+            }),
+        }
+    }
+
+    fn check_continuation(&self) -> Result<&Rc<Continuation>, RuntimeError> {
+        match self {
+            Value::Continuation(continuation) => Ok(continuation),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "continuation",
+            }),
+        }
+    }
+}
+
+// Values are written in the same way as `ir_let::interpreter::render` writes
+// the values of the let interpreter, so that the results of both interpreters
+// can be compared.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
+}
+
+impl Value {
+    // The tuples that are currently being written, since a tuple can contain
+    // itself after it has been mutated.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter,
+        enclosing: &mut Vec<*const RefCell<Vec<Value>>>,
+    ) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Closure(closure) => {
+                let name = Symbol::intern(&closure.name);
+                write!(f, "<closure {}>", name.source_name())
+            }
+            Value::Continuation(_) => write!(f, "<continuation>"),
+            Value::Tuple(tuple) if enclosing.contains(&Rc::as_ptr(tuple)) => write!(f, "<cycle>"),
+            Value::Tuple(tuple) => {
+                enclosing.push(Rc::as_ptr(tuple));
+                let field_values = tuple.borrow();
+                write!(f, "(")?;
+                for (i, field_value) in field_values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    field_value.write(f, enclosing)?;
+                }
+                if field_values.len() == 1 {
+                    write!(f, ",")?;
+                }
+                enclosing.pop();
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// A persistent environment, so that closures and continuations can share the
+// bindings of the scope in which they were created.
+#[derive(Debug, Clone)]
+pub struct Environment(Option<Rc<Binding>>);
+
+#[derive(Debug)]
+struct Binding {
+    name: String,
+    value: Value,
+    next: Environment,
+}
+
+impl Environment {
+    fn new() -> Self {
+        Environment(None)
+    }
+
+    fn bind(&self, name: String, value: Value) -> Self {
+        Environment(Some(Rc::new(Binding {
+            name,
+            value,
+            next: self.clone(),
+        })))
+    }
+
+    fn lookup(&self, name: &str) -> Result<&Value, RuntimeError> {
+        let mut current = &self.0;
+
+        while let Some(binding) = current {
+            if binding.name == name {
+                return Ok(&binding.value);
+            }
+            current = &binding.next.0;
+        }
+
+        Err(RuntimeError::UnboundVariable(name.to_owned()))
+    }
+}
+
+fn eval_primitive(primitive: &Primitive, environment: &Environment) -> Result<Value, RuntimeError> {
+    match primitive {
+        Primitive::Literal(Constant::Int { value }) => Ok(Value::Int(*value)),
+        Primitive::Literal(Constant::Bool { value }) => Ok(Value::Bool(*value)),
+        Primitive::BinOp { op, lhs, rhs } => {
+            let lhs = environment.lookup(lhs)?;
+            let rhs = environment.lookup(rhs)?;
+
+            match op {
+                BinOp::Add => Ok(Value::Int(lhs.check_int()?.wrapping_add(rhs.check_int()?))),
+                BinOp::Sub => Ok(Value::Int(lhs.check_int()?.wrapping_sub(rhs.check_int()?))),
+                BinOp::Eq => Ok(Value::Bool(lhs.check_int()? == rhs.check_int()?)),
+                BinOp::Get => {
+                    let index = rhs.check_int()?;
+                    let fields = lhs.check_tuple()?.borrow();
+
+                    match usize::try_from(index).ok().and_then(|i| fields.get(i)) {
+                        Some(value) => Ok(value.clone()),
+                        None => Err(RuntimeError::IndexOutOfRange {
+                            index,
+                            size: fields.len(),
+                        }),
+                    }
+                }
+            }
+        }
+        Primitive::Tuple { values } => {
+            let values = values
+                .iter()
+                .map(|v| environment.lookup(v).cloned())
+                .collect::<Result<Vec<Value>, RuntimeError>>()?;
+            Ok(Value::Tuple(Rc::new(RefCell::new(values))))
+        }
+        Primitive::Set {
+            tuple,
+            index,
+            new_value,
+        } => {
+            let new_value = environment.lookup(new_value)?.clone();
+            let mut fields = environment.lookup(tuple)?.check_tuple()?.borrow_mut();
+            let size = fields.len();
+
+            match fields.get_mut(*index as usize) {
+                Some(field) => *field = new_value,
+                None => {
+                    return Err(RuntimeError::IndexOutOfRange {
+                        index: (*index).into(),
+                        size,
+                    })
+                }
+            }
+
+            Ok(Value::Tuple(Rc::new(RefCell::new(Vec::new()))))
+        }
+    }
+}
+
+// Since every call in a CPS term is a tail call, the evaluator is a simple
+// loop that never has to remember where to return to: the continuations
+// contain everything that remains to be done.
+pub fn eval(program: &Term) -> Result<Value, RuntimeError> {
+    let mut term = Rc::new(program.clone());
+    let mut environment = Environment::new().bind(
+        HALT_CONTINUATION.to_owned(),
+        Value::Continuation(Rc::new(Continuation::Halt)),
+    );
+
+    loop {
+        let next = match &*term {
+            Term::LetPrim {
+                name,
+                primitive,
+                body,
+            } => {
+                let value = eval_primitive(primitive, &environment)?;
+                environment = environment.bind(name.clone(), value);
+                body.clone()
+            }
+            Term::LetFun {
+                name,
+                arg_names,
+                continuation,
+                function_body,
+                body,
+            } => {
+                let closure = Closure {
+                    name: name.clone(),
+                    arg_names: arg_names.clone(),
+                    continuation: continuation.clone(),
+                    body: function_body.clone(),
+                    environment: environment.clone(),
+                };
+                environment = environment.bind(name.clone(), Value::Closure(Rc::new(closure)));
+                body.clone()
+            }
+            Term::LetCont {
+                name,
+                arg_name,
+                continuation_body,
+                body,
+            } => {
+                let continuation = Continuation::Local {
+                    arg_name: arg_name.clone(),
+                    body: continuation_body.clone(),
+                    environment: environment.clone(),
+                };
+                environment =
+                    environment.bind(name.clone(), Value::Continuation(Rc::new(continuation)));
+                body.clone()
+            }
+            Term::AppFun {
+                func,
+                args,
+                continuation,
+            } => {
+                let closure = environment.lookup(func)?.check_closure()?.clone();
+
+                if closure.arg_names.len() != args.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: closure.arg_names.len(),
+                        actual: args.len(),
+                    });
+                }
+
+                let mut new_environment = closure
+                    .environment
+                    .bind(closure.name.clone(), Value::Closure(closure.clone()))
+                    .bind(
+                        closure.continuation.clone(),
+                        environment.lookup(continuation)?.clone(),
+                    );
+                for (arg_name, arg) in closure.arg_names.iter().zip(args) {
+                    new_environment =
+                        new_environment.bind(arg_name.clone(), environment.lookup(arg)?.clone());
+                }
+
+                environment = new_environment;
+                closure.body.clone()
+            }
+            Term::AppCont { continuation, arg } => {
+                let value = environment.lookup(arg)?.clone();
+
+                match &**environment.lookup(continuation)?.check_continuation()? {
+                    Continuation::Halt => return Ok(value),
+                    Continuation::Local {
+                        arg_name,
+                        body,
+                        environment: continuation_environment,
+                    } => {
+                        let body = body.clone();
+                        environment = continuation_environment.bind(arg_name.clone(), value);
+                        body
+                    }
+                }
+            }
+            Term::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => {
+                if environment.lookup(condition)?.check_bool()? {
+                    branch_success.clone()
+                } else {
+                    branch_failure.clone()
+                }
+            }
+        };
+
+        term = next;
+    }
+}
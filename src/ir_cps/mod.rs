@@ -0,0 +1,3 @@
+pub mod compiler;
+pub mod interpreter;
+pub mod syntax;
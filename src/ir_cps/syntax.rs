@@ -0,0 +1,66 @@
+use crate::lang::syntax::{BinOp, Constant};
+use std::rc::Rc;
+
+// Terms in continuation-passing style. Every intermediate value is bound to a
+// variable, and control never returns: functions receive the continuation to
+// which they should pass their result as an extra argument. Subterms are
+// reference counted so that closures and continuations can share them with the
+// program during evaluation.
+#[derive(Debug, Clone)]
+pub enum Term {
+    LetPrim {
+        name: String,
+        primitive: Primitive,
+        body: Rc<Term>,
+    },
+    // The function is in scope in its own body under its name, which allows
+    // it to call itself recursively.
+    LetFun {
+        name: String,
+        arg_names: Vec<String>,
+        continuation: String,
+        function_body: Rc<Term>,
+        body: Rc<Term>,
+    },
+    LetCont {
+        name: String,
+        arg_name: String,
+        continuation_body: Rc<Term>,
+        body: Rc<Term>,
+    },
+    AppFun {
+        func: String,
+        args: Vec<String>,
+        continuation: String,
+    },
+    AppCont {
+        continuation: String,
+        arg: String,
+    },
+    If {
+        condition: String,
+        branch_success: Rc<Term>,
+        branch_failure: Rc<Term>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum Primitive {
+    Literal(Constant),
+    BinOp {
+        op: BinOp,
+        lhs: String,
+        rhs: String,
+    },
+    Tuple {
+        values: Vec<String>,
+    },
+    Set {
+        tuple: String,
+        index: u32,
+        new_value: String,
+    },
+}
+
+// The continuation that ends the program with the value passed to it.
+pub const HALT_CONTINUATION: &str = "halt";
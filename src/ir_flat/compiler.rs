@@ -1,5 +1,8 @@
 use crate::ir_flat::syntax as target;
 use crate::ir_let::let_expr as source;
+use crate::symbol::Symbol;
+
+use std::collections::HashMap;
 
 use crate::ir_flat::frame_layout::ProgramFrameLayout;
 
@@ -8,6 +11,43 @@ use super::frame_layout::compute_program_frame_layout;
 struct Compiler<'a> {
     program: &'a source::Program,
     frame_layout: ProgramFrameLayout,
+    names: Names,
+}
+
+// The names of the closures of the program, in the order in which they are
+// first allocated.
+#[derive(Default)]
+struct Names {
+    ids: HashMap<Symbol, target::NameId>,
+    names: Vec<String>,
+}
+
+impl Names {
+    fn collect(program: &source::Program) -> Self {
+        let mut names = Names::default();
+        for function in &program.functions {
+            for block in &function.blocks {
+                for instruction in &block.instructions {
+                    if let source::Instruction::Assignment(source::Assignment {
+                        definition:
+                            source::Definition::Step(source::Step::Simple(
+                                source::Simple::Fun(source::AllocClosure { name, .. })
+                                | source::Simple::LiftedFun { name, .. },
+                            )),
+                        ..
+                    }) = instruction
+                    {
+                        let next_id = target::NameId(names.names.len() as u32);
+                        names.ids.entry(*name).or_insert_with(|| {
+                            names.names.push(name.to_string());
+                            next_id
+                        });
+                    }
+                }
+            }
+        }
+        names
+    }
 }
 
 impl<'a> Compiler<'a> {
@@ -15,14 +55,59 @@ impl<'a> Compiler<'a> {
         Compiler {
             program,
             frame_layout: compute_program_frame_layout(program),
+            names: Names::collect(program),
         }
     }
 
-    fn compile_function(&self, function: &source::Function) -> target::Function {
+    fn compile_program(&self) -> target::Program {
+        let mut compiled_functions = Vec::new();
+
+        for (i, f) in self.program.functions.iter().enumerate() {
+            compiled_functions.push(self.compile_function(i, f));
+        }
+
+        target::Program {
+            functions: compiled_functions,
+            names: self.names.names.clone(),
+        }
+    }
+
+    fn compile_function(
+        &self,
+        function_index: usize,
+        function: &source::Function,
+    ) -> target::Function {
+        // The local of the If through which every branch is entered, and the
+        // address of the instruction following it, where the branch continues
+        // when it exits.
+        let mut branch_exits = HashMap::new();
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                if let source::Instruction::Assignment(source::Assignment {
+                    name,
+                    definition:
+                        source::Definition::Step(source::Step::Control(source::Control::If {
+                            branch_success,
+                            branch_failure,
+                            ..
+                        })),
+                }) = instruction
+                {
+                    let continuation = source::TargetAddress {
+                        function_index,
+                        block_index,
+                        instruction_index: instruction_index + 1,
+                    };
+                    branch_exits.insert(branch_success.block_index, (*name, continuation));
+                    branch_exits.insert(branch_failure.block_index, (*name, continuation));
+                }
+            }
+        }
+
         let mut compiled_blocks = Vec::new();
 
         for (i, b) in function.blocks.iter().enumerate() {
-            compiled_blocks.push(self.compile_block(i, b));
+            compiled_blocks.push(self.compile_block(function_index, i, b, branch_exits.get(&i)));
         }
 
         target::Function {
@@ -32,11 +117,194 @@ impl<'a> Compiler<'a> {
                 .as_ref()
                 .expect("free names should be known")
                 .len(),
+            frame_size: self.frame_layout.function_frame_size(function_index),
             blocks: compiled_blocks,
         }
     }
 
-    fn compile_block(&self, _block_index: usize, _block: &source::Block) -> target::Block {
-        unimplemented!();
+    fn compile_block(
+        &self,
+        function_index: usize,
+        block_index: usize,
+        block: &source::Block,
+        branch_exit: Option<&(Symbol, source::TargetAddress)>,
+    ) -> target::Block {
+        let scope = Scope {
+            program: self.program,
+            frame_layout: &self.frame_layout,
+            names: &self.names.ids,
+            function_index,
+            block_index,
+        };
+
+        let mut instructions = Vec::new();
+        for instruction in &block.instructions {
+            match instruction {
+                source::Instruction::EnterBlock => {}
+                source::Instruction::ExitBlock(var) => match branch_exit {
+                    None => instructions.push(target::Instruction::Return(scope.compile_var(var))),
+                    Some((name, continuation)) => {
+                        instructions.push(target::Instruction::Assignment(target::Assignment {
+                            name: scope.local(*name),
+                            definition: target::Definition::Var(scope.compile_var(var)),
+                        }));
+                        instructions.push(target::Instruction::Jump(
+                            scope.compile_address(*continuation),
+                        ));
+                    }
+                },
+                source::Instruction::Assignment(source::Assignment { name, definition }) => {
+                    instructions.push(target::Instruction::Assignment(target::Assignment {
+                        name: scope.local(*name),
+                        definition: scope.compile_definition(definition),
+                    }));
+                }
+            }
+        }
+
+        target::Block {
+            instructions,
+            is_loop_header: block.is_loop_header,
+        }
     }
 }
+
+// The position in the program from which variable references are resolved.
+struct Scope<'a> {
+    program: &'a source::Program,
+    frame_layout: &'a ProgramFrameLayout,
+    names: &'a HashMap<Symbol, target::NameId>,
+    function_index: usize,
+    block_index: usize,
+}
+
+impl<'a> Scope<'a> {
+    fn local(&self, name: Symbol) -> target::LocalReference {
+        match self.lookup(name) {
+            target::Reference::Local(local) => local,
+            _ => panic!("assignment target should be a local variable"),
+        }
+    }
+
+    fn compile_definition(&self, definition: &source::Definition) -> target::Definition {
+        match definition {
+            source::Definition::Var(var) => target::Definition::Var(self.compile_var(var)),
+            source::Definition::Step(source::Step::Simple(simple)) => {
+                target::Definition::Step(target::Step::Simple(self.compile_simple(simple)))
+            }
+            source::Definition::Step(source::Step::Control(control)) => {
+                target::Definition::Step(target::Step::Control(self.compile_control(control)))
+            }
+        }
+    }
+
+    fn compile_simple(&self, simple: &source::Simple) -> target::Simple {
+        match simple {
+            source::Simple::Literal(c) => target::Simple::Literal(*c),
+            source::Simple::Fun(source::AllocClosure {
+                name,
+                free_names,
+                body,
+                ..
+            }) => target::Simple::Fun(target::AllocClosure {
+                name: Some(self.names[name]),
+                free_vars: free_names.iter().map(|v| self.compile_var(v)).collect(),
+                body: self.compile_address(*body),
+            }),
+            // The flat IR allocates a closure without free variables instead.
+            source::Simple::LiftedFun {
+                name,
+                function_index,
+            } => target::Simple::Fun(target::AllocClosure {
+                name: Some(self.names[name]),
+                free_vars: Vec::new(),
+                body: self.compile_address(source::TargetAddress {
+                    function_index: *function_index,
+                    block_index: 0,
+                    instruction_index: 0,
+                }),
+            }),
+            source::Simple::BinOp { op, lhs, rhs } => target::Simple::BinOp {
+                op: *op,
+                lhs: self.compile_var(lhs),
+                rhs: self.compile_var(rhs),
+            },
+            // Only the let interpreter updates tuples in place.
+            source::Simple::Tuple { args } | source::Simple::ReuseTuple { args, .. } => {
+                target::Simple::Tuple {
+                    args: args.iter().map(|a| self.compile_var(a)).collect(),
+                }
+            }
+            source::Simple::Set {
+                tuple,
+                index,
+                new_value,
+            } => target::Simple::Set {
+                tuple: self.compile_var(tuple),
+                index: *index,
+                new_value: self.compile_var(new_value),
+            },
+        }
+    }
+
+    fn compile_control(&self, control: &source::Control) -> target::Control {
+        match control {
+            source::Control::Call { func, args } => target::Control::Call {
+                func: self.compile_var(func),
+                args: args.iter().map(|a| self.compile_var(a)).collect(),
+            },
+            // The flat IR has no direct calls, so the closure is looked up
+            // again.
+            source::Control::CallDirect { func, args, .. } => target::Control::Call {
+                func: self.compile_var(func),
+                args: args.iter().map(|a| self.compile_var(a)).collect(),
+            },
+            source::Control::TailCall { func, args } => target::Control::TailCall {
+                func: self.compile_var(func),
+                args: args.iter().map(|a| self.compile_var(a)).collect(),
+            },
+            source::Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => target::Control::If {
+                condition: self.compile_var(condition),
+                branch_success: self.compile_address(*branch_success),
+                branch_failure: self.compile_address(*branch_failure),
+            },
+            source::Control::Jump { target, args } => target::Control::Jump {
+                target: self.compile_address(*target),
+                args: args.iter().map(|a| self.compile_var(a)).collect(),
+            },
+        }
+    }
+
+    fn compile_var(&self, var: &source::VariableReference) -> target::Reference {
+        self.lookup(var.var_name)
+    }
+
+    fn lookup(&self, name: Symbol) -> target::Reference {
+        self.frame_layout
+            .lookup_var(self.function_index, self.block_index, name)
+    }
+
+    // Instruction indices shift because EnterBlock instructions are dropped.
+    fn compile_address(&self, address: source::TargetAddress) -> target::TargetAddress {
+        let block = &self.program.functions[address.function_index].blocks[address.block_index];
+        let dropped = block.instructions[..address.instruction_index]
+            .iter()
+            .filter(|i| matches!(i, source::Instruction::EnterBlock))
+            .count();
+
+        target::TargetAddress {
+            function_index: address.function_index,
+            block_index: address.block_index,
+            instruction_index: address.instruction_index - dropped,
+        }
+    }
+}
+
+pub fn compile_program(program: &source::Program) -> target::Program {
+    let compiler = Compiler::new(program);
+    compiler.compile_program()
+}
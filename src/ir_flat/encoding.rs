@@ -0,0 +1,687 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use crate::ir_flat::object::ObjectFile;
+use crate::ir_flat::syntax::{
+    AllocClosure, ArgumentReference, Assignment, Block, ClosureReference, Control, Definition,
+    Function, Instruction, LocalReference, NameId, Program, Reference, Simple, Step, TargetAddress,
+};
+use crate::lang::syntax::{BinOp, Constant};
+use crate::result::Result;
+
+// Layout of an encoded program (all integers are little-endian u32 unless
+// noted otherwise, and integer constants are i64):
+//
+//   magic "BLY\0", format version (u8)
+//   constant pool: count, then one tagged entry per constant
+//   names: count, then a string constant per name
+//   functions: count, then per function its sizes and blocks
+//
+// Literals and names are stored once in the constant pool and referred to by
+// index from the instruction stream. Closures refer to their name by its
+// index in the names of the program, or NO_NAME if the debug info has been
+// stripped. An object file has the magic
+// "BLYO" and its export table between the constant pool and the functions:
+// the module name, then the imports, exports and free names, each a count
+// followed by string constants.
+const MAGIC: &[u8; 4] = b"BLY\0";
+const OBJECT_MAGIC: &[u8; 4] = b"BLYO";
+const VERSION: u8 = 5;
+
+const NO_NAME: u32 = u32::MAX;
+
+const CONSTANT_INT: u8 = 0;
+const CONSTANT_BOOL: u8 = 1;
+const CONSTANT_STRING: u8 = 2;
+
+const INSTRUCTION_ASSIGNMENT: u8 = 0;
+const INSTRUCTION_JUMP: u8 = 1;
+const INSTRUCTION_RETURN: u8 = 2;
+
+const DEFINITION_VAR: u8 = 0;
+const DEFINITION_LITERAL: u8 = 1;
+const DEFINITION_FUN: u8 = 2;
+const DEFINITION_BINOP: u8 = 3;
+const DEFINITION_TUPLE: u8 = 4;
+const DEFINITION_SET: u8 = 5;
+const DEFINITION_CALL: u8 = 6;
+const DEFINITION_IF: u8 = 7;
+const DEFINITION_TAIL_CALL: u8 = 8;
+const DEFINITION_JUMP: u8 = 9;
+
+const REFERENCE_LOCAL: u8 = 0;
+const REFERENCE_ARGUMENT: u8 = 1;
+const REFERENCE_CLOSURE: u8 = 2;
+const REFERENCE_THIS: u8 = 3;
+
+const BINOP_ADD: u8 = 0;
+const BINOP_SUB: u8 = 1;
+const BINOP_EQ: u8 = 2;
+const BINOP_GET: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PoolEntry {
+    Int(i64),
+    Bool(bool),
+    String(String),
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    InvalidTag { kind: &'static str, tag: u8 },
+    InvalidConstant(u32),
+    InvalidName(u32),
+    InvalidString,
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not an encoded bailey program")?,
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)?
+            }
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input")?,
+            DecodeError::InvalidTag { kind, tag } => write!(f, "invalid {} tag {}", kind, tag)?,
+            DecodeError::InvalidConstant(index) => {
+                write!(f, "invalid constant pool index {}", index)?
+            }
+            DecodeError::InvalidName(index) => write!(f, "invalid name index {}", index)?,
+            DecodeError::InvalidString => write!(f, "constant string is not valid UTF-8")?,
+            DecodeError::TrailingBytes => write!(f, "trailing bytes after program")?,
+        };
+
+        Ok(())
+    }
+}
+
+impl Error for DecodeError {}
+
+struct Encoder {
+    pool: Vec<PoolEntry>,
+    pool_indices: HashMap<PoolEntry, u32>,
+    code: Vec<u8>,
+}
+
+impl Encoder {
+    fn new() -> Self {
+        Encoder {
+            pool: Vec::new(),
+            pool_indices: HashMap::new(),
+            code: Vec::new(),
+        }
+    }
+
+    fn constant(&mut self, entry: PoolEntry) -> u32 {
+        if let Some(index) = self.pool_indices.get(&entry) {
+            return *index;
+        }
+
+        let index = self.pool.len() as u32;
+        self.pool.push(entry.clone());
+        self.pool_indices.insert(entry, index);
+        index
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.code.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.u32(u32::try_from(value).expect("value too large to encode"));
+    }
+
+    fn string(&mut self, value: &str) {
+        let index = self.constant(PoolEntry::String(value.to_owned()));
+        self.u32(index);
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.usize(values.len());
+        for value in values {
+            self.string(value);
+        }
+    }
+
+    fn encode_object(&mut self, object: &ObjectFile) {
+        self.string(&object.name);
+        self.strings(&object.imports);
+        self.strings(&object.exports);
+        self.strings(&object.free_names);
+        self.encode_program(&object.program);
+    }
+
+    fn encode_program(&mut self, program: &Program) {
+        self.strings(&program.names);
+        self.usize(program.functions.len());
+        for function in &program.functions {
+            self.encode_function(function);
+        }
+    }
+
+    fn encode_function(&mut self, function: &Function) {
+        self.usize(function.args_size);
+        self.usize(function.closure_env_size);
+        self.usize(function.frame_size);
+        self.usize(function.blocks.len());
+        for block in &function.blocks {
+            self.u8(block.is_loop_header as u8);
+            self.usize(block.instructions.len());
+            for instruction in &block.instructions {
+                self.encode_instruction(instruction);
+            }
+        }
+    }
+
+    fn encode_instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Assignment(Assignment { name, definition }) => {
+                self.u8(INSTRUCTION_ASSIGNMENT);
+                self.usize(name.0);
+                self.encode_definition(definition);
+            }
+            Instruction::Jump(target) => {
+                self.u8(INSTRUCTION_JUMP);
+                self.encode_address(*target);
+            }
+            Instruction::Return(reference) => {
+                self.u8(INSTRUCTION_RETURN);
+                self.encode_reference(*reference);
+            }
+        }
+    }
+
+    fn encode_definition(&mut self, definition: &Definition) {
+        match definition {
+            Definition::Var(reference) => {
+                self.u8(DEFINITION_VAR);
+                self.encode_reference(*reference);
+            }
+            Definition::Step(Step::Simple(Simple::Literal(c))) => {
+                let entry = match c {
+                    Constant::Int { value } => PoolEntry::Int(*value),
+                    Constant::Bool { value } => PoolEntry::Bool(*value),
+                };
+                let index = self.constant(entry);
+                self.u8(DEFINITION_LITERAL);
+                self.u32(index);
+            }
+            Definition::Step(Step::Simple(Simple::Fun(AllocClosure {
+                name,
+                free_vars,
+                body,
+            }))) => {
+                self.u8(DEFINITION_FUN);
+                self.u32(name.map_or(NO_NAME, |name| name.0));
+                self.encode_address(*body);
+                self.encode_references(free_vars);
+            }
+            Definition::Step(Step::Simple(Simple::BinOp { op, lhs, rhs })) => {
+                self.u8(DEFINITION_BINOP);
+                self.u8(match op {
+                    BinOp::Add => BINOP_ADD,
+                    BinOp::Sub => BINOP_SUB,
+                    BinOp::Eq => BINOP_EQ,
+                    BinOp::Get => BINOP_GET,
+                });
+                self.encode_reference(*lhs);
+                self.encode_reference(*rhs);
+            }
+            Definition::Step(Step::Simple(Simple::Tuple { args })) => {
+                self.u8(DEFINITION_TUPLE);
+                self.encode_references(args);
+            }
+            Definition::Step(Step::Simple(Simple::Set {
+                tuple,
+                index,
+                new_value,
+            })) => {
+                self.u8(DEFINITION_SET);
+                self.encode_reference(*tuple);
+                self.u32(*index);
+                self.encode_reference(*new_value);
+            }
+            Definition::Step(Step::Control(Control::Call { func, args })) => {
+                self.u8(DEFINITION_CALL);
+                self.encode_reference(*func);
+                self.encode_references(args);
+            }
+            Definition::Step(Step::Control(Control::TailCall { func, args })) => {
+                self.u8(DEFINITION_TAIL_CALL);
+                self.encode_reference(*func);
+                self.encode_references(args);
+            }
+            Definition::Step(Step::Control(Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            })) => {
+                self.u8(DEFINITION_IF);
+                self.encode_reference(*condition);
+                self.encode_address(*branch_success);
+                self.encode_address(*branch_failure);
+            }
+            Definition::Step(Step::Control(Control::Jump { target, args })) => {
+                self.u8(DEFINITION_JUMP);
+                self.encode_address(*target);
+                self.encode_references(args);
+            }
+        }
+    }
+
+    fn encode_references(&mut self, references: &[Reference]) {
+        self.usize(references.len());
+        for reference in references {
+            self.encode_reference(*reference);
+        }
+    }
+
+    fn encode_reference(&mut self, reference: Reference) {
+        match reference {
+            Reference::Local(LocalReference(offset)) => {
+                self.u8(REFERENCE_LOCAL);
+                self.usize(offset);
+            }
+            Reference::Argument(ArgumentReference(offset)) => {
+                self.u8(REFERENCE_ARGUMENT);
+                self.usize(offset);
+            }
+            Reference::Closure(ClosureReference(offset)) => {
+                self.u8(REFERENCE_CLOSURE);
+                self.usize(offset);
+            }
+            Reference::This => self.u8(REFERENCE_THIS),
+        }
+    }
+
+    fn encode_address(&mut self, address: TargetAddress) {
+        self.usize(address.function_index);
+        self.usize(address.block_index);
+        self.usize(address.instruction_index);
+    }
+
+    fn done(self, magic: &[u8; 4]) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(magic);
+        result.push(VERSION);
+
+        result.extend_from_slice(&(self.pool.len() as u32).to_le_bytes());
+        for entry in &self.pool {
+            match entry {
+                PoolEntry::Int(value) => {
+                    result.push(CONSTANT_INT);
+                    result.extend_from_slice(&value.to_le_bytes());
+                }
+                PoolEntry::Bool(value) => {
+                    result.push(CONSTANT_BOOL);
+                    result.push(*value as u8);
+                }
+                PoolEntry::String(value) => {
+                    result.push(CONSTANT_STRING);
+                    result.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    result.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+
+        result.extend_from_slice(&self.code);
+        result
+    }
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    position: usize,
+    pool: Vec<PoolEntry>,
+    name_count: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Decoder {
+            input,
+            position: 0,
+            pool: Vec::new(),
+            name_count: 0,
+        }
+    }
+
+    fn bytes(&mut self, count: usize) -> std::result::Result<&'a [u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let bytes = self
+            .input
+            .get(self.position..end)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> std::result::Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, DecodeError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn usize(&mut self) -> std::result::Result<usize, DecodeError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn constant(&mut self) -> std::result::Result<&PoolEntry, DecodeError> {
+        let index = self.u32()?;
+        self.pool
+            .get(index as usize)
+            .ok_or(DecodeError::InvalidConstant(index))
+    }
+
+    fn string(&mut self) -> std::result::Result<String, DecodeError> {
+        match self.constant()? {
+            PoolEntry::String(value) => Ok(value.clone()),
+            PoolEntry::Int(_) => Err(DecodeError::InvalidTag {
+                kind: "name",
+                tag: CONSTANT_INT,
+            }),
+            PoolEntry::Bool(_) => Err(DecodeError::InvalidTag {
+                kind: "name",
+                tag: CONSTANT_BOOL,
+            }),
+        }
+    }
+
+    fn strings(&mut self) -> std::result::Result<Vec<String>, DecodeError> {
+        let count = self.usize()?;
+        let mut values = Vec::new();
+        for _ in 0..count {
+            values.push(self.string()?);
+        }
+        Ok(values)
+    }
+
+    fn decode_header(&mut self, magic: &[u8; 4]) -> std::result::Result<(), DecodeError> {
+        if self.bytes(magic.len()).map_err(|_| DecodeError::BadMagic)? != magic {
+            return Err(DecodeError::BadMagic);
+        }
+
+        let version = self.u8()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let pool_size = self.usize()?;
+        for _ in 0..pool_size {
+            let entry = match self.u8()? {
+                CONSTANT_INT => {
+                    let bytes = self.bytes(8)?;
+                    PoolEntry::Int(i64::from_le_bytes(bytes.try_into().unwrap()))
+                }
+                CONSTANT_BOOL => PoolEntry::Bool(self.u8()? != 0),
+                CONSTANT_STRING => {
+                    let length = self.usize()?;
+                    let bytes = self.bytes(length)?;
+                    let value =
+                        std::str::from_utf8(bytes).map_err(|_| DecodeError::InvalidString)?;
+                    PoolEntry::String(value.to_owned())
+                }
+                tag => {
+                    return Err(DecodeError::InvalidTag {
+                        kind: "constant",
+                        tag,
+                    })
+                }
+            };
+            self.pool.push(entry);
+        }
+
+        Ok(())
+    }
+
+    fn decode_program(&mut self) -> std::result::Result<Program, DecodeError> {
+        let names = self.strings()?;
+        self.name_count = names.len();
+
+        let function_count = self.usize()?;
+        let mut functions = Vec::new();
+        for _ in 0..function_count {
+            functions.push(self.decode_function()?);
+        }
+
+        Ok(Program { functions, names })
+    }
+
+    fn decode_object(&mut self) -> std::result::Result<ObjectFile, DecodeError> {
+        Ok(ObjectFile {
+            name: self.string()?,
+            imports: self.strings()?,
+            exports: self.strings()?,
+            free_names: self.strings()?,
+            program: self.decode_program()?,
+        })
+    }
+
+    fn finish(&self) -> std::result::Result<(), DecodeError> {
+        if self.position != self.input.len() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(())
+    }
+
+    fn decode_function(&mut self) -> std::result::Result<Function, DecodeError> {
+        let args_size = self.usize()?;
+        let closure_env_size = self.usize()?;
+        let frame_size = self.usize()?;
+
+        let block_count = self.usize()?;
+        let mut blocks = Vec::new();
+        for _ in 0..block_count {
+            let is_loop_header = self.u8()? != 0;
+            let instruction_count = self.usize()?;
+            let mut instructions = Vec::new();
+            for _ in 0..instruction_count {
+                instructions.push(self.decode_instruction()?);
+            }
+            blocks.push(Block {
+                instructions,
+                is_loop_header,
+            });
+        }
+
+        Ok(Function {
+            args_size,
+            closure_env_size,
+            frame_size,
+            blocks,
+        })
+    }
+
+    fn decode_instruction(&mut self) -> std::result::Result<Instruction, DecodeError> {
+        match self.u8()? {
+            INSTRUCTION_ASSIGNMENT => {
+                let name = LocalReference(self.usize()?);
+                let definition = self.decode_definition()?;
+                Ok(Instruction::Assignment(Assignment { name, definition }))
+            }
+            INSTRUCTION_JUMP => Ok(Instruction::Jump(self.decode_address()?)),
+            INSTRUCTION_RETURN => Ok(Instruction::Return(self.decode_reference()?)),
+            tag => Err(DecodeError::InvalidTag {
+                kind: "instruction",
+                tag,
+            }),
+        }
+    }
+
+    fn decode_definition(&mut self) -> std::result::Result<Definition, DecodeError> {
+        let simple = |simple| Ok(Definition::Step(Step::Simple(simple)));
+        let control = |control| Ok(Definition::Step(Step::Control(control)));
+
+        match self.u8()? {
+            DEFINITION_VAR => Ok(Definition::Var(self.decode_reference()?)),
+            DEFINITION_LITERAL => match self.constant()? {
+                PoolEntry::Int(value) => simple(Simple::Literal(Constant::Int { value: *value })),
+                PoolEntry::Bool(value) => simple(Simple::Literal(Constant::Bool { value: *value })),
+                PoolEntry::String(_) => Err(DecodeError::InvalidTag {
+                    kind: "literal",
+                    tag: CONSTANT_STRING,
+                }),
+            },
+            DEFINITION_FUN => {
+                let name = match self.u32()? {
+                    NO_NAME => None,
+                    index if (index as usize) < self.name_count => Some(NameId(index)),
+                    index => return Err(DecodeError::InvalidName(index)),
+                };
+                let body = self.decode_address()?;
+                let free_vars = self.decode_references()?;
+                simple(Simple::Fun(AllocClosure {
+                    name,
+                    free_vars,
+                    body,
+                }))
+            }
+            DEFINITION_BINOP => {
+                let op = match self.u8()? {
+                    BINOP_ADD => BinOp::Add,
+                    BINOP_SUB => BinOp::Sub,
+                    BINOP_EQ => BinOp::Eq,
+                    BINOP_GET => BinOp::Get,
+                    tag => return Err(DecodeError::InvalidTag { kind: "binop", tag }),
+                };
+                let lhs = self.decode_reference()?;
+                let rhs = self.decode_reference()?;
+                simple(Simple::BinOp { op, lhs, rhs })
+            }
+            DEFINITION_TUPLE => simple(Simple::Tuple {
+                args: self.decode_references()?,
+            }),
+            DEFINITION_SET => {
+                let tuple = self.decode_reference()?;
+                let index = self.u32()?;
+                let new_value = self.decode_reference()?;
+                simple(Simple::Set {
+                    tuple,
+                    index,
+                    new_value,
+                })
+            }
+            DEFINITION_CALL => {
+                let func = self.decode_reference()?;
+                let args = self.decode_references()?;
+                control(Control::Call { func, args })
+            }
+            DEFINITION_TAIL_CALL => {
+                let func = self.decode_reference()?;
+                let args = self.decode_references()?;
+                control(Control::TailCall { func, args })
+            }
+            DEFINITION_IF => {
+                let condition = self.decode_reference()?;
+                let branch_success = self.decode_address()?;
+                let branch_failure = self.decode_address()?;
+                control(Control::If {
+                    condition,
+                    branch_success,
+                    branch_failure,
+                })
+            }
+            DEFINITION_JUMP => {
+                let target = self.decode_address()?;
+                let args = self.decode_references()?;
+                control(Control::Jump { target, args })
+            }
+            tag => Err(DecodeError::InvalidTag {
+                kind: "definition",
+                tag,
+            }),
+        }
+    }
+
+    fn decode_references(&mut self) -> std::result::Result<Vec<Reference>, DecodeError> {
+        let count = self.usize()?;
+        let mut references = Vec::new();
+        for _ in 0..count {
+            references.push(self.decode_reference()?);
+        }
+        Ok(references)
+    }
+
+    fn decode_reference(&mut self) -> std::result::Result<Reference, DecodeError> {
+        match self.u8()? {
+            REFERENCE_LOCAL => Ok(Reference::Local(LocalReference(self.usize()?))),
+            REFERENCE_ARGUMENT => Ok(Reference::Argument(ArgumentReference(self.usize()?))),
+            REFERENCE_CLOSURE => Ok(Reference::Closure(ClosureReference(self.usize()?))),
+            REFERENCE_THIS => Ok(Reference::This),
+            tag => Err(DecodeError::InvalidTag {
+                kind: "reference",
+                tag,
+            }),
+        }
+    }
+
+    fn decode_address(&mut self) -> std::result::Result<TargetAddress, DecodeError> {
+        Ok(TargetAddress {
+            function_index: self.usize()?,
+            block_index: self.usize()?,
+            instruction_index: self.usize()?,
+        })
+    }
+}
+
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.encode_program(program);
+    encoder.done(MAGIC)
+}
+
+pub fn decode(input: &[u8]) -> Result<Program> {
+    let mut decoder = Decoder::new(input);
+    decoder.decode_header(MAGIC)?;
+    let program = decoder.decode_program()?;
+    decoder.finish()?;
+    Ok(program)
+}
+
+pub fn encode_object(object: &ObjectFile) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.encode_object(object);
+    encoder.done(OBJECT_MAGIC)
+}
+
+pub fn decode_object(input: &[u8]) -> Result<ObjectFile> {
+    let mut decoder = Decoder::new(input);
+    decoder.decode_header(OBJECT_MAGIC)?;
+    let object = decoder.decode_object()?;
+    decoder.finish()?;
+    Ok(object)
+}
+
+pub fn save(program: &Program, path: &Path) -> Result<()> {
+    std::fs::write(path, encode(program))?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Program> {
+    decode(&std::fs::read(path)?)
+}
+
+pub fn save_object(object: &ObjectFile, path: &Path) -> Result<()> {
+    std::fs::write(path, encode_object(object))?;
+    Ok(())
+}
+
+pub fn load_object(path: &Path) -> Result<ObjectFile> {
+    decode_object(&std::fs::read(path)?)
+}
@@ -1,14 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
+use crate::ir_flat::liveness::analyze_function;
 use crate::ir_flat::syntax as target;
 use crate::ir_let::let_expr as source;
+use crate::symbol::Symbol;
 
-fn compute_layout(base_offset: usize, names: &[String]) -> HashMap<String, usize> {
+fn compute_layout(base_offset: usize, names: &[Symbol]) -> HashMap<Symbol, usize> {
     let original_length = names.len();
     let mut result = HashMap::new();
 
     for (i, n) in names.iter().enumerate() {
-        result.insert(n.clone(), base_offset + i);
+        result.insert(*n, base_offset + i);
     }
 
     // Ensure that all names were unique in the environment as a sanity check.
@@ -23,25 +26,27 @@ pub struct ProgramFrameLayout {
 }
 
 impl ProgramFrameLayout {
-    pub fn frame_size(&self, function_index: usize, block_index: usize) -> usize {
+    // Nested blocks are laid out after their enclosing blocks, so the frame
+    // needs to be as large as the deepest nesting of blocks.
+    pub fn function_frame_size(&self, function_index: usize) -> usize {
         let function_layout = self
             .functions
             .get(function_index)
             .expect("unknown function");
 
-        let block_layout = function_layout
+        function_layout
             .blocks
-            .get(block_index)
-            .expect("unknown block");
-
-        block_layout.offsets.len()
+            .iter()
+            .map(|b| b.end_offset())
+            .max()
+            .unwrap_or(0)
     }
 
     pub fn lookup_var(
         &self,
         function_index: usize,
         block_index: usize,
-        name: &str,
+        name: Symbol,
     ) -> target::Reference {
         let function_layout = self
             .functions
@@ -57,7 +62,7 @@ impl ProgramFrameLayout {
                 .get(block_index)
                 .expect("unknown block");
 
-            if let Some(offset) = block_layout.offsets.get(name) {
+            if let Some(offset) = block_layout.offsets.get(&name) {
                 return target::Reference::Local(*offset);
             }
 
@@ -66,7 +71,7 @@ impl ProgramFrameLayout {
 
         // Otherwise we check function arguments, function name itself (for
         // recursive calls), and finally closure environment.
-        if let Some(offset) = function_layout.offsets_arguments.get(name) {
+        if let Some(offset) = function_layout.offsets_arguments.get(&name) {
             return target::Reference::Argument(*offset);
         }
 
@@ -74,7 +79,7 @@ impl ProgramFrameLayout {
             return target::Reference::This;
         }
 
-        if let Some(offset) = function_layout.offsets_free_vars.get(name) {
+        if let Some(offset) = function_layout.offsets_free_vars.get(&name) {
             return target::Reference::Closure(*offset);
         }
 
@@ -82,11 +87,60 @@ impl ProgramFrameLayout {
     }
 }
 
+// The names ordered by their offsets.
+fn by_offset<T, F: Fn(&T) -> usize>(
+    offsets: &HashMap<Symbol, T>,
+    offset: F,
+) -> Vec<(usize, Symbol)> {
+    let mut names: Vec<(usize, Symbol)> = offsets
+        .iter()
+        .map(|(name, reference)| (offset(reference), *name))
+        .collect();
+    names.sort();
+    names
+}
+
+impl fmt::Display for ProgramFrameLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            writeln!(f, "function {} {}", i, function.this_name)?;
+
+            write!(f, "arguments")?;
+            for (offset, name) in by_offset(&function.offsets_arguments, |a| a.0) {
+                write!(f, " {}:{}", offset, name)?;
+            }
+            writeln!(f)?;
+
+            write!(f, "free names")?;
+            for (offset, name) in by_offset(&function.offsets_free_vars, |c| c.0) {
+                write!(f, " {}:{}", offset, name)?;
+            }
+            writeln!(f)?;
+
+            for (block_index, block) in function.blocks.iter().enumerate() {
+                write!(f, "block {}", block_index)?;
+                if let Some(parent) = block.parent_block_index {
+                    write!(f, " in {}", parent)?;
+                }
+                write!(f, " from {}:", block.start_offset)?;
+                for (offset, name) in by_offset(&block.offsets, |l| l.0) {
+                    write!(f, " {}:{}", offset, name)?;
+                }
+                writeln!(f)?;
+            }
+
+            writeln!(f, "frame size {}\n", self.function_frame_size(i))?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct FunctionFrameLayout {
-    this_name: String,
-    offsets_arguments: HashMap<String, target::ArgumentReference>,
-    offsets_free_vars: HashMap<String, target::ClosureReference>,
+    this_name: Symbol,
+    offsets_arguments: HashMap<Symbol, target::ArgumentReference>,
+    offsets_free_vars: HashMap<Symbol, target::ClosureReference>,
     blocks: Vec<BlockFrameLayout>,
 }
 
@@ -94,7 +148,7 @@ struct FunctionFrameLayout {
 struct BlockFrameLayout {
     // Starting offset from the base of the function stack frame
     start_offset: usize,
-    offsets: HashMap<String, target::LocalReference>,
+    offsets: HashMap<Symbol, target::LocalReference>,
     parent_block_index: Option<usize>,
 }
 
@@ -123,15 +177,11 @@ fn compute_function_frame_layout(function: &source::Function) -> FunctionFrameLa
     let mut block_layouts: Vec<BlockFrameLayout> = Vec::new();
 
     for b in &function.blocks {
-        let parent_layout = if let Some(parent_index) = b.parent_block_index {
-            Some(
-                block_layouts
-                    .get(parent_index)
-                    .expect("parent block should have been already processed"),
-            )
-        } else {
-            None
-        };
+        let parent_layout = b.parent_block_index.map(|parent_index| {
+            block_layouts
+                .get(parent_index)
+                .expect("parent block should have been already processed")
+        });
 
         let start_offset = if let Some(parent_layout) = parent_layout {
             parent_layout.end_offset()
@@ -152,7 +202,7 @@ fn compute_function_frame_layout(function: &source::Function) -> FunctionFrameLa
     }
 
     FunctionFrameLayout {
-        this_name: function.name.clone(),
+        this_name: function.name,
         offsets_arguments: compute_layout(0, &function.arg_names)
             .drain()
             .map(|(name, offset)| (name, target::ArgumentReference(offset)))
@@ -170,3 +220,103 @@ fn compute_function_frame_layout(function: &source::Function) -> FunctionFrameLa
         blocks: block_layouts,
     }
 }
+
+// Reassigns the locals of every function so that locals which are never live
+// at the same time share a slot, and shrinks the frame sizes of the blocks
+// accordingly. Afterwards the frame size of a block is the number of slots it
+// needs on top of the slots of its enclosing blocks.
+pub fn coalesce_slots(program: &mut target::Program) {
+    for function in &mut program.functions {
+        coalesce_function_slots(function);
+    }
+}
+
+fn coalesce_function_slots(function: &mut target::Function) {
+    let liveness = analyze_function(function);
+
+    // Two locals interfere if one of them is assigned while the other one is
+    // still live. The locals are colored in order of their first assignment.
+    let mut interference: HashMap<usize, HashSet<usize>> = HashMap::new();
+    let mut order = Vec::new();
+
+    for (block_index, block) in function.blocks.iter().enumerate() {
+        for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+            if let target::Instruction::Assignment(target::Assignment { name, .. }) = instruction {
+                if !interference.contains_key(&name.0) {
+                    order.push(name.0);
+                }
+                interference.entry(name.0).or_default();
+
+                for live in liveness.live_after(block_index, instruction_index) {
+                    if *live != *name {
+                        interference.entry(name.0).or_default().insert(live.0);
+                        interference.entry(live.0).or_default().insert(name.0);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut slots: HashMap<usize, usize> = HashMap::new();
+    for local in order {
+        let taken: HashSet<usize> = interference[&local]
+            .iter()
+            .filter_map(|other| slots.get(other).copied())
+            .collect();
+        let slot = (0..).find(|s| !taken.contains(s)).unwrap();
+        slots.insert(local, slot);
+    }
+
+    let rename = |local: &mut target::LocalReference| local.0 = slots[&local.0];
+
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            match instruction {
+                target::Instruction::Jump(_) => {}
+                target::Instruction::Return(result) => rename_reference(result, &rename),
+                target::Instruction::Assignment(target::Assignment { name, definition }) => {
+                    rename(name);
+
+                    for reference in definition_references(definition) {
+                        rename_reference(reference, &rename);
+                    }
+                }
+            }
+        }
+    }
+
+    function.frame_size = slots.values().map(|slot| slot + 1).max().unwrap_or(0);
+}
+
+fn rename_reference<F>(reference: &mut target::Reference, rename: &F)
+where
+    F: Fn(&mut target::LocalReference),
+{
+    if let target::Reference::Local(local) = reference {
+        rename(local);
+    }
+}
+
+fn definition_references(definition: &mut target::Definition) -> Vec<&mut target::Reference> {
+    match definition {
+        target::Definition::Var(var) => vec![var],
+        target::Definition::Step(target::Step::Simple(simple)) => match simple {
+            target::Simple::Literal(_) => vec![],
+            target::Simple::Fun(target::AllocClosure { free_vars, .. }) => {
+                free_vars.iter_mut().collect()
+            }
+            target::Simple::BinOp { lhs, rhs, .. } => vec![lhs, rhs],
+            target::Simple::Tuple { args } => args.iter_mut().collect(),
+            target::Simple::Set {
+                tuple, new_value, ..
+            } => vec![tuple, new_value],
+        },
+        target::Definition::Step(target::Step::Control(control)) => match control {
+            target::Control::Call { func, args } | target::Control::TailCall { func, args } => {
+                std::iter::once(func).chain(args.iter_mut()).collect()
+            }
+            target::Control::If { condition, .. } => vec![condition],
+            target::Control::Jump { args, .. } => args.iter_mut().collect(),
+        },
+    }
+}
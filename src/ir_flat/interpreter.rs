@@ -0,0 +1,631 @@
+use crate::ir_flat::syntax::{
+    AllocClosure, ArgumentReference, Assignment, ClosureReference, Control, Definition,
+    Instruction, LocalReference, NameId, Program, Reference, Simple, Step, TargetAddress,
+};
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::lang::syntax::{BinOp, Constant};
+use std::fmt;
+
+// The contents of a frame slot, tuple field or closure environment entry. A
+// word with its lowest bit set holds an integer in its upper 63 bits. The
+// other words are either one of the immediate words below, or the index of an
+// object on the heap shifted left by two. Only integers that do not fit in 63
+// bits are boxed, so arithmetic does not touch the heap or the reference
+// counts unless it overflows into the boxed range.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Word(u64);
+
+const INT_TAG: u64 = 0b1;
+const TAG_MASK: u64 = 0b11;
+const IMMEDIATE_TAG: u64 = 0b10;
+
+const FALSE: Word = Word(IMMEDIATE_TAG);
+const TRUE: Word = Word(0b100 | IMMEDIATE_TAG);
+// The contents of a local before it has been assigned.
+const EMPTY: Word = Word(0b1000 | IMMEDIATE_TAG);
+
+const SMALL_INT_MIN: i64 = i64::MIN >> 1;
+const SMALL_INT_MAX: i64 = i64::MAX >> 1;
+
+impl Word {
+    // None if the integer does not fit in 63 bits.
+    pub fn small_int(value: i64) -> Option<Word> {
+        if (SMALL_INT_MIN..=SMALL_INT_MAX).contains(&value) {
+            Some(Word(((value << 1) as u64) | INT_TAG))
+        } else {
+            None
+        }
+    }
+
+    pub fn bool(value: bool) -> Word {
+        if value {
+            TRUE
+        } else {
+            FALSE
+        }
+    }
+
+    fn pointer(index: usize) -> Word {
+        Word((index as u64) << 2)
+    }
+
+    pub fn as_small_int(self) -> Option<i64> {
+        if self.0 & INT_TAG != 0 {
+            Some((self.0 as i64) >> 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            TRUE => Some(true),
+            FALSE => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn as_pointer(self) -> Option<usize> {
+        if self.0 & TAG_MASK == 0 {
+            Some((self.0 >> 2) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(value) = self.as_small_int() {
+            write!(f, "Int({})", value)
+        } else if let Some(value) = self.as_bool() {
+            write!(f, "Bool({})", value)
+        } else if let Some(index) = self.as_pointer() {
+            write!(f, "Pointer({})", index)
+        } else {
+            write!(f, "Empty")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Object {
+    // An integer that does not fit in a word.
+    Int(i64),
+    Tuple(Vec<Word>),
+    Closure {
+        name: Option<NameId>,
+        function_index: usize,
+        environment: Vec<Word>,
+    },
+}
+
+impl Object {
+    fn references(&self) -> &[Word] {
+        match self {
+            Object::Int(_) => &[],
+            Object::Tuple(fields) => fields,
+            Object::Closure { environment, .. } => environment,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordStats {
+    pub instructions: usize,
+    pub allocations: usize,
+    pub boxed_ints: usize,
+    pub frees: usize,
+    // Reference count updates, which are only needed for pointers.
+    pub refcount_operations: usize,
+}
+
+impl fmt::Display for WordStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "instructions: {}", self.instructions)?;
+        writeln!(f, "allocations: {}", self.allocations)?;
+        writeln!(f, "boxed integers: {}", self.boxed_ints)?;
+        writeln!(f, "frees: {}", self.frees)?;
+        writeln!(
+            f,
+            "reference count operations: {}",
+            self.refcount_operations
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Cell {
+    refcount: u32,
+    object: Object,
+}
+
+// A reference-counted heap of the objects that do not fit in a word. The slots
+// of freed objects are reused by later allocations.
+#[derive(Debug, Default)]
+pub struct WordHeap {
+    cells: Vec<Option<Cell>>,
+    free_list: Vec<usize>,
+    stats: WordStats,
+}
+
+impl WordHeap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len() - self.free_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> &WordStats {
+        &self.stats
+    }
+
+    // The object has a reference count of one, owned by the returned word.
+    fn alloc(&mut self, object: Object) -> Word {
+        self.stats.allocations += 1;
+        let cell = Some(Cell {
+            refcount: 1,
+            object,
+        });
+        match self.free_list.pop() {
+            Some(index) => {
+                self.cells[index] = cell;
+                Word::pointer(index)
+            }
+            None => {
+                self.cells.push(cell);
+                Word::pointer(self.cells.len() - 1)
+            }
+        }
+    }
+
+    fn cell(&self, index: usize) -> &Cell {
+        self.cells[index].as_ref().expect("dangling pointer")
+    }
+
+    fn cell_mut(&mut self, index: usize) -> &mut Cell {
+        self.cells[index].as_mut().expect("dangling pointer")
+    }
+
+    pub fn deref(&self, word: Word) -> Option<&Object> {
+        word.as_pointer().map(|index| &self.cell(index).object)
+    }
+
+    pub fn int(&mut self, value: i64) -> Word {
+        Word::small_int(value).unwrap_or_else(|| {
+            self.stats.boxed_ints += 1;
+            self.alloc(Object::Int(value))
+        })
+    }
+
+    pub fn check_int(&self, word: Word) -> Result<i64, RuntimeError> {
+        if let Some(value) = word.as_small_int() {
+            return Ok(value);
+        }
+        match self.deref(word) {
+            Some(Object::Int(value)) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "int" }),
+        }
+    }
+
+    // Words that are not pointers are copied without any bookkeeping.
+    pub fn retain(&mut self, word: Word) -> Word {
+        if let Some(index) = word.as_pointer() {
+            self.stats.refcount_operations += 1;
+            self.cell_mut(index).refcount += 1;
+        }
+        word
+    }
+
+    pub fn release(&mut self, word: Word) {
+        if word.as_pointer().is_none() {
+            return;
+        }
+
+        let mut worklist = vec![word];
+        while let Some(word) = worklist.pop() {
+            let Some(index) = word.as_pointer() else {
+                continue;
+            };
+            self.stats.refcount_operations += 1;
+            let cell = self.cell_mut(index);
+            cell.refcount -= 1;
+            if cell.refcount == 0 {
+                let cell = self.cells[index].take().expect("dangling pointer");
+                worklist.extend_from_slice(cell.object.references());
+                self.free_list.push(index);
+                self.stats.frees += 1;
+            }
+        }
+    }
+}
+
+struct Frame {
+    function_index: usize,
+    // The closure being called, which the frame owns like all of its words.
+    closure: Word,
+    arguments: Vec<Word>,
+    locals: Vec<Word>,
+    block_index: usize,
+    instruction_index: usize,
+    // The local of the caller that receives the result, or None for the
+    // entry function.
+    result: Option<LocalReference>,
+}
+
+// Runs the flat IR directly, with a program counter in every frame. Blocks are
+// entered by an If and left by a jump to the instruction after it.
+pub struct WordInterpreter<'a> {
+    program: &'a Program,
+    heap: WordHeap,
+    frames: Vec<Frame>,
+}
+
+impl<'a> WordInterpreter<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        WordInterpreter {
+            program,
+            heap: WordHeap::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn heap(&self) -> &WordHeap {
+        &self.heap
+    }
+
+    pub fn stats(&self) -> &WordStats {
+        self.heap.stats()
+    }
+
+    // The result is owned by the caller, who can give it back with `release`.
+    pub fn run(&mut self) -> Result<Word, RuntimeError> {
+        let entry = self.heap.alloc(Object::Closure {
+            name: None,
+            function_index: 0,
+            environment: Vec::new(),
+        });
+        self.push_frame(entry, Vec::new(), None)?;
+
+        loop {
+            if let Some(result) = self.step()? {
+                return Ok(result);
+            }
+        }
+    }
+
+    pub fn release(&mut self, word: Word) {
+        self.heap.release(word);
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no active frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no active frame")
+    }
+
+    // Takes ownership of the closure and the arguments.
+    fn push_frame(
+        &mut self,
+        closure: Word,
+        arguments: Vec<Word>,
+        result: Option<LocalReference>,
+    ) -> Result<(), RuntimeError> {
+        let function_index = match self.heap.deref(closure) {
+            Some(Object::Closure { function_index, .. }) => *function_index,
+            _ => {
+                self.heap.release(closure);
+                for argument in arguments {
+                    self.heap.release(argument);
+                }
+                return Err(RuntimeError::TypeMismatch {
+                    expected: "closure",
+                });
+            }
+        };
+
+        let function = &self.program.functions[function_index];
+        if function.args_size != arguments.len() {
+            let error = RuntimeError::ArityMismatch {
+                expected: function.args_size,
+                actual: arguments.len(),
+            };
+            self.heap.release(closure);
+            for argument in arguments {
+                self.heap.release(argument);
+            }
+            return Err(error);
+        }
+
+        self.frames.push(Frame {
+            function_index,
+            closure,
+            arguments,
+            locals: vec![EMPTY; function.frame_size],
+            block_index: 0,
+            instruction_index: 0,
+            result,
+        });
+        Ok(())
+    }
+
+    fn pop_frame(&mut self) -> Frame {
+        let frame = self.frames.pop().expect("no active frame");
+        self.heap.release(frame.closure);
+        for word in frame.arguments.iter().chain(&frame.locals) {
+            self.heap.release(*word);
+        }
+        frame
+    }
+
+    // The word is borrowed from the frame.
+    fn load(&self, reference: Reference) -> Word {
+        let frame = self.frame();
+        let word = match reference {
+            Reference::Local(LocalReference(offset)) => frame.locals[offset],
+            Reference::Argument(ArgumentReference(offset)) => frame.arguments[offset],
+            Reference::Closure(ClosureReference(offset)) => match self.heap.deref(frame.closure) {
+                Some(Object::Closure { environment, .. }) => environment[offset],
+                _ => panic!("frame without closure"),
+            },
+            Reference::This => frame.closure,
+        };
+        assert!(word != EMPTY, "use of uninitialized local");
+        word
+    }
+
+    fn load_owned(&mut self, reference: Reference) -> Word {
+        let word = self.load(reference);
+        self.heap.retain(word)
+    }
+
+    fn load_all(&mut self, references: &[Reference]) -> Vec<Word> {
+        references.iter().map(|r| self.load_owned(*r)).collect()
+    }
+
+    fn assign(&mut self, LocalReference(offset): LocalReference, word: Word) {
+        let old = std::mem::replace(&mut self.frame_mut().locals[offset], word);
+        self.heap.release(old);
+    }
+
+    fn jump(&mut self, target: TargetAddress) {
+        let frame = self.frame_mut();
+        frame.block_index = target.block_index;
+        frame.instruction_index = target.instruction_index;
+    }
+
+    fn binop(&mut self, op: BinOp, lhs: Word, rhs: Word) -> Result<Word, RuntimeError> {
+        // Both operands are small, so the result of adding or subtracting
+        // them fits in 64 bits.
+        if let (Some(lhs), Some(rhs)) = (lhs.as_small_int(), rhs.as_small_int()) {
+            match op {
+                BinOp::Add => return Ok(self.heap.int(lhs + rhs)),
+                BinOp::Sub => return Ok(self.heap.int(lhs - rhs)),
+                BinOp::Eq => return Ok(Word::bool(lhs == rhs)),
+                BinOp::Get => {}
+            }
+        }
+
+        match op {
+            BinOp::Add => {
+                let value = self
+                    .heap
+                    .check_int(lhs)?
+                    .wrapping_add(self.heap.check_int(rhs)?);
+                Ok(self.heap.int(value))
+            }
+            BinOp::Sub => {
+                let value = self
+                    .heap
+                    .check_int(lhs)?
+                    .wrapping_sub(self.heap.check_int(rhs)?);
+                Ok(self.heap.int(value))
+            }
+            BinOp::Eq => Ok(Word::bool(
+                self.heap.check_int(lhs)? == self.heap.check_int(rhs)?,
+            )),
+            BinOp::Get => {
+                let index = self.heap.check_int(rhs)?;
+                let Some(Object::Tuple(fields)) = self.heap.deref(lhs) else {
+                    return Err(RuntimeError::TypeMismatch { expected: "tuple" });
+                };
+                let field = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| fields.get(i))
+                    .copied()
+                    .ok_or(RuntimeError::IndexOutOfRange {
+                        index,
+                        size: fields.len(),
+                    })?;
+                Ok(self.heap.retain(field))
+            }
+        }
+    }
+
+    fn simple(&mut self, simple: &Simple) -> Result<Word, RuntimeError> {
+        match simple {
+            Simple::Literal(Constant::Int { value }) => Ok(self.heap.int(*value)),
+            Simple::Literal(Constant::Bool { value }) => Ok(Word::bool(*value)),
+            Simple::Fun(AllocClosure {
+                name,
+                free_vars,
+                body,
+            }) => {
+                let environment = self.load_all(free_vars);
+                Ok(self.heap.alloc(Object::Closure {
+                    name: *name,
+                    function_index: body.function_index,
+                    environment,
+                }))
+            }
+            Simple::BinOp { op, lhs, rhs } => {
+                let lhs = self.load(*lhs);
+                let rhs = self.load(*rhs);
+                self.binop(*op, lhs, rhs)
+            }
+            Simple::Tuple { args } => {
+                let fields = self.load_all(args);
+                Ok(self.heap.alloc(Object::Tuple(fields)))
+            }
+            Simple::Set {
+                tuple,
+                index,
+                new_value,
+            } => {
+                let tuple = self.load(*tuple);
+                let size = match self.heap.deref(tuple) {
+                    Some(Object::Tuple(fields)) => fields.len(),
+                    _ => return Err(RuntimeError::TypeMismatch { expected: "tuple" }),
+                };
+                if *index as usize >= size {
+                    return Err(RuntimeError::IndexOutOfRange {
+                        index: (*index).into(),
+                        size,
+                    });
+                }
+
+                let new_value = self.load_owned(*new_value);
+                let address = tuple.as_pointer().expect("tuple should be a pointer");
+                let Object::Tuple(fields) = &mut self.heap.cell_mut(address).object else {
+                    unreachable!("checked above");
+                };
+                let old = std::mem::replace(&mut fields[*index as usize], new_value);
+                self.heap.release(old);
+
+                Ok(self.heap.alloc(Object::Tuple(Vec::new())))
+            }
+        }
+    }
+
+    // Runs a single instruction of the current frame, and returns the result
+    // of the program once the entry function returns.
+    fn step(&mut self) -> Result<Option<Word>, RuntimeError> {
+        self.heap.stats.instructions += 1;
+
+        let program = self.program;
+        let frame = self.frame();
+        let instruction = &program.functions[frame.function_index].blocks[frame.block_index]
+            .instructions[frame.instruction_index];
+        self.frame_mut().instruction_index += 1;
+
+        let Assignment { name, definition } = match instruction {
+            Instruction::Jump(target) => {
+                self.jump(*target);
+                return Ok(None);
+            }
+            Instruction::Return(reference) => {
+                let result = self.load_owned(*reference);
+                let frame = self.pop_frame();
+                return match frame.result {
+                    Some(local) => {
+                        self.assign(local, result);
+                        Ok(None)
+                    }
+                    None => Ok(Some(result)),
+                };
+            }
+            Instruction::Assignment(assignment) => assignment,
+        };
+
+        let word = match definition {
+            Definition::Var(reference) => self.load_owned(*reference),
+            Definition::Step(Step::Simple(simple)) => self.simple(simple)?,
+            Definition::Step(Step::Control(Control::Call { func, args })) => {
+                let func = self.load_owned(*func);
+                let arguments = self.load_all(args);
+                self.push_frame(func, arguments, Some(*name))?;
+                return Ok(None);
+            }
+            Definition::Step(Step::Control(Control::TailCall { func, args })) => {
+                let func = self.load_owned(*func);
+                let arguments = self.load_all(args);
+                let frame = self.pop_frame();
+                self.push_frame(func, arguments, frame.result)?;
+                return Ok(None);
+            }
+            Definition::Step(Step::Control(Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            })) => {
+                let condition = self
+                    .load(*condition)
+                    .as_bool()
+                    .ok_or(RuntimeError::TypeMismatch { expected: "bool" })?;
+                self.jump(if condition {
+                    *branch_success
+                } else {
+                    *branch_failure
+                });
+                return Ok(None);
+            }
+            Definition::Step(Step::Control(Control::Jump { target, args })) => {
+                // The new arguments can depend on the old ones, so they are
+                // all loaded before any of them is rebound.
+                let arguments = self.load_all(args);
+                let old = std::mem::replace(&mut self.frame_mut().arguments, arguments);
+                for word in old {
+                    self.heap.release(word);
+                }
+                self.jump(*target);
+                return Ok(None);
+            }
+        };
+
+        self.assign(*name, word);
+        Ok(None)
+    }
+
+    // Writes a word the way the let interpreter renders values.
+    pub fn render(&self, word: Word) -> String {
+        let mut out = String::new();
+        self.render_word(word, &mut Vec::new(), &mut out);
+        out
+    }
+
+    fn render_word(&self, word: Word, enclosing: &mut Vec<usize>, out: &mut String) {
+        if let Some(value) = word.as_small_int() {
+            return out.push_str(&value.to_string());
+        }
+        if let Some(value) = word.as_bool() {
+            return out.push_str(&value.to_string());
+        }
+
+        let index = word.as_pointer().expect("expected value");
+        match &self.heap.cell(index).object {
+            Object::Int(value) => out.push_str(&value.to_string()),
+            Object::Closure { name: None, .. } => out.push_str("<closure>"),
+            Object::Closure {
+                name: Some(name), ..
+            } => out.push_str(&format!("<closure {}>", self.program.name(*name))),
+            Object::Tuple(_) if enclosing.contains(&index) => out.push_str("<cycle>"),
+            Object::Tuple(fields) => {
+                enclosing.push(index);
+                out.push('(');
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.render_word(*field, enclosing, out);
+                }
+                if fields.len() == 1 {
+                    out.push(',');
+                }
+                out.push(')');
+                enclosing.pop();
+            }
+        }
+    }
+}
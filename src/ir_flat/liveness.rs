@@ -0,0 +1,212 @@
+use crate::ir_flat::syntax::{
+    AllocClosure, Assignment, Control, Definition, Function, Instruction, LocalReference, Program,
+    Reference, Simple, Step,
+};
+use std::collections::HashSet;
+
+pub type LiveSet = HashSet<LocalReference>;
+
+#[derive(Debug, Clone)]
+pub struct BlockLiveness {
+    // The locals that are live right before and right after every instruction
+    // of the block.
+    pub live_before: Vec<LiveSet>,
+    pub live_after: Vec<LiveSet>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionLiveness {
+    pub blocks: Vec<BlockLiveness>,
+}
+
+impl FunctionLiveness {
+    pub fn live_before(&self, block_index: usize, instruction_index: usize) -> &LiveSet {
+        &self.blocks[block_index].live_before[instruction_index]
+    }
+
+    pub fn live_after(&self, block_index: usize, instruction_index: usize) -> &LiveSet {
+        &self.blocks[block_index].live_after[instruction_index]
+    }
+
+    // Returns true if the value of the local is needed at some point after
+    // the given instruction, i.e. the instruction is not its last use.
+    pub fn is_live_after(
+        &self,
+        block_index: usize,
+        instruction_index: usize,
+        local: LocalReference,
+    ) -> bool {
+        self.live_after(block_index, instruction_index)
+            .contains(&local)
+    }
+}
+
+// A backward analysis over the nested blocks of a function. Blocks are only
+// entered through an If and jump back to the instruction following it, so
+// apart from jumps to loop headers there are no loops and a single pass
+// suffices. The locals that are live after an If stay live during the
+// execution of its branches. The locals of a loop header are assigned anew in every iteration,
+// so only the locals that enclosing blocks assigned before entering the header
+// are (conservatively) considered live after a jump.
+struct LivenessAnalysis<'a> {
+    function: &'a Function,
+    blocks: Vec<BlockLiveness>,
+    // The block and the index of the If through which every block is entered.
+    entered_from: Vec<Option<(usize, usize)>>,
+}
+
+impl<'a> LivenessAnalysis<'a> {
+    fn new(function: &'a Function) -> Self {
+        let mut entered_from = vec![None; function.blocks.len()];
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                if let Instruction::Assignment(Assignment {
+                    definition:
+                        Definition::Step(Step::Control(Control::If {
+                            branch_success,
+                            branch_failure,
+                            ..
+                        })),
+                    ..
+                }) = instruction
+                {
+                    let entry = Some((block_index, instruction_index));
+                    entered_from[branch_success.block_index] = entry;
+                    entered_from[branch_failure.block_index] = entry;
+                }
+            }
+        }
+
+        LivenessAnalysis {
+            function,
+            entered_from,
+            blocks: function
+                .blocks
+                .iter()
+                .map(|b| BlockLiveness {
+                    live_before: vec![LiveSet::new(); b.instructions.len()],
+                    live_after: vec![LiveSet::new(); b.instructions.len()],
+                })
+                .collect(),
+        }
+    }
+
+    // Returns the locals that are live when the block is entered, given the
+    // locals that are live after it exits.
+    fn analyze_block(&mut self, block_index: usize, live_out: LiveSet) -> LiveSet {
+        let function = self.function;
+        let instructions = &function.blocks[block_index].instructions;
+        let mut live = live_out;
+
+        for (instruction_index, instruction) in instructions.iter().enumerate().rev() {
+            self.blocks[block_index].live_after[instruction_index] = live.clone();
+
+            match instruction {
+                // A branch jumps to the instruction following its If, so the
+                // locals that are live there are already in the live set.
+                Instruction::Jump(_) => {}
+                Instruction::Return(result) => add_use(&mut live, result),
+                Instruction::Assignment(Assignment { name, definition }) => {
+                    match definition {
+                        // The local of the If is assigned by the branches.
+                        Definition::Step(Step::Control(Control::If {
+                            condition,
+                            branch_success,
+                            branch_failure,
+                        })) => {
+                            let live_success =
+                                self.analyze_block(branch_success.block_index, live.clone());
+                            let live_failure =
+                                self.analyze_block(branch_failure.block_index, live.clone());
+                            live.remove(name);
+                            live.extend(live_success);
+                            live.extend(live_failure);
+                            add_use(&mut live, condition);
+                        }
+                        Definition::Step(Step::Control(Control::Jump { target, args })) => {
+                            live = self.enclosing_locals(target.block_index);
+                            for arg in args {
+                                add_use(&mut live, arg);
+                            }
+                        }
+                        _ => {
+                            live.remove(name);
+                            for reference in definition_uses(definition) {
+                                add_use(&mut live, reference);
+                            }
+                        }
+                    }
+                }
+            }
+
+            self.blocks[block_index].live_before[instruction_index] = live.clone();
+        }
+
+        live
+    }
+
+    fn enclosing_locals(&self, block_index: usize) -> LiveSet {
+        let mut locals = LiveSet::new();
+        let mut current = self.entered_from[block_index];
+
+        while let Some((parent, if_index)) = current {
+            for instruction in &self.function.blocks[parent].instructions[..if_index] {
+                if let Instruction::Assignment(Assignment { name, .. }) = instruction {
+                    locals.insert(*name);
+                }
+            }
+            current = self.entered_from[parent];
+        }
+
+        locals
+    }
+}
+
+fn add_use(live: &mut LiveSet, reference: &Reference) {
+    if let Reference::Local(local) = reference {
+        live.insert(*local);
+    }
+}
+
+// The variables read by a definition, not including the branches of an If.
+pub fn definition_uses(definition: &Definition) -> Vec<&Reference> {
+    match definition {
+        Definition::Var(var) => vec![var],
+        Definition::Step(Step::Simple(simple)) => match simple {
+            Simple::Literal(_) => vec![],
+            Simple::Fun(AllocClosure { free_vars, .. }) => free_vars.iter().collect(),
+            Simple::BinOp { lhs, rhs, .. } => vec![lhs, rhs],
+            Simple::Tuple { args } => args.iter().collect(),
+            Simple::Set {
+                tuple, new_value, ..
+            } => vec![tuple, new_value],
+        },
+        Definition::Step(Step::Control(control)) => match control {
+            Control::Call { func, args } | Control::TailCall { func, args } => {
+                std::iter::once(func).chain(args).collect()
+            }
+            Control::If { condition, .. } => vec![condition],
+            Control::Jump { args, .. } => args.iter().collect(),
+        },
+    }
+}
+
+pub fn analyze_function(function: &Function) -> FunctionLiveness {
+    let mut analysis = LivenessAnalysis::new(function);
+
+    if !function.blocks.is_empty() {
+        let live_in = analysis.analyze_block(0, LiveSet::new());
+        assert!(
+            live_in.is_empty(),
+            "locals should not be used before they are assigned"
+        );
+    }
+
+    FunctionLiveness {
+        blocks: analysis.blocks,
+    }
+}
+
+pub fn analyze_program(program: &Program) -> Vec<FunctionLiveness> {
+    program.functions.iter().map(analyze_function).collect()
+}
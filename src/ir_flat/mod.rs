@@ -1,3 +1,8 @@
 pub mod compiler;
+pub mod encoding;
 pub mod frame_layout;
+pub mod interpreter;
+pub mod liveness;
+pub mod object;
+pub mod ssa;
 pub mod syntax;
@@ -0,0 +1,174 @@
+use crate::ir_flat::syntax::{
+    AllocClosure, Assignment, Block, Control, Definition, Function, Instruction, LocalReference,
+    NameId, Program, Reference, Simple, Step, TargetAddress,
+};
+use crate::ir_let::linker::{link_order, qualified_name, LinkUnit};
+use crate::lang::syntax::{BinOp, Constant};
+use crate::result::Result;
+use std::collections::HashMap;
+
+// A module compiled to the flat IR on its own, see `encoding::encode_object`.
+// The first function is the toplevel function of the module, which returns a
+// tuple of the exports. Its closure environment holds the values of the free
+// names, in order: the qualified names of the definitions of imported modules,
+// which the linker resolves, and the globals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectFile {
+    pub name: String,
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+    pub free_names: Vec<String>,
+    pub program: Program,
+}
+
+impl LinkUnit for ObjectFile {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn imports(&self) -> &[String] {
+        &self.imports
+    }
+}
+
+fn rebase_address(address: &mut TargetAddress, offset: usize) {
+    address.function_index += offset;
+}
+
+fn rebase_function(function: &mut Function, offset: usize, names: &[NameId]) {
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            match instruction {
+                Instruction::Assignment(Assignment { definition, .. }) => match definition {
+                    Definition::Step(Step::Simple(Simple::Fun(closure))) => {
+                        rebase_address(&mut closure.body, offset);
+                        closure.name = closure.name.map(|name| names[name.0 as usize]);
+                    }
+                    Definition::Step(Step::Control(Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    })) => {
+                        rebase_address(branch_success, offset);
+                        rebase_address(branch_failure, offset);
+                    }
+                    Definition::Step(Step::Control(Control::Jump { target, .. })) => {
+                        rebase_address(target, offset)
+                    }
+                    _ => {}
+                },
+                Instruction::Jump(target) => rebase_address(target, offset),
+                Instruction::Return(_) => {}
+            }
+        }
+    }
+}
+
+// Allocates the locals of the linked toplevel function one after another.
+#[derive(Default)]
+struct ToplevelBuilder {
+    instructions: Vec<Instruction>,
+    frame_size: usize,
+}
+
+impl ToplevelBuilder {
+    fn assign(&mut self, definition: Definition) -> Reference {
+        let name = LocalReference(self.frame_size);
+        self.frame_size += 1;
+        self.instructions
+            .push(Instruction::Assignment(Assignment { name, definition }));
+        Reference::Local(name)
+    }
+}
+
+// Loads the object files that the main module depends on into a single
+// program. The new toplevel function runs the toplevel function of every
+// module once, and passes the exports it returns to the modules that import
+// them. The flat IR has no globals, so every free name has to be the export
+// of a module.
+pub fn link_objects(objects: &[ObjectFile], main: &str) -> Result<Program> {
+    let order = link_order(objects, main)?;
+
+    let mut linked = Program {
+        functions: Vec::new(),
+        names: Vec::new(),
+    };
+    let mut exports: HashMap<String, Reference> = HashMap::new();
+    let mut toplevel = ToplevelBuilder::default();
+    let mut result = None;
+
+    // The toplevel function comes first.
+    let offset = 1;
+    for object in order {
+        let module_offset = offset + linked.functions.len();
+        // The names of the module become names of the linked program.
+        let names: Vec<NameId> = object
+            .program
+            .names
+            .iter()
+            .map(|name| linked.intern(name))
+            .collect();
+        for function in &object.program.functions {
+            let mut function = function.clone();
+            rebase_function(&mut function, module_offset, &names);
+            linked.functions.push(function);
+        }
+
+        let mut free_vars = Vec::new();
+        for name in &object.free_names {
+            let reference = exports.get(name).ok_or_else(|| {
+                format!("module {} refers to unresolved name {}", object.name, name)
+            })?;
+            free_vars.push(*reference);
+        }
+        let closure = toplevel.assign(Definition::Step(Step::Simple(Simple::Fun(AllocClosure {
+            name: Some(linked.intern(&object.name)),
+            free_vars,
+            body: TargetAddress {
+                function_index: module_offset,
+                block_index: 0,
+                instruction_index: 0,
+            },
+        }))));
+        let module = toplevel.assign(Definition::Step(Step::Control(Control::Call {
+            func: closure,
+            args: vec![],
+        })));
+
+        if object.name == main {
+            result = Some(module);
+            continue;
+        }
+
+        for (index, export) in object.exports.iter().enumerate() {
+            let index = toplevel.assign(Definition::Step(Step::Simple(Simple::Literal(
+                Constant::Int {
+                    value: index as i64,
+                },
+            ))));
+            let value = toplevel.assign(Definition::Step(Step::Simple(Simple::BinOp {
+                op: BinOp::Get,
+                lhs: module,
+                rhs: index,
+            })));
+            exports.insert(qualified_name(&object.name, export), value);
+        }
+    }
+
+    let result = result.expect("main module is linked last");
+    toplevel.instructions.push(Instruction::Return(result));
+    linked.functions.insert(
+        0,
+        Function {
+            args_size: 0,
+            closure_env_size: 0,
+            frame_size: toplevel.frame_size,
+            blocks: vec![Block {
+                instructions: toplevel.instructions,
+                is_loop_header: false,
+            }],
+        },
+    );
+
+    Ok(linked)
+}
@@ -0,0 +1,346 @@
+use crate::ir_flat::ssa::syntax as target;
+use crate::ir_flat::syntax as source;
+
+use std::collections::HashMap;
+
+// Converts a function from nested blocks to a control-flow graph of basic
+// blocks in SSA form. Every If ends the current basic block and its branches
+// continue in a new join block, which selects the result of the If using a phi
+// node. Apart from the local of an If, which is assigned at the end of both of
+// its branches, local variables are never reassigned before their last use in
+// the flat IR, so the only join points that need phi nodes are those after an
+// If, and copies between
+// variables can be removed by renaming. The exception are the arguments, which
+// are rebound by jumps to loop headers. Every loop header therefore starts a
+// basic block with a phi node for every argument.
+struct SsaBuilder<'a> {
+    function: &'a source::Function,
+    blocks: Vec<PartialBlock>,
+    current_block: usize,
+    next_value: usize,
+    locals: HashMap<source::LocalReference, target::Operand>,
+    arguments: Vec<target::Operand>,
+    // The basic block starting every loop header that has been built.
+    loop_headers: HashMap<usize, usize>,
+}
+
+// Where control leaves a branch of an If, with the result of the branch and
+// the values of the arguments at that point.
+struct BlockExit {
+    block: usize,
+    result: target::Operand,
+    arguments: Vec<target::Operand>,
+}
+
+// A basic block whose terminator is not yet known.
+struct PartialBlock {
+    phis: Vec<target::Phi>,
+    instructions: Vec<target::Assignment>,
+    terminator: Option<target::Terminator>,
+}
+
+impl<'a> SsaBuilder<'a> {
+    fn new(function: &'a source::Function) -> Self {
+        SsaBuilder {
+            function,
+            blocks: Vec::new(),
+            current_block: 0,
+            next_value: 0,
+            locals: HashMap::new(),
+            arguments: (0..function.args_size)
+                .map(|i| target::Operand::Argument(source::ArgumentReference(i)))
+                .collect(),
+            loop_headers: HashMap::new(),
+        }
+    }
+
+    fn new_block(&mut self) -> usize {
+        self.blocks.push(PartialBlock {
+            phis: Vec::new(),
+            instructions: Vec::new(),
+            terminator: None,
+        });
+        self.blocks.len() - 1
+    }
+
+    fn new_value(&mut self) -> target::ValueId {
+        let value = target::ValueId(self.next_value);
+        self.next_value += 1;
+        value
+    }
+
+    fn terminate(&mut self, block: usize, terminator: target::Terminator) {
+        let previous = self.blocks[block].terminator.replace(terminator);
+        assert!(previous.is_none(), "basic block terminated twice");
+    }
+
+    fn emit(&mut self, name: source::LocalReference, definition: target::Definition) {
+        let value = self.new_value();
+        self.blocks[self.current_block]
+            .instructions
+            .push(target::Assignment {
+                name: value,
+                definition,
+            });
+        self.locals.insert(name, target::Operand::Value(value));
+    }
+
+    fn build_function(mut self) -> target::Function {
+        let entry = self.new_block();
+        self.current_block = entry;
+
+        let exit = self.build_block(0);
+        assert!(exit.is_none(), "function body should not jump out");
+
+        target::Function {
+            args_size: self.function.args_size,
+            closure_env_size: self.function.closure_env_size,
+            values_size: self.next_value,
+            blocks: self
+                .blocks
+                .into_iter()
+                .map(|b| target::BasicBlock {
+                    phis: b.phis,
+                    instructions: b.instructions,
+                    terminator: b.terminator.expect("basic block should be terminated"),
+                })
+                .collect(),
+        }
+    }
+
+    fn enter_loop_header(&mut self, block_index: usize) {
+        let header = self.new_block();
+        self.terminate(self.current_block, target::Terminator::Jump(header));
+
+        for i in 0..self.arguments.len() {
+            let value = self.new_value();
+            self.blocks[header].phis.push(target::Phi {
+                name: value,
+                incoming: vec![(self.current_block, self.arguments[i])],
+            });
+            self.arguments[i] = target::Operand::Value(value);
+        }
+
+        self.loop_headers.insert(block_index, header);
+        self.current_block = header;
+    }
+
+    // Appends the instructions of a block of the flat IR to the current basic
+    // block. Returns the basic block that jumps to the instruction following
+    // the If, or None if control does not continue there, because of a return,
+    // a tail call or a jump to a loop header.
+    fn build_block(&mut self, block_index: usize) -> Option<usize> {
+        let function = self.function;
+
+        if function.blocks[block_index].is_loop_header {
+            self.enter_loop_header(block_index);
+        }
+
+        for instruction in &function.blocks[block_index].instructions {
+            match instruction {
+                source::Instruction::Jump(_) => return Some(self.current_block),
+                source::Instruction::Return(result) => {
+                    let terminator = target::Terminator::Return(self.operand(result));
+                    self.terminate(self.current_block, terminator);
+                    return None;
+                }
+                source::Instruction::Assignment(source::Assignment { name, definition }) => {
+                    match definition {
+                        source::Definition::Var(var) => {
+                            let operand = self.operand(var);
+                            self.locals.insert(*name, operand);
+                        }
+                        source::Definition::Step(source::Step::Simple(simple)) => {
+                            let definition = self.build_simple(simple);
+                            self.emit(*name, definition);
+                        }
+                        source::Definition::Step(source::Step::Control(control)) => {
+                            if !self.build_control(*name, control) {
+                                return None;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        panic!("block should end with a jump or a return")
+    }
+
+    fn block_exit(&self, block: usize, name: source::LocalReference) -> BlockExit {
+        BlockExit {
+            block,
+            result: self.operand(&source::Reference::Local(name)),
+            arguments: self.arguments.clone(),
+        }
+    }
+
+    fn build_simple(&self, simple: &source::Simple) -> target::Definition {
+        match simple {
+            source::Simple::Literal(c) => target::Definition::Literal(*c),
+            source::Simple::Fun(source::AllocClosure {
+                name,
+                free_vars,
+                body,
+            }) => target::Definition::Fun {
+                name: *name,
+                function_index: body.function_index,
+                free_vars: self.operands(free_vars),
+            },
+            source::Simple::BinOp { op, lhs, rhs } => target::Definition::BinOp {
+                op: *op,
+                lhs: self.operand(lhs),
+                rhs: self.operand(rhs),
+            },
+            source::Simple::Tuple { args } => target::Definition::Tuple {
+                args: self.operands(args),
+            },
+            source::Simple::Set {
+                tuple,
+                index,
+                new_value,
+            } => target::Definition::Set {
+                tuple: self.operand(tuple),
+                index: *index,
+                new_value: self.operand(new_value),
+            },
+        }
+    }
+
+    // Returns false if control does not continue after the instruction.
+    fn build_control(&mut self, name: source::LocalReference, control: &source::Control) -> bool {
+        match control {
+            source::Control::Call { func, args } => {
+                let definition = target::Definition::Call {
+                    func: self.operand(func),
+                    args: self.operands(args),
+                };
+                self.emit(name, definition);
+                true
+            }
+            source::Control::TailCall { func, args } => {
+                let terminator = target::Terminator::TailCall {
+                    func: self.operand(func),
+                    args: self.operands(args),
+                };
+                self.terminate(self.current_block, terminator);
+                false
+            }
+            source::Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            } => {
+                let condition = self.operand(condition);
+                let success_block = self.new_block();
+                let failure_block = self.new_block();
+                self.terminate(
+                    self.current_block,
+                    target::Terminator::Branch {
+                        condition,
+                        branch_success: success_block,
+                        branch_failure: failure_block,
+                    },
+                );
+
+                // The variables defined in one branch are not in scope in
+                // the other branch or after the If.
+                let locals = self.locals.clone();
+                let arguments = self.arguments.clone();
+                self.current_block = success_block;
+                let success_exit = self
+                    .build_block(branch_success.block_index)
+                    .map(|block| self.block_exit(block, name));
+                self.locals = locals.clone();
+                self.arguments = arguments.clone();
+                self.current_block = failure_block;
+                let failure_exit = self
+                    .build_block(branch_failure.block_index)
+                    .map(|block| self.block_exit(block, name));
+                self.locals = locals;
+                self.arguments = arguments;
+
+                let exits: Vec<BlockExit> = success_exit.into_iter().chain(failure_exit).collect();
+                if exits.is_empty() {
+                    return false;
+                }
+
+                let join_block = self.new_block();
+                for exit in &exits {
+                    self.terminate(exit.block, target::Terminator::Jump(join_block));
+                }
+                self.current_block = join_block;
+
+                let result = self.join(join_block, &exits, |exit| exit.result);
+                self.locals.insert(name, result);
+                for i in 0..self.arguments.len() {
+                    self.arguments[i] = self.join(join_block, &exits, |exit| exit.arguments[i]);
+                }
+
+                true
+            }
+            source::Control::Jump { target, args } => {
+                let header = *self
+                    .loop_headers
+                    .get(&target.block_index)
+                    .expect("jump target should be an enclosing loop header");
+                let args = self.operands(args);
+
+                for (phi, arg) in self.blocks[header].phis.iter_mut().zip(args) {
+                    phi.incoming.push((self.current_block, arg));
+                }
+                self.terminate(self.current_block, target::Terminator::Jump(header));
+                false
+            }
+        }
+    }
+
+    // Selects the operand coming from each of the exits, using a phi node in
+    // the join block if they are not all the same.
+    fn join<F>(&mut self, join_block: usize, exits: &[BlockExit], operand: F) -> target::Operand
+    where
+        F: Fn(&BlockExit) -> target::Operand,
+    {
+        let first = operand(&exits[0]);
+        if exits.iter().all(|exit| operand(exit) == first) {
+            return first;
+        }
+
+        let value = self.new_value();
+        self.blocks[join_block].phis.push(target::Phi {
+            name: value,
+            incoming: exits
+                .iter()
+                .map(|exit| (exit.block, operand(exit)))
+                .collect(),
+        });
+        target::Operand::Value(value)
+    }
+
+    fn operand(&self, reference: &source::Reference) -> target::Operand {
+        match reference {
+            source::Reference::Local(local) => *self
+                .locals
+                .get(local)
+                .expect("local should be assigned before use"),
+            source::Reference::Argument(argument) => self.arguments[argument.0],
+            source::Reference::Closure(closure) => target::Operand::Closure(*closure),
+            source::Reference::This => target::Operand::This,
+        }
+    }
+
+    fn operands(&self, references: &[source::Reference]) -> Vec<target::Operand> {
+        references.iter().map(|r| self.operand(r)).collect()
+    }
+}
+
+pub fn convert_to_ssa(program: &source::Program) -> target::Program {
+    target::Program {
+        functions: program
+            .functions
+            .iter()
+            .map(|f| SsaBuilder::new(f).build_function())
+            .collect(),
+        names: program.names.clone(),
+    }
+}
@@ -0,0 +1,138 @@
+use crate::ir_flat::ssa::syntax::Function;
+
+pub fn predecessors(function: &Function) -> Vec<Vec<usize>> {
+    let mut predecessors = vec![Vec::new(); function.blocks.len()];
+    for (block_index, block) in function.blocks.iter().enumerate() {
+        for successor in block.terminator.successors() {
+            predecessors[successor].push(block_index);
+        }
+    }
+    predecessors
+}
+
+// The basic blocks that can be reached from the entry, in reverse postorder,
+// in which every block comes before its successors except along back edges.
+pub fn reverse_postorder(function: &Function) -> Vec<usize> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut postorder = Vec::new();
+    // The blocks being visited, with the successors still to visit.
+    let mut stack = vec![(0, function.blocks[0].terminator.successors())];
+    visited[0] = true;
+
+    while let Some((block_index, successors)) = stack.last_mut() {
+        match successors.pop() {
+            Some(successor) if !visited[successor] => {
+                visited[successor] = true;
+                let successors = function.blocks[successor].terminator.successors();
+                stack.push((successor, successors));
+            }
+            Some(_) => {}
+            None => {
+                postorder.push(*block_index);
+                stack.pop();
+            }
+        }
+    }
+
+    postorder.reverse();
+    postorder
+}
+
+// A block dominates another block if every path from the entry to the other
+// block passes through it. The dominators of a block form a chain, in which
+// the nearest one is its immediate dominator. Computed with the iterative
+// algorithm of Cooper, Harvey and Kennedy, which intersects the dominators of
+// the predecessors of every block until nothing changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DominatorTree {
+    // The immediate dominator of every block, which is the block itself for
+    // the entry and None for blocks that cannot be reached.
+    immediate_dominators: Vec<Option<usize>>,
+}
+
+impl DominatorTree {
+    pub fn new(function: &Function) -> Self {
+        let order = reverse_postorder(function);
+        let mut position = vec![usize::MAX; function.blocks.len()];
+        for (i, block_index) in order.iter().enumerate() {
+            position[*block_index] = i;
+        }
+        let predecessors = predecessors(function);
+
+        let mut immediate_dominators = vec![None; function.blocks.len()];
+        immediate_dominators[0] = Some(0);
+
+        let intersect = |dominators: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while position[a] > position[b] {
+                    a = dominators[a].expect("processed block should have a dominator");
+                }
+                while position[b] > position[a] {
+                    b = dominators[b].expect("processed block should have a dominator");
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block_index in order.iter().skip(1).copied() {
+                let mut dominator = None;
+                for predecessor in predecessors[block_index].iter().copied() {
+                    if immediate_dominators[predecessor].is_none() {
+                        continue;
+                    }
+                    dominator = Some(match dominator {
+                        None => predecessor,
+                        Some(d) => intersect(&immediate_dominators, d, predecessor),
+                    });
+                }
+
+                if dominator != immediate_dominators[block_index] {
+                    immediate_dominators[block_index] = dominator;
+                    changed = true;
+                }
+            }
+        }
+
+        DominatorTree {
+            immediate_dominators,
+        }
+    }
+
+    // None for the entry and for blocks that cannot be reached.
+    pub fn immediate_dominator(&self, block_index: usize) -> Option<usize> {
+        match self.immediate_dominators[block_index] {
+            Some(dominator) if dominator != block_index => Some(dominator),
+            _ => None,
+        }
+    }
+
+    pub fn is_reachable(&self, block_index: usize) -> bool {
+        self.immediate_dominators[block_index].is_some()
+    }
+
+    // Every reachable block dominates itself.
+    pub fn dominates(&self, dominator: usize, mut block_index: usize) -> bool {
+        if !self.is_reachable(block_index) {
+            return false;
+        }
+        loop {
+            if block_index == dominator {
+                return true;
+            }
+            match self.immediate_dominator(block_index) {
+                Some(next) => block_index = next,
+                None => return false,
+            }
+        }
+    }
+
+    // The blocks that the block is the immediate dominator of.
+    pub fn children(&self, block_index: usize) -> Vec<usize> {
+        (0..self.immediate_dominators.len())
+            .filter(|b| self.immediate_dominator(*b) == Some(block_index))
+            .collect()
+    }
+}
@@ -0,0 +1,98 @@
+use crate::ir_flat::ssa::dominators::{predecessors, DominatorTree};
+use crate::ir_flat::ssa::syntax::Function;
+
+// A loop with a single entry, its header, which dominates all blocks of the
+// loop. The latches are the blocks that jump back to the header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalLoop {
+    pub header: usize,
+    pub latches: Vec<usize>,
+    // In increasing order, including the header.
+    pub blocks: Vec<usize>,
+    // The index of the innermost loop that contains this loop.
+    pub parent: Option<usize>,
+}
+
+impl NaturalLoop {
+    pub fn contains(&self, block_index: usize) -> bool {
+        self.blocks.binary_search(&block_index).is_ok()
+    }
+}
+
+// The natural loops of a function, one for every block that is the target of
+// a back edge, which is an edge from a block to one of its dominators. Loops
+// are either disjoint or nested in each other, and outer loops come before the
+// loops that they contain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopForest {
+    pub loops: Vec<NaturalLoop>,
+}
+
+impl LoopForest {
+    pub fn new(function: &Function, dominators: &DominatorTree) -> Self {
+        let predecessors = predecessors(function);
+
+        let mut loops = Vec::new();
+        for header in 0..function.blocks.len() {
+            let latches: Vec<usize> = predecessors[header]
+                .iter()
+                .copied()
+                .filter(|p| dominators.dominates(header, *p))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+
+            // The loop consists of the blocks that can reach a latch without
+            // passing through the header.
+            let mut in_loop = vec![false; function.blocks.len()];
+            in_loop[header] = true;
+            let mut worklist = latches.clone();
+            while let Some(block_index) = worklist.pop() {
+                if in_loop[block_index] {
+                    continue;
+                }
+                in_loop[block_index] = true;
+                worklist.extend(
+                    predecessors[block_index]
+                        .iter()
+                        .filter(|p| dominators.is_reachable(**p)),
+                );
+            }
+
+            loops.push(NaturalLoop {
+                header,
+                latches,
+                blocks: (0..function.blocks.len()).filter(|b| in_loop[*b]).collect(),
+                parent: None,
+            });
+        }
+
+        // A loop that contains another one has more blocks.
+        loops.sort_by_key(|l| std::cmp::Reverse(l.blocks.len()));
+        for i in 0..loops.len() {
+            loops[i].parent = (0..i).rev().find(|j| loops[*j].contains(loops[i].header));
+        }
+
+        LoopForest { loops }
+    }
+
+    // The index of the innermost loop that contains the block.
+    pub fn innermost_loop(&self, block_index: usize) -> Option<usize> {
+        (0..self.loops.len())
+            .rev()
+            .find(|i| self.loops[*i].contains(block_index))
+    }
+
+    // The number of loops that contain the block.
+    pub fn depth(&self, block_index: usize) -> usize {
+        self.loops
+            .iter()
+            .filter(|l| l.contains(block_index))
+            .count()
+    }
+
+    pub fn is_header(&self, block_index: usize) -> bool {
+        self.loops.iter().any(|l| l.header == block_index)
+    }
+}
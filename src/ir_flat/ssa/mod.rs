@@ -0,0 +1,4 @@
+pub mod construction;
+pub mod dominators;
+pub mod loops;
+pub mod syntax;
@@ -0,0 +1,108 @@
+use crate::ir_flat::syntax::{ArgumentReference, ClosureReference, NameId};
+use crate::lang::syntax::{BinOp, Constant};
+
+// A value defined by exactly one phi node or assignment in a function.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ValueId(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Value(ValueId),
+    Argument(ArgumentReference),
+    Closure(ClosureReference),
+    This,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    // The names of the flat program, which the closure names refer to.
+    pub names: Vec<String>,
+}
+
+// The first basic block of a function is its entry point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub args_size: usize,
+    pub closure_env_size: usize,
+    pub values_size: usize,
+    pub blocks: Vec<BasicBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<Assignment>,
+    pub terminator: Terminator,
+}
+
+// Selects the operand belonging to the predecessor block from which the block
+// containing the phi node was entered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phi {
+    pub name: ValueId,
+    pub incoming: Vec<(usize, Operand)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub name: ValueId,
+    pub definition: Definition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Definition {
+    Literal(Constant),
+    Fun {
+        name: Option<NameId>,
+        function_index: usize,
+        free_vars: Vec<Operand>,
+    },
+    BinOp {
+        op: BinOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    Tuple {
+        args: Vec<Operand>,
+    },
+    Set {
+        tuple: Operand,
+        index: u32,
+        new_value: Operand,
+    },
+    Call {
+        func: Operand,
+        args: Vec<Operand>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Jump(usize),
+    Branch {
+        condition: Operand,
+        branch_success: usize,
+        branch_failure: usize,
+    },
+    Return(Operand),
+    TailCall {
+        func: Operand,
+        args: Vec<Operand>,
+    },
+}
+
+impl Terminator {
+    // The basic blocks that control can continue in within the function.
+    pub fn successors(&self) -> Vec<usize> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                branch_success,
+                branch_failure,
+                ..
+            } => vec![*branch_success, *branch_failure],
+            Terminator::Return(_) | Terminator::TailCall { .. } => vec![],
+        }
+    }
+}
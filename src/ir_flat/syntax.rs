@@ -1,6 +1,7 @@
 use crate::lang::syntax::{BinOp, Constant};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Reference {
     Local(LocalReference),
     Argument(ArgumentReference),
@@ -8,68 +9,115 @@ pub enum Reference {
     This,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LocalReference(pub usize);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ArgumentReference(pub usize);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClosureReference(pub usize);
 
-#[derive(Debug, Clone)]
+// An index into the names of a program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NameId(pub u32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub functions: Vec<Function>,
+    // The names of the closures, which travel with the program so that they
+    // are available for debugging and error messages at runtime, while the
+    // code itself only refers to them by index.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub names: Vec<String>,
+}
+
+impl Program {
+    pub fn name(&self, id: NameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+
+    pub fn intern(&mut self, name: &str) -> NameId {
+        match self.names.iter().position(|n| n == name) {
+            Some(index) => NameId(index as u32),
+            None => {
+                self.names.push(name.to_owned());
+                NameId(self.names.len() as u32 - 1)
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// Blocks do not introduce scopes: all locals of a function share a single
+// frame, and locals whose lifetimes do not overlap can share a slot.
 pub struct Function {
     pub args_size: usize,
     pub closure_env_size: usize,
+    pub frame_size: usize,
     pub blocks: Vec<Block>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
-    pub frame_size: usize,
     pub instructions: Vec<Instruction>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_loop_header: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
-    EnterBlock,
-    ExitBlock,
     Assignment(Assignment),
+    // Ends a branch of an If, after the result of the branch has been
+    // assigned to the local of the If, by continuing after the If.
+    Jump(TargetAddress),
+    // Ends the first block of a function by returning from the function.
+    Return(Reference),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Assignment {
     pub name: LocalReference,
     pub definition: Definition,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Definition {
     Var(Reference),
     Step(Step),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TargetAddress {
     pub function_index: usize,
     pub block_index: usize,
     pub instruction_index: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AllocClosure {
-    pub name: String,
-    pub arg_names: Vec<String>,
-    pub free_names: Vec<String>,
+    // None if the program was compiled without debug info.
+    pub name: Option<NameId>,
+    // The values to capture in the closure environment, in the order given by
+    // the closure offsets of the target function.
+    pub free_vars: Vec<Reference>,
     pub body: TargetAddress,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Simple {
     Literal(Constant),
     Fun(AllocClosure),
@@ -88,20 +136,33 @@ pub enum Simple {
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Control {
     Call {
         func: Reference,
         args: Vec<Reference>,
     },
+    TailCall {
+        func: Reference,
+        args: Vec<Reference>,
+    },
+    // The local of the If is assigned by the branches.
     If {
         condition: Reference,
         branch_success: TargetAddress,
         branch_failure: TargetAddress,
     },
+    // Rebinds the arguments and continues at the start of an enclosing loop
+    // header block.
+    Jump {
+        target: TargetAddress,
+        args: Vec<Reference>,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Step {
     Simple(Simple),
     Control(Control),
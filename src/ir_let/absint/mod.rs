@@ -0,0 +1,291 @@
+use crate::ir_let::let_expr::{
+    Control, Definition, Function, Instruction, Program, Simple, Step, VariableReference,
+};
+use crate::lang::syntax::{BinOp, Constant};
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+
+pub mod range;
+
+// The abstract values of an analysis, which describe sets of runtime values.
+// They form a lattice ordered by how many values they describe, from bottom
+// (no values, such as the result of code that is never reached) to top (any
+// value).
+pub trait Domain: Clone + PartialEq {
+    fn bottom() -> Self;
+    fn top() -> Self;
+    fn join(&self, other: &Self) -> Self;
+
+    // Used instead of join for the arguments of loop headers, and has to
+    // reach a fixpoint after a finite number of steps even in domains with
+    // infinite ascending chains.
+    fn widen(&self, next: &Self) -> Self {
+        self.join(next)
+    }
+
+    fn literal(value: Constant) -> Self;
+    fn binop(op: BinOp, lhs: &Self, rhs: &Self) -> Self;
+    fn tuple(fields: &[Self]) -> Self;
+
+    // Whether the value is known to be true or false when it is used as a
+    // condition.
+    fn truth(&self) -> Option<bool> {
+        None
+    }
+
+    // Whether accessing the field of the tuple at the index is known to
+    // succeed.
+    fn index_in_range(_tuple: &Self, _index: &Self) -> bool {
+        false
+    }
+}
+
+// What an analysis found out about a program. Variable names are unique, so
+// every variable has a single abstract value, which describes all the values
+// it takes in every run of the program. Variables that are missing are never
+// assigned, for example because they are in a branch that is never taken.
+#[derive(Debug, Clone)]
+pub struct Analysis<D> {
+    pub values: HashMap<Symbol, D>,
+    // The conditionals, by the variable they assign, whose condition is
+    // always true or always false.
+    pub conditions: HashMap<Symbol, bool>,
+    // The field accesses and updates, by the variable they assign, whose
+    // index is always in range of the tuple.
+    pub accesses_in_range: HashSet<Symbol>,
+}
+
+impl<D: Domain> Analysis<D> {
+    pub fn value(&self, name: Symbol) -> D {
+        self.values.get(&name).cloned().unwrap_or_else(D::bottom)
+    }
+}
+
+// Interprets the functions of a program over the abstract domain, one after
+// the other. The blocks of a function are structured: conditionals enter
+// their branches and continue with the result, and jumps only go back to loop
+// headers, which are interpreted until the arguments of the function reach a
+// fixpoint. Calls are not followed, so the arguments of functions and the
+// results of calls are unknown. Closures can refer to the variables of the
+// functions that allocate them, which are known when those functions come
+// first.
+struct Interpreter<D> {
+    values: HashMap<Symbol, D>,
+    // The facts found by the last interpretation of every instruction, which
+    // for instructions in loops describes all iterations.
+    conditions: HashMap<Symbol, Option<bool>>,
+    accesses: HashMap<Symbol, bool>,
+    // The joined arguments of the jumps to every loop header that is being
+    // interpreted.
+    jumps: HashMap<usize, Vec<D>>,
+}
+
+type Environment<D> = HashMap<Symbol, D>;
+
+impl<D: Domain> Interpreter<D> {
+    fn new() -> Self {
+        Interpreter {
+            values: HashMap::new(),
+            conditions: HashMap::new(),
+            accesses: HashMap::new(),
+            jumps: HashMap::new(),
+        }
+    }
+
+    fn lookup(&self, env: &Environment<D>, var: &VariableReference) -> D {
+        self.lookup_name(env, var.var_name)
+    }
+
+    fn lookup_name(&self, env: &Environment<D>, name: Symbol) -> D {
+        env.get(&name)
+            .or_else(|| self.values.get(&name))
+            .cloned()
+            .unwrap_or_else(D::top)
+    }
+
+    fn interpret_function(&mut self, function: &Function) {
+        let mut env = Environment::new();
+        for arg_name in &function.arg_names {
+            env.insert(*arg_name, D::top());
+        }
+        self.enter_block(function, 0, &mut env);
+    }
+
+    // Jumps assign the arguments of the function, so their values at the
+    // start of a loop header are the join of the values when the loop is
+    // entered and the values of all jumps back to it. The arguments keep the
+    // values of the last iteration after the loop.
+    fn enter_block(
+        &mut self,
+        function: &Function,
+        block_index: usize,
+        env: &mut Environment<D>,
+    ) -> D {
+        if !function.blocks[block_index].is_loop_header {
+            return self.interpret_block(function, block_index, env.clone());
+        }
+
+        loop {
+            let entry: Vec<D> = function
+                .arg_names
+                .iter()
+                .map(|name| self.lookup_name(env, *name))
+                .collect();
+            let result = self.interpret_block(function, block_index, env.clone());
+
+            let Some(jump_args) = self.jumps.remove(&block_index) else {
+                return result;
+            };
+            let next: Vec<D> = entry
+                .iter()
+                .zip(&jump_args)
+                .map(|(entry, jump)| entry.join(jump))
+                .collect();
+            if next == entry {
+                return result;
+            }
+
+            for ((name, entry), next) in function.arg_names.iter().zip(&entry).zip(&next) {
+                env.insert(*name, entry.widen(next));
+            }
+        }
+    }
+
+    fn interpret_block(
+        &mut self,
+        function: &Function,
+        block_index: usize,
+        mut env: Environment<D>,
+    ) -> D {
+        for instruction in &function.blocks[block_index].instructions {
+            match instruction {
+                Instruction::EnterBlock => {}
+                Instruction::ExitBlock(var) => return self.lookup(&env, var),
+                Instruction::Assignment(assignment) => {
+                    let value = self.interpret_definition(
+                        function,
+                        assignment.name,
+                        &assignment.definition,
+                        &mut env,
+                    );
+                    self.values.insert(assignment.name, value.clone());
+                    env.insert(assignment.name, value);
+                }
+            }
+        }
+
+        D::bottom()
+    }
+
+    fn interpret_definition(
+        &mut self,
+        function: &Function,
+        name: Symbol,
+        definition: &Definition,
+        env: &mut Environment<D>,
+    ) -> D {
+        match definition {
+            Definition::Var(var) => self.lookup(env, var),
+            Definition::Step(Step::Simple(simple)) => match simple {
+                Simple::Literal(value) => D::literal(*value),
+                Simple::BinOp { op, lhs, rhs } => {
+                    let lhs = self.lookup(env, lhs);
+                    let rhs = self.lookup(env, rhs);
+                    if *op == BinOp::Get {
+                        self.accesses.insert(name, D::index_in_range(&lhs, &rhs));
+                    }
+                    D::binop(*op, &lhs, &rhs)
+                }
+                Simple::Tuple { args } | Simple::ReuseTuple { args, .. } => {
+                    let fields: Vec<D> = args.iter().map(|arg| self.lookup(env, arg)).collect();
+                    D::tuple(&fields)
+                }
+                Simple::Set { tuple, index, .. } => {
+                    let tuple = self.lookup(env, tuple);
+                    let index = D::literal(Constant::Int {
+                        value: (*index).into(),
+                    });
+                    self.accesses
+                        .insert(name, D::index_in_range(&tuple, &index));
+                    D::top()
+                }
+                Simple::Fun(_) | Simple::LiftedFun { .. } => D::top(),
+            },
+            Definition::Step(Step::Control(control)) => match control {
+                Control::Call { .. } | Control::TailCall { .. } | Control::CallDirect { .. } => {
+                    D::top()
+                }
+                Control::If {
+                    condition,
+                    branch_success,
+                    branch_failure,
+                } => {
+                    let truth = self.lookup(env, condition).truth();
+                    self.conditions.insert(name, truth);
+
+                    // Within a branch, the condition is known.
+                    let mut result = D::bottom();
+                    for (value, branch) in [(true, branch_success), (false, branch_failure)] {
+                        if truth == Some(!value) {
+                            continue;
+                        }
+                        let mut branch_env = env.clone();
+                        branch_env.insert(condition.var_name, D::literal(Constant::Bool { value }));
+                        result = result.join(&self.enter_block(
+                            function,
+                            branch.block_index,
+                            &mut branch_env,
+                        ));
+
+                        // Jumps in the branch assign the arguments.
+                        for arg_name in &function.arg_names {
+                            let value = self.lookup_name(&branch_env, *arg_name);
+                            env.insert(*arg_name, self.lookup_name(env, *arg_name).join(&value));
+                        }
+                    }
+                    result
+                }
+                Control::Jump { target, args } => {
+                    let args: Vec<D> = args.iter().map(|arg| self.lookup(env, arg)).collect();
+                    let jump_args = match self.jumps.remove(&target.block_index) {
+                        Some(previous) => previous
+                            .iter()
+                            .zip(&args)
+                            .map(|(previous, arg)| previous.join(arg))
+                            .collect(),
+                        None => args,
+                    };
+                    self.jumps.insert(target.block_index, jump_args);
+
+                    // A jump does not return.
+                    D::bottom()
+                }
+            },
+        }
+    }
+
+    fn done(self) -> Analysis<D> {
+        Analysis {
+            values: self.values,
+            conditions: self
+                .conditions
+                .into_iter()
+                .filter_map(|(name, truth)| Some((name, truth?)))
+                .collect(),
+            accesses_in_range: self
+                .accesses
+                .into_iter()
+                .filter(|(_, in_range)| *in_range)
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+}
+
+pub fn analyze<D: Domain>(program: &Program) -> Analysis<D> {
+    let mut interpreter = Interpreter::new();
+    for function in &program.functions {
+        interpreter.interpret_function(function);
+    }
+    interpreter.done()
+}
@@ -0,0 +1,154 @@
+use crate::ir_let::absint::Domain;
+use crate::lang::syntax::{BinOp, Constant};
+
+// Constants and ranges of integers. An integer is described by the range of
+// values it lies in, which is a single value for a constant, and a tuple by
+// its number of fields, which updates cannot change.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RangeValue {
+    Bottom,
+    // Both bounds are inclusive.
+    Int { min: i64, max: i64 },
+    Bool { value: Option<bool> },
+    Tuple { size: usize },
+    Top,
+}
+
+impl RangeValue {
+    pub fn constant(&self) -> Option<Constant> {
+        match *self {
+            RangeValue::Int { min, max } if min == max => Some(Constant::Int { value: min }),
+            RangeValue::Bool { value: Some(value) } => Some(Constant::Bool { value }),
+            _ => None,
+        }
+    }
+}
+
+const ANY_INT: RangeValue = RangeValue::Int {
+    min: i64::MIN,
+    max: i64::MAX,
+};
+
+impl Domain for RangeValue {
+    fn bottom() -> Self {
+        RangeValue::Bottom
+    }
+
+    fn top() -> Self {
+        RangeValue::Top
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (RangeValue::Bottom, value) | (value, RangeValue::Bottom) => value,
+            (
+                RangeValue::Int { min, max },
+                RangeValue::Int {
+                    min: other_min,
+                    max: other_max,
+                },
+            ) => RangeValue::Int {
+                min: min.min(other_min),
+                max: max.max(other_max),
+            },
+            (RangeValue::Bool { value }, RangeValue::Bool { value: other }) => RangeValue::Bool {
+                value: if value == other { value } else { None },
+            },
+            (RangeValue::Tuple { size }, RangeValue::Tuple { size: other }) if size == other => {
+                RangeValue::Tuple { size }
+            }
+            _ => RangeValue::Top,
+        }
+    }
+
+    // A bound that is still moving is given up on, so that a counter in a
+    // loop does not take an iteration for every value.
+    fn widen(&self, next: &Self) -> Self {
+        match (*self, self.join(next)) {
+            (
+                RangeValue::Int { min, max },
+                RangeValue::Int {
+                    min: next_min,
+                    max: next_max,
+                },
+            ) => RangeValue::Int {
+                min: if next_min < min { i64::MIN } else { min },
+                max: if next_max > max { i64::MAX } else { max },
+            },
+            (_, joined) => joined,
+        }
+    }
+
+    fn literal(value: Constant) -> Self {
+        match value {
+            Constant::Int { value } => RangeValue::Int {
+                min: value,
+                max: value,
+            },
+            Constant::Bool { value } => RangeValue::Bool { value: Some(value) },
+        }
+    }
+
+    // An operation on a bottom operand is never evaluated. Operations on
+    // anything but integers fail, so their result only matters when they
+    // succeed, and an addition or subtraction whose bounds overflow can
+    // result in any integer, depending on the overflow behavior.
+    fn binop(op: BinOp, lhs: &Self, rhs: &Self) -> Self {
+        let (
+            RangeValue::Int { min, max },
+            RangeValue::Int {
+                min: rhs_min,
+                max: rhs_max,
+            },
+        ) = (*lhs, *rhs)
+        else {
+            return match (op, lhs, rhs) {
+                (_, RangeValue::Bottom, _) | (_, _, RangeValue::Bottom) => RangeValue::Bottom,
+                (BinOp::Add | BinOp::Sub, _, _) => ANY_INT,
+                (BinOp::Eq, _, _) => RangeValue::Bool { value: None },
+                (BinOp::Get, _, _) => RangeValue::Top,
+            };
+        };
+
+        match op {
+            BinOp::Add => match (min.checked_add(rhs_min), max.checked_add(rhs_max)) {
+                (Some(min), Some(max)) => RangeValue::Int { min, max },
+                _ => ANY_INT,
+            },
+            BinOp::Sub => match (min.checked_sub(rhs_max), max.checked_sub(rhs_min)) {
+                (Some(min), Some(max)) => RangeValue::Int { min, max },
+                _ => ANY_INT,
+            },
+            BinOp::Eq => RangeValue::Bool {
+                value: if min == max && rhs_min == rhs_max {
+                    Some(min == rhs_min)
+                } else if max < rhs_min || rhs_max < min {
+                    Some(false)
+                } else {
+                    None
+                },
+            },
+            BinOp::Get => RangeValue::Top,
+        }
+    }
+
+    fn tuple(fields: &[Self]) -> Self {
+        RangeValue::Tuple { size: fields.len() }
+    }
+
+    fn truth(&self) -> Option<bool> {
+        match *self {
+            RangeValue::Bool { value } => value,
+            _ => None,
+        }
+    }
+
+    fn index_in_range(tuple: &Self, index: &Self) -> bool {
+        match (*tuple, *index) {
+            (RangeValue::Tuple { size }, RangeValue::Int { min, max }) => {
+                min >= 0 && (max as u64) < size as u64
+            }
+            _ => false,
+        }
+    }
+}
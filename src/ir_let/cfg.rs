@@ -0,0 +1,248 @@
+use crate::ir_let::let_expr::{
+    Assignment, Control, Definition, Instruction, Program, Simple, Step,
+};
+use crate::result::Result;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EdgeKind {
+    BranchSuccess,
+    BranchFailure,
+    // Control returns to the block containing the If that entered the block,
+    // or to the caller for the first block of a function.
+    Exit,
+    Call,
+    TailCall,
+    // A backward jump to a loop header.
+    Jump,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EdgeTarget {
+    Block(usize),
+    Return,
+    // Calls are only resolved to a function when the closure being called is
+    // known statically.
+    Function(Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub source_block: usize,
+    pub instruction_index: usize,
+    pub target: EdgeTarget,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionCfg {
+    pub function_index: usize,
+    pub edges: Vec<Edge>,
+}
+
+impl FunctionCfg {
+    pub fn successors(&self, block_index: usize) -> impl Iterator<Item = &Edge> {
+        self.edges
+            .iter()
+            .filter(move |e| e.source_block == block_index)
+    }
+
+    // Renders the graph in the Graphviz dot format, with the instructions of
+    // every block as its label.
+    pub fn to_dot(&self, program: &Program) -> String {
+        let function = &program.functions[self.function_index];
+        let mut out = String::new();
+
+        // Writing to a String cannot fail.
+        writeln!(out, "digraph \"{}\" {{", escape(function.name.as_str())).unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            let mut label = format!("block {}\\l", i);
+            for instruction in &block.instructions {
+                label += &escape(&instruction.to_string());
+                label += "\\l";
+            }
+            writeln!(out, "    b{} [label=\"{}\"];", i, label).unwrap();
+        }
+
+        let mut callees = Vec::new();
+        for edge in &self.edges {
+            let target = match edge.target {
+                EdgeTarget::Block(block_index) => format!("b{}", block_index),
+                EdgeTarget::Return => "return".to_owned(),
+                EdgeTarget::Function(Some(function_index)) => {
+                    callees.push(Some(function_index));
+                    format!("f{}", function_index)
+                }
+                EdgeTarget::Function(None) => {
+                    callees.push(None);
+                    "unknown".to_owned()
+                }
+            };
+            let style = match edge.kind {
+                EdgeKind::BranchSuccess => "label=\"then\"",
+                EdgeKind::BranchFailure => "label=\"else\"",
+                EdgeKind::Exit => "style=dashed",
+                EdgeKind::Call => "style=dotted",
+                EdgeKind::TailCall => "style=dotted, label=\"tail\"",
+                EdgeKind::Jump => "label=\"loop\"",
+            };
+            writeln!(out, "    b{} -> {} [{}];", edge.source_block, target, style).unwrap();
+        }
+
+        writeln!(out, "    return [shape=oval];").unwrap();
+        callees.sort();
+        callees.dedup();
+        for callee in callees {
+            match callee {
+                Some(function_index) => writeln!(
+                    out,
+                    "    f{} [shape=oval, label=\"{}\"];",
+                    function_index,
+                    escape(program.functions[function_index].name.as_str())
+                )
+                .unwrap(),
+                None => writeln!(out, "    unknown [shape=oval, label=\"?\"];").unwrap(),
+            }
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn build_cfg(program: &Program) -> Vec<FunctionCfg> {
+    // Variable names are unique in a program, so the closures that are
+    // allocated anywhere can be identified by name.
+    let mut closures = HashMap::new();
+    for (function_index, function) in program.functions.iter().enumerate() {
+        closures.insert(function.name.as_str(), function_index);
+
+        for block in &function.blocks {
+            for instruction in &block.instructions {
+                let Instruction::Assignment(Assignment {
+                    name,
+                    definition: Definition::Step(Step::Simple(simple)),
+                }) = instruction
+                else {
+                    continue;
+                };
+                match simple {
+                    Simple::Fun(closure) => {
+                        closures.insert(name.as_str(), closure.body.function_index);
+                    }
+                    Simple::LiftedFun { function_index, .. } => {
+                        closures.insert(name.as_str(), *function_index);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+
+    for (function_index, function) in program.functions.iter().enumerate() {
+        let mut edges = Vec::new();
+        let mut entered_from = HashMap::new();
+
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                let control = match instruction {
+                    Instruction::Assignment(Assignment {
+                        definition: Definition::Step(Step::Control(control)),
+                        ..
+                    }) => control,
+                    _ => continue,
+                };
+
+                let mut add_edge = |target, kind| {
+                    edges.push(Edge {
+                        source_block: block_index,
+                        instruction_index,
+                        target,
+                        kind,
+                    })
+                };
+
+                match control {
+                    Control::Call { func, .. } => add_edge(
+                        EdgeTarget::Function(closures.get(func.var_name.as_str()).copied()),
+                        EdgeKind::Call,
+                    ),
+                    Control::CallDirect { function_index, .. } => {
+                        add_edge(EdgeTarget::Function(Some(*function_index)), EdgeKind::Call)
+                    }
+                    Control::TailCall { func, .. } => add_edge(
+                        EdgeTarget::Function(closures.get(func.var_name.as_str()).copied()),
+                        EdgeKind::TailCall,
+                    ),
+                    Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    } => {
+                        add_edge(
+                            EdgeTarget::Block(branch_success.block_index),
+                            EdgeKind::BranchSuccess,
+                        );
+                        add_edge(
+                            EdgeTarget::Block(branch_failure.block_index),
+                            EdgeKind::BranchFailure,
+                        );
+                        entered_from.insert(branch_success.block_index, block_index);
+                        entered_from.insert(branch_failure.block_index, block_index);
+                    }
+                    Control::Jump { target, .. } => {
+                        add_edge(EdgeTarget::Block(target.block_index), EdgeKind::Jump)
+                    }
+                }
+            }
+        }
+
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                if let Instruction::ExitBlock(_) = instruction {
+                    let target = match entered_from.get(&block_index) {
+                        Some(caller_block) => EdgeTarget::Block(*caller_block),
+                        None => EdgeTarget::Return,
+                    };
+
+                    edges.push(Edge {
+                        source_block: block_index,
+                        instruction_index,
+                        target,
+                        kind: EdgeKind::Exit,
+                    });
+                }
+            }
+        }
+
+        result.push(FunctionCfg {
+            function_index,
+            edges,
+        });
+    }
+
+    result
+}
+
+// Writes one file named `<function index>_<function name>.dot` per function.
+pub fn save_dot_files(program: &Program, directory: &Path) -> Result<()> {
+    std::fs::create_dir_all(directory)?;
+
+    for cfg in build_cfg(program) {
+        let name = &program.functions[cfg.function_index].name;
+        let path = directory.join(format!("{}_{}.dot", cfg.function_index, name));
+        std::fs::write(path, cfg.to_dot(program))?;
+    }
+
+    Ok(())
+}
@@ -3,16 +3,25 @@ use crate::ir_let::let_expr::{
     AllocClosure, Assignment, Block, Control, Definition, Function, Instruction, Program, Simple,
     Step, TargetAddress, VariableReference,
 };
-use crate::lang::syntax::Expr;
+use crate::ir_let::linker::{qualified_name, CompiledModule};
+use crate::lang::syntax::{BinOp, Constant, Expr, Module, MAX_NESTING};
 use crate::result::Result;
-use std::collections::HashMap;
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
 
 struct LetNormalizer {
     program: Program,
     current_function_index: Option<usize>,
     current_block_index: Option<usize>,
     var_counter: u64,
-    var_substitution: HashMap<String, String>,
+    var_substitution: HashMap<String, Symbol>,
+    globals: Vec<Symbol>,
+    // The module being compiled, whose name is part of every variable name,
+    // so that the names stay unique when modules are linked together.
+    module_name: Option<String>,
+    // The number of expressions that are being normalized, one inside the
+    // other.
+    depth: usize,
 }
 
 impl LetNormalizer {
@@ -25,18 +34,24 @@ impl LetNormalizer {
             current_block_index: None,
             var_counter: 0,
             var_substitution: HashMap::new(),
+            globals: Vec::new(),
+            module_name: None,
+            depth: 0,
         }
     }
 
-    // TODO: Implement more efficient/less hacky variable generation (probably
-    // want to do interning anyway instead of having String all over the place).
-    fn fresh(&mut self, base_name: &str) -> String {
+    fn fresh(&mut self, base_name: &str) -> Symbol {
         let count = self.var_counter;
         self.var_counter += 1;
-        base_name.to_owned() + "__" + &count.to_string()
+        match &self.module_name {
+            Some(module_name) => {
+                Symbol::intern(&format!("{}__{}__{}", module_name, base_name, count))
+            }
+            None => Symbol::intern(&format!("{}__{}", base_name, count)),
+        }
     }
 
-    fn with_substitution<F, R>(&mut self, from: String, to: String, f: F) -> R
+    fn with_substitution<F, R>(&mut self, from: String, to: Symbol, f: F) -> R
     where
         F: FnOnce(&mut LetNormalizer) -> R,
     {
@@ -56,7 +71,7 @@ impl LetNormalizer {
 
     fn with_substitutions<F, R>(
         &mut self,
-        mut reverse_substitutions: Vec<(String, String)>,
+        mut reverse_substitutions: Vec<(String, Symbol)>,
         f: F,
     ) -> R
     where
@@ -82,31 +97,38 @@ impl LetNormalizer {
     }
 
     fn normalize_var(&mut self, e: &Expr) -> Result<VariableReference> {
-        let norm_rhs = self.normalize_rhs(e)?;
+        self.normalize_var_at(e, false)
+    }
+
+    // Expressions in tail position are those whose value is immediately
+    // returned from the enclosing function. Calls in tail position are emitted
+    // as tail calls.
+    fn normalize_var_at(&mut self, e: &Expr, is_tail: bool) -> Result<VariableReference> {
+        let norm_rhs = self.normalize_rhs(e, is_tail)?;
 
         match norm_rhs {
             Definition::Var(expr_at) => Ok(expr_at),
             Definition::Step(step) => {
                 let var_name = self.fresh("__gen");
                 self.emit(Instruction::Assignment(Assignment {
-                    name: var_name.clone(),
+                    name: var_name,
                     definition: Definition::Step(step),
                 }));
-                Ok(VariableReference { var_name })
+                Ok(VariableReference::new(var_name))
             }
         }
     }
 
     fn normalize_function_body(
         &mut self,
-        name: String,
-        arg_names: Vec<String>,
+        name: Symbol,
+        arg_names: Vec<Symbol>,
         e: &Expr,
     ) -> Result<AllocClosure> {
         let old_function_index = self.current_function_index;
         let new_function_index = self.program.functions.len();
         self.program.functions.push(Function {
-            name: name.clone(),
+            name,
             arg_names: arg_names.clone(),
             free_names: None,
             blocks: Vec::new(),
@@ -115,24 +137,26 @@ impl LetNormalizer {
         let old_block_index = self.current_block_index;
         self.current_block_index = None;
 
-        let body_address = self.normalize_block(e)?;
+        let body_address = self.normalize_block(e, true)?;
 
-        let freevars: Vec<String> = FreeVars::free_vars_function(
+        // The free names are sorted, so that closures capture their variables
+        // in the same order every time the program is compiled.
+        let mut freevars: Vec<Symbol> = FreeVars::free_vars_function(
             &self.program.functions[new_function_index].blocks,
-            &name,
+            name,
             &arg_names,
             body_address.block_index,
         )
-        .iter()
-        .map(|&x| x.to_owned())
+        .into_iter()
         .collect();
+        freevars.sort_by_key(|name| name.as_str());
 
         self.program.functions[new_function_index].free_names = Some(freevars.clone());
 
         let function = AllocClosure {
             name,
             arg_names,
-            free_names: freevars,
+            free_names: freevars.into_iter().map(VariableReference::new).collect(),
             body: body_address,
         };
 
@@ -142,122 +166,251 @@ impl LetNormalizer {
         Ok(function)
     }
 
-    fn normalize_rhs(&mut self, e: &Expr) -> Result<Definition> {
+    // Normalization recurses once per level of nesting, so expressions that
+    // are built without the parser are held to its limit here.
+    fn normalize_rhs(&mut self, e: &Expr, is_tail: bool) -> Result<Definition> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("expression nested more than {} levels deep", MAX_NESTING).into());
+        }
+        self.depth += 1;
+        let result = self.normalize_nested_rhs(e, is_tail);
+        self.depth -= 1;
+        result
+    }
+
+    // Every case is normalized in a method of its own, which keeps the stack
+    // frames of the recursion small.
+    fn normalize_nested_rhs(&mut self, e: &Expr, is_tail: bool) -> Result<Definition> {
         match e {
             Expr::Literal(c) => Ok(Definition::Step(Step::Simple(Simple::Literal(*c)))),
-            Expr::Var { var_name } => Ok(Definition::Var(VariableReference {
-                var_name: self
-                    .var_substitution
-                    .get(var_name)
-                    .expect("could not find substitution")
-                    .clone(),
-            })),
+            Expr::Var { var_name } => self.normalize_name(var_name),
             Expr::Fun {
-                name: original_name,
-                arg_names: original_arg_names,
+                name,
+                arg_names,
                 body,
-            } => {
-                let unique_name = self.fresh(original_name);
-
-                let mut arg_substitutions = Vec::new();
-                let mut unique_arg_names = Vec::new();
-                for original_arg_name in original_arg_names.iter().rev() {
-                    let unique_arg_name = self.fresh(original_arg_name);
-                    arg_substitutions.push((original_arg_name.clone(), unique_arg_name.clone()));
-                    unique_arg_names.push(unique_arg_name);
-                }
-                unique_arg_names.reverse();
-
-                let function = self.with_substitutions(arg_substitutions, |comp| {
-                    comp.with_substitution(original_name.clone(), unique_name.clone(), |comp| {
-                        comp.normalize_function_body(
-                            unique_name.clone(),
-                            unique_arg_names.clone(),
-                            body,
-                        )
-                    })
-                })?;
-
-                Ok(Definition::Step(Step::Simple(Simple::Fun(function))))
-            }
-            Expr::Call { func, args } => {
-                let fun_at = self.normalize_var(func)?;
-                let mut args_at = Vec::new();
-                for arg in args {
-                    args_at.push(self.normalize_var(arg)?);
-                }
-                Ok(Definition::Step(Step::Control(Control::Call {
-                    func: fun_at,
-                    args: args_at,
-                })))
-            }
-            Expr::BinOp { op, lhs, rhs } => {
-                let lhs_at = self.normalize_var(lhs)?;
-                let rhs_at = self.normalize_var(rhs)?;
-                Ok(Definition::Step(Step::Simple(Simple::BinOp {
-                    op: *op,
-                    lhs: lhs_at,
-                    rhs: rhs_at,
-                })))
-            }
+            } => self.normalize_fun(name, arg_names, body),
+            Expr::Call { func, args } => self.normalize_call(func, args, is_tail),
+            Expr::Delay { body } => self.normalize_delay(body, is_tail),
+            Expr::Force { thunk } => self.normalize_force(thunk, is_tail),
+            Expr::BinOp { op, lhs, rhs } => self.normalize_binop(*op, lhs, rhs),
             Expr::Let {
-                name: original_name,
+                name,
                 definition,
                 body,
-            } => {
-                let def_c = self.normalize_rhs(definition)?;
-                let unique_name = self.fresh(original_name);
-                self.emit(Instruction::Assignment(Assignment {
-                    name: unique_name.clone(),
-                    definition: def_c,
-                }));
-
-                self.with_substitution(original_name.clone(), unique_name, |comp| {
-                    comp.normalize_rhs(body)
-                })
-            }
+            } => self.normalize_let(name, definition, body, is_tail),
+            Expr::LetTuple {
+                names,
+                definition,
+                body,
+            } => self.normalize_let_tuple(names, definition, body, is_tail),
             Expr::If {
                 condition,
                 branch_success,
                 branch_failure,
-            } => {
-                let cond_at = self.normalize_var(condition)?;
-                let branch_success = self.normalize_block(branch_success)?;
-                let branch_failure = self.normalize_block(branch_failure)?;
-                Ok(Definition::Step(Step::Control(Control::If {
-                    condition: cond_at,
-                    branch_success,
-                    branch_failure,
-                })))
-            }
-            Expr::Tuple { values } => {
-                let mut args_norm = Vec::new();
-
-                for arg in values {
-                    args_norm.push(self.normalize_var(arg)?);
-                }
-
-                Ok(Definition::Step(Step::Simple(Simple::Tuple {
-                    args: args_norm,
-                })))
-            }
+            } => self.normalize_if(condition, branch_success, branch_failure, is_tail),
+            Expr::Tuple { values } => self.normalize_tuple(values),
             Expr::Set {
                 tuple,
                 index,
                 new_expr,
-            } => {
-                let tuple_at = self.normalize_var(tuple)?;
-                let new_at = self.normalize_var(new_expr)?;
-                Ok(Definition::Step(Step::Simple(Simple::Set {
-                    tuple: tuple_at,
-                    index: *index,
-                    new_value: new_at,
-                })))
-            }
+            } => self.normalize_set(tuple, *index, new_expr),
+        }
+    }
+
+    fn normalize_name(&mut self, var_name: &str) -> Result<Definition> {
+        Ok(Definition::Var(VariableReference::new(
+            *self
+                .var_substitution
+                .get(var_name)
+                .ok_or_else(|| format!("unbound variable {}", var_name))?,
+        )))
+    }
+
+    fn normalize_fun(
+        &mut self,
+        original_name: &str,
+        original_arg_names: &[String],
+        body: &Expr,
+    ) -> Result<Definition> {
+        let unique_name = self.fresh(original_name);
+
+        let mut arg_substitutions = Vec::new();
+        let mut unique_arg_names = Vec::new();
+        for original_arg_name in original_arg_names.iter().rev() {
+            let unique_arg_name = self.fresh(original_arg_name);
+            arg_substitutions.push((original_arg_name.clone(), unique_arg_name));
+            unique_arg_names.push(unique_arg_name);
+        }
+        unique_arg_names.reverse();
+
+        let function = self.with_substitutions(arg_substitutions, |comp| {
+            comp.with_substitution(original_name.to_owned(), unique_name, |comp| {
+                comp.normalize_function_body(unique_name, unique_arg_names.clone(), body)
+            })
+        })?;
+
+        Ok(Definition::Step(Step::Simple(Simple::Fun(function))))
+    }
+
+    fn normalize_call(&mut self, func: &Expr, args: &[Expr], is_tail: bool) -> Result<Definition> {
+        let fun_at = self.normalize_var(func)?;
+        let mut args_at = Vec::new();
+        for arg in args {
+            args_at.push(self.normalize_var(arg)?);
+        }
+        Ok(Self::call(fun_at, args_at, is_tail))
+    }
+
+    // The body becomes a function without arguments, which cannot refer to
+    // itself.
+    fn normalize_delay(&mut self, body: &Expr, is_tail: bool) -> Result<Definition> {
+        let delay_at = self.global("delay")?;
+
+        let unique_name = self.fresh("thunk");
+        let function = self.normalize_function_body(unique_name, vec![], body)?;
+        self.emit(Instruction::Assignment(Assignment {
+            name: unique_name,
+            definition: Definition::Step(Step::Simple(Simple::Fun(function))),
+        }));
+
+        Ok(Self::call(
+            delay_at,
+            vec![VariableReference::new(unique_name)],
+            is_tail,
+        ))
+    }
+
+    fn normalize_force(&mut self, thunk: &Expr, is_tail: bool) -> Result<Definition> {
+        let force_at = self.global("force")?;
+        let thunk_at = self.normalize_var(thunk)?;
+        Ok(Self::call(force_at, vec![thunk_at], is_tail))
+    }
+
+    fn normalize_binop(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<Definition> {
+        let lhs_at = self.normalize_var(lhs)?;
+        let rhs_at = self.normalize_var(rhs)?;
+        Ok(Definition::Step(Step::Simple(Simple::BinOp {
+            op,
+            lhs: lhs_at,
+            rhs: rhs_at,
+        })))
+    }
+
+    fn normalize_let(
+        &mut self,
+        original_name: &str,
+        definition: &Expr,
+        body: &Expr,
+        is_tail: bool,
+    ) -> Result<Definition> {
+        let def_c = self.normalize_rhs(definition, false)?;
+        let unique_name = self.fresh(original_name);
+        self.emit(Instruction::Assignment(Assignment {
+            name: unique_name,
+            definition: def_c,
+        }));
+
+        self.with_substitution(original_name.to_owned(), unique_name, |comp| {
+            comp.normalize_rhs(body, is_tail)
+        })
+    }
+
+    fn normalize_let_tuple(
+        &mut self,
+        original_names: &[String],
+        definition: &Expr,
+        body: &Expr,
+        is_tail: bool,
+    ) -> Result<Definition> {
+        let tuple_at = self.normalize_var(definition)?;
+
+        let mut substitutions = Vec::new();
+        for (index, original_name) in original_names.iter().enumerate() {
+            let index_name = self.fresh("__gen");
+            self.emit(Instruction::Assignment(Assignment {
+                name: index_name,
+                definition: Definition::Step(Step::Simple(Simple::Literal(Constant::Int {
+                    value: index as i64,
+                }))),
+            }));
+
+            let unique_name = self.fresh(original_name);
+            self.emit(Instruction::Assignment(Assignment {
+                name: unique_name,
+                definition: Definition::Step(Step::Simple(Simple::BinOp {
+                    op: BinOp::Get,
+                    lhs: tuple_at.clone(),
+                    rhs: VariableReference::new(index_name),
+                })),
+            }));
+            substitutions.push((original_name.clone(), unique_name));
         }
+        // A name that occurs more than once refers to the last field it is
+        // bound to, which has to be the innermost substitution.
+        substitutions.reverse();
+
+        self.with_substitutions(substitutions, |comp| comp.normalize_rhs(body, is_tail))
+    }
+
+    fn normalize_if(
+        &mut self,
+        condition: &Expr,
+        branch_success: &Expr,
+        branch_failure: &Expr,
+        is_tail: bool,
+    ) -> Result<Definition> {
+        let cond_at = self.normalize_var(condition)?;
+        let branch_success = self.normalize_block(branch_success, is_tail)?;
+        let branch_failure = self.normalize_block(branch_failure, is_tail)?;
+        Ok(Definition::Step(Step::Control(Control::If {
+            condition: cond_at,
+            branch_success,
+            branch_failure,
+        })))
+    }
+
+    fn normalize_tuple(&mut self, values: &[Expr]) -> Result<Definition> {
+        let mut args_norm = Vec::new();
+
+        for arg in values {
+            args_norm.push(self.normalize_var(arg)?);
+        }
+
+        Ok(Definition::Step(Step::Simple(Simple::Tuple {
+            args: args_norm,
+        })))
+    }
+
+    fn normalize_set(&mut self, tuple: &Expr, index: u32, new_expr: &Expr) -> Result<Definition> {
+        let tuple_at = self.normalize_var(tuple)?;
+        let new_at = self.normalize_var(new_expr)?;
+        Ok(Definition::Step(Step::Simple(Simple::Set {
+            tuple: tuple_at,
+            index,
+            new_value: new_at,
+        })))
     }
 
-    fn normalize_block(&mut self, e: &Expr) -> Result<TargetAddress> {
+    fn call(func: VariableReference, args: Vec<VariableReference>, is_tail: bool) -> Definition {
+        if is_tail {
+            Definition::Step(Step::Control(Control::TailCall { func, args }))
+        } else {
+            Definition::Step(Step::Control(Control::Call { func, args }))
+        }
+    }
+
+    // The natives that expressions such as `delay` are compiled to are
+    // referred to by their global name, which cannot be hidden by a local
+    // variable.
+    fn global(&self, name: &str) -> Result<VariableReference> {
+        let symbol = Symbol::intern(name);
+        if !self.globals.contains(&symbol) {
+            return Err(format!("{} requires the global {}", name, name).into());
+        }
+        Ok(VariableReference::new(symbol))
+    }
+
+    fn normalize_block(&mut self, e: &Expr, is_tail: bool) -> Result<TargetAddress> {
         let current_function_index = self
             .current_function_index
             .expect("should have active function");
@@ -268,6 +421,7 @@ impl LetNormalizer {
             .push(Block {
                 instructions: Vec::new(),
                 parent_block_index: self.current_block_index,
+                is_loop_header: false,
             });
 
         // Save the current block index so we can restore it later.
@@ -275,7 +429,7 @@ impl LetNormalizer {
         self.current_block_index = Some(new_block_index);
 
         self.emit(Instruction::EnterBlock);
-        let result = self.normalize_var(e)?;
+        let result = self.normalize_var_at(e, is_tail)?;
         self.emit(Instruction::ExitBlock(result));
 
         // Restore the old current block index
@@ -288,13 +442,145 @@ impl LetNormalizer {
         })
     }
 
-    fn normalize_program(mut self, e: &Expr) -> Result<Program> {
-        self.normalize_function_body("toplevel".to_owned(), vec![], e)?;
+    // Globals are free variables of the toplevel function. They keep their
+    // name, so that the evaluator can look up what they are bound to.
+    fn normalize_program(
+        mut self,
+        e: &Expr,
+        globals: &[String],
+        open_modules: &[&CompiledModule],
+    ) -> Result<Program> {
+        self.bind_globals(globals);
+        for module in open_modules {
+            for export in &module.exports {
+                self.var_substitution.insert(
+                    export.clone(),
+                    Symbol::intern(&qualified_name(&module.name, export)),
+                );
+            }
+        }
+
+        self.normalize_function_body(Symbol::intern("toplevel"), vec![], e)?;
+        Ok(self.program)
+    }
+
+    fn bind_globals(&mut self, globals: &[String]) {
+        for global in globals {
+            self.var_substitution
+                .insert(global.clone(), Symbol::intern(global));
+            self.globals.push(Symbol::intern(global));
+        }
+    }
+
+    // The toplevel function of a module returns the value of its body, or a
+    // tuple of its definitions if it has none. The definitions of imported
+    // modules are free variables of the toplevel function, named after the
+    // module that defines them, which the linker binds.
+    fn normalize_module(
+        mut self,
+        module: &Module,
+        interfaces: &HashMap<String, Vec<String>>,
+        globals: &[String],
+    ) -> Result<Program> {
+        self.bind_globals(globals);
+        self.module_name = Some(module.name.clone());
+
+        let mut aliases = HashSet::new();
+        for import in &module.imports {
+            if !aliases.insert(&import.alias) {
+                return Err(format!(
+                    "module {} imports {} more than once",
+                    module.name, import.alias
+                )
+                .into());
+            }
+            let exports = interfaces.get(&import.module).ok_or_else(|| {
+                format!(
+                    "module {} imports unknown module {}",
+                    module.name, import.module
+                )
+            })?;
+            for export in exports {
+                self.var_substitution.insert(
+                    format!("{}.{}", import.alias, export),
+                    Symbol::intern(&qualified_name(&import.module, export)),
+                );
+            }
+        }
+
+        let mut e = match &module.body {
+            Some(body) => body.clone(),
+            None => Expr::Tuple {
+                values: module
+                    .definitions
+                    .iter()
+                    .map(|(name, _)| Expr::Var {
+                        var_name: name.clone(),
+                    })
+                    .collect(),
+            },
+        };
+        for (name, definition) in module.definitions.iter().rev() {
+            e = Expr::Let {
+                name: name.clone(),
+                definition: Box::new(definition.clone()),
+                body: Box::new(e),
+            };
+        }
+
+        self.normalize_function_body(Symbol::intern(&module.name), vec![], &e)?;
         Ok(self.program)
     }
 }
 
 pub fn let_normalize(e: &Expr) -> Result<Program> {
+    let_normalize_with_globals(e, &[])
+}
+
+pub fn let_normalize_with_globals(e: &Expr, globals: &[String]) -> Result<Program> {
+    let_normalize_with_open_modules(e, globals, &[])
+}
+
+// The definitions of the open modules can be used without qualifying them,
+// like globals, but they are free variables named after the module that
+// defines them, like the imports of a module. They take precedence over
+// globals with the same name.
+pub fn let_normalize_with_open_modules(
+    e: &Expr,
+    globals: &[String],
+    open_modules: &[&CompiledModule],
+) -> Result<Program> {
+    let normalizer = LetNormalizer::new();
+    normalizer.normalize_program(e, globals, open_modules)
+}
+
+// The names that a module defines, in the order in which its toplevel function
+// returns them.
+pub fn module_interface(module: &Module) -> Result<Vec<String>> {
+    let mut exports: Vec<String> = Vec::new();
+    for (name, _) in &module.definitions {
+        if exports.contains(name) {
+            return Err(format!("module {} defines {} more than once", module.name, name).into());
+        }
+        exports.push(name.clone());
+    }
+    Ok(exports)
+}
+
+// Compiles a single module. The interfaces of the modules that it imports are
+// looked up by module name.
+pub fn let_normalize_module(
+    module: &Module,
+    interfaces: &HashMap<String, Vec<String>>,
+    globals: &[String],
+) -> Result<CompiledModule> {
+    let exports = module_interface(module)?;
     let normalizer = LetNormalizer::new();
-    normalizer.normalize_program(e)
+    let program = normalizer.normalize_module(module, interfaces, globals)?;
+    Ok(CompiledModule {
+        name: module.name.clone(),
+        imports: module.imports.iter().map(|i| i.module.clone()).collect(),
+        exports,
+        program,
+    })
 }
@@ -0,0 +1,290 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Assignment, Control, Definition, Function, Instruction, Program, Simple, Step,
+    TargetAddress, VariableReference,
+};
+use crate::ir_let::passes::manager::DumpPoint;
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+use std::fmt;
+
+// The number of unchanged lines shown around every change.
+const CONTEXT: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDiff {
+    pub name: Symbol,
+    pub lines: Vec<DiffLine>,
+}
+
+// The differences between two versions of a program, such as the program
+// before and after a pass, for the functions that changed. Passes renumber
+// blocks and functions, so the functions are matched by name and blocks are
+// labelled by how they are entered: the first block of a function is its
+// entry, and the branches of an if are named after the variable the if
+// assigns. Variable names are unique and kept by the passes, so the labels
+// are the same in both versions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramDiff {
+    pub functions: Vec<FunctionDiff>,
+}
+
+impl ProgramDiff {
+    pub fn new(before: &Program, after: &Program) -> Self {
+        let before_lines: HashMap<Symbol, Vec<String>> = before
+            .functions
+            .iter()
+            .map(|function| (function.name, labelled_lines(before, function)))
+            .collect();
+
+        let mut functions = Vec::new();
+        for function in &after.functions {
+            let after_lines = labelled_lines(after, function);
+            let lines = match before_lines.get(&function.name) {
+                Some(before_lines) => diff_lines(before_lines, &after_lines),
+                None => after_lines.into_iter().map(DiffLine::Added).collect(),
+            };
+            if lines.iter().any(|line| !matches!(line, DiffLine::Same(_))) {
+                functions.push(FunctionDiff {
+                    name: function.name,
+                    lines,
+                });
+            }
+        }
+
+        for function in &before.functions {
+            if !after.functions.iter().any(|f| f.name == function.name) {
+                functions.push(FunctionDiff {
+                    name: function.name,
+                    lines: before_lines[&function.name]
+                        .iter()
+                        .cloned()
+                        .map(DiffLine::Removed)
+                        .collect(),
+                });
+            }
+        }
+
+        ProgramDiff { functions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+impl fmt::Display for ProgramDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for function in &self.functions {
+            writeln!(f, "@@ function {}", function.name)?;
+
+            let changed: Vec<bool> = function
+                .lines
+                .iter()
+                .map(|line| !matches!(line, DiffLine::Same(_)))
+                .collect();
+            let mut skipped = false;
+            for (i, line) in function.lines.iter().enumerate() {
+                let start = i.saturating_sub(CONTEXT);
+                let end = (i + CONTEXT + 1).min(changed.len());
+                if !changed[start..end].iter().any(|c| *c) {
+                    skipped = true;
+                    continue;
+                }
+                if skipped {
+                    writeln!(f, "  ...")?;
+                    skipped = false;
+                }
+
+                match line {
+                    DiffLine::Same(text) => writeln!(f, "  {}", text)?,
+                    DiffLine::Removed(text) => writeln!(f, "- {}", text)?,
+                    DiffLine::Added(text) => writeln!(f, "+ {}", text)?,
+                }
+            }
+            if skipped {
+                writeln!(f, "  ...")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The longest common subsequence of the lines is kept, and the other lines
+// are removed or added.
+fn diff_lines(before: &[String], after: &[String]) -> Vec<DiffLine> {
+    // common[i][j] is the length of the longest common subsequence of
+    // before[i..] and after[j..].
+    let mut common = vec![vec![0; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push(DiffLine::Same(before[i].clone()));
+            i += 1;
+            j += 1;
+        } else if i < before.len() && (j == after.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(before[i].clone()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(after[j].clone()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+fn args(args: &[VariableReference]) -> String {
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    args.join(", ")
+}
+
+// The function, with addresses replaced by the labels of the blocks they
+// refer to and function indices by function names.
+fn labelled_lines(program: &Program, function: &Function) -> Vec<String> {
+    let mut labels = vec![None; function.blocks.len()];
+    labels[0] = Some("entry".to_owned());
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            if let Instruction::Assignment(Assignment {
+                name,
+                definition:
+                    Definition::Step(Step::Control(Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    })),
+            }) = instruction
+            {
+                labels[branch_success.block_index] = Some(format!("{}.then", name));
+                labels[branch_failure.block_index] = Some(format!("{}.else", name));
+            }
+        }
+    }
+
+    let block_label = |block_index: usize| {
+        labels[block_index]
+            .clone()
+            .unwrap_or_else(|| format!("block{}", block_index))
+    };
+    // Branches and jumps stay within the function.
+    let label = |address: &TargetAddress| {
+        if address.instruction_index == 0 {
+            block_label(address.block_index)
+        } else {
+            format!(
+                "{}+{}",
+                block_label(address.block_index),
+                address.instruction_index
+            )
+        }
+    };
+    let function_name = |function_index: usize| program.functions[function_index].name;
+
+    let mut arguments = function
+        .arg_names
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Some(free_names) = function.free_names.as_ref().filter(|n| !n.is_empty()) {
+        let free_names: Vec<String> = free_names.iter().map(|n| n.to_string()).collect();
+        arguments = format!("{}; {}", arguments, free_names.join(", "));
+    }
+    let mut lines = vec![format!("function {}({})", function.name, arguments)];
+
+    for (block_index, block) in function.blocks.iter().enumerate() {
+        let mut header = format!("block {}", block_label(block_index));
+        if block.is_loop_header {
+            header.push_str(" (loop header)");
+        }
+        lines.push(header);
+
+        for instruction in &block.instructions {
+            let text = match instruction {
+                Instruction::EnterBlock => continue,
+                Instruction::ExitBlock(_) => instruction.to_string(),
+                Instruction::Assignment(Assignment { name, definition }) => {
+                    let definition = match definition {
+                        Definition::Step(Step::Simple(Simple::Fun(AllocClosure {
+                            free_names,
+                            body,
+                            ..
+                        }))) => format!(
+                            "closure {}({})",
+                            function_name(body.function_index),
+                            args(free_names)
+                        ),
+                        Definition::Step(Step::Simple(Simple::LiftedFun {
+                            function_index,
+                            ..
+                        })) => format!("lifted {}", function_name(*function_index)),
+                        Definition::Step(Step::Control(Control::CallDirect {
+                            func,
+                            function_index,
+                            args: call_args,
+                        })) => format!(
+                            "calldirect {} {}({})",
+                            func,
+                            function_name(*function_index),
+                            args(call_args)
+                        ),
+                        Definition::Step(Step::Control(Control::If {
+                            condition,
+                            branch_success,
+                            branch_failure,
+                        })) => format!(
+                            "if {} then {} else {}",
+                            condition,
+                            label(branch_success),
+                            label(branch_failure)
+                        ),
+                        Definition::Step(Step::Control(Control::Jump {
+                            target,
+                            args: jump_args,
+                        })) => format!("jump {}({})", label(target), args(jump_args)),
+                        _ => definition.to_string(),
+                    };
+                    format!("{} = {}", name, definition)
+                }
+            };
+            lines.push(format!("    {}", text));
+        }
+    }
+    lines
+}
+
+// A hook for the pass manager that prints what every pass changed.
+pub fn print_diffs() -> impl FnMut(&str, DumpPoint, &Program) {
+    let mut before = None;
+    move |pass_name, point, program| match point {
+        DumpPoint::BeforePass => before = Some(program.clone()),
+        DumpPoint::AfterPass => {
+            let before = before.take().expect("pass should have started");
+            let diff = ProgramDiff::new(&before, program);
+            if diff.is_empty() {
+                println!("; {} made no changes", pass_name);
+            } else {
+                println!("; changes by {}", pass_name);
+                print!("{}", diff);
+            }
+        }
+    }
+}
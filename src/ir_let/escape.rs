@@ -0,0 +1,180 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Assignment, Block, Control, Definition, Instruction, Program, Simple, Step,
+    VariableReference,
+};
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+
+// The result of escape analysis: the tuples and closures (identified by the
+// variable they are assigned to) that never outlive the call of the function
+// that allocates them, and could therefore be allocated on its stack frame.
+#[derive(Debug, Clone, Default)]
+pub struct EscapeInfo {
+    pub non_escaping: HashSet<Symbol>,
+}
+
+impl EscapeInfo {
+    pub fn is_non_escaping(&self, name: Symbol) -> bool {
+        self.non_escaping.contains(&name)
+    }
+}
+
+// Variables that may refer to the same value are grouped together using a
+// union-find structure. A value escapes if any variable in its group is
+// returned from a function, passed to a call, stored in a tuple or captured by
+// a closure. Since variable names are unique in a program, a single structure
+// is used for the whole program.
+struct EscapeAnalysis {
+    parents: HashMap<Symbol, Symbol>,
+    escaping: HashSet<Symbol>,
+    allocations: Vec<Symbol>,
+}
+
+impl EscapeAnalysis {
+    fn new() -> Self {
+        EscapeAnalysis {
+            parents: HashMap::new(),
+            escaping: HashSet::new(),
+            allocations: Vec::new(),
+        }
+    }
+
+    fn find(&mut self, name: Symbol) -> Symbol {
+        let mut root = name;
+        while let Some(parent) = self.parents.get(&root) {
+            root = *parent;
+        }
+
+        // Point every variable on the path directly at the root.
+        let mut current = name;
+        while current != root {
+            current = self
+                .parents
+                .insert(current, root)
+                .expect("variable on the path should have a parent");
+        }
+
+        root
+    }
+
+    fn union(&mut self, a: Symbol, b: Symbol) {
+        let root_a = self.find(a);
+        let root_b = self.find(b);
+
+        if root_a != root_b {
+            self.parents.insert(root_a, root_b);
+        }
+    }
+
+    fn escape(&mut self, var: &VariableReference) {
+        self.escaping.insert(var.var_name);
+    }
+
+    fn analyze_program(&mut self, program: &Program) {
+        for function in &program.functions {
+            for block in &function.blocks {
+                self.analyze_block(block, &function.blocks);
+            }
+
+            // Returned from the function.
+            if let Some(result) = exit_var(&function.blocks[0]) {
+                self.escape(result);
+            }
+        }
+    }
+
+    fn analyze_block(&mut self, block: &Block, blocks: &[Block]) {
+        for instruction in &block.instructions {
+            let Instruction::Assignment(Assignment { name, definition }) = instruction else {
+                continue;
+            };
+
+            match definition {
+                Definition::Var(var) => self.union(*name, var.var_name),
+                Definition::Step(Step::Simple(simple)) => match simple {
+                    // Lifted closures are not allocated by the function.
+                    Simple::Literal(_) | Simple::LiftedFun { .. } | Simple::BinOp { .. } => {}
+                    Simple::Fun(AllocClosure {
+                        name: function_name,
+                        free_names,
+                        ..
+                    }) => {
+                        self.allocations.push(*name);
+                        // The closure refers to itself by its function name.
+                        self.union(*name, *function_name);
+                        self.escaping.extend(free_names.iter().map(|v| v.var_name));
+                    }
+                    Simple::Tuple { args } => {
+                        self.allocations.push(*name);
+                        for arg in args {
+                            self.escape(arg);
+                        }
+                    }
+                    // The tuple can be the reused one.
+                    Simple::ReuseTuple { reused, args } => {
+                        self.allocations.push(*name);
+                        self.union(*name, reused.var_name);
+                        for arg in args {
+                            self.escape(arg);
+                        }
+                    }
+                    Simple::Set { new_value, .. } => self.escape(new_value),
+                },
+                Definition::Step(Step::Control(control)) => match control {
+                    Control::Call { args, .. }
+                    | Control::TailCall { args, .. }
+                    | Control::CallDirect { args, .. }
+                    | Control::Jump { args, .. } => {
+                        for arg in args {
+                            self.escape(arg);
+                        }
+                    }
+                    Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    } => {
+                        for address in [branch_success, branch_failure] {
+                            if let Some(result) = exit_var(&blocks[address.block_index]) {
+                                self.union(*name, result.var_name);
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    fn finish(mut self) -> EscapeInfo {
+        let escaping_roots: HashSet<Symbol> = self
+            .escaping
+            .clone()
+            .into_iter()
+            .map(|name| self.find(name))
+            .collect();
+
+        let allocations = std::mem::take(&mut self.allocations);
+        EscapeInfo {
+            non_escaping: allocations
+                .into_iter()
+                .filter(|name| !escaping_roots.contains(&self.find(*name)))
+                .collect(),
+        }
+    }
+}
+
+fn exit_var(block: &Block) -> Option<&VariableReference> {
+    block
+        .instructions
+        .iter()
+        .find_map(|instruction| match instruction {
+            Instruction::ExitBlock(var) => Some(var),
+            _ => None,
+        })
+}
+
+pub fn analyze_escapes(program: &Program) -> EscapeInfo {
+    let mut analysis = EscapeAnalysis::new();
+    analysis.analyze_program(program);
+    analysis.finish()
+}
@@ -0,0 +1,31 @@
+use crate::ir_let::let_expr::TargetAddress;
+use crate::symbol::Symbol;
+use std::fmt;
+
+// A function on the call stack, together with the instruction it is executing
+// or will return to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackEntry {
+    pub function_name: Symbol,
+    pub address: TargetAddress,
+}
+
+// The call stack at the point where the program failed, the outermost function
+// first. It is written the other way around, like a Rust backtrace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backtrace {
+    pub entries: Vec<StackEntry>,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, entry) in self.entries.iter().rev().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{} at {}", entry.function_name, entry.address)?;
+        }
+
+        Ok(())
+    }
+}
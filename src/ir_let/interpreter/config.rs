@@ -0,0 +1,104 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use std::collections::HashSet;
+
+// What happens when the result of integer arithmetic does not fit in an i64.
+// This does not depend on whether the evaluator was built in debug or release
+// mode.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum OverflowMode {
+    #[default]
+    Wrapping,
+    Saturating,
+    // The evaluation fails with `RuntimeError::Overflow`.
+    Checked,
+}
+
+impl OverflowMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wrap" => Some(OverflowMode::Wrapping),
+            "saturate" => Some(OverflowMode::Saturating),
+            "check" => Some(OverflowMode::Checked),
+            _ => None,
+        }
+    }
+
+    pub fn add(self, lhs: i64, rhs: i64) -> Result<i64, RuntimeError> {
+        match self {
+            OverflowMode::Wrapping => Ok(lhs.wrapping_add(rhs)),
+            OverflowMode::Saturating => Ok(lhs.saturating_add(rhs)),
+            OverflowMode::Checked => lhs.checked_add(rhs).ok_or(RuntimeError::Overflow),
+        }
+    }
+
+    pub fn sub(self, lhs: i64, rhs: i64) -> Result<i64, RuntimeError> {
+        match self {
+            OverflowMode::Wrapping => Ok(lhs.wrapping_sub(rhs)),
+            OverflowMode::Saturating => Ok(lhs.saturating_sub(rhs)),
+            OverflowMode::Checked => lhs.checked_sub(rhs).ok_or(RuntimeError::Overflow),
+        }
+    }
+}
+
+// Limits on the resources a program may use. Exceeding a limit makes the
+// evaluation fail with a `RuntimeError` instead of exhausting host memory.
+// A limit of None means that the resource is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct EvaluatorConfig {
+    // The maximum number of simultaneously live heap objects.
+    pub max_heap_objects: Option<usize>,
+    // The maximum number of nested calls. Tail calls and loops do not count,
+    // since they reuse the frame of the caller.
+    pub max_call_depth: Option<usize>,
+    // The maximum number of instructions to execute, including those of
+    // functions that natives call, and the work that natives do on the heap.
+    // Running out fails with `RuntimeError::OutOfFuel`.
+    pub max_instructions: Option<u64>,
+    // The only natives that the program may be bound to. Binding any other
+    // fails with `RuntimeError::NativeNotAllowed`.
+    pub allowed_natives: Option<HashSet<String>>,
+    pub overflow: OverflowMode,
+    // Buffers the reference count updates of stack slots and applies them at
+    // block exits and calls (`interpreter::deferred`).
+    pub deferred_refcounts: bool,
+}
+
+// The limits for running an untrusted program, in one place. Unlike in
+// `EvaluatorConfig`, every limit has to be given, so nothing is unlimited by
+// accident. Natives are held to the same heap limit, and pay fuel for the
+// objects they touch (see `NativeHeap`). Work that does not touch the heap is
+// free, so allowed natives should not do much of it.
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    pub fuel: u64,
+    pub max_heap_objects: usize,
+    pub max_call_depth: usize,
+    pub allowed_natives: HashSet<String>,
+}
+
+impl SandboxConfig {
+    // No natives are allowed until they are added with `allow`.
+    pub fn new(fuel: u64, max_heap_objects: usize, max_call_depth: usize) -> Self {
+        SandboxConfig {
+            fuel,
+            max_heap_objects,
+            max_call_depth,
+            allowed_natives: HashSet::new(),
+        }
+    }
+
+    pub fn allow(mut self, native: &str) -> Self {
+        self.allowed_natives.insert(native.to_owned());
+        self
+    }
+
+    pub fn evaluator_config(&self) -> EvaluatorConfig {
+        EvaluatorConfig {
+            max_heap_objects: Some(self.max_heap_objects),
+            max_call_depth: Some(self.max_call_depth),
+            max_instructions: Some(self.fuel),
+            allowed_natives: Some(self.allowed_natives.clone()),
+            ..EvaluatorConfig::default()
+        }
+    }
+}
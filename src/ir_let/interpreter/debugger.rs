@@ -0,0 +1,120 @@
+use crate::ir_let::interpreter::backtrace::StackEntry;
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{Heap, RefCountHeap};
+use crate::ir_let::interpreter::heap_value::Value;
+use crate::ir_let::interpreter::simple_eval::{ProgramEvaluator, StepOutcome};
+use crate::ir_let::let_expr::TargetAddress;
+use crate::symbol::Symbol;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    Address(TargetAddress),
+    // Stops on entry to every function with the given name. The compiler
+    // makes function names unique by adding a suffix, so both the name from
+    // the source program and the unique name match.
+    Function(String),
+}
+
+fn matches_function(function_name: Symbol, name: &str) -> bool {
+    function_name.as_str() == name || function_name.source_name() == name
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(TargetAddress),
+    Finished(Value),
+}
+
+#[derive(Debug)]
+pub struct Debugger<H: Heap = RefCountHeap> {
+    evaluator: ProgramEvaluator<H>,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl<H: Heap> Debugger<H> {
+    pub fn new(evaluator: ProgramEvaluator<H>) -> Self {
+        Debugger {
+            evaluator,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn evaluator(&self) -> &ProgramEvaluator<H> {
+        &self.evaluator
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    // Returns whether the breakpoint had been set.
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    fn is_at_breakpoint(&self) -> bool {
+        let address = self.evaluator.program_counter();
+        let function = &self.evaluator.program().functions[address.function_index];
+        let at_entry = address.block_index == 0 && address.instruction_index == 0;
+
+        self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Address(target) => *target == address,
+            Breakpoint::Function(name) => at_entry && matches_function(function.name, name),
+        })
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome, RuntimeError> {
+        self.evaluator.step()
+    }
+
+    // Always executes at least one instruction, so that continuing from a
+    // breakpoint does not stop at the same breakpoint again.
+    pub fn continue_execution(&mut self) -> Result<StopReason, RuntimeError> {
+        loop {
+            if let StepOutcome::Finished(result) = self.evaluator.step()? {
+                return Ok(StopReason::Finished(result));
+            }
+
+            if self.is_at_breakpoint() {
+                return Ok(StopReason::Breakpoint(self.evaluator.program_counter()));
+            }
+        }
+    }
+
+    // The locals of the innermost block that have been assigned so far,
+    // in the order of their assignment.
+    pub fn locals(&self) -> Vec<(Symbol, Value)> {
+        if self.evaluator.is_finished() {
+            return Vec::new();
+        }
+
+        let address = self.evaluator.program_counter();
+        let block =
+            &self.evaluator.program().functions[address.function_index].blocks[address.block_index];
+        let frame = self.evaluator.stack().frames().last();
+        let values = frame
+            .and_then(|frame| frame.blocks().last())
+            .map_or(&[][..], |block_frame| &block_frame.values);
+
+        block
+            .block_names()
+            .into_iter()
+            .zip(values.iter().copied())
+            .collect()
+    }
+
+    // The functions on the call stack, the toplevel function first. Every
+    // function except for the innermost one is at the instruction it returns
+    // to.
+    pub fn call_stack(&self) -> Vec<StackEntry> {
+        self.evaluator.call_stack()
+    }
+}
@@ -0,0 +1,124 @@
+use crate::ir_let::interpreter::heap_value::HeapAddress;
+use std::fmt;
+
+// Reconciles early once this many updates are waiting, so that loops that
+// never reach a safe point do not buffer without bound.
+const MAX_PENDING: usize = 256;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeferredStats {
+    // The updates for stack slots that were buffered instead of applied.
+    pub deferred: usize,
+    // The buffered increments that cancelled against a buffered decrement of
+    // the same object, so that neither touched the heap.
+    pub cancelled: usize,
+    pub reconciliations: usize,
+}
+
+impl fmt::Display for DeferredStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "deferred refcounts: {} deferred, {} pairs cancelled, {} reconciliations",
+            self.deferred, self.cancelled, self.reconciliations
+        )
+    }
+}
+
+// The updates to apply at a safe point, see `DeferredRefCounts::take`.
+pub struct Reconciliation {
+    pub increments: Vec<HeapAddress>,
+    pub decrements: Vec<HeapAddress>,
+    pub cancelled: Vec<HeapAddress>,
+}
+
+// The reference count updates caused by writing values into stack slots and
+// releasing them again, which are buffered until the evaluator reaches a safe
+// point. A local that is assigned and released between two safe points, such
+// as a temporary in a block without calls, then never touches its object.
+#[derive(Debug, Default)]
+pub struct DeferredRefCounts {
+    increments: Vec<HeapAddress>,
+    decrements: Vec<HeapAddress>,
+    stats: DeferredStats,
+}
+
+impl DeferredRefCounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn retain(&mut self, address: HeapAddress) {
+        self.stats.deferred += 1;
+        self.increments.push(address);
+    }
+
+    pub fn release(&mut self, address: HeapAddress) {
+        self.stats.deferred += 1;
+        self.decrements.push(address);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.increments.is_empty() && self.decrements.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.increments.len() + self.decrements.len() >= MAX_PENDING
+    }
+
+    pub fn stats(&self) -> &DeferredStats {
+        &self.stats
+    }
+
+    // The updates that still have to be applied, with the increments and
+    // decrements of the same object cancelled against each other. All of the
+    // increments have to be applied before any of the decrements, since an
+    // object may only be referenced by a slot whose increment is pending.
+    // An object whose updates cancelled may not be referenced by anything
+    // else, such as a temporary tuple, so the evaluator has to check the
+    // objects in the last list once the others have been applied.
+    pub fn take(&mut self) -> Reconciliation {
+        self.stats.reconciliations += 1;
+        let mut increments = std::mem::take(&mut self.increments);
+        let mut decrements = std::mem::take(&mut self.decrements);
+        if increments.is_empty() || decrements.is_empty() {
+            return Reconciliation {
+                increments,
+                decrements,
+                cancelled: Vec::new(),
+            };
+        }
+
+        let key = |address: &HeapAddress| (address.index, address.generation);
+        increments.sort_unstable_by_key(key);
+        decrements.sort_unstable_by_key(key);
+
+        let mut remaining_increments = Vec::new();
+        let mut remaining_decrements = Vec::new();
+        let mut cancelled = Vec::new();
+        let mut decrement_iter = decrements.into_iter().peekable();
+        for increment in increments {
+            while let Some(decrement) =
+                decrement_iter.next_if(|decrement| key(decrement) < key(&increment))
+            {
+                remaining_decrements.push(decrement);
+            }
+            if decrement_iter
+                .next_if(|decrement| key(decrement) == key(&increment))
+                .is_some()
+            {
+                self.stats.cancelled += 1;
+                cancelled.push(increment);
+            } else {
+                remaining_increments.push(increment);
+            }
+        }
+        remaining_decrements.extend(decrement_iter);
+
+        Reconciliation {
+            increments: remaining_increments,
+            decrements: remaining_decrements,
+            cancelled,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt;
+
+// Failures of the program being evaluated, which an embedder can handle.
+// Violations of the invariants of the evaluator itself still panic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    // An allocation would have exceeded the maximum number of live heap
+    // objects. The failing instruction has no effect on the heap.
+    OutOfMemory,
+    // A call would have exceeded the maximum call depth.
+    StackOverflow,
+    // More instructions would have been executed than the fuel allows.
+    OutOfFuel,
+    TypeMismatch { expected: &'static str },
+    ArityMismatch { expected: usize, actual: usize },
+    IndexOutOfRange { index: i64, size: usize },
+    // A field of a tuple was set after the tuple was frozen.
+    FrozenTuple,
+    // Integer arithmetic overflowed with `OverflowMode::Checked`.
+    Overflow,
+    UnboundVariable(String),
+    // A global was bound to a native that the configuration does not allow.
+    NativeNotAllowed(String),
+    // A native function failed for a reason of its own.
+    Native(String),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::OutOfMemory => write!(f, "out of memory")?,
+            RuntimeError::StackOverflow => write!(f, "stack overflow")?,
+            RuntimeError::OutOfFuel => write!(f, "out of fuel")?,
+            RuntimeError::TypeMismatch { expected } => write!(f, "expected {}", expected)?,
+            RuntimeError::ArityMismatch { expected, actual } => write!(
+                f,
+                "incorrect number of arguments: expected {}, got {}",
+                expected, actual
+            )?,
+            RuntimeError::IndexOutOfRange { index, size } => {
+                write!(f, "index {} out of range for tuple of size {}", index, size)?
+            }
+            RuntimeError::FrozenTuple => write!(f, "cannot set a field of a frozen tuple")?,
+            RuntimeError::Overflow => write!(f, "integer overflow")?,
+            RuntimeError::UnboundVariable(name) => write!(f, "unbound variable {}", name)?,
+            RuntimeError::NativeNotAllowed(name) => write!(f, "native {} is not allowed", name)?,
+            RuntimeError::Native(message) => write!(f, "{}", message)?,
+        };
+
+        Ok(())
+    }
+}
+
+impl Error for RuntimeError {}
@@ -0,0 +1,71 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{Heap, HeapObject, HeapStats};
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue};
+
+// A heap that never frees anything. Useful as a baseline when measuring the
+// overhead of the other memory management strategies.
+#[derive(Debug)]
+pub struct ArenaHeap {
+    memory: Vec<HeapValue>,
+    stats: HeapStats,
+}
+
+impl ArenaHeap {
+    pub fn new() -> Self {
+        ArenaHeap {
+            memory: Vec::new(),
+            stats: HeapStats::default(),
+        }
+    }
+}
+
+impl Default for ArenaHeap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap for ArenaHeap {
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError> {
+        self.stats.record_alloc(&heap_value);
+        let address = HeapAddress::new(self.memory.len() as u32);
+        self.memory.push(heap_value);
+        Ok(address)
+    }
+
+    fn deref(&self, heap_address: HeapAddress) -> &HeapValue {
+        self.memory
+            .get(heap_address.index as usize)
+            .expect("invalid pointer")
+    }
+
+    fn deref_mut(&mut self, heap_address: HeapAddress) -> &mut HeapValue {
+        self.memory
+            .get_mut(heap_address.index as usize)
+            .expect("invalid pointer")
+    }
+
+    fn is_live(&self, heap_address: HeapAddress) -> bool {
+        (heap_address.index as usize) < self.memory.len()
+    }
+
+    fn inc_refcount(&mut self, _heap_address: HeapAddress) {}
+
+    fn dec_refcount(&mut self, _heap_address: HeapAddress, _on_free: &mut dyn FnMut(HeapAddress)) {}
+
+    fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+
+    fn objects(&self) -> Vec<HeapObject<'_>> {
+        self.memory
+            .iter()
+            .enumerate()
+            .map(|(index, heap_value)| HeapObject {
+                address: HeapAddress::new(index as u32),
+                refcount: None,
+                heap_value,
+            })
+            .collect()
+    }
+}
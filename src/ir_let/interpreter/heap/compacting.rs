@@ -0,0 +1,200 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{Heap, HeapObject, HeapStats};
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue};
+
+const INITIAL_COLLECTION_THRESHOLD: usize = 1024;
+
+#[derive(Debug)]
+struct Handle {
+    generation: u32,
+    // The position of the object in the storage, or None once it is freed.
+    position: Option<u32>,
+}
+
+// A mark-compact collector. Addresses are handles into a table that holds the
+// current position of every object, so objects can be moved without updating
+// the values that refer to them: the evaluator, the fields of tuples and weak
+// references only ever see the handle. A collection marks everything
+// reachable from the roots and slides the live objects towards the start of
+// the storage in allocation order, so the storage has no holes afterwards.
+// Reference counting operations are ignored, like in `MarkSweepHeap`.
+#[derive(Debug)]
+pub struct CompactingHeap {
+    handles: Vec<Handle>,
+    free_handles: Vec<u32>,
+    // The objects together with the index of their handle, which is updated
+    // when an object moves.
+    storage: Vec<(u32, HeapValue)>,
+    collection_threshold: usize,
+    moved_objects: usize,
+    stats: HeapStats,
+}
+
+impl CompactingHeap {
+    pub fn new() -> Self {
+        CompactingHeap {
+            handles: Vec::new(),
+            free_handles: Vec::new(),
+            storage: Vec::new(),
+            collection_threshold: INITIAL_COLLECTION_THRESHOLD,
+            moved_objects: 0,
+            stats: HeapStats::default(),
+        }
+    }
+
+    // The number of times an object was moved to another position by a
+    // collection.
+    pub fn moved_objects(&self) -> usize {
+        self.moved_objects
+    }
+
+    // Where the object currently is in the storage. Only useful to observe
+    // that objects move, since the address stays the same.
+    pub fn position(&self, heap_address: HeapAddress) -> usize {
+        self.position_of(heap_address) as usize
+    }
+
+    fn position_of(&self, heap_address: HeapAddress) -> u32 {
+        let handle = self
+            .handles
+            .get(heap_address.index as usize)
+            .expect("invalid pointer");
+        assert!(
+            handle.generation == heap_address.generation,
+            "dangling pointer"
+        );
+        handle.position.expect("dangling pointer")
+    }
+
+    // Marks by position in the storage, so that compacting can walk the marks
+    // in order.
+    fn mark(&self, roots: &mut dyn Iterator<Item = HeapAddress>) -> Vec<bool> {
+        let mut marked = vec![false; self.storage.len()];
+        let mut worklist: Vec<HeapAddress> = roots.collect();
+
+        while let Some(address) = worklist.pop() {
+            let position = self.position_of(address) as usize;
+            if !marked[position] {
+                marked[position] = true;
+                worklist.extend(self.storage[position].1.references());
+            }
+        }
+
+        marked
+    }
+}
+
+impl Default for CompactingHeap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap for CompactingHeap {
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError> {
+        self.stats.record_alloc(&heap_value);
+        let position = u32::try_from(self.storage.len()).expect("heap address space exhausted");
+
+        let index = match self.free_handles.pop() {
+            Some(index) => index,
+            None => {
+                self.handles.push(Handle {
+                    generation: 0,
+                    position: None,
+                });
+                u32::try_from(self.handles.len() - 1).expect("heap address space exhausted")
+            }
+        };
+
+        let handle = &mut self.handles[index as usize];
+        handle.position = Some(position);
+        self.storage.push((index, heap_value));
+
+        Ok(HeapAddress {
+            index,
+            generation: handle.generation,
+        })
+    }
+
+    fn deref(&self, heap_address: HeapAddress) -> &HeapValue {
+        &self.storage[self.position_of(heap_address) as usize].1
+    }
+
+    fn deref_mut(&mut self, heap_address: HeapAddress) -> &mut HeapValue {
+        let position = self.position_of(heap_address) as usize;
+        &mut self.storage[position].1
+    }
+
+    fn is_live(&self, heap_address: HeapAddress) -> bool {
+        self.handles
+            .get(heap_address.index as usize)
+            .is_some_and(|handle| {
+                handle.generation == heap_address.generation && handle.position.is_some()
+            })
+    }
+
+    fn inc_refcount(&mut self, _heap_address: HeapAddress) {}
+
+    fn dec_refcount(&mut self, _heap_address: HeapAddress, _on_free: &mut dyn FnMut(HeapAddress)) {}
+
+    fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+
+    fn objects(&self) -> Vec<HeapObject<'_>> {
+        self.storage
+            .iter()
+            .map(|(index, heap_value)| HeapObject {
+                address: HeapAddress {
+                    index: *index,
+                    generation: self.handles[*index as usize].generation,
+                },
+                refcount: None,
+                heap_value,
+            })
+            .collect()
+    }
+
+    fn wants_collection(&self) -> bool {
+        self.storage.len() >= self.collection_threshold
+    }
+
+    fn collect(
+        &mut self,
+        roots: &mut dyn Iterator<Item = HeapAddress>,
+        on_free: &mut dyn FnMut(HeapAddress),
+    ) {
+        let marked = self.mark(roots);
+
+        // Live objects slide down over the dead ones, which end up after them
+        // and are dropped all at once.
+        let mut next_position = 0;
+        for (position, is_marked) in marked.into_iter().enumerate() {
+            let (index, heap_value) = &self.storage[position];
+            let handle = &mut self.handles[*index as usize];
+            if is_marked {
+                if position != next_position {
+                    self.moved_objects += 1;
+                    self.storage.swap(position, next_position);
+                }
+                handle.position = Some(next_position as u32);
+                next_position += 1;
+            } else {
+                let address = HeapAddress {
+                    index: *index,
+                    generation: handle.generation,
+                };
+                handle.position = None;
+                handle.generation = handle.generation.wrapping_add(1);
+                self.free_handles.push(*index);
+                self.stats.record_free(heap_value);
+                on_free(address);
+            }
+        }
+        self.storage.truncate(next_position);
+
+        // Grow the threshold along with the live data, so that the cost of
+        // collecting stays proportional to the amount of allocation.
+        self.collection_threshold = (2 * self.storage.len()).max(INITIAL_COLLECTION_THRESHOLD);
+    }
+}
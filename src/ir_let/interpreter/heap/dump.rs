@@ -0,0 +1,144 @@
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+// A live object on the heap. Heaps that do not count references have no
+// reference counts to report.
+#[derive(Debug, Clone)]
+pub struct HeapObject<'a> {
+    pub address: HeapAddress,
+    pub refcount: Option<u32>,
+    pub heap_value: &'a HeapValue,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DumpFormat {
+    Json,
+    // A Graphviz graph with an edge for every reference between objects.
+    Dot,
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(DumpFormat::Json),
+            "dot" => Some(DumpFormat::Dot),
+            _ => None,
+        }
+    }
+}
+
+fn address_name(address: HeapAddress) -> String {
+    format!("{}:{}", address.index, address.generation)
+}
+
+fn type_name(heap_value: &HeapValue) -> String {
+    match heap_value {
+        HeapValue::Tuple(tuple) => format!("tuple/{}", tuple.field_values.len()),
+        HeapValue::Closure(closure) => format!("closure {}", closure.name),
+        HeapValue::NativeFn(native) => format!("native {}", native.name),
+        HeapValue::Host(_) => "host".to_owned(),
+        HeapValue::Generator(_) => "generator".to_owned(),
+        HeapValue::WeakRef(target) => format!("weak {}", address_name(*target)),
+        HeapValue::Thunk(_) => "thunk".to_owned(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn dump_json(objects: &[HeapObject], roots: &HashSet<HeapAddress>) -> String {
+    let mut out = String::new();
+
+    // Writing to a String cannot fail.
+    writeln!(out, "[").unwrap();
+    for (i, object) in objects.iter().enumerate() {
+        let refcount = match object.refcount {
+            Some(refcount) => refcount.to_string(),
+            None => "null".to_owned(),
+        };
+        let references: Vec<String> = object
+            .heap_value
+            .references()
+            .into_iter()
+            .map(|r| format!("\"{}\"", address_name(r)))
+            .collect();
+        let separator = if i + 1 < objects.len() { "," } else { "" };
+
+        writeln!(
+            out,
+            "  {{\"address\": \"{}\", \"refcount\": {}, \"root\": {}, \"type\": \"{}\", \"references\": [{}]}}{}",
+            address_name(object.address),
+            refcount,
+            roots.contains(&object.address),
+            escape(&type_name(object.heap_value)),
+            references.join(", "),
+            separator
+        )
+        .unwrap();
+    }
+    writeln!(out, "]").unwrap();
+
+    out
+}
+
+// Roots are drawn with a double border.
+fn dump_dot(objects: &[HeapObject], roots: &HashSet<HeapAddress>) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "digraph heap {{").unwrap();
+    writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+    for object in objects {
+        let mut label = format!(
+            "{}\\n{}",
+            address_name(object.address),
+            escape(&type_name(object.heap_value))
+        );
+        if let Some(refcount) = object.refcount {
+            label += &format!("\\nrefcount {}", refcount);
+        }
+        let style = if roots.contains(&object.address) {
+            ", peripheries=2"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "    \"{}\" [label=\"{}\"{}];",
+            address_name(object.address),
+            label,
+            style
+        )
+        .unwrap();
+
+        for reference in object.heap_value.references() {
+            writeln!(
+                out,
+                "    \"{}\" -> \"{}\";",
+                address_name(object.address),
+                address_name(reference)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+// The objects are written in order of their addresses, so that dumps of the
+// same heap are identical. The roots are marked, so that leaked objects stand
+// out as the ones that cannot be reached from any of them.
+pub fn dump_objects(
+    mut objects: Vec<HeapObject>,
+    roots: &HashSet<HeapAddress>,
+    format: DumpFormat,
+) -> String {
+    objects.sort_by_key(|o| (o.address.index, o.address.generation));
+
+    match format {
+        DumpFormat::Json => dump_json(&objects, roots),
+        DumpFormat::Dot => dump_dot(&objects, roots),
+    }
+}
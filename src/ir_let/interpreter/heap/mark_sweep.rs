@@ -0,0 +1,115 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{Heap, HeapObject, HeapStats};
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue};
+use std::collections::{HashMap, HashSet};
+
+const INITIAL_COLLECTION_THRESHOLD: usize = 1024;
+
+// A tracing garbage collector. Reference counting operations are ignored, and
+// instead unreachable values are reclaimed by marking everything reachable from
+// the roots and sweeping the rest once the heap has grown enough.
+#[derive(Debug)]
+pub struct MarkSweepHeap {
+    memory: HashMap<HeapAddress, HeapValue>,
+    heap_next_address: HeapAddress,
+    collection_threshold: usize,
+    stats: HeapStats,
+}
+
+impl MarkSweepHeap {
+    pub fn new() -> Self {
+        MarkSweepHeap {
+            memory: HashMap::new(),
+            heap_next_address: HeapAddress::new(0),
+            collection_threshold: INITIAL_COLLECTION_THRESHOLD,
+            stats: HeapStats::default(),
+        }
+    }
+
+    fn mark(&self, roots: &mut dyn Iterator<Item = HeapAddress>) -> HashSet<HeapAddress> {
+        let mut marked = HashSet::new();
+        let mut worklist: Vec<HeapAddress> = roots.collect();
+
+        while let Some(address) = worklist.pop() {
+            if marked.insert(address) {
+                worklist.extend(self.deref(address).references());
+            }
+        }
+
+        marked
+    }
+}
+
+impl Default for MarkSweepHeap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap for MarkSweepHeap {
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError> {
+        self.stats.record_alloc(&heap_value);
+        let address = self.heap_next_address;
+        self.heap_next_address = HeapAddress::new(self.heap_next_address.index + 1);
+        self.memory.insert(address, heap_value);
+        Ok(address)
+    }
+
+    fn deref(&self, heap_address: HeapAddress) -> &HeapValue {
+        self.memory.get(&heap_address).expect("invalid pointer")
+    }
+
+    fn deref_mut(&mut self, heap_address: HeapAddress) -> &mut HeapValue {
+        self.memory.get_mut(&heap_address).expect("invalid pointer")
+    }
+
+    // Addresses are never reused, so an address that is not in memory belongs
+    // to an object that has been collected.
+    fn is_live(&self, heap_address: HeapAddress) -> bool {
+        self.memory.contains_key(&heap_address)
+    }
+
+    fn inc_refcount(&mut self, _heap_address: HeapAddress) {}
+
+    fn dec_refcount(&mut self, _heap_address: HeapAddress, _on_free: &mut dyn FnMut(HeapAddress)) {}
+
+    fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+
+    fn objects(&self) -> Vec<HeapObject<'_>> {
+        self.memory
+            .iter()
+            .map(|(address, heap_value)| HeapObject {
+                address: *address,
+                refcount: None,
+                heap_value,
+            })
+            .collect()
+    }
+
+    fn wants_collection(&self) -> bool {
+        self.memory.len() >= self.collection_threshold
+    }
+
+    fn collect(
+        &mut self,
+        roots: &mut dyn Iterator<Item = HeapAddress>,
+        on_free: &mut dyn FnMut(HeapAddress),
+    ) {
+        let marked = self.mark(roots);
+        let stats = &mut self.stats;
+        self.memory.retain(|address, heap_value| {
+            let is_marked = marked.contains(address);
+            if !is_marked {
+                stats.record_free(heap_value);
+                on_free(*address);
+            }
+            is_marked
+        });
+
+        // Grow the threshold along with the live data, so that the cost of
+        // collecting stays proportional to the amount of allocation.
+        self.collection_threshold = (2 * self.memory.len()).max(INITIAL_COLLECTION_THRESHOLD);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue, Value};
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+mod arena;
+mod compacting;
+mod dump;
+mod mark_sweep;
+mod pool;
+mod refcount;
+mod stats;
+
+pub use arena::ArenaHeap;
+pub use compacting::CompactingHeap;
+pub use dump::{DumpFormat, HeapObject};
+pub use mark_sweep::MarkSweepHeap;
+pub use pool::{TuplePool, MAX_POOLED_ARITY};
+pub use refcount::RefCountHeap;
+pub use stats::HeapStats;
+
+// The interface between the evaluator and its memory manager. The evaluator
+// always performs the reference counting operations, and implementations that
+// do not use reference counting simply ignore them.
+pub trait Heap: Debug {
+    // Allocation either fully succeeds or leaves the heap untouched, so callers
+    // should only take ownership of any field values after it has succeeded.
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError>;
+
+    // Empty storage for the fields of a new tuple. Heaps that free objects can
+    // hand out the storage of freed tuples here instead of allocating.
+    fn tuple_storage(&mut self, arity: usize) -> Vec<Value> {
+        Vec::with_capacity(arity)
+    }
+
+    fn deref(&self, heap_address: HeapAddress) -> &HeapValue;
+
+    // Whether the object can be overwritten in place by a reuse instruction,
+    // which is only the case if the evaluator holds the single reference to
+    // it. Heaps that do not count references never allow this.
+    fn reuse_in_place(&mut self, _heap_address: HeapAddress) -> bool {
+        false
+    }
+
+    fn deref_mut(&mut self, heap_address: HeapAddress) -> &mut HeapValue;
+
+    // Whether the object at the address has not been freed yet. Unlike
+    // `deref`, this also accepts the address of an object that has been freed,
+    // which is how weak references find out that their target is gone.
+    fn is_live(&self, heap_address: HeapAddress) -> bool;
+
+    fn inc_refcount(&mut self, heap_address: HeapAddress);
+
+    // The address of every object that is freed as a result is passed to
+    // `on_free`.
+    fn dec_refcount(&mut self, heap_address: HeapAddress, on_free: &mut dyn FnMut(HeapAddress));
+
+    // Frees the object if it is still live and nothing refers to it anymore,
+    // for objects whose increment and decrement cancelled out when reference
+    // counts were deferred.
+    fn free_if_unreferenced(
+        &mut self,
+        _heap_address: HeapAddress,
+        _on_free: &mut dyn FnMut(HeapAddress),
+    ) {
+    }
+
+    fn stats(&self) -> &HeapStats;
+
+    fn objects(&self) -> Vec<HeapObject<'_>>;
+
+    // Writes every live object with its reference count and the objects it
+    // refers to, for debugging leaks. `ProgramEvaluator::dump_heap` also marks
+    // the roots.
+    fn dump(&self, format: DumpFormat) -> String {
+        self.dump_with_roots(format, &HashSet::new())
+    }
+
+    fn dump_with_roots(&self, format: DumpFormat, roots: &HashSet<HeapAddress>) -> String {
+        dump::dump_objects(self.objects(), roots, format)
+    }
+
+    // Root enumeration hook. The evaluator checks this between instructions,
+    // and if it returns true it passes its roots (`ProgramEvaluator::roots`)
+    // to `collect`.
+    fn wants_collection(&self) -> bool {
+        false
+    }
+
+    fn collect(
+        &mut self,
+        _roots: &mut dyn Iterator<Item = HeapAddress>,
+        _on_free: &mut dyn FnMut(HeapAddress),
+    ) {
+    }
+}
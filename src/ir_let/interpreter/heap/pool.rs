@@ -0,0 +1,50 @@
+use crate::ir_let::interpreter::heap_value::Value;
+
+// Tuples with at most this many fields have their storage recycled.
+pub const MAX_POOLED_ARITY: usize = 8;
+
+// Bounds the memory that the pools hold on to after a burst of frees.
+const MAX_POOL_SIZE: usize = 1024;
+
+// The field storage of freed tuples, segregated by the number of fields, so
+// that programs that keep allocating and freeing small tuples such as pairs
+// and cons cells do not go through the allocator for every one of them.
+#[derive(Debug, Default)]
+pub struct TuplePool {
+    // The storage for tuples with `i + 1` fields, which is always empty.
+    pools: [Vec<Vec<Value>>; MAX_POOLED_ARITY],
+}
+
+impl TuplePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Empty storage with room for the fields, and whether it was recycled.
+    pub fn take(&mut self, arity: usize) -> (Vec<Value>, bool) {
+        let recycled = match arity {
+            1..=MAX_POOLED_ARITY => self.pools[arity - 1].pop(),
+            _ => None,
+        };
+        match recycled {
+            Some(storage) => (storage, true),
+            None => (Vec::with_capacity(arity), false),
+        }
+    }
+
+    pub fn give(&mut self, mut storage: Vec<Value>) {
+        let arity = storage.len();
+        if (1..=MAX_POOLED_ARITY).contains(&arity) && self.pools[arity - 1].len() < MAX_POOL_SIZE {
+            storage.clear();
+            self.pools[arity - 1].push(storage);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.iter().map(|pool| pool.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
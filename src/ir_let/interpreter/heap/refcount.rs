@@ -0,0 +1,229 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{Heap, HeapObject, HeapStats, TuplePool};
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue, RefCountedHeapValue, Value};
+
+#[derive(Debug)]
+struct Slot {
+    generation: u32,
+    value: Option<RefCountedHeapValue>,
+}
+
+// The objects are stored in a vector of slots, indexed by the address. The
+// slots of freed objects are kept on a free list and reused by later
+// allocations, and so is the field storage of small freed tuples.
+#[derive(Debug)]
+pub struct RefCountHeap {
+    slots: Vec<Slot>,
+    free_list: Vec<u32>,
+    tuple_pool: TuplePool,
+    stats: HeapStats,
+}
+
+impl RefCountHeap {
+    pub fn new() -> Self {
+        RefCountHeap {
+            slots: Vec::new(),
+            free_list: Vec::new(),
+            tuple_pool: TuplePool::new(),
+            stats: HeapStats::default(),
+        }
+    }
+
+    // The number of objects that are currently allocated.
+    pub fn len(&self) -> usize {
+        self.stats.live_objects
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.live_objects == 0
+    }
+
+    // The number of freed tuples whose storage is waiting to be reused.
+    pub fn pooled_tuples(&self) -> usize {
+        self.tuple_pool.len()
+    }
+
+    pub fn refcount(&self, heap_address: HeapAddress) -> u32 {
+        self.get(heap_address).refcount
+    }
+
+    fn slot(&self, heap_address: HeapAddress) -> &Slot {
+        let slot = self
+            .slots
+            .get(heap_address.index as usize)
+            .expect("invalid pointer");
+        assert!(
+            slot.generation == heap_address.generation,
+            "dangling pointer"
+        );
+        slot
+    }
+
+    fn slot_mut(&mut self, heap_address: HeapAddress) -> &mut Slot {
+        let slot = self
+            .slots
+            .get_mut(heap_address.index as usize)
+            .expect("invalid pointer");
+        assert!(
+            slot.generation == heap_address.generation,
+            "dangling pointer"
+        );
+        slot
+    }
+
+    fn get(&self, heap_address: HeapAddress) -> &RefCountedHeapValue {
+        self.slot(heap_address)
+            .value
+            .as_ref()
+            .expect("dangling pointer")
+    }
+
+    fn get_mut(&mut self, heap_address: HeapAddress) -> &mut RefCountedHeapValue {
+        self.slot_mut(heap_address)
+            .value
+            .as_mut()
+            .expect("dangling pointer")
+    }
+
+    // Frees the object and everything that it held the last reference to.
+    // The objects to free are kept on a worklist instead of the host stack,
+    // so that long chains such as lists can be dropped.
+    fn free(&mut self, heap_address: HeapAddress, on_free: &mut dyn FnMut(HeapAddress)) {
+        let mut worklist = vec![heap_address];
+        while let Some(heap_address) = worklist.pop() {
+            let slot = self.slot_mut(heap_address);
+            let destroying_value = slot
+                .value
+                .take()
+                .expect("attempt to free invalid pointer")
+                .heap_value;
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free_list.push(heap_address.index);
+            self.stats.record_free(&destroying_value);
+            on_free(heap_address);
+
+            let references = destroying_value.references();
+            if let HeapValue::Tuple(tuple) = destroying_value {
+                self.tuple_pool.give(tuple.field_values);
+            }
+
+            for addr in references {
+                let refcounted = self.get_mut(addr);
+                refcounted.refcount -= 1;
+                if refcounted.refcount == 0 {
+                    worklist.push(addr);
+                }
+            }
+        }
+    }
+}
+
+impl Default for RefCountHeap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap for RefCountHeap {
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError> {
+        self.stats.record_alloc(&heap_value);
+        let refcounted = RefCountedHeapValue {
+            refcount: 0,
+            heap_value,
+        };
+
+        let index = match self.free_list.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: None,
+                });
+                u32::try_from(self.slots.len() - 1).expect("heap address space exhausted")
+            }
+        };
+
+        let slot = &mut self.slots[index as usize];
+        slot.value = Some(refcounted);
+
+        Ok(HeapAddress {
+            index,
+            generation: slot.generation,
+        })
+    }
+
+    fn tuple_storage(&mut self, arity: usize) -> Vec<Value> {
+        let (storage, recycled) = self.tuple_pool.take(arity);
+        if recycled {
+            self.stats.recycled_tuples += 1;
+        }
+        storage
+    }
+
+    fn reuse_in_place(&mut self, heap_address: HeapAddress) -> bool {
+        let unique = self.get(heap_address).refcount == 1;
+        if unique {
+            self.stats.reused_tuples += 1;
+        }
+        unique
+    }
+
+    fn deref(&self, heap_address: HeapAddress) -> &HeapValue {
+        &self.get(heap_address).heap_value
+    }
+
+    fn deref_mut(&mut self, heap_address: HeapAddress) -> &mut HeapValue {
+        &mut self.get_mut(heap_address).heap_value
+    }
+
+    fn is_live(&self, heap_address: HeapAddress) -> bool {
+        self.slots
+            .get(heap_address.index as usize)
+            .is_some_and(|slot| slot.generation == heap_address.generation && slot.value.is_some())
+    }
+
+    fn inc_refcount(&mut self, heap_address: HeapAddress) {
+        self.get_mut(heap_address).refcount += 1;
+    }
+
+    fn dec_refcount(&mut self, heap_address: HeapAddress, on_free: &mut dyn FnMut(HeapAddress)) {
+        let refcounted = self.get_mut(heap_address);
+        refcounted.refcount -= 1;
+
+        if refcounted.refcount == 0 {
+            self.free(heap_address, on_free);
+        }
+    }
+
+    fn free_if_unreferenced(
+        &mut self,
+        heap_address: HeapAddress,
+        on_free: &mut dyn FnMut(HeapAddress),
+    ) {
+        if self.is_live(heap_address) && self.refcount(heap_address) == 0 {
+            self.free(heap_address, on_free);
+        }
+    }
+
+    fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+
+    fn objects(&self) -> Vec<HeapObject<'_>> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let refcounted = slot.value.as_ref()?;
+                Some(HeapObject {
+                    address: HeapAddress {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    refcount: Some(refcounted.refcount),
+                    heap_value: &refcounted.heap_value,
+                })
+            })
+            .collect()
+    }
+}
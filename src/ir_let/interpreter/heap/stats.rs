@@ -0,0 +1,76 @@
+use crate::ir_let::interpreter::heap_value::HeapValue;
+use std::fmt;
+
+// Counters that every heap keeps up to date. Integers and booleans are stored
+// inline in values, so mostly tuples and closures are allocated. Other heap
+// values, such as native functions and generators, are only counted in the
+// totals.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    pub live_objects: usize,
+    pub peak_objects: usize,
+    pub allocations: usize,
+    // An estimate, see `HeapValue::size_in_bytes`.
+    pub allocated_bytes: usize,
+    pub frees: usize,
+    pub live_tuples: usize,
+    pub live_closures: usize,
+    pub tuple_allocations: usize,
+    // Tuple allocations that reused the storage of a freed tuple.
+    pub recycled_tuples: usize,
+    // Tuples that were overwritten in place instead of allocating a new one.
+    pub reused_tuples: usize,
+    pub closure_allocations: usize,
+}
+
+impl HeapStats {
+    pub fn record_alloc(&mut self, heap_value: &HeapValue) {
+        self.live_objects += 1;
+        self.peak_objects = self.peak_objects.max(self.live_objects);
+        self.allocations += 1;
+        self.allocated_bytes += heap_value.size_in_bytes();
+
+        match heap_value {
+            HeapValue::Tuple(_) => {
+                self.live_tuples += 1;
+                self.tuple_allocations += 1;
+            }
+            HeapValue::Closure(_) => {
+                self.live_closures += 1;
+                self.closure_allocations += 1;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn record_free(&mut self, heap_value: &HeapValue) {
+        self.live_objects -= 1;
+        self.frees += 1;
+
+        match heap_value {
+            HeapValue::Tuple(_) => self.live_tuples -= 1,
+            HeapValue::Closure(_) => self.live_closures -= 1,
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "live objects: {}", self.live_objects)?;
+        writeln!(f, "peak objects: {}", self.peak_objects)?;
+        writeln!(f, "allocations: {}", self.allocations)?;
+        writeln!(f, "allocated bytes: {}", self.allocated_bytes)?;
+        writeln!(f, "frees: {}", self.frees)?;
+        writeln!(
+            f,
+            "tuples: {} live, {} allocated, {} recycled, {} reused",
+            self.live_tuples, self.tuple_allocations, self.recycled_tuples, self.reused_tuples
+        )?;
+        write!(
+            f,
+            "closures: {} live, {} allocated",
+            self.live_closures, self.closure_allocations
+        )
+    }
+}
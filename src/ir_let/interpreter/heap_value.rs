@@ -1,67 +1,330 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::native::NativeFunction;
+use crate::ir_let::interpreter::stack::CallStackFrame;
 use crate::ir_let::let_expr::TargetAddress;
-use std::collections::HashMap;
+use crate::symbol::Symbol;
+use std::any::Any;
 
+// Heaps that reuse the slots of freed objects increment the generation of the
+// slot every time it is freed, so that a dangling pointer to an earlier
+// occupant of the slot can be detected.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct HeapAddress(pub u32);
+pub struct HeapAddress {
+    pub index: u32,
+    pub generation: u32,
+}
+
+impl HeapAddress {
+    pub fn new(index: u32) -> Self {
+        HeapAddress {
+            index,
+            generation: 0,
+        }
+    }
+}
 
-// Using Strings everywhere it likely not very efficient (as opposed to interning
-// or using offsets into stack frames), but this is just a proof-of-concept simple
-// implementation.
+// The values stored in variables, tuple fields and closure environments.
+// Integers and booleans are stored inline, so only tuples and closures live on
+// the heap and are reference counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Pointer(HeapAddress),
+}
+
+impl Value {
+    pub fn address(&self) -> Option<HeapAddress> {
+        match self {
+            Value::Pointer(address) => Some(*address),
+            _ => None,
+        }
+    }
+
+    pub fn check_int(&self) -> Result<i64, RuntimeError> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "int" }),
+        }
+    }
+
+    pub fn check_bool(&self) -> Result<bool, RuntimeError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(RuntimeError::TypeMismatch { expected: "bool" }),
+        }
+    }
+
+    // The expected kind of heap value is reported if this is not a pointer.
+    pub fn check_pointer(&self, expected: &'static str) -> Result<HeapAddress, RuntimeError> {
+        match self {
+            Value::Pointer(address) => Ok(*address),
+            _ => Err(RuntimeError::TypeMismatch { expected }),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Closure {
-    pub name: String,
-    pub arg_names: Vec<String>,
-    pub environment: HashMap<String, HeapAddress>,
+    pub name: Symbol,
+    pub arg_names: Vec<Symbol>,
+    // The values of the free names of the function, in the same order.
+    pub environment: Vec<Value>,
     pub body: TargetAddress,
 }
 
 #[derive(Debug, Clone)]
 pub struct Tuple {
-    pub field_values: Vec<HeapAddress>,
+    pub field_values: Vec<Value>,
+    // Set by `freeze`, after which the fields can no longer be changed.
+    // Only the tuple itself is frozen, not the tuples in its fields.
+    pub frozen: bool,
 }
 
-#[derive(Debug, Clone)]
+impl Tuple {
+    pub fn new(field_values: Vec<Value>) -> Self {
+        Tuple {
+            field_values,
+            frozen: false,
+        }
+    }
+}
+
+// The frames of a suspended generator are moved off the stack into the heap,
+// and moved back onto the stack when it is resumed.
+#[derive(Debug)]
+pub enum Generator {
+    NotStarted {
+        function: Value,
+    },
+    Running,
+    Suspended {
+        frames: Vec<CallStackFrame>,
+        resume_address: TargetAddress,
+    },
+    Finished,
+}
+
+// A delayed computation, which calls its function the first time it is forced
+// and remembers the result.
+#[derive(Debug)]
+pub enum Thunk {
+    Delayed { function: Value },
+    Forcing,
+    Forced { value: Value },
+}
+
+// Called with the object of a host value when the value is freed, by reference
+// counting or by a collection, so that the embedder can release the resources
+// it stands for, such as a file handle. Objects that are still live when the
+// heap is dropped are finalized then, so every finalizer runs exactly once.
+pub type Finalizer = Box<dyn FnOnce(Box<dyn Any>)>;
+
+pub struct HostValue {
+    object: Box<dyn Any>,
+    finalizer: Option<Finalizer>,
+}
+
+impl HostValue {
+    pub fn new(object: Box<dyn Any>) -> Self {
+        HostValue {
+            object,
+            finalizer: None,
+        }
+    }
+
+    pub fn with_finalizer<T: Any>(object: T, finalizer: impl FnOnce(T) + 'static) -> Self {
+        HostValue {
+            object: Box::new(object),
+            finalizer: Some(Box::new(|object: Box<dyn Any>| {
+                let object = object
+                    .downcast::<T>()
+                    .expect("host object should keep its type");
+                finalizer(*object)
+            })),
+        }
+    }
+
+    pub fn object(&self) -> &dyn Any {
+        &*self.object
+    }
+
+    pub fn object_mut(&mut self) -> &mut dyn Any {
+        &mut *self.object
+    }
+}
+
+impl Drop for HostValue {
+    fn drop(&mut self) {
+        if let Some(finalizer) = self.finalizer.take() {
+            finalizer(std::mem::replace(&mut self.object, Box::new(())));
+        }
+    }
+}
+
+impl std::fmt::Debug for HostValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("HostValue")
+            .field("finalizer", &self.finalizer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
 pub enum HeapValue {
-    Int(i32),
-    Bool(bool),
     Tuple(Tuple),
     Closure(Closure),
+    NativeFn(NativeFunction),
+    // A Rust object owned by the embedder. Guest code can only pass it around,
+    // and it is dropped, after running its finalizer if it has one, when the
+    // heap value is freed.
+    Host(HostValue),
+    Generator(Generator),
+    // A reference that does not keep its target alive, see `Heap::is_live`.
+    WeakRef(HeapAddress),
+    Thunk(Thunk),
 }
 
 impl HeapValue {
-    pub fn check_closure(&self) -> &Closure {
+    // The heap values directly referenced by this value.
+    pub fn references(&self) -> Vec<HeapAddress> {
+        match self {
+            HeapValue::Tuple(Tuple { field_values, .. }) => {
+                field_values.iter().filter_map(|v| v.address()).collect()
+            }
+            HeapValue::Closure(Closure { environment, .. }) => {
+                environment.iter().filter_map(|v| v.address()).collect()
+            }
+            HeapValue::Generator(Generator::NotStarted { function }) => {
+                function.address().into_iter().collect()
+            }
+            HeapValue::Generator(Generator::Suspended { frames, .. }) => frames
+                .iter()
+                .flat_map(|frame| frame.values())
+                .filter_map(|v| v.address())
+                .collect(),
+            HeapValue::Thunk(Thunk::Delayed { function }) => {
+                function.address().into_iter().collect()
+            }
+            HeapValue::Thunk(Thunk::Forced { value }) => value.address().into_iter().collect(),
+            HeapValue::NativeFn(_)
+            | HeapValue::Host(_)
+            | HeapValue::WeakRef(_)
+            | HeapValue::Generator(Generator::Running | Generator::Finished)
+            | HeapValue::Thunk(Thunk::Forcing) => Vec::new(),
+        }
+    }
+
+    // An estimate of the memory used by the value, counting the value itself
+    // and the vectors that it owns.
+    pub fn size_in_bytes(&self) -> usize {
+        let owned = match self {
+            HeapValue::Tuple(tuple) => tuple.field_values.len() * size_of::<Value>(),
+            HeapValue::Closure(closure) => {
+                closure.environment.len() * size_of::<Value>()
+                    + closure.arg_names.len() * size_of::<Symbol>()
+            }
+            HeapValue::Host(host) => size_of_val(host.object()),
+            HeapValue::Generator(Generator::Suspended { frames, .. }) => {
+                frames
+                    .iter()
+                    .map(|frame| frame.values().count())
+                    .sum::<usize>()
+                    * size_of::<Value>()
+            }
+            HeapValue::NativeFn(_)
+            | HeapValue::Generator(_)
+            | HeapValue::WeakRef(_)
+            | HeapValue::Thunk(_) => 0,
+        };
+
+        size_of::<HeapValue>() + owned
+    }
+
+    pub fn check_closure(&self) -> Result<&Closure, RuntimeError> {
+        match self {
+            HeapValue::Closure(clos) => Ok(clos),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "closure",
+            }),
+        }
+    }
+
+    pub fn check_host<T: Any>(&self) -> Result<&T, RuntimeError> {
+        match self {
+            HeapValue::Host(host) => {
+                host.object()
+                    .downcast_ref()
+                    .ok_or(RuntimeError::TypeMismatch {
+                        expected: std::any::type_name::<T>(),
+                    })
+            }
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "host value",
+            }),
+        }
+    }
+
+    pub fn check_host_mut<T: Any>(&mut self) -> Result<&mut T, RuntimeError> {
+        match self {
+            HeapValue::Host(host) => {
+                host.object_mut()
+                    .downcast_mut()
+                    .ok_or(RuntimeError::TypeMismatch {
+                        expected: std::any::type_name::<T>(),
+                    })
+            }
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "host value",
+            }),
+        }
+    }
+
+    pub fn check_generator(&self) -> Result<&Generator, RuntimeError> {
+        match self {
+            HeapValue::Generator(generator) => Ok(generator),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "generator",
+            }),
+        }
+    }
+
+    pub fn check_generator_mut(&mut self) -> Result<&mut Generator, RuntimeError> {
         match self {
-            HeapValue::Closure(clos) => clos,
-            _ => panic!("expected closure"),
+            HeapValue::Generator(generator) => Ok(generator),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "generator",
+            }),
         }
     }
 
-    pub fn check_int(&self) -> i32 {
+    pub fn check_thunk_mut(&mut self) -> Result<&mut Thunk, RuntimeError> {
         match self {
-            HeapValue::Int(value) => *value,
-            _ => panic!("expected int"),
+            HeapValue::Thunk(thunk) => Ok(thunk),
+            _ => Err(RuntimeError::TypeMismatch { expected: "thunk" }),
         }
     }
 
-    pub fn check_bool(&self) -> bool {
+    pub fn check_weak_ref(&self) -> Result<HeapAddress, RuntimeError> {
         match self {
-            HeapValue::Bool(value) => *value,
-            _ => panic!("expected bool"),
+            HeapValue::WeakRef(target) => Ok(*target),
+            _ => Err(RuntimeError::TypeMismatch {
+                expected: "weak reference",
+            }),
         }
     }
 
-    pub fn check_tuple(&self) -> &Tuple {
+    pub fn check_tuple(&self) -> Result<&Tuple, RuntimeError> {
         match self {
-            HeapValue::Tuple(tuple) => tuple,
-            _ => panic!("expected tuple"),
+            HeapValue::Tuple(tuple) => Ok(tuple),
+            _ => Err(RuntimeError::TypeMismatch { expected: "tuple" }),
         }
     }
 
-    pub fn check_tuple_mut(&mut self) -> &mut Tuple {
+    pub fn check_tuple_mut(&mut self) -> Result<&mut Tuple, RuntimeError> {
         match self {
-            HeapValue::Tuple(tuple) => tuple,
-            _ => panic!("expected tuple"),
+            HeapValue::Tuple(tuple) => Ok(tuple),
+            _ => Err(RuntimeError::TypeMismatch { expected: "tuple" }),
         }
     }
 }
@@ -1,4 +1,14 @@
-mod heap;
-mod heap_value;
+pub mod backtrace;
+pub mod config;
+pub mod debugger;
+pub mod deferred;
+pub mod error;
+pub mod heap;
+pub mod heap_value;
+pub mod native;
+pub mod profile;
+pub mod record;
+pub mod render;
 pub mod simple_eval;
-mod stack;
+pub mod stack;
+pub mod trace;
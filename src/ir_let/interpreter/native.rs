@@ -0,0 +1,641 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{Heap, HeapObject, HeapStats};
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue, Tuple, Value};
+use crate::symbol::Symbol;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+
+// The code of a function implemented in Rust. The arguments are borrowed from
+// the caller. The result is owned by the caller, so a newly allocated result
+// is returned without incrementing its reference count, just like the
+// evaluator does for its own allocations. The reference counts of values that
+// are stored in a new heap value do have to be incremented.
+pub type NativeCode = fn(&mut dyn Heap, &[Value]) -> Result<Value, RuntimeError>;
+
+// The code of a native function that calls back into the guest program, such
+// as a sort that takes a comparison function. The same rules as for
+// `NativeCode` apply.
+pub type ReentrantCode = fn(&mut dyn NativeContext, &[Value]) -> Result<Value, RuntimeError>;
+
+// What the evaluator offers to a reentrant native function while it runs.
+pub trait NativeContext {
+    fn heap(&mut self) -> NativeHeap<'_>;
+
+    // Calls a closure or another native function and runs it to completion,
+    // nested inside the call of the native function. The arguments and the
+    // result are kept alive until the native function returns, so newly
+    // allocated values can be passed without touching their reference counts.
+    // Intrinsics such as yield cannot be called this way.
+    fn call(&mut self, function: Value, arguments: &[Value]) -> Result<Value, RuntimeError>;
+}
+
+// The heap of the evaluator as native functions see it. Allocations beyond the
+// heap limit fail with `RuntimeError::OutOfMemory`, just like those of the
+// evaluator. Every object that is looked up or allocated counts as work, one
+// unit for the object and one for each of its fields, which the evaluator
+// charges as fuel once the native function returns. Allocations fail with
+// `RuntimeError::OutOfFuel` as soon as the work exceeds the remaining fuel,
+// so that a native function cannot keep building a large structure.
+#[derive(Debug)]
+pub struct NativeHeap<'a> {
+    heap: &'a mut dyn Heap,
+    max_objects: Option<usize>,
+    fuel: Option<u64>,
+    work: &'a Cell<u64>,
+}
+
+impl<'a> NativeHeap<'a> {
+    pub fn new(
+        heap: &'a mut dyn Heap,
+        max_objects: Option<usize>,
+        fuel: Option<u64>,
+        work: &'a Cell<u64>,
+    ) -> Self {
+        NativeHeap {
+            heap,
+            max_objects,
+            fuel,
+            work,
+        }
+    }
+}
+
+fn work_of(heap_value: &HeapValue) -> u64 {
+    let fields = match heap_value {
+        HeapValue::Tuple(tuple) => tuple.field_values.len(),
+        HeapValue::Closure(closure) => closure.environment.len(),
+        _ => 0,
+    };
+    1 + fields as u64
+}
+
+impl Heap for NativeHeap<'_> {
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError> {
+        if let Some(max_objects) = self.max_objects {
+            if self.heap.stats().live_objects >= max_objects {
+                return Err(RuntimeError::OutOfMemory);
+            }
+        }
+        self.work.set(self.work.get() + work_of(&heap_value));
+        if self.fuel.is_some_and(|fuel| self.work.get() > fuel) {
+            return Err(RuntimeError::OutOfFuel);
+        }
+
+        self.heap.alloc(heap_value)
+    }
+
+    fn tuple_storage(&mut self, arity: usize) -> Vec<Value> {
+        self.heap.tuple_storage(arity)
+    }
+
+    fn deref(&self, heap_address: HeapAddress) -> &HeapValue {
+        let heap_value = self.heap.deref(heap_address);
+        self.work.set(self.work.get() + work_of(heap_value));
+        heap_value
+    }
+
+    fn reuse_in_place(&mut self, heap_address: HeapAddress) -> bool {
+        self.heap.reuse_in_place(heap_address)
+    }
+
+    fn deref_mut(&mut self, heap_address: HeapAddress) -> &mut HeapValue {
+        let heap_value = self.heap.deref_mut(heap_address);
+        self.work.set(self.work.get() + work_of(heap_value));
+        heap_value
+    }
+
+    fn is_live(&self, heap_address: HeapAddress) -> bool {
+        self.heap.is_live(heap_address)
+    }
+
+    fn inc_refcount(&mut self, heap_address: HeapAddress) {
+        self.heap.inc_refcount(heap_address);
+    }
+
+    fn dec_refcount(&mut self, heap_address: HeapAddress, on_free: &mut dyn FnMut(HeapAddress)) {
+        self.heap.dec_refcount(heap_address, on_free);
+    }
+
+    fn free_if_unreferenced(
+        &mut self,
+        heap_address: HeapAddress,
+        on_free: &mut dyn FnMut(HeapAddress),
+    ) {
+        self.heap.free_if_unreferenced(heap_address, on_free);
+    }
+
+    fn stats(&self) -> &HeapStats {
+        self.heap.stats()
+    }
+
+    fn objects(&self) -> Vec<HeapObject<'_>> {
+        self.heap.objects()
+    }
+}
+
+// Functions that are built into the evaluator, because they need access to
+// more than the heap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Intrinsic {
+    // generator(f) creates a generator that calls f with the value of the
+    // first resume.
+    Generator,
+    // resume(g, v) runs the generator until it yields or returns, and
+    // evaluates to the value it yielded or returned. The yield in the
+    // generator evaluates to v.
+    Resume,
+    // yield(v) suspends the innermost running generator.
+    Yield,
+    // finished(g) is true once the function of the generator has returned.
+    Finished,
+    // delay(f) creates a thunk that calls f without arguments when it is
+    // first forced.
+    Delay,
+    // force(t) evaluates to the result of the function of the thunk, which is
+    // only called the first time.
+    Force,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum NativeBody {
+    Code(NativeCode),
+    // The results can differ between runs, so they are recorded and replayed.
+    Nondeterministic(NativeCode),
+    Reentrant(ReentrantCode),
+    Intrinsic(Intrinsic),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NativeFunction {
+    pub name: Symbol,
+    pub arity: usize,
+    pub body: NativeBody,
+}
+
+// The native functions that guest programs can call by name. The program has
+// to be compiled with these names as globals, see `CompileOptions::globals`.
+#[derive(Debug, Clone, Default)]
+pub struct Natives {
+    functions: HashMap<Symbol, NativeFunction>,
+}
+
+impl Natives {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registering a function under an existing name replaces it.
+    pub fn register(&mut self, name: &str, arity: usize, code: NativeCode) {
+        self.register_body(name, arity, NativeBody::Code(code));
+    }
+
+    // Like `register`, for a function whose results can differ between runs
+    // with the same arguments, such as a clock or a random number generator.
+    // Only the calls to these are recorded, and their results have to be
+    // integers, booleans or tuples of them.
+    pub fn register_nondeterministic(&mut self, name: &str, arity: usize, code: NativeCode) {
+        self.register_body(name, arity, NativeBody::Nondeterministic(code));
+    }
+
+    // Like `register`, for a function that calls back into the guest program
+    // through its `NativeContext`.
+    pub fn register_reentrant(&mut self, name: &str, arity: usize, code: ReentrantCode) {
+        self.register_body(name, arity, NativeBody::Reentrant(code));
+    }
+
+    // Makes generators available under the names generator, resume, yield and
+    // finished.
+    pub fn register_generators(&mut self) {
+        self.register_body("generator", 1, NativeBody::Intrinsic(Intrinsic::Generator));
+        self.register_body("resume", 2, NativeBody::Intrinsic(Intrinsic::Resume));
+        self.register_body("yield", 1, NativeBody::Intrinsic(Intrinsic::Yield));
+        self.register_body("finished", 1, NativeBody::Intrinsic(Intrinsic::Finished));
+    }
+
+    // Makes thunks available under the names delay and force, which the
+    // `delay` and `force` expressions are compiled to.
+    pub fn register_thunks(&mut self) {
+        self.register_body("delay", 1, NativeBody::Intrinsic(Intrinsic::Delay));
+        self.register_body("force", 1, NativeBody::Intrinsic(Intrinsic::Force));
+    }
+
+    // Makes weak references available as weak(v), which creates a weak
+    // reference to the heap value v, and deref_weak(w). The latter evaluates to
+    // (true, v) if v is still alive, and to (false, 0) otherwise.
+    pub fn register_weak_refs(&mut self) {
+        self.register("weak", 1, weak);
+        self.register("deref_weak", 1, deref_weak);
+    }
+
+    // Makes type_of(v) available, which evaluates to the tag of the runtime
+    // type of v (see `TypeTag`), so that guest code can check what kind of
+    // value it has before an operation would fail on it.
+    pub fn register_reflection(&mut self) {
+        self.register("type_of", 1, type_of);
+    }
+
+    // Makes copy(v) available, which evaluates to a deep copy of the tuples
+    // reachable from v through tuple fields. Tuples that are shared or form a
+    // cycle are shared or form a cycle in the copy as well. Other heap values,
+    // such as closures, are not copied but shared with the original.
+    pub fn register_copy(&mut self) {
+        self.register("copy", 1, copy);
+    }
+
+    // Makes hash(v) available, which evaluates to a structural hash of the
+    // integers, booleans and tuples in v, see `hash_value`.
+    pub fn register_hash(&mut self) {
+        self.register("hash", 1, hash);
+    }
+
+    // Makes freeze(t) available, which marks the tuple t as immutable and
+    // evaluates to t. Setting a field of a frozen tuple fails with
+    // `RuntimeError::FrozenTuple`, and copies of it are not frozen.
+    pub fn register_freeze(&mut self) {
+        self.register("freeze", 1, freeze);
+    }
+
+    // Makes sort_by(t, less) available, which evaluates to a new tuple with
+    // the fields of the tuple t, sorted by the guest function less. It is
+    // called as less(a, b) and should be true if a comes before b. The sort
+    // is stable.
+    pub fn register_sorting(&mut self) {
+        self.register_reentrant("sort_by", 2, sort_by);
+    }
+
+    // Makes the natives that the prelude is written against available:
+    // less(a, b), which compares two integers, and assert(c), which fails
+    // unless c is true and otherwise evaluates to the empty tuple. See
+    // `lang::prelude`.
+    pub fn register_prelude(&mut self) {
+        self.register("less", 2, less);
+        self.register("assert", 1, assert);
+    }
+
+    fn register_body(&mut self, name: &str, arity: usize, body: NativeBody) {
+        let name = Symbol::intern(name);
+        self.functions
+            .insert(name, NativeFunction { name, arity, body });
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<&NativeFunction> {
+        self.functions.get(&name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .functions
+            .keys()
+            .map(|name| name.as_str().to_owned())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+fn weak(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    let target = args[0].check_pointer("heap value")?;
+    let address = heap.alloc(HeapValue::WeakRef(target))?;
+    Ok(Value::Pointer(address))
+}
+
+fn deref_weak(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    let weak_ref = args[0].check_pointer("weak reference")?;
+    let target = heap.deref(weak_ref).check_weak_ref()?;
+
+    let is_live = heap.is_live(target);
+    let field_values = if is_live {
+        vec![Value::Bool(true), Value::Pointer(target)]
+    } else {
+        vec![Value::Bool(false), Value::Int(0)]
+    };
+    let address = heap.alloc(HeapValue::Tuple(Tuple::new(field_values)))?;
+    if is_live {
+        heap.inc_refcount(target);
+    }
+    Ok(Value::Pointer(address))
+}
+
+// The runtime types that `type_of` tells apart. The language has no strings,
+// so guest code compares the result with the integer tags, in the order of
+// the variants starting at 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TypeTag {
+    Int,
+    Bool,
+    Tuple,
+    Closure,
+    Native,
+    Host,
+    Generator,
+    WeakRef,
+    Thunk,
+}
+
+impl TypeTag {
+    pub const ALL: [TypeTag; 9] = [
+        TypeTag::Int,
+        TypeTag::Bool,
+        TypeTag::Tuple,
+        TypeTag::Closure,
+        TypeTag::Native,
+        TypeTag::Host,
+        TypeTag::Generator,
+        TypeTag::WeakRef,
+        TypeTag::Thunk,
+    ];
+
+    pub fn of(heap: &dyn Heap, value: Value) -> Self {
+        match value {
+            Value::Int(_) => TypeTag::Int,
+            Value::Bool(_) => TypeTag::Bool,
+            Value::Pointer(address) => match heap.deref(address) {
+                HeapValue::Tuple(_) => TypeTag::Tuple,
+                HeapValue::Closure(_) => TypeTag::Closure,
+                HeapValue::NativeFn(_) => TypeTag::Native,
+                HeapValue::Host(_) => TypeTag::Host,
+                HeapValue::Generator(_) => TypeTag::Generator,
+                HeapValue::WeakRef(_) => TypeTag::WeakRef,
+                HeapValue::Thunk(_) => TypeTag::Thunk,
+            },
+        }
+    }
+
+    pub fn tag(self) -> i64 {
+        self as i64
+    }
+
+    pub fn from_tag(tag: i64) -> Option<Self> {
+        usize::try_from(tag)
+            .ok()
+            .and_then(|index| Self::ALL.get(index).copied())
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TypeTag::Int => "int",
+            TypeTag::Bool => "bool",
+            TypeTag::Tuple => "tuple",
+            TypeTag::Closure => "closure",
+            TypeTag::Native => "native",
+            TypeTag::Host => "host",
+            TypeTag::Generator => "generator",
+            TypeTag::WeakRef => "weak",
+            TypeTag::Thunk => "thunk",
+        }
+    }
+}
+
+fn type_of(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Int(TypeTag::of(heap, args[0]).tag()))
+}
+
+fn copy(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    let is_tuple = |heap: &dyn Heap, value: Value| {
+        value
+            .address()
+            .filter(|address| matches!(heap.deref(*address), HeapValue::Tuple(_)))
+    };
+    let Some(root) = is_tuple(heap, args[0]) else {
+        return Ok(args[0]);
+    };
+
+    // Every tuple is found before any of them is copied, so that the copies
+    // can refer to each other in the same way as the originals do. The
+    // worklist keeps deep structures such as long lists off the host stack.
+    let mut originals = vec![root];
+    let mut seen = HashSet::from([root]);
+    let mut worklist = vec![root];
+    while let Some(address) = worklist.pop() {
+        let fields = &heap.deref(address).check_tuple()?.field_values;
+        for field in fields {
+            if let Some(field) = is_tuple(heap, *field) {
+                if seen.insert(field) {
+                    originals.push(field);
+                    worklist.push(field);
+                }
+            }
+        }
+    }
+
+    // The copies start out empty, so that they can be freed again if an
+    // allocation fails.
+    let mut copies: HashMap<HeapAddress, HeapAddress> = HashMap::new();
+    for original in &originals {
+        match heap.alloc(HeapValue::Tuple(Tuple::new(Vec::new()))) {
+            Ok(address) => {
+                copies.insert(*original, address);
+            }
+            Err(error) => {
+                for address in copies.into_values() {
+                    heap.inc_refcount(address);
+                    heap.dec_refcount(address, &mut |_| {});
+                }
+                return Err(error);
+            }
+        }
+    }
+
+    for original in &originals {
+        let field_values: Vec<Value> = heap
+            .deref(*original)
+            .check_tuple()?
+            .field_values
+            .iter()
+            .map(|field| match field.address().and_then(|a| copies.get(&a)) {
+                Some(copy) => Value::Pointer(*copy),
+                None => *field,
+            })
+            .collect();
+        for field in &field_values {
+            if let Some(address) = field.address() {
+                heap.inc_refcount(address);
+            }
+        }
+        heap.deref_mut(copies[original])
+            .check_tuple_mut()?
+            .field_values = field_values;
+    }
+
+    Ok(Value::Pointer(copies[&root]))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// The kinds of values are mixed into the hash, so that 1 and true differ.
+const HASH_INT: u64 = 0;
+const HASH_BOOL: u64 = 1;
+const HASH_TUPLE: u64 = 2;
+const HASH_CYCLE: u64 = 3;
+
+fn mix(state: u64, word: u64) -> u64 {
+    word.to_le_bytes().iter().fold(state, |state, byte| {
+        (state ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// A tuple whose fields are being hashed.
+struct HashFrame {
+    address: HeapAddress,
+    fields: Vec<Value>,
+    next_field: usize,
+    state: u64,
+}
+
+// A structural hash of a value made of integers, booleans and tuples, which
+// is the same in every run and only depends on the structure: tuples with
+// equal fields hash the same whether or not they are shared. A tuple that
+// contains itself contributes a fixed marker where the cycle closes. Other
+// values have no structure to hash, so they are reported as a type mismatch.
+// The hash of every tuple is computed once, and the tuples are visited with a
+// worklist, so long lists do not recurse deeply.
+pub fn hash_value(heap: &dyn Heap, value: Value) -> Result<i64, RuntimeError> {
+    let mut hashes: HashMap<HeapAddress, u64> = HashMap::new();
+    let mut stack: Vec<HashFrame> = Vec::new();
+    // The tuples on the stack.
+    let mut entered: HashSet<HeapAddress> = HashSet::new();
+    let mut next = value;
+
+    loop {
+        // The hash of the next value, unless it is a tuple that has to be
+        // entered first.
+        let known = match next {
+            Value::Int(value) => Some(mix(mix(FNV_OFFSET, HASH_INT), value as u64)),
+            Value::Bool(value) => Some(mix(mix(FNV_OFFSET, HASH_BOOL), u64::from(value))),
+            Value::Pointer(address) => {
+                if let Some(hash) = hashes.get(&address) {
+                    Some(*hash)
+                } else if entered.contains(&address) {
+                    Some(mix(FNV_OFFSET, HASH_CYCLE))
+                } else {
+                    let fields = match heap.deref(address) {
+                        HeapValue::Tuple(tuple) => tuple.field_values.clone(),
+                        _ => {
+                            return Err(RuntimeError::TypeMismatch {
+                                expected: "int, bool or tuple",
+                            })
+                        }
+                    };
+                    let state = mix(mix(FNV_OFFSET, HASH_TUPLE), fields.len() as u64);
+                    entered.insert(address);
+                    stack.push(HashFrame {
+                        address,
+                        fields,
+                        next_field: 0,
+                        state,
+                    });
+                    None
+                }
+            }
+        };
+
+        let mut hash = known;
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                let hash = hash.expect("the value should have been hashed");
+                return Ok(hash as i64);
+            };
+            if let Some(field_hash) = hash.take() {
+                frame.state = mix(frame.state, field_hash);
+            }
+            if let Some(field) = frame.fields.get(frame.next_field) {
+                frame.next_field += 1;
+                next = *field;
+                break;
+            }
+
+            let frame = stack.pop().expect("frame should be on the stack");
+            entered.remove(&frame.address);
+            hashes.insert(frame.address, frame.state);
+            hash = Some(frame.state);
+        }
+    }
+}
+
+fn hash(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Int(hash_value(heap, args[0])?))
+}
+
+fn freeze(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    heap.deref_mut(args[0].check_pointer("tuple")?)
+        .check_tuple_mut()?
+        .frozen = true;
+    Ok(args[0])
+}
+
+fn sort_by(context: &mut dyn NativeContext, args: &[Value]) -> Result<Value, RuntimeError> {
+    let less = args[1];
+    let fields = context
+        .heap()
+        .deref(args[0].check_pointer("tuple")?)
+        .check_tuple()?
+        .field_values
+        .clone();
+
+    // The comparison may change the tuple, so the fields are held by the sort
+    // itself. The new tuple takes over these references.
+    for field in &fields {
+        if let Some(address) = field.address() {
+            context.heap().inc_refcount(address);
+        }
+    }
+
+    let mut sorted = fields.clone();
+    let result = merge_sort(context, less, &mut sorted)
+        .and_then(|()| context.heap().alloc(HeapValue::Tuple(Tuple::new(sorted))));
+    match result {
+        Ok(address) => Ok(Value::Pointer(address)),
+        Err(error) => {
+            for field in fields {
+                if let Some(address) = field.address() {
+                    context.heap().dec_refcount(address, &mut |_| {});
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+// A merge sort, since the comparison can fail. Taking from the right half only
+// if it is strictly less keeps equal values in their original order.
+fn merge_sort(
+    context: &mut dyn NativeContext,
+    less: Value,
+    values: &mut Vec<Value>,
+) -> Result<(), RuntimeError> {
+    if values.len() <= 1 {
+        return Ok(());
+    }
+
+    let mut right = values.split_off(values.len() / 2);
+    merge_sort(context, less, values)?;
+    merge_sort(context, less, &mut right)?;
+
+    let left = std::mem::take(values);
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if context.call(less, &[right[j], left[i]])?.check_bool()? {
+            values.push(right[j]);
+            j += 1;
+        } else {
+            values.push(left[i]);
+            i += 1;
+        }
+    }
+    values.extend_from_slice(&left[i..]);
+    values.extend_from_slice(&right[j..]);
+    Ok(())
+}
+
+fn less(_heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Bool(args[0].check_int()? < args[1].check_int()?))
+}
+
+fn assert(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    if !args[0].check_bool()? {
+        return Err(RuntimeError::Native("assertion failed".to_owned()));
+    }
+    let address = heap.alloc(HeapValue::Tuple(Tuple::new(Vec::new())))?;
+    Ok(Value::Pointer(address))
+}
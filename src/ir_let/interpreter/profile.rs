@@ -0,0 +1,198 @@
+use crate::ir_let::interpreter::heap::HeapStats;
+use crate::ir_let::let_expr::Program;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionAllocations {
+    pub allocations: usize,
+    pub bytes: usize,
+}
+
+// The allocations made while executing the instructions of each function,
+// indexed by function. Allocations by native functions are attributed to the
+// function that called them.
+#[derive(Debug, Clone, Default)]
+pub struct AllocationProfile {
+    pub functions: Vec<FunctionAllocations>,
+}
+
+impl AllocationProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Attributes the difference between the heap statistics before and after
+    // an instruction to the function of the instruction.
+    pub fn record(&mut self, function_index: usize, before: &HeapStats, after: &HeapStats) {
+        if self.functions.len() <= function_index {
+            self.functions
+                .resize(function_index + 1, FunctionAllocations::default());
+        }
+
+        let function = &mut self.functions[function_index];
+        function.allocations += after.allocations - before.allocations;
+        function.bytes += after.allocated_bytes - before.allocated_bytes;
+    }
+
+    // The functions that allocated anything, the largest volume first.
+    pub fn report(&self, program: &Program) -> String {
+        let mut functions: Vec<(usize, &FunctionAllocations)> = self
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.allocations > 0)
+            .collect();
+        functions.sort_by_key(|(index, f)| (std::cmp::Reverse(f.bytes), *index));
+
+        let mut out = String::new();
+        writeln!(out, "{:>12} {:>12}  function", "bytes", "allocations").unwrap();
+        for (index, f) in functions {
+            writeln!(
+                out,
+                "{:>12} {:>12}  {}",
+                f.bytes, f.allocations, program.functions[index].name
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl ReportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(ReportFormat::Text),
+            "json" => Some(ReportFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionExecution {
+    pub instructions: usize,
+    // Calls made by the function, and calls made to it.
+    pub calls_made: usize,
+    pub times_called: usize,
+}
+
+// The instructions executed in each function, indexed by function, and the
+// number of calls between every pair of functions. Calls to native functions
+// are not counted.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionProfile {
+    pub functions: Vec<FunctionExecution>,
+    pub call_edges: HashMap<(usize, usize), usize>,
+}
+
+impl ExecutionProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn function_mut(&mut self, function_index: usize) -> &mut FunctionExecution {
+        if self.functions.len() <= function_index {
+            self.functions
+                .resize(function_index + 1, FunctionExecution::default());
+        }
+        &mut self.functions[function_index]
+    }
+
+    pub fn record_instruction(&mut self, function_index: usize) {
+        self.function_mut(function_index).instructions += 1;
+    }
+
+    pub fn record_call(&mut self, caller_index: usize, callee_index: usize) {
+        self.function_mut(caller_index).calls_made += 1;
+        self.function_mut(callee_index).times_called += 1;
+        *self
+            .call_edges
+            .entry((caller_index, callee_index))
+            .or_insert(0) += 1;
+    }
+
+    // The functions are listed by the number of instructions they executed,
+    // and the call edges by their count, the largest first.
+    pub fn report(&self, program: &Program, format: ReportFormat) -> String {
+        let mut functions: Vec<(usize, &FunctionExecution)> = self
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.instructions > 0)
+            .collect();
+        functions.sort_by_key(|(index, f)| (std::cmp::Reverse(f.instructions), *index));
+
+        let mut edges: Vec<(&(usize, usize), &usize)> = self.call_edges.iter().collect();
+        edges.sort_by_key(|(edge, count)| (std::cmp::Reverse(**count), **edge));
+
+        let name = |index: usize| program.functions[index].name.as_str();
+
+        let mut out = String::new();
+        match format {
+            ReportFormat::Text => {
+                writeln!(
+                    out,
+                    "{:>12} {:>10} {:>12}  function",
+                    "instructions", "calls made", "times called"
+                )
+                .unwrap();
+                for (index, f) in functions {
+                    writeln!(
+                        out,
+                        "{:>12} {:>10} {:>12}  {}",
+                        f.instructions,
+                        f.calls_made,
+                        f.times_called,
+                        name(index)
+                    )
+                    .unwrap();
+                }
+
+                writeln!(out, "{:>12}  call edge", "calls").unwrap();
+                for ((caller, callee), count) in edges {
+                    writeln!(out, "{:>12}  {} -> {}", count, name(*caller), name(*callee)).unwrap();
+                }
+            }
+            // Names are written as Rust string literals, which are valid JSON
+            // strings for the identifiers that name functions.
+            ReportFormat::Json => {
+                let functions: Vec<String> = functions
+                    .into_iter()
+                    .map(|(index, f)| {
+                        format!(
+                            "    {{\"name\": {:?}, \"instructions\": {}, \"calls_made\": {}, \"times_called\": {}}}",
+                            name(index),
+                            f.instructions,
+                            f.calls_made,
+                            f.times_called
+                        )
+                    })
+                    .collect();
+                let edges: Vec<String> = edges
+                    .into_iter()
+                    .map(|((caller, callee), count)| {
+                        format!(
+                            "    {{\"caller\": {:?}, \"callee\": {:?}, \"calls\": {}}}",
+                            name(*caller),
+                            name(*callee),
+                            count
+                        )
+                    })
+                    .collect();
+
+                writeln!(out, "{{").unwrap();
+                writeln!(out, "  \"functions\": [\n{}\n  ],", functions.join(",\n")).unwrap();
+                writeln!(out, "  \"call_edges\": [\n{}\n  ]", edges.join(",\n")).unwrap();
+                writeln!(out, "}}").unwrap();
+            }
+        }
+        out
+    }
+}
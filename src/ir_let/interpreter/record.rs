@@ -0,0 +1,198 @@
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::result::Result;
+use crate::symbol::Symbol;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+// The results of nondeterministic native functions are the only inputs of a
+// program that can differ between runs. They are kept as host values, and
+// only those that can be rebuilt on replay are recorded, which rules out
+// closures and host values.
+pub type RecordedValue = crate::embed::Value;
+
+// A failing native function is recorded with its error, which is replayed as
+// it was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    pub native: String,
+    pub result: std::result::Result<RecordedValue, RuntimeError>,
+}
+
+// The calls to nondeterministic native functions during a run, in the order
+// in which they were made. The text format has a line per call:
+// "ok <native> <value>" or "err <native> <error>", where the error is its
+// variant in snake case followed by its fields, such as
+// "index_out_of_range 5 3".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub calls: Vec<RecordedCall>,
+}
+
+// Values can be nested as deeply as a long list, so the tuples that are
+// being parsed are kept on an explicit stack.
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<RecordedValue> {
+    let mut open: Vec<Vec<RecordedValue>> = Vec::new();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let value = if chars.next_if_eq(&'(').is_some() {
+            open.push(Vec::new());
+            continue;
+        } else if !open.is_empty() && chars.next_if_eq(&')').is_some() {
+            RecordedValue::tuple(open.pop().expect("expected open tuple"))
+        } else {
+            parse_word(chars)?
+        };
+
+        let Some(fields) = open.last_mut() else {
+            return Ok(value);
+        };
+        fields.push(value);
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next_if_eq(&',').is_none() && chars.peek() != Some(&')') {
+            return Err("expected , or ) in tuple".into());
+        }
+    }
+}
+
+fn parse_word(chars: &mut Peekable<Chars>) -> Result<RecordedValue> {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '-') {
+        word.push(c);
+    }
+
+    match word.as_str() {
+        "true" => Ok(RecordedValue::Bool(true)),
+        "false" => Ok(RecordedValue::Bool(false)),
+        _ => {
+            Ok(RecordedValue::Int(word.parse().map_err(|_| {
+                format!("invalid recorded value {:?}", word)
+            })?))
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(text: Option<&str>) -> Result<T> {
+    let text = text.unwrap_or("");
+    text.parse()
+        .map_err(|_| format!("invalid number {:?} in recorded error", text).into())
+}
+
+fn parse_error(text: &str) -> Result<RuntimeError> {
+    let (kind, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let mut numbers = rest.split(' ');
+    let error = match kind {
+        "out_of_memory" => RuntimeError::OutOfMemory,
+        "stack_overflow" => RuntimeError::StackOverflow,
+        "out_of_fuel" => RuntimeError::OutOfFuel,
+        "type_mismatch" => RuntimeError::TypeMismatch {
+            expected: Symbol::intern(rest).as_str(),
+        },
+        "arity_mismatch" => RuntimeError::ArityMismatch {
+            expected: parse_number(numbers.next())?,
+            actual: parse_number(numbers.next())?,
+        },
+        "index_out_of_range" => RuntimeError::IndexOutOfRange {
+            index: parse_number(numbers.next())?,
+            size: parse_number(numbers.next())?,
+        },
+        "frozen_tuple" => RuntimeError::FrozenTuple,
+        "overflow" => RuntimeError::Overflow,
+        "unbound_variable" => RuntimeError::UnboundVariable(rest.to_owned()),
+        "native_not_allowed" => RuntimeError::NativeNotAllowed(rest.to_owned()),
+        "native" => RuntimeError::Native(rest.to_owned()),
+        _ => return Err(format!("unknown recorded error {}", kind).into()),
+    };
+    Ok(error)
+}
+
+fn write_error(f: &mut fmt::Formatter, error: &RuntimeError) -> fmt::Result {
+    match error {
+        RuntimeError::OutOfMemory => write!(f, "out_of_memory"),
+        RuntimeError::StackOverflow => write!(f, "stack_overflow"),
+        RuntimeError::OutOfFuel => write!(f, "out_of_fuel"),
+        RuntimeError::TypeMismatch { expected } => write!(f, "type_mismatch {}", expected),
+        RuntimeError::ArityMismatch { expected, actual } => {
+            write!(f, "arity_mismatch {} {}", expected, actual)
+        }
+        RuntimeError::IndexOutOfRange { index, size } => {
+            write!(f, "index_out_of_range {} {}", index, size)
+        }
+        RuntimeError::FrozenTuple => write!(f, "frozen_tuple"),
+        RuntimeError::Overflow => write!(f, "overflow"),
+        RuntimeError::UnboundVariable(name) => write!(f, "unbound_variable {}", name),
+        RuntimeError::NativeNotAllowed(name) => write!(f, "native_not_allowed {}", name),
+        RuntimeError::Native(message) => write!(f, "native {}", message),
+    }
+}
+
+impl Recording {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut calls = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(3, ' ');
+            let (kind, native, rest) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(kind), Some(native), Some(rest)) => (kind, native, rest),
+                _ => return Err(format!("line {}: incomplete call", line_number + 1).into()),
+            };
+
+            let result = match kind {
+                "ok" => {
+                    let mut chars = rest.chars().peekable();
+                    let value = parse_value(&mut chars)?;
+                    if chars.any(|c| !c.is_whitespace()) {
+                        return Err(format!("line {}: trailing input", line_number + 1).into());
+                    }
+                    Ok(value)
+                }
+                "err" => Err(parse_error(rest)
+                    .map_err(|error| format!("line {}: {}", line_number + 1, error))?),
+                _ => return Err(format!("line {}: unknown kind {}", line_number + 1, kind).into()),
+            };
+
+            calls.push(RecordedCall {
+                native: native.to_owned(),
+                result,
+            });
+        }
+
+        Ok(Recording { calls })
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for call in &self.calls {
+            match &call.result {
+                Ok(value) => writeln!(f, "ok {} {}", call.native, value)?,
+                Err(error) => {
+                    write!(f, "err {} ", call.native)?;
+                    write_error(f, error)?;
+                    writeln!(f)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// While recording, the result of every call to a nondeterministic native
+// function is logged. While replaying, those natives are not called at all,
+// and their results are taken from the recording instead.
+#[derive(Debug, Default)]
+pub enum RecordMode {
+    #[default]
+    Off,
+    Recording(Recording),
+    Replaying {
+        recording: Recording,
+        position: usize,
+    },
+}
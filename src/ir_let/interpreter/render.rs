@@ -0,0 +1,56 @@
+use crate::ir_let::interpreter::heap::Heap;
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue, Value};
+
+// Writes a value the way it would be written in the source program, following
+// pointers into the heap. Closures are only shown by their name.
+pub fn render<H: Heap>(value: Value, heap: &H) -> String {
+    let mut out = String::new();
+    render_value(value, heap, &mut Vec::new(), &mut out);
+    out
+}
+
+// A tuple can contain itself after it has been mutated, so the tuples that are
+// currently being written are tracked to avoid recursing forever.
+fn render_value<H: Heap>(
+    value: Value,
+    heap: &H,
+    enclosing: &mut Vec<HeapAddress>,
+    out: &mut String,
+) {
+    let address = match value {
+        Value::Int(value) => return out.push_str(&value.to_string()),
+        Value::Bool(value) => return out.push_str(&value.to_string()),
+        Value::Pointer(address) => address,
+    };
+
+    match heap.deref(address) {
+        HeapValue::Closure(closure) => {
+            out.push_str(&format!("<closure {}>", closure.name.source_name()));
+        }
+        HeapValue::NativeFn(native) => {
+            out.push_str(&format!("<native {}>", native.name));
+        }
+        HeapValue::Host(_) => out.push_str("<host value>"),
+        HeapValue::Generator(_) => out.push_str("<generator>"),
+        HeapValue::WeakRef(_) => out.push_str("<weak reference>"),
+        HeapValue::Thunk(_) => out.push_str("<thunk>"),
+        HeapValue::Tuple(_) if enclosing.contains(&address) => out.push_str("<cycle>"),
+        HeapValue::Tuple(tuple) => {
+            enclosing.push(address);
+            out.push('(');
+            for (i, field_value) in tuple.field_values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                render_value(*field_value, heap, enclosing, out);
+            }
+            // A tuple with a single field would otherwise look like a value
+            // in parentheses.
+            if tuple.field_values.len() == 1 {
+                out.push(',');
+            }
+            out.push(')');
+            enclosing.pop();
+        }
+    }
+}
@@ -1,87 +1,313 @@
-use crate::ir_let::interpreter::heap::Heap;
-use crate::ir_let::interpreter::heap_value::{Closure, HeapAddress, HeapValue, Tuple};
-use crate::ir_let::interpreter::stack::{ReturnInfo, Stack};
+use crate::ir_let::interpreter::backtrace::{Backtrace, StackEntry};
+use crate::ir_let::interpreter::config::{EvaluatorConfig, SandboxConfig};
+use crate::ir_let::interpreter::deferred::{DeferredRefCounts, DeferredStats};
+use crate::ir_let::interpreter::error::RuntimeError;
+use crate::ir_let::interpreter::heap::{DumpFormat, Heap, RefCountHeap};
+use crate::ir_let::interpreter::heap_value::{
+    Closure, Generator, HeapAddress, HeapValue, HostValue, Thunk, Tuple, Value,
+};
+use crate::ir_let::interpreter::native::{
+    Intrinsic, NativeBody, NativeCode, NativeContext, NativeFunction, NativeHeap, Natives,
+    ReentrantCode,
+};
+use crate::ir_let::interpreter::profile::{AllocationProfile, ExecutionProfile};
+use crate::ir_let::interpreter::record::{RecordMode, RecordedCall, RecordedValue, Recording};
+use crate::ir_let::interpreter::stack::{Parameters, ReturnInfo, Stack};
+use crate::ir_let::interpreter::trace::{NoTrace, TraceSink};
 use crate::ir_let::let_expr::{
     AllocClosure, Assignment, Control, Definition, Instruction, Program, Simple, Step,
-    TargetAddress, VariableReference,
+    TargetAddress, VariableLocation, VariableReference,
 };
+use crate::ir_let::resolve::resolve_variables;
+use crate::ir_let::verify::verify_program;
 use crate::lang::syntax::{BinOp, Constant};
-use std::collections::HashMap;
+use crate::symbol::Symbol;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
 
+// A generator that is running, together with the depth of the call stack at
+// which its frames start.
 #[derive(Debug)]
-struct InstructionEvaluator {
-    heap: Heap,
+struct ActiveGenerator {
+    address: HeapAddress,
+    depth: usize,
+}
+
+// A thunk whose function is running, together with the depth of the call stack
+// at which the frame of the function starts.
+#[derive(Debug)]
+struct ActiveThunk {
+    address: HeapAddress,
+    depth: usize,
+}
+
+#[derive(Debug)]
+struct InstructionEvaluator<H: Heap> {
+    heap: H,
     stack: Stack,
+    config: EvaluatorConfig,
+    trace: Box<dyn TraceSink>,
+    // The innermost running generator comes last.
+    generators: Vec<ActiveGenerator>,
+    // The innermost thunk that is being forced comes last.
+    thunks: Vec<ActiveThunk>,
+    // The depth of the call stack at every call of a reentrant native
+    // function that is running, the innermost last.
+    native_depths: Vec<usize>,
+    record_mode: RecordMode,
+    execution_profile: Option<ExecutionProfile>,
+    // The closures of lifted functions by function index, which are
+    // allocated the first time they are needed and kept alive until the
+    // program has finished.
+    lifted_closures: BTreeMap<usize, HeapAddress>,
+    // The pending updates of stack slots, if they are deferred.
+    deferred: Option<DeferredRefCounts>,
+    // The number of instructions that may still be executed, if limited.
+    fuel: Option<u64>,
 }
 
-impl InstructionEvaluator {
-    fn new() -> Self {
+impl<H: Heap> InstructionEvaluator<H> {
+    fn new(heap: H, config: EvaluatorConfig) -> Self {
         InstructionEvaluator {
-            heap: Heap::new(),
+            heap,
             stack: Stack::new(),
+            trace: Box::new(NoTrace),
+            generators: Vec::new(),
+            thunks: Vec::new(),
+            native_depths: Vec::new(),
+            record_mode: RecordMode::Off,
+            execution_profile: None,
+            lifted_closures: BTreeMap::new(),
+            deferred: config.deferred_refcounts.then(DeferredRefCounts::new),
+            fuel: config.max_instructions,
+            config,
         }
     }
 
-    fn set_var(&mut self, name: String, address: HeapAddress) {
-        self.heap.inc_refcount(address);
-        self.stack.set_var_no_refcount(name, address);
+    fn check_heap_limit(&self) -> Result<(), RuntimeError> {
+        if let Some(max_heap_objects) = self.config.max_heap_objects {
+            if self.heap.stats().live_objects >= max_heap_objects {
+                return Err(RuntimeError::OutOfMemory);
+            }
+        }
+
+        Ok(())
     }
 
-    fn eval_binop(
-        &mut self,
-        op: BinOp,
-        lhs_addr: HeapAddress,
-        rhs_addr: HeapAddress,
-    ) -> HeapAddress {
+    fn alloc(&mut self, heap_value: HeapValue) -> Result<HeapAddress, RuntimeError> {
+        self.check_heap_limit()?;
+        let address = self.heap.alloc(heap_value)?;
+        self.trace.alloc(address, self.heap.deref(address));
+        Ok(address)
+    }
+
+    // Reference counting only applies to values stored on the heap.
+    fn inc_refcount(&mut self, value: Value) {
+        if let Value::Pointer(address) = value {
+            self.heap.inc_refcount(address);
+        }
+    }
+
+    // A pending increment may be all that keeps the object alive, so they are
+    // applied before the decrement.
+    fn dec_refcount(&mut self, value: Value) {
+        self.reconcile();
+        if let Value::Pointer(address) = value {
+            self.dec_refcount_now(address);
+        }
+    }
+
+    fn dec_refcount_now(&mut self, address: HeapAddress) {
+        let trace = &mut self.trace;
+        self.heap
+            .dec_refcount(address, &mut |freed| trace.free(freed));
+    }
+
+    // Reference count updates for values that are written into or removed
+    // from stack slots, which are buffered if they are deferred.
+    fn retain_slot(&mut self, value: Value) {
+        match (&mut self.deferred, value) {
+            (Some(deferred), Value::Pointer(address)) => deferred.retain(address),
+            _ => self.inc_refcount(value),
+        }
+    }
+
+    fn release_slot(&mut self, value: Value) {
+        match (&mut self.deferred, value) {
+            (Some(deferred), Value::Pointer(address)) => deferred.release(address),
+            _ => self.dec_refcount(value),
+        }
+    }
+
+    // Applies the pending updates of stack slots, at a safe point or before
+    // anything that depends on the exact reference counts.
+    fn reconcile(&mut self) {
+        let Some(deferred) = &mut self.deferred else {
+            return;
+        };
+        if deferred.is_empty() {
+            return;
+        }
+
+        let reconciliation = deferred.take();
+        for address in reconciliation.increments {
+            self.heap.inc_refcount(address);
+        }
+        for address in reconciliation.decrements {
+            self.dec_refcount_now(address);
+        }
+        for address in reconciliation.cancelled {
+            let trace = &mut self.trace;
+            self.heap
+                .free_if_unreferenced(address, &mut |freed| trace.free(freed));
+        }
+    }
+
+    fn push_var(&mut self, value: Value) {
+        self.retain_slot(value);
+        self.stack.push_var_no_refcount(value);
+    }
+
+    fn eval_binop(&mut self, op: BinOp, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
         match op {
-            BinOp::Add => {
-                let lhs_value = self.heap.deref(lhs_addr).check_int();
-                let rhs_value = self.heap.deref(rhs_addr).check_int();
-                self.heap.alloc(HeapValue::Int(lhs_value + rhs_value))
-            }
-            BinOp::Sub => {
-                let lhs_value = self.heap.deref(lhs_addr).check_int();
-                let rhs_value = self.heap.deref(rhs_addr).check_int();
-                self.heap.alloc(HeapValue::Int(lhs_value - rhs_value))
-            }
-            BinOp::Eq => {
-                let lhs_value = self.heap.deref(lhs_addr).check_int();
-                let rhs_value = self.heap.deref(rhs_addr).check_int();
-                self.heap.alloc(HeapValue::Bool(lhs_value == rhs_value))
-            }
+            BinOp::Add => Ok(Value::Int(
+                self.config
+                    .overflow
+                    .add(lhs.check_int()?, rhs.check_int()?)?,
+            )),
+            BinOp::Sub => Ok(Value::Int(
+                self.config
+                    .overflow
+                    .sub(lhs.check_int()?, rhs.check_int()?)?,
+            )),
+            BinOp::Eq => Ok(Value::Bool(lhs.check_int()? == rhs.check_int()?)),
             BinOp::Get => {
-                let tuple = self.heap.deref(lhs_addr).check_tuple();
-                let index = self.heap.deref(rhs_addr).check_int();
+                let tuple = self.heap.deref(lhs.check_pointer("tuple")?).check_tuple()?;
+                let index = rhs.check_int()?;
 
-                match tuple.field_values.get(index as usize) {
-                    Some(value) => *value,
-                    None => panic!("field index out of range"),
+                match usize::try_from(index)
+                    .ok()
+                    .and_then(|i| tuple.field_values.get(i))
+                {
+                    Some(value) => Ok(*value),
+                    None => Err(RuntimeError::IndexOutOfRange {
+                        index,
+                        size: tuple.field_values.len(),
+                    }),
                 }
             }
         }
     }
 
-    fn eval_var(&mut self, e: &VariableReference) -> HeapAddress {
-        self.stack.lookup_var(&e.var_name)
+    // Variables that could not be resolved are unbound.
+    fn eval_var(&mut self, e: &VariableReference) -> Result<Value, RuntimeError> {
+        match e.location {
+            Some(VariableLocation::Closure(offset)) => self.load_captured(offset),
+            Some(location) => Ok(self.stack.load(location)),
+            None => Err(RuntimeError::UnboundVariable(e.var_name.to_string())),
+        }
+    }
+
+    // Captured variables are read from the environment of the closure that
+    // the current function was called through.
+    fn load_captured(&self, offset: usize) -> Result<Value, RuntimeError> {
+        match self.stack.this() {
+            Some(this) => {
+                let closure = self
+                    .heap
+                    .deref(this.check_pointer("closure")?)
+                    .check_closure()?;
+                Ok(closure.environment[offset])
+            }
+            None => Ok(self.stack.toplevel_environment()[offset]),
+        }
+    }
+
+    fn alloc_tuple(&mut self, args: &[VariableReference]) -> Result<Value, RuntimeError> {
+        // Storage taken from the pool would be lost if the allocation failed
+        // afterwards.
+        self.check_heap_limit()?;
+        let mut field_values = self.heap.tuple_storage(args.len());
+
+        for arg in args {
+            field_values.push(self.eval_var(arg)?);
+        }
+
+        // The tuple only takes ownership of its fields once it has been
+        // allocated, so a failed allocation leaks nothing.
+        let tuple_address = self.alloc(HeapValue::Tuple(Tuple::new(field_values)))?;
+
+        for index in 0..args.len() {
+            let value = self.heap.deref(tuple_address).check_tuple()?.field_values[index];
+            self.inc_refcount(value);
+        }
+
+        Ok(Value::Pointer(tuple_address))
+    }
+
+    // The address of the reused tuple if it has the same number of fields as
+    // the new one, and the variable holds the only reference to it. Frozen
+    // tuples are never changed, not even when nothing else can see them.
+    fn reusable_tuple(
+        &mut self,
+        reused: &VariableReference,
+        arity: usize,
+    ) -> Result<Option<HeapAddress>, RuntimeError> {
+        let Value::Pointer(address) = self.eval_var(reused)? else {
+            return Ok(None);
+        };
+        match self.heap.deref(address) {
+            HeapValue::Tuple(tuple) if tuple.field_values.len() == arity && !tuple.frozen => {}
+            _ => return Ok(None),
+        }
+
+        self.reconcile();
+        Ok(self.heap.reuse_in_place(address).then_some(address))
     }
 
-    fn eval_simple(&mut self, e: &Simple) -> HeapAddress {
+    fn eval_simple(&mut self, program: &Program, e: &Simple) -> Result<Value, RuntimeError> {
         match e {
-            Simple::Literal(Constant::Int { value }) => self.heap.alloc(HeapValue::Int(*value)),
-            Simple::Literal(Constant::Bool { value }) => self.heap.alloc(HeapValue::Bool(*value)),
-            Simple::Tuple { args } => {
-                let mut field_values = Vec::new();
+            Simple::Literal(Constant::Int { value }) => Ok(Value::Int(*value)),
+            Simple::Literal(Constant::Bool { value }) => Ok(Value::Bool(*value)),
+            Simple::Tuple { args } => self.alloc_tuple(args),
+            Simple::ReuseTuple { reused, args } => match self.reusable_tuple(reused, args.len())? {
+                Some(address) => {
+                    // Every new field is also held by its variable, so
+                    // releasing an old field cannot free a new one.
+                    for (index, arg) in args.iter().enumerate() {
+                        let value = self.eval_var(arg)?;
+                        self.inc_refcount(value);
+                        let tuple = self.heap.deref_mut(address).check_tuple_mut()?;
+                        let old_value = std::mem::replace(&mut tuple.field_values[index], value);
+                        self.dec_refcount(old_value);
+                    }
 
-                for arg in args {
-                    let value_addr = self.eval_var(arg);
-                    field_values.push(value_addr);
+                    Ok(Value::Pointer(address))
                 }
-
-                for addr in &field_values {
-                    self.heap.inc_refcount(*addr);
+                None => self.alloc_tuple(args),
+            },
+            Simple::LiftedFun {
+                name,
+                function_index,
+            } => {
+                if let Some(address) = self.lifted_closures.get(function_index) {
+                    return Ok(Value::Pointer(*address));
                 }
 
-                self.heap.alloc(HeapValue::Tuple(Tuple { field_values }))
+                let address = self.alloc(HeapValue::Closure(Closure {
+                    name: *name,
+                    arg_names: program.functions[*function_index].arg_names.clone(),
+                    environment: Vec::new(),
+                    body: TargetAddress {
+                        function_index: *function_index,
+                        block_index: 0,
+                        instruction_index: 0,
+                    },
+                }))?;
+                self.heap.inc_refcount(address);
+                self.lifted_closures.insert(*function_index, address);
+                Ok(Value::Pointer(address))
             }
             Simple::Fun(AllocClosure {
                 name,
@@ -89,191 +315,1400 @@ impl InstructionEvaluator {
                 free_names,
                 body,
             }) => {
-                let mut closure_environment = HashMap::new();
-
-                for free_name in free_names {
-                    let value_addr = self.stack.lookup_var(free_name);
-
-                    closure_environment.insert(free_name.clone(), value_addr);
-                }
-
-                for value_addr in closure_environment.values() {
-                    self.heap.inc_refcount(*value_addr);
-                }
+                let closure_environment = free_names
+                    .iter()
+                    .map(|var| self.eval_var(var))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()?;
+                let captured_values = closure_environment.clone();
 
-                self.heap.alloc(HeapValue::Closure(Closure {
-                    name: name.clone(),
+                let closure_address = self.alloc(HeapValue::Closure(Closure {
+                    name: *name,
                     arg_names: arg_names.clone(),
                     environment: closure_environment,
                     body: *body,
-                }))
+                }))?;
+
+                for value in captured_values {
+                    self.inc_refcount(value);
+                }
+
+                Ok(Value::Pointer(closure_address))
             }
             Simple::BinOp { op, lhs, rhs } => {
-                let lhs_address = self.eval_var(lhs);
-                let rhs_address = self.eval_var(rhs);
-                self.eval_binop(*op, lhs_address, rhs_address)
+                let lhs_value = self.eval_var(lhs)?;
+                let rhs_value = self.eval_var(rhs)?;
+                self.eval_binop(*op, lhs_value, rhs_value)
             }
             Simple::Set {
                 tuple,
                 index,
                 new_value,
             } => {
-                let tuple_address = self.eval_var(tuple);
-                let new_value = self.eval_var(new_value);
+                let tuple_address = self.eval_var(tuple)?.check_pointer("tuple")?;
+                let new_value = self.eval_var(new_value)?;
 
-                let tuple = self.heap.deref_mut(tuple_address).check_tuple_mut();
+                let tuple = self.heap.deref(tuple_address).check_tuple()?;
+                if tuple.frozen {
+                    return Err(RuntimeError::FrozenTuple);
+                }
+                let size = tuple.field_values.len();
+                if *index as usize >= size {
+                    return Err(RuntimeError::IndexOutOfRange {
+                        index: (*index).into(),
+                        size,
+                    });
+                }
 
-                if (*index as usize) < tuple.field_values.len() {
-                    let old_value = tuple.field_values[*index as usize];
-                    tuple.field_values[*index as usize] = new_value;
+                // Allocate the result before mutating, so that running out of
+                // memory leaves the tuple unchanged.
+                let unit_address = self.alloc(HeapValue::Tuple(Tuple::new(Vec::new())))?;
 
-                    // Ordering is important here, because in case new_value == old_value we do
-                    // not want to destroy the value we are assigning, as would happen when we swap the lines.
-                    self.heap.inc_refcount(new_value);
-                    self.heap.dec_refcount(old_value);
-                } else {
-                    panic!("tuple index out of range during mutation");
-                }
+                let tuple = self.heap.deref_mut(tuple_address).check_tuple_mut()?;
+                let old_value = tuple.field_values[*index as usize];
+                tuple.field_values[*index as usize] = new_value;
+
+                // Ordering is important here, because in case new_value == old_value we do
+                // not want to destroy the value we are assigning, as would happen when we swap the lines.
+                self.inc_refcount(new_value);
+                self.dec_refcount(old_value);
 
-                self.heap.alloc(HeapValue::Tuple(Tuple {
-                    field_values: Vec::new(),
-                }))
+                Ok(Value::Pointer(unit_address))
             }
         }
     }
 
-    fn eval_control(&mut self, control: &Control, return_info: ReturnInfo) -> TargetAddress {
+    // For a tail call, the frame of the current function is released and the
+    // callee returns directly to where the current function would have
+    // returned.
+    fn eval_control(
+        &mut self,
+        program: &Program,
+        control: &Control,
+        return_info: ReturnInfo,
+    ) -> Result<TargetAddress, RuntimeError> {
         match control {
-            Control::Call { func, args } => {
-                let closure_address = self.eval_var(func);
+            Control::Call { func, args } | Control::TailCall { func, args } => {
+                if let Control::Call { .. } = control {
+                    self.check_call_depth()?;
+                }
+
+                let closure_value = self.eval_var(func)?;
 
                 let mut arg_values = Vec::new();
                 for arg in args {
-                    arg_values.push(self.eval_var(arg));
+                    arg_values.push(self.eval_var(arg)?);
                 }
 
-                let closure = self.heap.deref(closure_address).check_closure().clone();
-
-                if closure.arg_names.len() != args.len() {
-                    panic!("incorrect number of arguments");
-                }
+                let (name, body) = match self.heap.deref(closure_value.check_pointer("closure")?) {
+                    HeapValue::NativeFn(native) => {
+                        let native = *native;
+                        return self.eval_native_call(program, native, &arg_values, return_info);
+                    }
+                    heap_value => {
+                        let closure = heap_value.check_closure()?;
+                        if closure.arg_names.len() != args.len() {
+                            return Err(RuntimeError::ArityMismatch {
+                                expected: closure.arg_names.len(),
+                                actual: args.len(),
+                            });
+                        }
+                        (closure.name, closure.body)
+                    }
+                };
 
-                self.stack.enter_function(return_info);
+                // Allow the function to recursively calling itself by passing
+                // a pointer to its own closure along with the arguments. The
+                // closure also gives the callee access to its environment.
+                let parameters = Parameters {
+                    this: Some(closure_value),
+                    arguments: arg_values,
+                };
+                let is_tail_call = matches!(control, Control::TailCall { .. });
+                Ok(self.enter_closure(name, body, parameters, is_tail_call, return_info))
+            }
+            // The code of the closure is known, so it does not have to be
+            // looked up.
+            Control::CallDirect {
+                func,
+                function_index,
+                args,
+            } => {
+                self.check_call_depth()?;
 
-                for (name, value) in closure.environment.iter() {
-                    self.set_var(name.clone(), *value);
-                }
+                let closure_value = self.eval_var(func)?;
 
-                for (name, arg_value) in closure.arg_names.iter().zip(arg_values) {
-                    self.set_var(name.clone(), arg_value);
+                let mut arg_values = Vec::new();
+                for arg in args {
+                    arg_values.push(self.eval_var(arg)?);
                 }
 
-                // Allow the function to recursively calling itself by inserting
-                // a pointer to its own closure into its environment when
-                // calling it.
-                self.set_var(closure.name.clone(), closure_address);
-
-                closure.body
+                let parameters = Parameters {
+                    this: Some(closure_value),
+                    arguments: arg_values,
+                };
+                let body = TargetAddress {
+                    function_index: *function_index,
+                    block_index: 0,
+                    instruction_index: 0,
+                };
+                Ok(self.enter_closure(
+                    program.functions[*function_index].name,
+                    body,
+                    parameters,
+                    false,
+                    return_info,
+                ))
             }
             Control::If {
                 condition,
                 branch_success,
                 branch_failure,
             } => {
-                let condition_address = self.eval_var(condition);
-                let condition_value = self.heap.deref(condition_address).check_bool();
+                let condition_value = self.eval_var(condition)?.check_bool()?;
+
+                self.stack.enter_block(return_info);
 
                 if condition_value {
-                    *branch_success
+                    Ok(*branch_success)
                 } else {
-                    *branch_failure
+                    Ok(*branch_failure)
+                }
+            }
+            Control::Jump { target, args } => {
+                let mut arg_values = Vec::new();
+                for arg in args {
+                    arg_values.push(self.eval_var(arg)?);
+                }
+
+                for value in &arg_values {
+                    self.retain_slot(*value);
+                }
+
+                let block_depth =
+                    program.functions[target.function_index].block_depth(target.block_index);
+                for value in self.stack.jump(block_depth, arg_values) {
+                    self.release_slot(value);
+                }
+                if self.deferred.as_ref().is_some_and(|d| d.is_full()) {
+                    self.reconcile();
+                }
+
+                Ok(*target)
+            }
+        }
+    }
+
+    // The stack, the closures of lifted functions and the generators and
+    // thunks that are running, which the frames on the stack need not refer
+    // to anymore.
+    fn roots(&self) -> impl Iterator<Item = HeapAddress> + '_ {
+        self.stack
+            .roots()
+            .chain(self.lifted_closures.values().copied())
+            .chain(self.generators.iter().map(|active| active.address))
+            .chain(self.thunks.iter().map(|active| active.address))
+    }
+
+    fn release_lifted_closures(&mut self) {
+        for (_, address) in std::mem::take(&mut self.lifted_closures) {
+            self.dec_refcount(Value::Pointer(address));
+        }
+    }
+
+    // Running out of fuel leaves the instruction unexecuted, like any other
+    // failure.
+    fn consume_fuel(&mut self) -> Result<(), RuntimeError> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RuntimeError::OutOfFuel);
+            }
+            *fuel -= 1;
+        }
+
+        Ok(())
+    }
+
+    // Native functions are charged for their work once they have returned.
+    fn charge_fuel(&mut self, amount: u64) -> Result<(), RuntimeError> {
+        if let Some(fuel) = &mut self.fuel {
+            if amount > *fuel {
+                *fuel = 0;
+                return Err(RuntimeError::OutOfFuel);
+            }
+            *fuel -= amount;
+        }
+
+        Ok(())
+    }
+
+    fn check_call_depth(&self) -> Result<(), RuntimeError> {
+        if let Some(max_call_depth) = self.config.max_call_depth {
+            if self.stack.call_depth() >= max_call_depth {
+                return Err(RuntimeError::StackOverflow);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn enter_closure(
+        &mut self,
+        name: Symbol,
+        body: TargetAddress,
+        parameters: Parameters,
+        is_tail_call: bool,
+        return_info: ReturnInfo,
+    ) -> TargetAddress {
+        for value in parameters.values().collect::<Vec<_>>() {
+            self.retain_slot(value);
+        }
+
+        let caller_index = return_info.return_address.function_index;
+        let released_values = if is_tail_call {
+            let (caller_return_info, values) = self.stack.exit_function();
+            self.stack.enter_function(caller_return_info, parameters);
+            values
+        } else {
+            self.stack.enter_function(Some(return_info), parameters);
+            Vec::new()
+        };
+
+        self.trace.call(name, body, is_tail_call);
+        if let Some(profile) = &mut self.execution_profile {
+            profile.record_call(caller_index, body.function_index);
+        }
+
+        // Only release the old frame after the callee has taken ownership of
+        // its arguments, which may be stored in it.
+        for value in released_values {
+            self.release_slot(value);
+        }
+        self.reconcile();
+
+        body
+    }
+
+    // A native function is called like any other function, but its result is
+    // available immediately. The instruction after a tail call returns the
+    // result, so that tail calls need no special treatment.
+    fn eval_native_call(
+        &mut self,
+        program: &Program,
+        native: NativeFunction,
+        arg_values: &[Value],
+        return_info: ReturnInfo,
+    ) -> Result<TargetAddress, RuntimeError> {
+        if native.arity != arg_values.len() {
+            return Err(RuntimeError::ArityMismatch {
+                expected: native.arity,
+                actual: arg_values.len(),
+            });
+        }
+
+        // Natives may update reference counts themselves.
+        self.reconcile();
+
+        let result = match native.body {
+            NativeBody::Code(code) => self.run_native_code(code, arg_values)?,
+            NativeBody::Nondeterministic(code) => {
+                self.call_nondeterministic(native, code, arg_values)?
+            }
+            NativeBody::Reentrant(code) => {
+                // The result is owned here, and passed on to the frame.
+                let result =
+                    self.call_reentrant(program, code, arg_values, return_info.return_address)?;
+                self.push_var(result);
+                self.dec_refcount(result);
+                return Ok(return_info.return_address);
+            }
+            NativeBody::Intrinsic(intrinsic) => {
+                return self.eval_intrinsic(intrinsic, arg_values, return_info)
+            }
+        };
+        self.push_var(result);
+        Ok(return_info.return_address)
+    }
+
+    // Other natives always run, also while replaying, since their effects on
+    // the heap, such as freezing a tuple, are part of the run.
+    fn call_nondeterministic(
+        &mut self,
+        native: NativeFunction,
+        code: NativeCode,
+        arg_values: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        match &mut self.record_mode {
+            RecordMode::Off => self.run_native_code(code, arg_values),
+            RecordMode::Recording(_) => self.record_native_call(native, code, arg_values),
+            RecordMode::Replaying { .. } => self.replay_native_call(native),
+        }
+    }
+
+    // Runs the code of a native function on the heap that it sees, and charges
+    // the work that it did. A result that can no longer be paid for is freed
+    // again.
+    fn run_native_code(
+        &mut self,
+        code: NativeCode,
+        arg_values: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        let work = Cell::new(0);
+        let mut heap = NativeHeap::new(
+            &mut self.heap,
+            self.config.max_heap_objects,
+            self.fuel,
+            &work,
+        );
+        let result = code(&mut heap, arg_values);
+        self.paid(result, work.get())
+    }
+
+    // The result of a native function, once its work has been charged. The
+    // result is not owned by anything yet, so it is freed if the fuel runs
+    // out.
+    fn paid(
+        &mut self,
+        result: Result<Value, RuntimeError>,
+        work: u64,
+    ) -> Result<Value, RuntimeError> {
+        let charged = self.charge_fuel(work);
+        match (result, charged) {
+            (Ok(value), Err(error)) => {
+                self.inc_refcount(value);
+                self.dec_refcount(value);
+                Err(error)
+            }
+            (result, _) => result,
+        }
+    }
+
+    // Reentrant natives are not recorded: they only depend on the heap and on
+    // the functions they call, and the natives among those are recorded as
+    // usual. Unlike for other natives, the caller owns a reference to the
+    // result, since the values the native held are released before this
+    // returns.
+    fn call_reentrant(
+        &mut self,
+        program: &Program,
+        code: ReentrantCode,
+        arg_values: &[Value],
+        return_address: TargetAddress,
+    ) -> Result<Value, RuntimeError> {
+        self.native_depths.push(self.stack.frames().len());
+        let mut reentry = Reentry {
+            evaluator: self,
+            program,
+            return_address,
+            held: Vec::new(),
+            work: Cell::new(0),
+        };
+        let result = code(&mut reentry, arg_values);
+        let held = reentry.held;
+        let work = reentry.work.get();
+        self.native_depths.pop();
+        let result = self.paid(result, work);
+
+        if let Ok(value) = result {
+            self.inc_refcount(value);
+        }
+        for value in held {
+            self.dec_refcount(value);
+        }
+        result
+    }
+
+    // Runs a function that a reentrant native calls until it returns, with
+    // its frames on top of the frame of the caller of the native. The caller
+    // owns a reference to the result. If the function fails, its frames are
+    // removed again, so that the native can carry on.
+    fn call_nested(
+        &mut self,
+        program: &Program,
+        function: Value,
+        arg_values: &[Value],
+        return_address: TargetAddress,
+    ) -> Result<Value, RuntimeError> {
+        let (name, body) = match self.heap.deref(function.check_pointer("closure")?) {
+            HeapValue::NativeFn(native) => {
+                let native = *native;
+                if native.arity != arg_values.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: native.arity,
+                        actual: arg_values.len(),
+                    });
+                }
+                return match native.body {
+                    NativeBody::Code(code) => {
+                        let result = self.run_native_code(code, arg_values)?;
+                        self.inc_refcount(result);
+                        Ok(result)
+                    }
+                    NativeBody::Nondeterministic(code) => {
+                        let result = self.call_nondeterministic(native, code, arg_values)?;
+                        self.inc_refcount(result);
+                        Ok(result)
+                    }
+                    NativeBody::Reentrant(code) => {
+                        self.call_reentrant(program, code, arg_values, return_address)
+                    }
+                    NativeBody::Intrinsic(_) => Err(RuntimeError::Native(format!(
+                        "{} cannot be called from a native function",
+                        native.name
+                    ))),
+                };
+            }
+            heap_value => {
+                let closure = heap_value.check_closure()?;
+                if closure.arg_names.len() != arg_values.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: closure.arg_names.len(),
+                        actual: arg_values.len(),
+                    });
+                }
+                (closure.name, closure.body)
+            }
+        };
+
+        self.check_call_depth()?;
+        let depth = self.stack.frames().len();
+        let parameters = Parameters {
+            this: Some(function),
+            arguments: arg_values.to_vec(),
+        };
+        let return_info = ReturnInfo { return_address };
+        let mut address = self.enter_closure(name, body, parameters, false, return_info);
+        while self.stack.frames().len() > depth {
+            let instruction = &block_instructions(program, address)[address.instruction_index];
+            match ProgramEvaluator::execute(program, self, address, instruction) {
+                Ok(Transfer::Goto(next)) => address = next,
+                Ok(Transfer::Finish(_)) => unreachable!("only the toplevel function finishes"),
+                Err(error) => {
+                    self.unwind(depth);
+                    return Err(error);
+                }
+            }
+        }
+
+        // The function returned its result into the frame of the caller of
+        // the native, where it does not belong.
+        let result = self.stack.pop_var_no_refcount();
+        self.reconcile();
+        Ok(result)
+    }
+
+    // Removes the frames from the given depth upwards after a nested call
+    // failed. Generators that were running in them have finished, and thunks
+    // that were being forced stay that way.
+    fn unwind(&mut self, depth: usize) {
+        while let Some(active) = self.generators.pop_if(|active| active.depth >= depth) {
+            if let Ok(generator) = self.heap.deref_mut(active.address).check_generator_mut() {
+                *generator = Generator::Finished;
+            }
+        }
+        while self.thunks.pop_if(|active| active.depth >= depth).is_some() {}
+
+        for frame in self.stack.split_off(depth) {
+            for value in frame.values() {
+                self.release_slot(value);
+            }
+        }
+        self.reconcile();
+    }
+
+    fn record_native_call(
+        &mut self,
+        native: NativeFunction,
+        code: NativeCode,
+        arg_values: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        let result = self.run_native_code(code, arg_values);
+        let recorded_result = match &result {
+            Ok(value) => match RecordedValue::from_heap(*value, &self.heap) {
+                Some(recorded) if recorded.is_data() => Ok(recorded),
+                _ => {
+                    // The result is not owned by anything yet.
+                    self.inc_refcount(*value);
+                    self.dec_refcount(*value);
+                    return Err(RuntimeError::Native(format!(
+                        "cannot record the result of {}",
+                        native.name
+                    )));
+                }
+            },
+            Err(error) => Err(error.clone()),
+        };
+
+        if let RecordMode::Recording(recording) = &mut self.record_mode {
+            recording.calls.push(RecordedCall {
+                native: native.name.to_string(),
+                result: recorded_result,
+            });
+        }
+
+        result
+    }
+
+    fn replay_native_call(&mut self, native: NativeFunction) -> Result<Value, RuntimeError> {
+        let call = match &mut self.record_mode {
+            RecordMode::Replaying {
+                recording,
+                position,
+            } => {
+                let call = recording.calls.get(*position).cloned().ok_or_else(|| {
+                    RuntimeError::Native(format!("replay has no result for {}", native.name))
+                })?;
+                *position += 1;
+                call
+            }
+            _ => unreachable!("not replaying"),
+        };
+
+        if call.native != native.name.as_str() {
+            return Err(RuntimeError::Native(format!(
+                "replay diverged: recorded a call to {}, but {} was called",
+                call.native, native.name
+            )));
+        }
+
+        match call.result {
+            Ok(value) => self.alloc_recorded(&value),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Rebuilds a recorded value on the heap, with an explicit stack since it
+    // can be nested as deeply as a long list. Tuples that were shared are
+    // shared again. Every tuple is owned by the tuples that contain it, so if
+    // an allocation fails, freeing the outermost ones built so far frees all.
+    fn alloc_recorded(&mut self, value: &RecordedValue) -> Result<Value, RuntimeError> {
+        if !value.is_data() {
+            return Err(RuntimeError::Native(format!("cannot replay {}", value)));
+        }
+        let root = match value {
+            RecordedValue::Int(value) => return Ok(Value::Int(*value)),
+            RecordedValue::Bool(value) => return Ok(Value::Bool(*value)),
+            RecordedValue::Tuple(fields) => fields,
+            RecordedValue::Opaque(_) => unreachable!("checked to be data"),
+        };
+
+        let mut built: HashMap<*const Vec<RecordedValue>, HeapAddress> = HashMap::new();
+        let mut outermost: HashSet<HeapAddress> = HashSet::new();
+        // The tuples that are being built, each with the number of its fields
+        // that have been visited.
+        let mut stack = vec![(root, 0)];
+        while let Some((fields, next)) = stack.last_mut() {
+            if let Some(field) = fields.get(*next) {
+                *next += 1;
+                if let RecordedValue::Tuple(field_fields) = field {
+                    if !built.contains_key(&Arc::as_ptr(field_fields)) {
+                        stack.push((field_fields, 0));
+                    }
+                }
+                continue;
+            }
+
+            let fields = *fields;
+            stack.pop();
+            let field_values: Vec<Value> = fields
+                .iter()
+                .map(|field| match field {
+                    RecordedValue::Int(value) => Value::Int(*value),
+                    RecordedValue::Bool(value) => Value::Bool(*value),
+                    RecordedValue::Tuple(field_fields) => {
+                        Value::Pointer(built[&Arc::as_ptr(field_fields)])
+                    }
+                    RecordedValue::Opaque(_) => unreachable!("checked to be data"),
+                })
+                .collect();
+            match self.alloc(HeapValue::Tuple(Tuple::new(field_values.clone()))) {
+                Ok(address) => {
+                    for field_value in field_values {
+                        if let Value::Pointer(field_address) = field_value {
+                            self.inc_refcount(field_value);
+                            outermost.remove(&field_address);
+                        }
+                    }
+                    built.insert(Arc::as_ptr(fields), address);
+                    outermost.insert(address);
+                }
+                Err(error) => {
+                    for address in outermost {
+                        self.inc_refcount(Value::Pointer(address));
+                        self.dec_refcount(Value::Pointer(address));
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(Value::Pointer(built[&Arc::as_ptr(root)]))
+    }
+
+    fn eval_intrinsic(
+        &mut self,
+        intrinsic: Intrinsic,
+        arg_values: &[Value],
+        return_info: ReturnInfo,
+    ) -> Result<TargetAddress, RuntimeError> {
+        let result = match intrinsic {
+            Intrinsic::Generator => {
+                let function = arg_values[0];
+                self.heap
+                    .deref(function.check_pointer("closure")?)
+                    .check_closure()?;
+
+                let address =
+                    self.alloc(HeapValue::Generator(Generator::NotStarted { function }))?;
+                self.inc_refcount(function);
+                Value::Pointer(address)
+            }
+            Intrinsic::Finished => {
+                let generator = self
+                    .heap
+                    .deref(arg_values[0].check_pointer("generator")?)
+                    .check_generator()?;
+                Value::Bool(matches!(generator, Generator::Finished))
+            }
+            Intrinsic::Delay => {
+                let function = arg_values[0];
+                self.heap
+                    .deref(function.check_pointer("closure")?)
+                    .check_closure()?;
+
+                let address = self.alloc(HeapValue::Thunk(Thunk::Delayed { function }))?;
+                self.inc_refcount(function);
+                Value::Pointer(address)
+            }
+            Intrinsic::Force => return self.force(arg_values[0], return_info),
+            Intrinsic::Resume => return self.resume(arg_values[0], arg_values[1], return_info),
+            Intrinsic::Yield => return self.suspend(arg_values[0], return_info),
+        };
+
+        self.push_var(result);
+        Ok(return_info.return_address)
+    }
+
+    // The frames of the generator are pushed on top of the frame of the
+    // function that resumes it, and the outermost of them returns there.
+    fn resume(
+        &mut self,
+        generator_value: Value,
+        value: Value,
+        return_info: ReturnInfo,
+    ) -> Result<TargetAddress, RuntimeError> {
+        let address = generator_value.check_pointer("generator")?;
+        let generator = self.heap.deref_mut(address).check_generator_mut()?;
+
+        let (frames, resume_address) = match std::mem::replace(generator, Generator::Running) {
+            Generator::NotStarted { function } => {
+                let closure = self
+                    .heap
+                    .deref(function.check_pointer("closure")?)
+                    .check_closure()?
+                    .clone();
+                if closure.arg_names.len() != 1 {
+                    *self.heap.deref_mut(address).check_generator_mut()? =
+                        Generator::NotStarted { function };
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: closure.arg_names.len(),
+                        actual: 1,
+                    });
                 }
+
+                let parameters = Parameters {
+                    this: Some(function),
+                    arguments: vec![value],
+                };
+                for value in parameters.values().collect::<Vec<_>>() {
+                    self.inc_refcount(value);
+                }
+
+                self.generators.push(ActiveGenerator {
+                    address,
+                    depth: self.stack.frames().len(),
+                });
+                self.stack.enter_function(Some(return_info), parameters);
+                self.trace.call(closure.name, closure.body, false);
+
+                // The function is now referenced by the frame instead of the
+                // generator.
+                self.dec_refcount(function);
+                return Ok(closure.body);
+            }
+            Generator::Suspended {
+                frames,
+                resume_address,
+            } => (frames, resume_address),
+            state => {
+                let message = match state {
+                    Generator::Running => "generator is already running",
+                    _ => "generator has finished",
+                };
+                *self.heap.deref_mut(address).check_generator_mut()? = state;
+                return Err(RuntimeError::Native(message.to_owned()));
+            }
+        };
+
+        self.generators.push(ActiveGenerator {
+            address,
+            depth: self.stack.frames().len(),
+        });
+        self.stack.restore(frames, return_info);
+
+        // The yield that suspended the generator evaluates to the value it is
+        // resumed with.
+        self.push_var(value);
+        Ok(resume_address)
+    }
+
+    // Moves the frames of the innermost running generator into the heap, and
+    // passes the value to the function that resumed it.
+    fn suspend(
+        &mut self,
+        value: Value,
+        return_info: ReturnInfo,
+    ) -> Result<TargetAddress, RuntimeError> {
+        let active = self
+            .generators
+            .pop()
+            .ok_or_else(|| RuntimeError::Native("yield outside of a generator".to_owned()))?;
+        // The frames of a native function are not on the stack, so they
+        // cannot be suspended.
+        if self
+            .native_depths
+            .last()
+            .is_some_and(|depth| active.depth < *depth)
+        {
+            self.generators.push(active);
+            return Err(RuntimeError::Native(
+                "cannot yield across a native function".to_owned(),
+            ));
+        }
+
+        let frames = self.stack.split_off(active.depth);
+        let resumer_return_info = frames[0].blocks()[0]
+            .return_info
+            .clone()
+            .expect("generator should return to where it was resumed");
+
+        // Keep the value alive while it is passed from the frames of the
+        // generator to the resumer.
+        self.push_var(value);
+        *self.heap.deref_mut(active.address).check_generator_mut()? = Generator::Suspended {
+            frames,
+            resume_address: return_info.return_address,
+        };
+
+        Ok(resumer_return_info.return_address)
+    }
+
+    // Called after leaving a function: if it was the function of the innermost
+    // running generator, the generator has finished.
+    fn finish_generator(&mut self) -> Result<(), RuntimeError> {
+        if let Some(active) = self.generators.last() {
+            if self.stack.frames().len() == active.depth {
+                let address = active.address;
+                self.generators.pop();
+                *self.heap.deref_mut(address).check_generator_mut()? = Generator::Finished;
             }
         }
+
+        Ok(())
+    }
+
+    // Calls the function of a thunk that has not been forced yet, which returns
+    // to the function that forces it.
+    fn force(
+        &mut self,
+        thunk_value: Value,
+        return_info: ReturnInfo,
+    ) -> Result<TargetAddress, RuntimeError> {
+        let address = thunk_value.check_pointer("thunk")?;
+        let thunk = self.heap.deref_mut(address).check_thunk_mut()?;
+
+        let function = match std::mem::replace(thunk, Thunk::Forcing) {
+            Thunk::Delayed { function } => function,
+            Thunk::Forced { value } => {
+                *thunk = Thunk::Forced { value };
+                self.push_var(value);
+                return Ok(return_info.return_address);
+            }
+            Thunk::Forcing => {
+                return Err(RuntimeError::Native(
+                    "thunk is forced while it is being forced".to_owned(),
+                ))
+            }
+        };
+
+        let closure = self
+            .heap
+            .deref(function.check_pointer("closure")?)
+            .check_closure()?
+            .clone();
+        if !closure.arg_names.is_empty() {
+            *self.heap.deref_mut(address).check_thunk_mut()? = Thunk::Delayed { function };
+            return Err(RuntimeError::ArityMismatch {
+                expected: closure.arg_names.len(),
+                actual: 0,
+            });
+        }
+
+        let parameters = Parameters {
+            this: Some(function),
+            arguments: Vec::new(),
+        };
+        for value in parameters.values().collect::<Vec<_>>() {
+            self.retain_slot(value);
+        }
+
+        self.thunks.push(ActiveThunk {
+            address,
+            depth: self.stack.frames().len(),
+        });
+        self.stack.enter_function(Some(return_info), parameters);
+        self.trace.call(closure.name, closure.body, false);
+
+        // The function is now referenced by the frame instead of the thunk.
+        self.dec_refcount(function);
+        Ok(closure.body)
+    }
+
+    // Called after leaving a function: if it was the function of the innermost
+    // thunk that is being forced, the thunk remembers its result.
+    fn finish_thunk(&mut self, value: Value) -> Result<(), RuntimeError> {
+        if let Some(active) = self.thunks.last() {
+            if self.stack.frames().len() == active.depth {
+                let address = active.address;
+                self.thunks.pop();
+                *self.heap.deref_mut(address).check_thunk_mut()? = Thunk::Forced { value };
+                self.inc_refcount(value);
+            }
+        }
+
+        Ok(())
     }
 
     fn eval_instruction(
         &mut self,
+        program: &Program,
         address: TargetAddress,
         instruction: &Assignment,
-    ) -> TargetAddress {
+    ) -> Result<TargetAddress, RuntimeError> {
         match &instruction.definition {
             Definition::Var(var) => {
-                let value = self.eval_var(&var);
-                self.set_var(instruction.name.clone(), value);
-                address.next()
+                let value = self.eval_var(var)?;
+                self.push_var(value);
+                Ok(address.next())
             }
             Definition::Step(Step::Simple(simple)) => {
-                let value = self.eval_simple(&simple);
-                self.set_var(instruction.name.clone(), value);
-                address.next()
+                let value = self.eval_simple(program, simple)?;
+                self.push_var(value);
+                Ok(address.next())
             }
             Definition::Step(Step::Control(control)) => {
+                // The result of the control instruction is pushed onto the
+                // block frame when returning to the next instruction.
                 let return_info = ReturnInfo {
-                    result_variable: instruction.name.clone(),
                     return_address: address.next(),
                 };
-                self.eval_control(&control, return_info)
+                self.eval_control(program, control, return_info)
             }
         }
     }
 }
 
+// How a reentrant native function reaches the evaluator. The values that it
+// passes to functions and gets back from them are held until it returns.
+struct Reentry<'a, H: Heap> {
+    evaluator: &'a mut InstructionEvaluator<H>,
+    program: &'a Program,
+    return_address: TargetAddress,
+    held: Vec<Value>,
+    // The work on the heap since the last call, which has not been charged
+    // yet.
+    work: Cell<u64>,
+}
+
+impl<H: Heap> NativeContext for Reentry<'_, H> {
+    fn heap(&mut self) -> NativeHeap<'_> {
+        let evaluator = &mut *self.evaluator;
+        NativeHeap::new(
+            &mut evaluator.heap,
+            evaluator.config.max_heap_objects,
+            evaluator.fuel,
+            &self.work,
+        )
+    }
+
+    fn call(&mut self, function: Value, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        self.evaluator.charge_fuel(self.work.take())?;
+        for value in arguments {
+            self.evaluator.inc_refcount(*value);
+            self.held.push(*value);
+        }
+        let result =
+            self.evaluator
+                .call_nested(self.program, function, arguments, self.return_address)?;
+        self.held.push(result);
+        Ok(result)
+    }
+}
+
+// The result of executing a single instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Running,
+    Finished(Value),
+}
+
+// Where execution continues after an instruction.
+enum Transfer {
+    Goto(TargetAddress),
+    Finish(Value),
+}
+
+// A program whose variables have been resolved and which has been verified,
+// so that it is ready to run. Cloning it is cheap, so any number of
+// evaluators can run the same program without copying its functions.
+#[derive(Debug, Clone)]
+pub struct PreparedProgram(Arc<Program>);
+
+impl PreparedProgram {
+    // Fails if the program is invalid, which can happen for programs that were
+    // not produced by the compiler, such as hand-written ones.
+    pub fn new(mut program: Program) -> crate::result::Result<Self> {
+        resolve_variables(&mut program);
+        verify_program(&program).map_err(|error| format!("invalid program: {}", error))?;
+        Ok(PreparedProgram(Arc::new(program)))
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.0
+    }
+}
+
+// Panics if the program is invalid, since the compiler should not have
+// produced it. Programs from elsewhere should go through `PreparedProgram::new`.
+impl From<Program> for PreparedProgram {
+    fn from(program: Program) -> Self {
+        Self::new(program).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+// The evaluator never changes the program, so it only holds a reference to
+// it, and the heap and the stack are its own.
 #[derive(Debug)]
-pub struct ProgramEvaluator {
-    program: Program,
-    instruction_evaluator: InstructionEvaluator,
+pub struct ProgramEvaluator<H: Heap = RefCountHeap> {
+    program: Arc<Program>,
+    instruction_evaluator: InstructionEvaluator<H>,
     program_counter: TargetAddress,
+    // Set once the toplevel function has returned.
+    result: Option<Value>,
+    allocation_profile: Option<AllocationProfile>,
+    // The call stack at the most recent failure.
+    backtrace: Option<Backtrace>,
 }
 
 impl ProgramEvaluator {
-    pub fn new(program: Program) -> Self {
+    pub fn new(program: impl Into<PreparedProgram>) -> Self {
+        Self::with_heap(program, RefCountHeap::new())
+    }
+
+    pub fn with_config(program: impl Into<PreparedProgram>, config: EvaluatorConfig) -> Self {
+        Self::with_heap_and_config(program, RefCountHeap::new(), config)
+    }
+
+    // Limits the number of simultaneously live heap objects. Allocations beyond
+    // the limit make the evaluation fail with `RuntimeError::OutOfMemory`.
+    pub fn with_heap_limit(program: impl Into<PreparedProgram>, max_objects: usize) -> Self {
+        let config = EvaluatorConfig {
+            max_heap_objects: Some(max_objects),
+            ..EvaluatorConfig::default()
+        };
+        Self::with_config(program, config)
+    }
+
+    pub fn with_sandbox(program: impl Into<PreparedProgram>, sandbox: &SandboxConfig) -> Self {
+        Self::with_config(program, sandbox.evaluator_config())
+    }
+}
+
+impl<H: Heap> ProgramEvaluator<H> {
+    pub fn with_heap(program: impl Into<PreparedProgram>, heap: H) -> Self {
+        Self::with_heap_and_config(program, heap, EvaluatorConfig::default())
+    }
+
+    pub fn with_heap_and_config(
+        program: impl Into<PreparedProgram>,
+        heap: H,
+        config: EvaluatorConfig,
+    ) -> Self {
+        let PreparedProgram(program) = program.into();
         ProgramEvaluator {
             program,
-            instruction_evaluator: InstructionEvaluator::new(),
+            instruction_evaluator: InstructionEvaluator::new(heap, config),
             program_counter: TargetAddress {
                 function_index: 0,
                 block_index: 0,
                 instruction_index: 0,
             },
+            result: None,
+            allocation_profile: None,
+            backtrace: None,
         }
     }
 
-    pub fn run(&mut self) -> HeapValue {
-        loop {
-            let result = self.step();
+    pub fn heap(&self) -> &H {
+        &self.instruction_evaluator.heap
+    }
+
+    // Every heap address that the program can still reach without going
+    // through another object: the values on the stack, the objects that the
+    // evaluator keeps alive itself, and the result once the program has
+    // finished. Everything else that is live should be reachable from these,
+    // which is what tracing collectors rely on and what heap dumps show.
+    pub fn roots(&self) -> impl Iterator<Item = HeapAddress> + '_ {
+        self.instruction_evaluator
+            .roots()
+            .chain(self.result.and_then(|result| result.address()))
+    }
 
-            if let Some(result) = result {
-                return result;
+    pub fn dump_heap(&self, format: DumpFormat) -> String {
+        self.heap().dump_with_roots(format, &self.roots().collect())
+    }
+
+    // None unless the configuration limits the number of instructions.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.instruction_evaluator.fuel
+    }
+
+    // None unless the configuration defers the reference counts of stack
+    // slots.
+    pub fn deferred_stats(&self) -> Option<&DeferredStats> {
+        self.instruction_evaluator
+            .deferred
+            .as_ref()
+            .map(|deferred| deferred.stats())
+    }
+
+    // Sends the events of the evaluation to the given sink from now on. By
+    // default, they are ignored.
+    pub fn set_trace_sink(&mut self, trace: Box<dyn TraceSink>) {
+        self.instruction_evaluator.trace = trace;
+    }
+
+    // Records the result of every call to a nondeterministic native function
+    // from now on.
+    pub fn start_recording(&mut self) {
+        self.instruction_evaluator.record_mode = RecordMode::Recording(Recording::default());
+    }
+
+    // Returns what has been recorded so far and stops recording.
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        match std::mem::take(&mut self.instruction_evaluator.record_mode) {
+            RecordMode::Recording(recording) => Some(recording),
+            record_mode => {
+                self.instruction_evaluator.record_mode = record_mode;
+                None
             }
         }
     }
 
-    fn step(&mut self) -> Option<HeapValue> {
-        println!("PC: {:?}", self.program_counter);
+    // Takes the results of nondeterministic native functions from the
+    // recording instead of calling them, so that a recorded run can be
+    // repeated exactly, for example in the debugger. The natives still have to
+    // be bound, since the others are called as usual.
+    pub fn replay(&mut self, recording: Recording) {
+        self.instruction_evaluator.record_mode = RecordMode::Replaying {
+            recording,
+            position: 0,
+        };
+    }
 
-        let current_instruction = self.program.get_instruction(self.program_counter);
+    pub fn bind_natives(&mut self, natives: &Natives) -> Result<(), RuntimeError> {
+        self.bind_globals(natives, HashMap::new())
+    }
 
-        println!("instruction: {}", current_instruction);
+    // Binds the free variables of the toplevel function, which are the globals
+    // the program was compiled with, to native functions or to host values.
+    // This has to happen before the first step.
+    pub fn bind_globals(
+        &mut self,
+        natives: &Natives,
+        mut host_values: HashMap<String, HostValue>,
+    ) -> Result<(), RuntimeError> {
+        let free_names = self.program.functions[0]
+            .free_names
+            .clone()
+            .unwrap_or_default();
 
-        match current_instruction {
-            Instruction::EnterBlock => {
-                self.program_counter = self.program_counter.next();
-                None
+        let allowed_natives = &self.instruction_evaluator.config.allowed_natives;
+        let mut heap_values = Vec::new();
+        for name in free_names {
+            let heap_value = if let Some(native) = natives.get(name) {
+                if allowed_natives
+                    .as_ref()
+                    .is_some_and(|allowed| !allowed.contains(name.as_str()))
+                {
+                    return Err(RuntimeError::NativeNotAllowed(name.to_string()));
+                }
+                HeapValue::NativeFn(*native)
+            } else if let Some(host) = host_values.remove(name.as_str()) {
+                HeapValue::Host(host)
+            } else {
+                return Err(RuntimeError::UnboundVariable(name.to_string()));
+            };
+            heap_values.push(heap_value);
+        }
+
+        // If an allocation fails, the values that have already been allocated
+        // are released again.
+        let mut environment = Vec::new();
+        for heap_value in heap_values {
+            match self.instruction_evaluator.alloc(heap_value) {
+                Ok(address) => {
+                    let value = Value::Pointer(address);
+                    self.instruction_evaluator.inc_refcount(value);
+                    environment.push(value);
+                }
+                Err(error) => {
+                    for value in environment {
+                        self.instruction_evaluator.dec_refcount(value);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        self.instruction_evaluator
+            .stack
+            .bind_toplevel_environment(environment);
+
+        Ok(())
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.instruction_evaluator.stack
+    }
+
+    // The program after variable resolution, which is the one being run.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    // The program being run, for starting other evaluators of it.
+    pub fn prepared_program(&self) -> PreparedProgram {
+        PreparedProgram(Arc::clone(&self.program))
+    }
+
+    // The address of the instruction that the next step executes.
+    pub fn program_counter(&self) -> TargetAddress {
+        self.program_counter
+    }
+
+    pub fn current_instruction(&self) -> &Instruction {
+        self.program.get_instruction(self.program_counter)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    // Runs the program to the end. Unlike `step`, this keeps the instructions
+    // of the current block at hand, and only looks them up again when it
+    // moves to another block.
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        // The allocation profile compares the heap before and after every
+        // step.
+        if self.allocation_profile.is_some() || self.result.is_some() {
+            loop {
+                if let StepOutcome::Finished(result) = self.step()? {
+                    return Ok(result);
+                }
             }
+        }
+
+        let program = &self.program;
+        let evaluator = &mut self.instruction_evaluator;
+        let mut address = self.program_counter;
+        let mut instructions = block_instructions(program, address);
+        let outcome = loop {
+            let instruction = &instructions[address.instruction_index];
+            match Self::execute(program, evaluator, address, instruction) {
+                Ok(Transfer::Goto(next)) => {
+                    if next.function_index != address.function_index
+                        || next.block_index != address.block_index
+                    {
+                        instructions = block_instructions(program, next);
+                    }
+                    address = next;
+                }
+                Ok(Transfer::Finish(value)) => break Ok(value),
+                Err(error) => break Err(error),
+            }
+        };
+
+        self.program_counter = address;
+        match outcome {
+            Ok(value) => {
+                self.result = Some(value);
+                Ok(value)
+            }
+            Err(error) => {
+                self.instruction_evaluator.reconcile();
+                self.backtrace = Some(Backtrace {
+                    entries: self.call_stack(),
+                });
+                Err(error)
+            }
+        }
+    }
+
+    // Attributes every allocation from now on to the function whose
+    // instruction made it.
+    pub fn enable_allocation_profile(&mut self) {
+        self.allocation_profile = Some(AllocationProfile::new());
+    }
+
+    pub fn allocation_profile(&self) -> Option<&AllocationProfile> {
+        self.allocation_profile.as_ref()
+    }
+
+    // Counts the instructions executed in every function and the calls
+    // between functions from now on.
+    pub fn enable_execution_profile(&mut self) {
+        self.instruction_evaluator.execution_profile = Some(ExecutionProfile::new());
+    }
+
+    pub fn execution_profile(&self) -> Option<&ExecutionProfile> {
+        self.instruction_evaluator.execution_profile.as_ref()
+    }
+
+    // Executes a single instruction. Once the program has finished, stepping
+    // again keeps returning the result.
+    pub fn step(&mut self) -> Result<StepOutcome, RuntimeError> {
+        let function_index = self.program_counter.function_index;
+        let before = self
+            .allocation_profile
+            .as_ref()
+            .map(|_| self.instruction_evaluator.heap.stats().clone());
+        let outcome = self.step_instruction();
+
+        if let (Some(profile), Some(before)) = (&mut self.allocation_profile, before) {
+            profile.record(
+                function_index,
+                &before,
+                self.instruction_evaluator.heap.stats(),
+            );
+        }
+        if outcome.is_err() {
+            self.instruction_evaluator.reconcile();
+            self.backtrace = Some(Backtrace {
+                entries: self.call_stack(),
+            });
+        }
+
+        outcome
+    }
+
+    // The functions that are currently active, the outermost first. The
+    // address of the innermost function is the program counter, and the others
+    // are at the instruction that the function they called will return to.
+    pub fn call_stack(&self) -> Vec<StackEntry> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+
+        let functions = &self.program.functions;
+        self.stack()
+            .frames()
+            .iter()
+            .skip(1)
+            .filter_map(|frame| frame.blocks().first()?.return_info.as_ref())
+            .map(|return_info| return_info.return_address)
+            .chain(std::iter::once(self.program_counter))
+            .map(|address| StackEntry {
+                function_name: functions[address.function_index].name,
+                address,
+            })
+            .collect()
+    }
+
+    // Where the program was when a step last failed. A failing instruction
+    // leaves the stack as it was, so this is also where the program stopped.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    fn step_instruction(&mut self) -> Result<StepOutcome, RuntimeError> {
+        if let Some(result) = self.result {
+            return Ok(StepOutcome::Finished(result));
+        }
+
+        let address = self.program_counter;
+        let instruction = &block_instructions(&self.program, address)[address.instruction_index];
+        match Self::execute(
+            &self.program,
+            &mut self.instruction_evaluator,
+            address,
+            instruction,
+        )? {
+            Transfer::Goto(next) => {
+                self.program_counter = next;
+                Ok(StepOutcome::Running)
+            }
+            Transfer::Finish(value) => {
+                self.result = Some(value);
+                Ok(StepOutcome::Finished(value))
+            }
+        }
+    }
+
+    // Executes a single instruction. The evaluator is passed apart from the
+    // program, so that `run` can keep the instructions of the current block
+    // borrowed while it executes them.
+    fn execute(
+        program: &Program,
+        evaluator: &mut InstructionEvaluator<H>,
+        address: TargetAddress,
+        instruction: &Instruction,
+    ) -> Result<Transfer, RuntimeError> {
+        evaluator.consume_fuel()?;
+
+        // A reentrant native may hold values that are not roots, so there is
+        // no collection until it has returned.
+        if evaluator.heap.wants_collection() && evaluator.native_depths.is_empty() {
+            let roots: Vec<HeapAddress> = evaluator.roots().collect();
+            let trace = &mut evaluator.trace;
+            evaluator
+                .heap
+                .collect(&mut roots.into_iter(), &mut |freed| trace.free(freed));
+        }
+
+        evaluator.trace.instruction(address, instruction);
+        if let Some(profile) = &mut evaluator.execution_profile {
+            profile.record_instruction(address.function_index);
+        }
+
+        match instruction {
+            Instruction::EnterBlock => Ok(Transfer::Goto(address.next())),
             Instruction::ExitBlock(return_var) => {
                 // If there is no return address, the program is finished and we
                 // can return the final value from this function.
-                let block = self.instruction_evaluator.stack.exit_block();
-
-                let return_value = block
-                    .lookup_var(&return_var.var_name)
-                    .expect("could not find return value of block in block local variables");
+                // The return value is not necessarily a local of the block
+                // itself, so it has to be looked up before leaving the block.
+                let return_value = evaluator.eval_var(return_var)?;
+                let frame_count = evaluator.stack.frames().len();
+                let block = evaluator.stack.exit_block();
+                if evaluator.stack.frames().len() < frame_count {
+                    evaluator.trace.function_return(return_value);
+                    evaluator.finish_generator()?;
+                    evaluator.finish_thunk(return_value)?;
+                }
 
                 // TODO: Some code duplication here
                 match block.return_info {
                     None => {
-                        let result = self.instruction_evaluator.heap.deref(return_value).clone();
+                        evaluator.retain_slot(return_value);
 
                         // Decrease reference counts on the locals that are
                         // going out of scope. In the current implementation,
@@ -282,16 +1717,17 @@ impl ProgramEvaluator {
                         // that will increment the reference count, keeping the
                         // return value alive instead of potentially destroying
                         // it at the block exit.
-                        for address in &block.values {
-                            self.instruction_evaluator.heap.dec_refcount(*address);
+                        for value in &block.values {
+                            evaluator.release_slot(*value);
                         }
+                        evaluator.reconcile();
+                        evaluator.release_lifted_closures();
 
-                        Some(result)
+                        Ok(Transfer::Finish(return_value))
                     }
                     Some(return_info) => {
                         // Put the return value into the caller's stack frame.
-                        self.instruction_evaluator
-                            .set_var(return_info.result_variable, return_value);
+                        evaluator.push_var(return_value);
 
                         // Decrease reference counts on the locals that are
                         // going out of scope. In the current implementation,
@@ -300,22 +1736,25 @@ impl ProgramEvaluator {
                         // that will increment the reference count, keeping the
                         // return value alive instead of potentially destroying
                         // it at the block exit.
-                        for address in &block.values {
-                            self.instruction_evaluator.heap.dec_refcount(*address);
+                        for value in &block.values {
+                            evaluator.release_slot(*value);
                         }
+                        evaluator.reconcile();
 
-                        self.program_counter = return_info.return_address;
-                        None
+                        Ok(Transfer::Goto(return_info.return_address))
                     }
                 }
             }
             Instruction::Assignment(assignment) => {
-                let next_address = self
-                    .instruction_evaluator
-                    .eval_instruction(self.program_counter, assignment);
-                self.program_counter = next_address;
-                None
+                let next_address = evaluator.eval_instruction(program, address, assignment)?;
+                Ok(Transfer::Goto(next_address))
             }
         }
     }
 }
+
+// The instructions of the block that contains the address. The program has
+// been verified, so the block exists.
+fn block_instructions(program: &Program, address: TargetAddress) -> &[Instruction] {
+    &program.functions[address.function_index].blocks[address.block_index].instructions
+}
@@ -1,61 +1,79 @@
-use crate::ir_let::interpreter::heap_value::HeapAddress;
-use crate::ir_let::let_expr::TargetAddress;
-
-use std::collections::HashMap;
+use crate::ir_let::interpreter::heap_value::{HeapAddress, Value};
+use crate::ir_let::let_expr::{TargetAddress, VariableLocation};
 
 #[derive(Debug, Clone)]
 pub struct ReturnInfo {
-    pub result_variable: String,
     pub return_address: TargetAddress,
 }
 
+// The locals of a block, in the order in which they were assigned.
 #[derive(Debug)]
 pub struct BlockFrame {
-    pub values: Vec<HeapAddress>,
-    pub variable_offsets: HashMap<String, usize>,
+    pub values: Vec<Value>,
     pub return_info: Option<ReturnInfo>,
 }
 
 impl BlockFrame {
-    fn new(return_info: ReturnInfo) -> Self {
+    fn new(return_info: Option<ReturnInfo>) -> Self {
         BlockFrame {
             values: Vec::new(),
-            variable_offsets: HashMap::new(),
-            return_info: Some(return_info),
+            return_info,
         }
     }
+}
 
-    pub fn lookup_var(&self, name: &str) -> Option<HeapAddress> {
-        let offset = self.variable_offsets.get(name);
-
-        if let Some(offset) = offset {
-            Some(*self.values.get(*offset).expect("stack index out of range"))
-        } else {
-            None
-        }
-    }
+// The values a function was called with. The captured variables are not
+// copied into the frame, but read from the closure in place, which stays alive
+// as long as the frame does. The toplevel function is not called through a
+// closure, so it has no closure value.
+#[derive(Debug, Default)]
+pub struct Parameters {
+    pub this: Option<Value>,
+    pub arguments: Vec<Value>,
+}
 
-    fn set_var(&mut self, name: String, value: HeapAddress) {
-        let new_offset = self.values.len();
-        self.values.push(value);
-        self.variable_offsets.insert(name, new_offset);
+impl Parameters {
+    pub fn values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.this.iter().chain(self.arguments.iter()).copied()
     }
 }
 
 #[derive(Debug)]
-struct CallStackFrame {
+pub struct CallStackFrame {
+    parameters: Parameters,
     nested_block_frames: Vec<BlockFrame>,
 }
 
 impl CallStackFrame {
-    fn new(return_info: ReturnInfo) -> Self {
+    pub fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+
+    // The values of the parameters and of the locals of every active block.
+    pub fn values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.parameters.values().chain(
+            self.nested_block_frames
+                .iter()
+                .flat_map(|block| block.values.iter().copied()),
+        )
+    }
+
+    // The blocks of the function that are currently active, the outermost
+    // block first.
+    pub fn blocks(&self) -> &[BlockFrame] {
+        &self.nested_block_frames
+    }
+
+    fn new(return_info: Option<ReturnInfo>, parameters: Parameters) -> Self {
         CallStackFrame {
+            parameters,
             nested_block_frames: vec![BlockFrame::new(return_info)],
         }
     }
 
     fn enter_block(&mut self, return_info: ReturnInfo) {
-        self.nested_block_frames.push(BlockFrame::new(return_info))
+        self.nested_block_frames
+            .push(BlockFrame::new(Some(return_info)))
     }
 
     fn exit_block(&mut self) -> BlockFrame {
@@ -70,67 +88,198 @@ impl CallStackFrame {
             .expect("expected active block")
     }
 
-    fn lookup_var(&self, name: &str) -> HeapAddress {
-        // Walk backwards from the innermost block frame to the outermost
-        // one to find the lexically closest one that binds the variable we are looking for.
-        for frame in self.nested_block_frames.iter().rev() {
-            if let Some(value) = frame.lookup_var(name) {
-                return value;
-            }
+    fn load(&self, location: VariableLocation) -> Value {
+        match location {
+            VariableLocation::Local {
+                block_depth,
+                offset,
+            } => *self
+                .nested_block_frames
+                .get(block_depth)
+                .and_then(|block| block.values.get(offset))
+                .expect("stack index out of range"),
+            VariableLocation::Argument(offset) => self.parameters.arguments[offset],
+            VariableLocation::Closure(_) => panic!("captured variables are not in the frame"),
+            VariableLocation::This => self
+                .parameters
+                .this
+                .expect("toplevel function has no closure"),
         }
-
-        panic!("could not find variable in stack frame")
     }
 
-    fn set_var_no_refcount(&mut self, name: String, value: HeapAddress) {
-        self.current_block_mut().set_var(name, value);
+    fn push_var_no_refcount(&mut self, value: Value) {
+        self.current_block_mut().values.push(value);
     }
 }
 
 #[derive(Debug)]
 pub struct Stack {
     frames: Vec<CallStackFrame>,
+    // The values of the free variables of the toplevel function.
+    toplevel_environment: Vec<Value>,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Stack {
     pub fn new() -> Self {
         Stack {
-            frames: vec![CallStackFrame {
-                nested_block_frames: vec![BlockFrame {
-                    values: Vec::new(),
-                    variable_offsets: HashMap::new(),
-                    return_info: None,
-                }],
-            }],
+            frames: vec![CallStackFrame::new(None, Parameters::default())],
+            toplevel_environment: Vec::new(),
         }
     }
 
-    pub fn enter_function(&mut self, return_info: ReturnInfo) {
-        self.frames.push(CallStackFrame::new(return_info));
+    // A return info of None means that returning from the function ends the
+    // program. The caller is responsible for the reference counts of the
+    // parameters.
+    pub fn enter_function(&mut self, return_info: Option<ReturnInfo>, parameters: Parameters) {
+        self.frames
+            .push(CallStackFrame::new(return_info, parameters));
+    }
+
+    // The toplevel function is not called through a closure, so the values of
+    // its free variables are provided separately.
+    pub fn bind_toplevel_environment(&mut self, environment: Vec<Value>) {
+        assert!(self.frames.len() == 1, "toplevel function is not running");
+        self.toplevel_environment = environment;
+    }
+
+    pub fn toplevel_environment(&self) -> &[Value] {
+        &self.toplevel_environment
+    }
+
+    // Leaves the current function from any of its blocks. Returns where the
+    // function would have returned to, together with the values of all of its
+    // parameters and variables, whose reference counts the caller is
+    // responsible for.
+    pub fn exit_function(&mut self) -> (Option<ReturnInfo>, Vec<Value>) {
+        let frame = self.frames.pop().expect("stack should not be empty");
+        let mut blocks = frame.nested_block_frames.into_iter();
+
+        let outermost = blocks.next().expect("expected active block");
+        let mut values: Vec<Value> = frame.parameters.values().collect();
+        values.extend(outermost.values);
+        for block in blocks {
+            values.extend(block.values);
+        }
+
+        (outermost.return_info, values)
     }
 
     pub fn enter_block(&mut self, return_info: ReturnInfo) {
         self.current_frame_mut().enter_block(return_info)
     }
 
+    // Prepares the current function for the next iteration of a loop whose
+    // header is the block frame at the given depth: the blocks nested inside
+    // of it are left, its own locals are cleared, and the arguments are
+    // replaced. Returns the values that are no longer stored in the frame,
+    // whose reference counts the caller is responsible for.
+    pub fn jump(&mut self, block_depth: usize, arguments: Vec<Value>) -> Vec<Value> {
+        let frame = self.current_frame_mut();
+        if frame.parameters.arguments.len() != arguments.len() {
+            panic!("incorrect number of arguments");
+        }
+
+        let mut released = Vec::new();
+
+        while frame.nested_block_frames.len() > block_depth + 1 {
+            released.extend(frame.exit_block().values);
+        }
+
+        released.append(&mut frame.current_block_mut().values);
+        released.extend(std::mem::replace(
+            &mut frame.parameters.arguments,
+            arguments,
+        ));
+        released
+    }
+
+    // Upon exiting the outermost block of the function, the parameters of the
+    // function are released together with the locals of the block.
     pub fn exit_block(&mut self) -> BlockFrame {
-        let frame = self.current_frame_mut().exit_block();
+        let mut block = self.current_frame_mut().exit_block();
 
-        // We pop the call stack frame upon exiting the outermost block
-        // of the function.
         if self.current_frame().nested_block_frames.is_empty() {
-            self.frames.pop();
+            let frame = self.frames.pop().expect("stack should not be empty");
+            block.values.extend(frame.parameters.values());
+            if self.frames.is_empty() {
+                block.values.append(&mut self.toplevel_environment);
+            }
         }
 
-        frame
+        block
+    }
+
+    // Assigns the next local of the current block.
+    pub fn push_var_no_refcount(&mut self, value: Value) {
+        self.current_frame_mut().push_var_no_refcount(value);
+    }
+
+    // Removes the most recently assigned local of the current block, whose
+    // reference count the caller is responsible for. Used to take the result
+    // of a call that a native function made.
+    pub fn pop_var_no_refcount(&mut self) -> Value {
+        self.current_frame_mut()
+            .current_block_mut()
+            .values
+            .pop()
+            .expect("block has no locals")
+    }
+
+    // The number of active calls, not including the toplevel function.
+    pub fn call_depth(&self) -> usize {
+        self.frames.len() - 1
+    }
+
+    // Captured variables are stored in the closure of the current function,
+    // which is on the heap, so they cannot be loaded from the stack.
+    pub fn load(&self, location: VariableLocation) -> Value {
+        self.current_frame().load(location)
+    }
+
+    // The closure of the current function, or None for the toplevel function.
+    pub fn this(&self) -> Option<Value> {
+        self.current_frame().parameters.this
+    }
+
+    // The active calls, the toplevel function first.
+    pub fn frames(&self) -> &[CallStackFrame] {
+        &self.frames
+    }
+
+    // Every heap address stored in a variable of any active block, in any
+    // function on the call stack, including the closures that the functions
+    // were called through and the environment of the toplevel function. The
+    // evaluator adds its own roots, see `ProgramEvaluator::roots`.
+    pub fn roots(&self) -> impl Iterator<Item = HeapAddress> + '_ {
+        self.frames
+            .iter()
+            .flat_map(|frame| frame.values())
+            .chain(self.toplevel_environment.iter().copied())
+            .filter_map(|v| v.address())
     }
 
-    pub fn set_var_no_refcount(&mut self, name: String, value: HeapAddress) {
-        self.current_frame_mut().set_var_no_refcount(name, value);
+    // Removes the frames from the given depth upwards, for suspending a
+    // generator.
+    pub fn split_off(&mut self, depth: usize) -> Vec<CallStackFrame> {
+        assert!(depth > 0, "cannot remove the toplevel function");
+        self.frames.split_off(depth)
     }
 
-    pub fn lookup_var(&self, name: &str) -> HeapAddress {
-        self.current_frame().lookup_var(name)
+    // Puts the frames of a suspended generator back onto the stack. The
+    // outermost of them returns to the given address.
+    pub fn restore(&mut self, mut frames: Vec<CallStackFrame>, return_info: ReturnInfo) {
+        let outermost = frames
+            .first_mut()
+            .and_then(|frame| frame.nested_block_frames.first_mut())
+            .expect("generator without frames");
+        outermost.return_info = Some(return_info);
+        self.frames.append(&mut frames);
     }
 
     fn current_frame_mut(&mut self) -> &mut CallStackFrame {
@@ -0,0 +1,57 @@
+use crate::ir_let::interpreter::heap_value::{HeapAddress, HeapValue, Value};
+use crate::ir_let::let_expr::{Instruction, TargetAddress};
+use crate::symbol::Symbol;
+use std::fmt::Debug;
+
+// Receives the events of an evaluation. Every event is ignored unless the
+// sink overrides it.
+pub trait TraceSink: Debug {
+    fn instruction(&mut self, _address: TargetAddress, _instruction: &Instruction) {}
+
+    // A call to the function with the given name, whose body starts at the
+    // given address.
+    fn call(&mut self, _name: Symbol, _body: TargetAddress, _is_tail_call: bool) {}
+
+    // A function returns the given value. Tail calls do not return from the
+    // calling function separately.
+    fn function_return(&mut self, _value: Value) {}
+
+    fn alloc(&mut self, _address: HeapAddress, _heap_value: &HeapValue) {}
+
+    fn free(&mut self, _address: HeapAddress) {}
+}
+
+#[derive(Debug, Default)]
+pub struct NoTrace;
+
+impl TraceSink for NoTrace {}
+
+// Prints every event to standard output.
+#[derive(Debug, Default)]
+pub struct ConsoleTrace;
+
+impl TraceSink for ConsoleTrace {
+    fn instruction(&mut self, address: TargetAddress, instruction: &Instruction) {
+        println!("{} {}", address, instruction);
+    }
+
+    fn call(&mut self, name: Symbol, body: TargetAddress, is_tail_call: bool) {
+        let kind = if is_tail_call { "tailcall" } else { "call" };
+        println!("{} {} at {}", kind, name, body);
+    }
+
+    fn function_return(&mut self, value: Value) {
+        println!("return {:?}", value);
+    }
+
+    fn alloc(&mut self, address: HeapAddress, heap_value: &HeapValue) {
+        println!(
+            "alloc {}:{} {:?}",
+            address.index, address.generation, heap_value
+        );
+    }
+
+    fn free(&mut self, address: HeapAddress) {
+        println!("free {}:{}", address.index, address.generation);
+    }
+}
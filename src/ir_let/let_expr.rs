@@ -1,7 +1,9 @@
 use crate::lang::syntax::{BinOp, Constant};
+use crate::symbol::Symbol;
 use std::fmt;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub functions: Vec<Function>,
 }
@@ -25,10 +27,10 @@ impl Program {
 
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "program\n")?;
+        writeln!(f, "program")?;
 
         for (i, func) in self.functions.iter().enumerate() {
-            write!(f, "begin function {}\n", i)?;
+            writeln!(f, "begin function {}", i)?;
             write!(f, "{}", func)?;
             write!(f, "end function {}\n\n", i)?;
         }
@@ -38,22 +40,54 @@ impl fmt::Display for Program {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Function {
-    pub name: String,
-    pub arg_names: Vec<String>,
+    pub name: Symbol,
+    pub arg_names: Vec<Symbol>,
     // TODO: This should never be None after the compiler is done constructing
     // the function. Could enforce that in the type, but would require tweaking
     // compiler internals a bit.
-    pub free_names: Option<Vec<String>>,
+    pub free_names: Option<Vec<Symbol>>,
     pub blocks: Vec<Block>,
 }
 
+impl Function {
+    // The number of blocks enclosing the given block.
+    pub fn block_depth(&self, block_index: usize) -> usize {
+        let mut depth = 0;
+        let mut current = self.blocks[block_index].parent_block_index;
+
+        while let Some(parent) = current {
+            depth += 1;
+            current = self.blocks[parent].parent_block_index;
+        }
+
+        depth
+    }
+}
+
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "name {}", self.name)?;
+
+        write!(f, "arguments")?;
+        for arg_name in &self.arg_names {
+            write!(f, " {}", arg_name)?;
+        }
+        writeln!(f)?;
+
+        if let Some(free_names) = &self.free_names {
+            write!(f, "free names")?;
+            for free_name in free_names {
+                write!(f, " {}", free_name)?;
+            }
+            writeln!(f)?;
+        }
+
         for (i, block) in self.blocks.iter().enumerate() {
-            write!(f, "begin block {}\n", i)?;
+            writeln!(f, "begin block {}", i)?;
             write!(f, "{}", block)?;
-            write!(f, "begin block {}\n\n", i)?;
+            write!(f, "end block {}\n\n", i)?;
         }
 
         Ok(())
@@ -61,23 +95,24 @@ impl fmt::Display for Function {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     // The last instruction represents the result of evaluating the
     // entire sequence of instructions.
     pub instructions: Vec<Instruction>,
     pub parent_block_index: Option<usize>,
+    // Loop headers are the only blocks that can be the target of a Jump.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_loop_header: bool,
 }
 
 impl Block {
-    pub fn block_names(&self) -> Vec<String> {
+    pub fn block_names(&self) -> Vec<Symbol> {
         let mut result = Vec::new();
 
         for instruction in &self.instructions {
-            match instruction {
-                Instruction::Assignment(Assignment { name, .. }) => {
-                    result.push(name.clone());
-                }
-                _ => {}
+            if let Instruction::Assignment(Assignment { name, .. }) = instruction {
+                result.push(*name);
             }
         }
 
@@ -88,13 +123,17 @@ impl Block {
 impl fmt::Display for Block {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(parent_block_index) = self.parent_block_index {
-            write!(f, "parent block {}\n", parent_block_index)?;
+            writeln!(f, "parent block {}", parent_block_index)?;
         } else {
-            write!(f, "no parent block\n")?;
+            writeln!(f, "no parent block")?;
+        }
+
+        if self.is_loop_header {
+            writeln!(f, "loop header")?;
         }
 
         for instruction in self.instructions.iter() {
-            write!(f, "{}\n", instruction)?;
+            writeln!(f, "{}", instruction)?;
         }
 
         Ok(())
@@ -102,6 +141,7 @@ impl fmt::Display for Block {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     EnterBlock,
     ExitBlock(VariableReference),
@@ -123,12 +163,14 @@ impl fmt::Display for Instruction {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Assignment {
-    pub name: String,
+    pub name: Symbol,
     pub definition: Definition,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Definition {
     Var(VariableReference),
     Step(Step),
@@ -145,7 +187,8 @@ impl fmt::Display for Definition {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TargetAddress {
     pub function_index: usize,
     pub block_index: usize,
@@ -175,17 +218,24 @@ impl fmt::Display for TargetAddress {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AllocClosure {
-    pub name: String,
-    pub arg_names: Vec<String>,
-    pub free_names: Vec<String>,
+    pub name: Symbol,
+    pub arg_names: Vec<Symbol>,
+    pub free_names: Vec<VariableReference>,
     pub body: TargetAddress,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Simple {
     Literal(Constant),
     Fun(AllocClosure),
+    // A closure without free names, which only has to be allocated once.
+    LiftedFun {
+        name: Symbol,
+        function_index: usize,
+    },
     BinOp {
         op: BinOp,
         lhs: VariableReference,
@@ -194,6 +244,14 @@ pub enum Simple {
     Tuple {
         args: Vec<VariableReference>,
     },
+    // Allocates a tuple like Tuple, but if the evaluator finds that it holds
+    // the only reference to the reused tuple, which has the same number of
+    // fields, the fields are overwritten in place instead. The reused variable
+    // must not be used after this instruction.
+    ReuseTuple {
+        reused: VariableReference,
+        args: Vec<VariableReference>,
+    },
     Set {
         tuple: VariableReference,
         index: u32,
@@ -222,6 +280,10 @@ impl fmt::Display for Simple {
                 }
                 write!(f, "])")?;
             }
+            Simple::LiftedFun {
+                name,
+                function_index,
+            } => write!(f, "lifted({}, {})", name, function_index)?,
             Simple::BinOp { op, lhs, rhs } => {
                 write!(f, "{} ", lhs)?;
                 match op {
@@ -239,6 +301,13 @@ impl fmt::Display for Simple {
                 }
                 write!(f, ")")?
             }
+            Simple::ReuseTuple { reused, args } => {
+                write!(f, "reuse({}, (", reused)?;
+                for arg in args {
+                    write!(f, "{}, ", arg)?;
+                }
+                write!(f, "))")?
+            }
             Simple::Set {
                 tuple,
                 index,
@@ -251,22 +320,48 @@ impl fmt::Display for Simple {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Control {
     Call {
         func: VariableReference,
         args: Vec<VariableReference>,
     },
+    // A call whose result is immediately returned from the current function,
+    // so the frame of the current function can be reused for the callee.
+    TailCall {
+        func: VariableReference,
+        args: Vec<VariableReference>,
+    },
+    // A call of a closure that is known to have been allocated in the same
+    // function, so its code does not have to be looked up.
+    CallDirect {
+        func: VariableReference,
+        function_index: usize,
+        args: Vec<VariableReference>,
+    },
     If {
         condition: VariableReference,
         branch_success: TargetAddress,
         branch_failure: TargetAddress,
     },
+    // Starts the next iteration of a loop: the arguments of the current
+    // function are rebound to the given values, and execution continues at
+    // the start of a loop header block enclosing the jump. The blocks entered
+    // since the loop header are left without producing a result.
+    Jump {
+        target: TargetAddress,
+        args: Vec<VariableReference>,
+    },
 }
 
 impl fmt::Display for Control {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Control::Call { func, args } => {
+            Control::Call { func, args } | Control::TailCall { func, args } => {
+                if let Control::TailCall { .. } = self {
+                    write!(f, "tailcall ")?;
+                }
+
                 write!(f, "{}(", func)?;
 
                 if let Some((first, rest)) = args.split_first() {
@@ -279,6 +374,23 @@ impl fmt::Display for Control {
 
                 write!(f, ")")?;
             }
+            Control::CallDirect {
+                func,
+                function_index,
+                args,
+            } => {
+                write!(f, "calldirect {} {}(", func, function_index)?;
+
+                if let Some((first, rest)) = args.split_first() {
+                    write!(f, "{}", first)?;
+
+                    for arg in rest {
+                        write!(f, ", {}", arg)?;
+                    }
+                }
+
+                write!(f, ")")?;
+            }
             Control::If {
                 condition,
                 branch_success,
@@ -290,6 +402,19 @@ impl fmt::Display for Control {
                     condition, branch_success, branch_failure
                 )?;
             }
+            Control::Jump { target, args } => {
+                write!(f, "jump {}(", target)?;
+
+                if let Some((first, rest)) = args.split_first() {
+                    write!(f, "{}", first)?;
+
+                    for arg in rest {
+                        write!(f, ", {}", arg)?;
+                    }
+                }
+
+                write!(f, ")")?;
+            }
         };
 
         Ok(())
@@ -297,6 +422,7 @@ impl fmt::Display for Control {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Step {
     Simple(Simple),
     Control(Control),
@@ -314,8 +440,21 @@ impl fmt::Display for Step {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VariableReference {
-    pub var_name: String,
+    pub var_name: Symbol,
+    // Filled in by `resolve_variables`, after which the interpreter no longer
+    // needs the name to find the value of the variable.
+    pub location: Option<VariableLocation>,
+}
+
+impl VariableReference {
+    pub fn new(var_name: Symbol) -> Self {
+        VariableReference {
+            var_name,
+            location: None,
+        }
+    }
 }
 
 impl fmt::Display for VariableReference {
@@ -325,3 +464,15 @@ impl fmt::Display for VariableReference {
         Ok(())
     }
 }
+
+// Where the value of a variable is stored in the call stack frame of the
+// function that uses it. Locals are identified by the nesting depth of the
+// block that assigns them and the position of the assignment in that block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VariableLocation {
+    Local { block_depth: usize, offset: usize },
+    Argument(usize),
+    Closure(usize),
+    This,
+}
@@ -0,0 +1,213 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Assignment, Block, Control, Definition, Function, Instruction, Program, Simple,
+    Step, TargetAddress, VariableReference,
+};
+use crate::ir_let::visit::VisitorMut;
+use crate::lang::syntax::{BinOp, Constant};
+use crate::result::Result;
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+
+// A module compiled on its own. Its toplevel function returns a tuple of its
+// exports, and refers to the exports of the modules that it imports by their
+// qualified name.
+#[derive(Debug, Clone)]
+pub struct CompiledModule {
+    pub name: String,
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+    pub program: Program,
+}
+
+// What the linker needs to know to decide which modules to link, and in which
+// order.
+pub trait LinkUnit {
+    fn name(&self) -> &str;
+    fn imports(&self) -> &[String];
+}
+
+impl LinkUnit for CompiledModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn imports(&self) -> &[String] {
+        &self.imports
+    }
+}
+
+// The name of the variable that holds a definition of a module in the linked
+// program.
+pub fn qualified_name(module: &str, name: &str) -> String {
+    format!("{}__{}", module, name)
+}
+
+// The other variables of the linked toplevel function. A number cannot be the
+// name of a definition, and the names that the compiler generates for a
+// module end in a number but have another part in between.
+fn linker_name(module: &str, index: usize) -> Symbol {
+    Symbol::intern(&format!("{}__{}", module, index))
+}
+
+// Moves the references of a function to other functions of its module by the
+// index at which the functions of the module start in the linked program.
+struct Rebase {
+    offset: usize,
+}
+
+impl VisitorMut for Rebase {
+    fn visit_address(&mut self, address: &mut TargetAddress) {
+        address.function_index += self.offset;
+    }
+
+    fn visit_function_index(&mut self, function_index: &mut usize) {
+        *function_index += self.offset;
+    }
+}
+
+// The modules that the main module depends on, each after the modules it
+// imports. The main module comes last.
+pub fn link_order<'a, M: LinkUnit>(modules: &'a [M], main: &str) -> Result<Vec<&'a M>> {
+    fn visit<'a, M: LinkUnit>(
+        name: &str,
+        modules: &HashMap<&str, &'a M>,
+        active: &mut Vec<String>,
+        order: &mut Vec<&'a M>,
+    ) -> Result<()> {
+        if order.iter().any(|m| m.name() == name) {
+            return Ok(());
+        }
+        if active.iter().any(|m| m == name) {
+            return Err(format!("import cycle through module {}", name).into());
+        }
+        let module = *modules
+            .get(name)
+            .ok_or_else(|| format!("unknown module {}", name))?;
+
+        active.push(name.to_owned());
+        for import in module.imports() {
+            visit(import, modules, active, order)?;
+        }
+        active.pop();
+
+        order.push(module);
+        Ok(())
+    }
+
+    let mut by_name = HashMap::new();
+    for module in modules {
+        if by_name.insert(module.name(), module).is_some() {
+            return Err(format!("module {} is defined more than once", module.name()).into());
+        }
+    }
+
+    let mut order = Vec::new();
+    visit(main, &by_name, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+// Merges the modules into a single program, whose result is the result of the
+// main module. The new toplevel function runs the toplevel function of every
+// module that the main module depends on, once, and binds their exports to
+// the qualified names that the modules importing them refer to. The names of
+// the globals are left free.
+pub fn link(modules: &[CompiledModule], main: &str) -> Result<Program> {
+    let order = link_order(modules, main)?;
+
+    let mut functions = vec![];
+    let mut instructions = vec![Instruction::EnterBlock];
+    let mut bound = HashSet::new();
+    let mut free_names: Vec<Symbol> = Vec::new();
+    let mut result = None;
+
+    // The toplevel function comes first.
+    let offset = 1;
+    for module in order {
+        let module_offset = offset + functions.len();
+        for function in &module.program.functions {
+            let mut function = function.clone();
+            Rebase {
+                offset: module_offset,
+            }
+            .visit_function(module_offset, &mut function);
+            functions.push(function);
+        }
+
+        let toplevel = &functions[module_offset - offset];
+        let captured = toplevel.free_names.clone().unwrap_or_default();
+        for name in &captured {
+            if !bound.contains(name) && !free_names.contains(name) {
+                free_names.push(*name);
+            }
+        }
+
+        let init_name = linker_name(&module.name, 0);
+        let module_name = linker_name(&module.name, 1);
+        let mut assign = |name, definition| {
+            instructions.push(Instruction::Assignment(Assignment { name, definition }));
+        };
+        assign(
+            init_name,
+            Definition::Step(Step::Simple(Simple::Fun(AllocClosure {
+                name: toplevel.name,
+                arg_names: vec![],
+                free_names: captured.into_iter().map(VariableReference::new).collect(),
+                body: TargetAddress {
+                    function_index: module_offset,
+                    block_index: 0,
+                    instruction_index: 0,
+                },
+            }))),
+        );
+        assign(
+            module_name,
+            Definition::Step(Step::Control(Control::Call {
+                func: VariableReference::new(init_name),
+                args: vec![],
+            })),
+        );
+
+        if module.name == main {
+            result = Some(module_name);
+            continue;
+        }
+
+        for (index, export) in module.exports.iter().enumerate() {
+            let index_name = linker_name(&module.name, index + 2);
+            assign(
+                index_name,
+                Definition::Step(Step::Simple(Simple::Literal(Constant::Int {
+                    value: index as i64,
+                }))),
+            );
+            let export_name = Symbol::intern(&qualified_name(&module.name, export));
+            assign(
+                export_name,
+                Definition::Step(Step::Simple(Simple::BinOp {
+                    op: BinOp::Get,
+                    lhs: VariableReference::new(module_name),
+                    rhs: VariableReference::new(index_name),
+                })),
+            );
+            bound.insert(export_name);
+        }
+    }
+
+    let result = result.expect("main module is linked last");
+    instructions.push(Instruction::ExitBlock(VariableReference::new(result)));
+    free_names.sort_by_key(|name| name.as_str());
+
+    let toplevel = Function {
+        name: Symbol::intern("toplevel"),
+        arg_names: vec![],
+        free_names: Some(free_names),
+        blocks: vec![Block {
+            instructions,
+            parent_block_index: None,
+            is_loop_header: false,
+        }],
+    };
+    functions.insert(0, toplevel);
+
+    Ok(Program { functions })
+}
@@ -1,4 +1,15 @@
+pub mod absint;
+pub mod cfg;
 pub mod compiler;
+pub mod diff;
+pub mod escape;
 mod free_vars;
 pub mod interpreter;
 pub mod let_expr;
+pub mod linker;
+pub mod parser;
+pub mod passes;
+pub mod resolve;
+pub mod stats;
+pub mod verify;
+pub mod visit;
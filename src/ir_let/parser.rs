@@ -0,0 +1,487 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Assignment, Block, Control, Definition, Function, Instruction, Program, Simple,
+    Step, TargetAddress, VariableReference,
+};
+use crate::lang::syntax::{BinOp, Constant};
+use crate::result::Result;
+use crate::symbol::Symbol;
+
+// Parses the text format produced by the Display implementation of Program.
+// The format is line based: every instruction is on a line of its own, and
+// empty lines are ignored.
+struct Parser<'a> {
+    lines: Vec<(usize, &'a str)>,
+    position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Symbol(&'static str),
+}
+
+// Longer symbols come first, so that "==" is not read as two "=" symbols.
+const SYMBOLS: [&str; 11] = ["==", "!!", "(", ")", "[", "]", ",", "=", "+", "-", "."];
+
+fn tokenize(line: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        let word_length = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+
+        if word_length > 0 {
+            tokens.push(Token::Word(rest[..word_length].to_owned()));
+            rest = &rest[word_length..];
+        } else if let Some(symbol) = SYMBOLS.iter().chain(&["."]).find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            return Err(format!("unexpected character in {:?}", rest));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+// Parses a single line from a list of tokens.
+struct LineParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl LineParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> std::result::Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("unexpected end of line")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position == self.tokens.len()
+    }
+
+    fn next_is(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> std::result::Result<(), String> {
+        match self.next()? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            token => Err(format!("expected {:?} but found {:?}", symbol, token)),
+        }
+    }
+
+    fn word(&mut self) -> std::result::Result<String, String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(format!("expected a name but found {:?}", token)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> std::result::Result<(), String> {
+        match self.word()? {
+            ref word if word == keyword => Ok(()),
+            word => Err(format!("expected {:?} but found {:?}", keyword, word)),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> std::result::Result<T, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number but found {:?}", word))
+    }
+
+    fn var(&mut self) -> std::result::Result<VariableReference, String> {
+        Ok(VariableReference::new(Symbol::intern(&self.word()?)))
+    }
+
+    fn address(&mut self) -> std::result::Result<TargetAddress, String> {
+        self.expect_symbol("(")?;
+        let function_index = self.number()?;
+        self.expect_symbol(",")?;
+        let block_index = self.number()?;
+        self.expect_symbol(",")?;
+        let instruction_index = self.number()?;
+        self.expect_symbol(")")?;
+
+        Ok(TargetAddress {
+            function_index,
+            block_index,
+            instruction_index,
+        })
+    }
+
+    // Parses variables up to the closing symbol. Every variable is followed
+    // by the separator, except possibly the last one.
+    fn vars_until(
+        &mut self,
+        separator: Option<&str>,
+        close: &str,
+    ) -> std::result::Result<Vec<VariableReference>, String> {
+        let mut vars = Vec::new();
+
+        while !self.next_is(close) {
+            vars.push(self.var()?);
+
+            match separator {
+                Some(separator) if !self.next_is(close) => self.expect_symbol(separator)?,
+                _ => {}
+            }
+        }
+        self.expect_symbol(close)?;
+
+        Ok(vars)
+    }
+
+    fn names_until(&mut self, close: &str) -> std::result::Result<Vec<Symbol>, String> {
+        Ok(self
+            .vars_until(None, close)?
+            .into_iter()
+            .map(|v| v.var_name)
+            .collect())
+    }
+
+    fn instruction(&mut self) -> std::result::Result<Instruction, String> {
+        let first = self.word()?;
+
+        let instruction = match first.as_str() {
+            "enterblock" => Instruction::EnterBlock,
+            "exitblock" => {
+                self.expect_symbol("(")?;
+                let var = self.var()?;
+                self.expect_symbol(")")?;
+                Instruction::ExitBlock(var)
+            }
+            _ => {
+                self.expect_symbol("=")?;
+                Instruction::Assignment(Assignment {
+                    name: Symbol::intern(&first),
+                    definition: self.definition()?,
+                })
+            }
+        };
+
+        if !self.is_at_end() {
+            return Err(format!("unexpected {:?}", self.peek().unwrap()));
+        }
+
+        Ok(instruction)
+    }
+
+    fn definition(&mut self) -> std::result::Result<Definition, String> {
+        if self.next_is("(") {
+            self.next()?;
+            let args = self.vars_until(Some(","), ")")?;
+            return Ok(Definition::Step(Step::Simple(Simple::Tuple { args })));
+        }
+
+        if self.next_is("-") {
+            self.next()?;
+            let word = self.word()?;
+            let value = format!("-{}", word)
+                .parse()
+                .map_err(|_| format!("expected a number but found {:?}", word))?;
+            return Ok(Definition::Step(Step::Simple(Simple::Literal(
+                Constant::Int { value },
+            ))));
+        }
+
+        let first = self.word()?;
+        match first.as_str() {
+            "tailcall" => {
+                let func = self.var()?;
+                self.expect_symbol("(")?;
+                let args = self.vars_until(Some(","), ")")?;
+                return Ok(Definition::Step(Step::Control(Control::TailCall {
+                    func,
+                    args,
+                })));
+            }
+            "calldirect" => {
+                let func = self.var()?;
+                let function_index = self.number()?;
+                self.expect_symbol("(")?;
+                let args = self.vars_until(Some(","), ")")?;
+                return Ok(Definition::Step(Step::Control(Control::CallDirect {
+                    func,
+                    function_index,
+                    args,
+                })));
+            }
+            "jump" => {
+                let target = self.address()?;
+                self.expect_symbol("(")?;
+                let args = self.vars_until(Some(","), ")")?;
+                return Ok(Definition::Step(Step::Control(Control::Jump {
+                    target,
+                    args,
+                })));
+            }
+            "if" => {
+                let condition = self.var()?;
+                self.keyword("then")?;
+                let branch_success = self.address()?;
+                self.keyword("else")?;
+                let branch_failure = self.address()?;
+                return Ok(Definition::Step(Step::Control(Control::If {
+                    condition,
+                    branch_success,
+                    branch_failure,
+                })));
+            }
+            "lifted" if self.next_is("(") => {
+                self.expect_symbol("(")?;
+                let name = Symbol::intern(&self.word()?);
+                self.expect_symbol(",")?;
+                let function_index = self.number()?;
+                self.expect_symbol(")")?;
+                return Ok(Definition::Step(Step::Simple(Simple::LiftedFun {
+                    name,
+                    function_index,
+                })));
+            }
+            "reuse" if self.next_is("(") => {
+                self.expect_symbol("(")?;
+                let reused = self.var()?;
+                self.expect_symbol(",")?;
+                self.expect_symbol("(")?;
+                let args = self.vars_until(Some(","), ")")?;
+                self.expect_symbol(")")?;
+                return Ok(Definition::Step(Step::Simple(Simple::ReuseTuple {
+                    reused,
+                    args,
+                })));
+            }
+            "closure" if self.next_is("(") => {
+                self.expect_symbol("(")?;
+                let name = Symbol::intern(&self.word()?);
+                self.expect_symbol(",")?;
+                let body = self.address()?;
+                self.expect_symbol(",")?;
+                self.expect_symbol("[")?;
+                let arg_names = self.names_until("]")?;
+                self.expect_symbol(",")?;
+                self.expect_symbol("[")?;
+                let free_names = self.vars_until(None, "]")?;
+                self.expect_symbol(")")?;
+                return Ok(Definition::Step(Step::Simple(Simple::Fun(AllocClosure {
+                    name,
+                    arg_names,
+                    free_names,
+                    body,
+                }))));
+            }
+            _ => {}
+        }
+
+        let var = VariableReference::new(Symbol::intern(&first));
+        let op = match self.peek() {
+            None => {
+                return Ok(if first == "true" || first == "false" {
+                    Definition::Step(Step::Simple(Simple::Literal(Constant::Bool {
+                        value: first == "true",
+                    })))
+                } else if let Ok(value) = first.parse() {
+                    Definition::Step(Step::Simple(Simple::Literal(Constant::Int { value })))
+                } else {
+                    Definition::Var(var)
+                });
+            }
+            Some(Token::Symbol("(")) => {
+                self.next()?;
+                let args = self.vars_until(Some(","), ")")?;
+                return Ok(Definition::Step(Step::Control(Control::Call {
+                    func: var,
+                    args,
+                })));
+            }
+            Some(Token::Symbol(".")) => {
+                self.next()?;
+                let index = self.number()?;
+                self.expect_symbol("=")?;
+                let new_value = self.var()?;
+                return Ok(Definition::Step(Step::Simple(Simple::Set {
+                    tuple: var,
+                    index,
+                    new_value,
+                })));
+            }
+            Some(Token::Symbol("+")) => BinOp::Add,
+            Some(Token::Symbol("-")) => BinOp::Sub,
+            Some(Token::Symbol("==")) => BinOp::Eq,
+            Some(Token::Symbol("!!")) => BinOp::Get,
+            Some(token) => return Err(format!("unexpected {:?}", token)),
+        };
+        self.next()?;
+
+        Ok(Definition::Step(Step::Simple(Simple::BinOp {
+            op,
+            lhs: var,
+            rhs: self.var()?,
+        })))
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser {
+            lines: input
+                .lines()
+                .enumerate()
+                .map(|(i, line)| (i + 1, line.trim()))
+                .filter(|(_, line)| !line.is_empty())
+                .collect(),
+            position: 0,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T> {
+        match self.lines.get(self.position) {
+            Some((line_number, _)) => Err(format!("line {}: {}", line_number, message).into()),
+            None => Err(format!("end of input: {}", message).into()),
+        }
+    }
+
+    fn peek_line(&self) -> Option<&'a str> {
+        self.lines.get(self.position).map(|(_, line)| *line)
+    }
+
+    fn next_line(&mut self) -> Result<&'a str> {
+        match self.peek_line() {
+            Some(line) => {
+                self.position += 1;
+                Ok(line)
+            }
+            None => self.error("unexpected end of input".to_owned()),
+        }
+    }
+
+    fn expect_line(&mut self, expected: &str) -> Result<()> {
+        match self.peek_line() {
+            Some(line) if line == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(line) => self.error(format!("expected {:?} but found {:?}", expected, line)),
+            None => self.error(format!("expected {:?}", expected)),
+        }
+    }
+
+    // Returns the rest of the next line if it starts with the given keyword.
+    fn line_with_prefix(&mut self, prefix: &str) -> Option<&'a str> {
+        let line = self.peek_line()?;
+        let rest = line.strip_prefix(prefix)?;
+
+        if rest.is_empty() || rest.starts_with(' ') {
+            self.position += 1;
+            Some(rest.trim())
+        } else {
+            None
+        }
+    }
+
+    fn parse_program(mut self) -> Result<Program> {
+        self.expect_line("program")?;
+
+        let mut functions = Vec::new();
+        while self.peek_line().is_some() {
+            let index = functions.len();
+            self.expect_line(&format!("begin function {}", index))?;
+            functions.push(self.parse_function()?);
+            self.expect_line(&format!("end function {}", index))?;
+        }
+
+        Ok(Program { functions })
+    }
+
+    fn parse_function(&mut self) -> Result<Function> {
+        let name = match self.line_with_prefix("name") {
+            Some(name) if !name.is_empty() => Symbol::intern(name),
+            _ => return self.error("expected function name".to_owned()),
+        };
+        let arg_names = match self.line_with_prefix("arguments") {
+            Some(args) => args.split_whitespace().map(Symbol::intern).collect(),
+            None => return self.error("expected function arguments".to_owned()),
+        };
+        let free_names = self
+            .line_with_prefix("free names")
+            .map(|names| names.split_whitespace().map(Symbol::intern).collect());
+
+        let mut blocks = Vec::new();
+        while self
+            .peek_line()
+            .is_some_and(|l| l.starts_with("begin block"))
+        {
+            let index = blocks.len();
+            self.expect_line(&format!("begin block {}", index))?;
+            blocks.push(self.parse_block()?);
+            self.expect_line(&format!("end block {}", index))?;
+        }
+
+        Ok(Function {
+            name,
+            arg_names,
+            free_names,
+            blocks,
+        })
+    }
+
+    fn parse_block(&mut self) -> Result<Block> {
+        let parent_block_index = if let Some(parent) = self.line_with_prefix("parent block") {
+            match parent.parse() {
+                Ok(parent) => Some(parent),
+                Err(_) => return self.error(format!("invalid parent block {:?}", parent)),
+            }
+        } else {
+            self.expect_line("no parent block")?;
+            None
+        };
+        let is_loop_header = self.line_with_prefix("loop header").is_some();
+
+        let mut instructions = Vec::new();
+        while self
+            .peek_line()
+            .is_some_and(|l| !l.starts_with("end block"))
+        {
+            let line = self.next_line()?;
+            let result = tokenize(line).and_then(|tokens| {
+                LineParser {
+                    tokens,
+                    position: 0,
+                }
+                .instruction()
+            });
+
+            match result {
+                Ok(instruction) => instructions.push(instruction),
+                Err(message) => {
+                    self.position -= 1;
+                    return self.error(message);
+                }
+            }
+        }
+
+        Ok(Block {
+            instructions,
+            parent_block_index,
+            is_loop_header,
+        })
+    }
+}
+
+pub fn parse_program(input: &str) -> Result<Program> {
+    Parser::new(input).parse_program()
+}
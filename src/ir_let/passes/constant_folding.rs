@@ -0,0 +1,187 @@
+use crate::ir_let::let_expr::{
+    Assignment, Block, Control, Definition, Function, Instruction, Program, Simple, Step,
+    VariableReference,
+};
+use crate::ir_let::passes::manager::Pass;
+use crate::ir_let::passes::remove_unreachable_blocks;
+use crate::lang::syntax::{BinOp, Constant};
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+
+// Evaluates binary operations on literal operands at compile time and replaces
+// conditionals on constant conditions by the instructions of the branch that
+// would be taken. Since all variable names in a program are unique, the known
+// constants do not have to be scoped.
+struct ConstantFolder {
+    constants: HashMap<Symbol, Constant>,
+}
+
+impl ConstantFolder {
+    fn new() -> Self {
+        ConstantFolder {
+            constants: HashMap::new(),
+        }
+    }
+
+    fn fold_function(&mut self, function: &mut Function) {
+        let mut absorbed = vec![false; function.blocks.len()];
+
+        // Parent blocks always precede their children, so the constants
+        // defined in enclosing blocks are known when a block is processed.
+        for block_index in 0..function.blocks.len() {
+            if absorbed[block_index] {
+                continue;
+            }
+
+            let mut instructions = Vec::new();
+            self.fold_block(
+                &mut function.blocks,
+                &mut absorbed,
+                block_index,
+                block_index,
+                &mut instructions,
+            );
+            function.blocks[block_index].instructions = instructions;
+        }
+
+        remove_unreachable_blocks(function);
+    }
+
+    // Appends the folded instructions of `block_index` to `output`, which
+    // holds the instructions of `output_block_index`. When a block is inlined
+    // into another one, its EnterBlock and ExitBlock instructions are dropped
+    // and the variable holding its result is returned instead.
+    fn fold_block(
+        &mut self,
+        blocks: &mut [Block],
+        absorbed: &mut [bool],
+        block_index: usize,
+        output_block_index: usize,
+        output: &mut Vec<Instruction>,
+    ) -> Option<VariableReference> {
+        let is_inlined = block_index != output_block_index;
+
+        for instruction in blocks[block_index].instructions.clone() {
+            match instruction {
+                Instruction::EnterBlock if is_inlined => {}
+                Instruction::ExitBlock(result) if is_inlined => return Some(result),
+                Instruction::Assignment(Assignment { name, definition }) => {
+                    // Loop headers are kept, since jumps refer to them.
+                    let taken_block_index = self
+                        .known_branch(&definition)
+                        .filter(|taken| !blocks[*taken].is_loop_header);
+
+                    let definition = if let Some(taken_block_index) = taken_block_index {
+                        // Nested blocks of the inlined branch now live in the
+                        // block we are inlining into.
+                        for block in blocks.iter_mut() {
+                            if block.parent_block_index == Some(taken_block_index) {
+                                block.parent_block_index = Some(output_block_index);
+                            }
+                        }
+                        absorbed[taken_block_index] = true;
+
+                        let result = self
+                            .fold_block(
+                                blocks,
+                                absorbed,
+                                taken_block_index,
+                                output_block_index,
+                                output,
+                            )
+                            .expect("inlined block should have a result");
+                        self.fold_definition(Definition::Var(result))
+                    } else {
+                        self.fold_definition(definition)
+                    };
+
+                    if let Definition::Step(Step::Simple(Simple::Literal(c))) = &definition {
+                        self.constants.insert(name, *c);
+                    }
+                    output.push(Instruction::Assignment(Assignment { name, definition }));
+                }
+                instruction => output.push(instruction),
+            }
+        }
+
+        None
+    }
+
+    fn known_branch(&self, definition: &Definition) -> Option<usize> {
+        match definition {
+            Definition::Step(Step::Control(Control::If {
+                condition,
+                branch_success,
+                branch_failure,
+            })) => match self.constants.get(&condition.var_name) {
+                Some(Constant::Bool { value: true }) => Some(branch_success.block_index),
+                Some(Constant::Bool { value: false }) => Some(branch_failure.block_index),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn fold_definition(&self, definition: Definition) -> Definition {
+        let folded = match &definition {
+            Definition::Var(var) => self.constants.get(&var.var_name).copied(),
+            Definition::Step(Step::Simple(Simple::BinOp { op, lhs, rhs })) => {
+                match (
+                    self.constants.get(&lhs.var_name),
+                    self.constants.get(&rhs.var_name),
+                ) {
+                    (Some(lhs), Some(rhs)) => fold_binop(*op, *lhs, *rhs),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match folded {
+            Some(c) => Definition::Step(Step::Simple(Simple::Literal(c))),
+            None => definition,
+        }
+    }
+}
+
+// Returns None when the operation cannot be evaluated at compile time, which
+// includes operations that would fail at runtime. These are left alone so that
+// the failure still happens when the program runs.
+fn fold_binop(op: BinOp, lhs: Constant, rhs: Constant) -> Option<Constant> {
+    match (op, lhs, rhs) {
+        (BinOp::Add, Constant::Int { value: lhs }, Constant::Int { value: rhs }) => {
+            Some(Constant::Int {
+                value: lhs.checked_add(rhs)?,
+            })
+        }
+        (BinOp::Sub, Constant::Int { value: lhs }, Constant::Int { value: rhs }) => {
+            Some(Constant::Int {
+                value: lhs.checked_sub(rhs)?,
+            })
+        }
+        (BinOp::Eq, Constant::Int { value: lhs }, Constant::Int { value: rhs }) => {
+            Some(Constant::Bool { value: lhs == rhs })
+        }
+        _ => None,
+    }
+}
+
+pub fn fold_constants(program: &mut Program) {
+    let mut folder = ConstantFolder::new();
+
+    for function in &mut program.functions {
+        folder.fold_function(function);
+    }
+}
+
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &str {
+        "constant-folding"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        fold_constants(program);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Control, Definition, Function, Instruction, Program, Simple, Step,
+    VariableReference,
+};
+use crate::ir_let::passes::manager::Pass;
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+
+// Removes aliasing assignments of the form `x = y` by replacing every use of
+// `x` with `y`. Since all variable names in a program are unique and uses only
+// occur in the scope of a definition (which includes closures nested inside of
+// it), a single substitution for the whole program suffices. The only names
+// that are assigned more than once are the arguments of functions with loops,
+// which every jump rebinds, so copies of those are kept: a copy taken before
+// the jump still holds the old value afterwards.
+struct CopyPropagator {
+    substitution: HashMap<Symbol, Symbol>,
+    rebound: HashSet<Symbol>,
+}
+
+impl CopyPropagator {
+    fn new(program: &Program) -> Self {
+        let mut rebound = HashSet::new();
+        for function in &program.functions {
+            let has_jump = function
+                .blocks
+                .iter()
+                .flat_map(|block| &block.instructions)
+                .any(|instruction| {
+                    matches!(
+                        instruction,
+                        Instruction::Assignment(assignment)
+                            if matches!(
+                                assignment.definition,
+                                Definition::Step(Step::Control(Control::Jump { .. }))
+                            )
+                    )
+                });
+            if has_jump {
+                rebound.extend(function.arg_names.iter().copied());
+            }
+        }
+
+        CopyPropagator {
+            substitution: HashMap::new(),
+            rebound,
+        }
+    }
+
+    fn resolve(&self, name: Symbol) -> Symbol {
+        match self.substitution.get(&name) {
+            Some(target) => *target,
+            None => name,
+        }
+    }
+
+    fn propagate_var(&self, var: &mut VariableReference) {
+        var.var_name = self.resolve(var.var_name);
+    }
+
+    fn propagate_names(&self, names: &mut Vec<Symbol>) {
+        let mut result: Vec<Symbol> = Vec::new();
+
+        // Two captured variables can become the same after substitution.
+        for name in names.iter() {
+            let name = self.resolve(*name);
+            if !result.contains(&name) {
+                result.push(name);
+            }
+        }
+
+        *names = result;
+    }
+
+    fn propagate_function(&mut self, function: &mut Function) {
+        if let Some(free_names) = &mut function.free_names {
+            self.propagate_names(free_names);
+        }
+
+        // Parent blocks precede their children, so every alias is known
+        // before it is used.
+        for block in &mut function.blocks {
+            let instructions = std::mem::take(&mut block.instructions);
+
+            for mut instruction in instructions {
+                match &mut instruction {
+                    Instruction::EnterBlock => {}
+                    Instruction::ExitBlock(var) => self.propagate_var(var),
+                    Instruction::Assignment(assignment) => {
+                        if let Definition::Var(var) = &assignment.definition {
+                            let target = self.resolve(var.var_name);
+                            if !self.rebound.contains(&target) {
+                                self.substitution.insert(assignment.name, target);
+                                continue;
+                            }
+                        }
+
+                        self.propagate_definition(&mut assignment.definition);
+                    }
+                }
+
+                block.instructions.push(instruction);
+            }
+        }
+    }
+
+    fn propagate_definition(&self, definition: &mut Definition) {
+        match definition {
+            Definition::Var(var) => self.propagate_var(var),
+            Definition::Step(Step::Simple(simple)) => match simple {
+                Simple::Literal(_) | Simple::LiftedFun { .. } => {}
+                Simple::Fun(AllocClosure { free_names, .. }) => {
+                    // Two captured variables can become the same after
+                    // substitution, just like the free names of the function.
+                    let mut captured = HashSet::new();
+                    free_names.retain_mut(|var| {
+                        self.propagate_var(var);
+                        captured.insert(var.var_name)
+                    });
+                }
+                Simple::BinOp { lhs, rhs, .. } => {
+                    self.propagate_var(lhs);
+                    self.propagate_var(rhs);
+                }
+                Simple::Tuple { args } => {
+                    for arg in args {
+                        self.propagate_var(arg);
+                    }
+                }
+                Simple::ReuseTuple { reused, args } => {
+                    self.propagate_var(reused);
+                    for arg in args {
+                        self.propagate_var(arg);
+                    }
+                }
+                Simple::Set {
+                    tuple, new_value, ..
+                } => {
+                    self.propagate_var(tuple);
+                    self.propagate_var(new_value);
+                }
+            },
+            Definition::Step(Step::Control(control)) => match control {
+                Control::Call { func, args } | Control::TailCall { func, args } => {
+                    self.propagate_var(func);
+                    for arg in args {
+                        self.propagate_var(arg);
+                    }
+                }
+                Control::CallDirect { func, args, .. } => {
+                    self.propagate_var(func);
+                    for var in args {
+                        self.propagate_var(var);
+                    }
+                }
+                Control::If { condition, .. } => self.propagate_var(condition),
+                Control::Jump { args, .. } => {
+                    for arg in args {
+                        self.propagate_var(arg);
+                    }
+                }
+            },
+        }
+    }
+}
+
+pub fn propagate_copies(program: &mut Program) {
+    let mut propagator = CopyPropagator::new(program);
+
+    for function in &mut program.functions {
+        propagator.propagate_function(function);
+    }
+}
+
+pub struct CopyPropagation;
+
+impl Pass for CopyPropagation {
+    fn name(&self) -> &str {
+        "copy-propagation"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        propagate_copies(program);
+    }
+}
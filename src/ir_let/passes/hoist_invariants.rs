@@ -0,0 +1,224 @@
+use crate::ir_let::let_expr::{
+    Assignment, Block, Control, Definition, Function, Instruction, Program, Simple, Step,
+    TargetAddress, VariableReference,
+};
+use crate::ir_let::passes::manager::Pass;
+use crate::lang::syntax::Constant;
+use crate::symbol::Symbol;
+use std::collections::HashSet;
+
+// Literals and lifted closures have the same value in every iteration of a
+// loop, and evaluating them can never fail. Other simple steps stay in the
+// loop: arithmetic and field accesses can fail, so moving them out of a branch
+// that is not taken would change the behavior of the program, and every
+// iteration has to allocate a tuple of its own, since tuples are mutable.
+fn is_invariant(definition: &Definition) -> bool {
+    matches!(
+        definition,
+        Definition::Step(Step::Simple(Simple::Literal(_) | Simple::LiftedFun { .. }))
+    )
+}
+
+fn var(name: Symbol) -> VariableReference {
+    VariableReference {
+        var_name: name,
+        location: None,
+    }
+}
+
+fn assignment(name: Symbol, definition: Definition) -> Instruction {
+    Instruction::Assignment(Assignment { name, definition })
+}
+
+// Generates names that do not occur anywhere in the program.
+struct FreshNames {
+    used: HashSet<Symbol>,
+    count: usize,
+}
+
+impl FreshNames {
+    fn new(program: &Program) -> Self {
+        let mut used = HashSet::new();
+        for function in &program.functions {
+            used.insert(function.name);
+            used.extend(function.arg_names.iter().copied());
+            for block in &function.blocks {
+                used.extend(block.block_names());
+            }
+        }
+
+        FreshNames { used, count: 0 }
+    }
+
+    fn fresh(&mut self) -> Symbol {
+        loop {
+            let name = Symbol::intern(&format!("__hoist__{}", self.count));
+            self.count += 1;
+            if self.used.insert(name) {
+                return name;
+            }
+        }
+    }
+}
+
+// Whether the block is the given ancestor or nested inside of it.
+fn is_nested_in(function: &Function, mut block_index: usize, ancestor: usize) -> bool {
+    loop {
+        if block_index == ancestor {
+            return true;
+        }
+        match function.blocks[block_index].parent_block_index {
+            Some(parent) => block_index = parent,
+            None => return false,
+        }
+    }
+}
+
+// Moves the invariant instructions of the loop with the given header into a
+// new block that encloses the header and is inserted in its place, so that
+// they are evaluated once before the loop is entered instead of in every
+// iteration. Jumps go back to the header, which clears its locals, so the
+// hoisted instructions cannot stay in the header itself. The new block enters
+// the header with a conditional on a constant, which constant folding keeps
+// since the header is a loop header.
+fn hoist_loop(
+    function_index: usize,
+    function: &mut Function,
+    header: usize,
+    names: &mut FreshNames,
+) {
+    let mut hoisted = Vec::new();
+    for block_index in header..function.blocks.len() {
+        if !is_nested_in(function, block_index, header) {
+            continue;
+        }
+        let block = &mut function.blocks[block_index];
+        let (invariant, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut block.instructions)
+            .into_iter()
+            .partition(|instruction| {
+                matches!(instruction, Instruction::Assignment(a) if is_invariant(&a.definition))
+            });
+        hoisted.extend(invariant);
+        block.instructions = rest;
+    }
+
+    if hoisted.is_empty() {
+        return;
+    }
+
+    // The new block takes the index of the header, so that parents still
+    // precede their children, and the blocks from the header onwards move up
+    // by one. Conditionals that entered the header now enter the new block,
+    // while jumps still go to the header itself.
+    let renumber = |block_index: usize| {
+        if block_index >= header {
+            block_index + 1
+        } else {
+            block_index
+        }
+    };
+    for block in &mut function.blocks {
+        block.parent_block_index = block.parent_block_index.map(renumber);
+        for instruction in &mut block.instructions {
+            let Instruction::Assignment(assignment) = instruction else {
+                continue;
+            };
+            match &mut assignment.definition {
+                Definition::Step(Step::Control(Control::If {
+                    branch_success,
+                    branch_failure,
+                    ..
+                })) => {
+                    for branch in [branch_success, branch_failure] {
+                        if branch.block_index != header {
+                            branch.block_index = renumber(branch.block_index);
+                        }
+                    }
+                }
+                Definition::Step(Step::Control(Control::Jump { target, .. })) => {
+                    target.block_index = renumber(target.block_index);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let parent_block_index = function.blocks[header].parent_block_index;
+    function.blocks[header].parent_block_index = Some(header);
+
+    let condition = names.fresh();
+    let result = names.fresh();
+    let address = |block_index| TargetAddress {
+        function_index,
+        block_index,
+        instruction_index: 0,
+    };
+
+    let mut instructions = vec![Instruction::EnterBlock];
+    instructions.extend(hoisted);
+    instructions.push(assignment(
+        condition,
+        Definition::Step(Step::Simple(Simple::Literal(Constant::Bool {
+            value: true,
+        }))),
+    ));
+    instructions.push(assignment(
+        result,
+        Definition::Step(Step::Control(Control::If {
+            condition: var(condition),
+            branch_success: address(header + 1),
+            branch_failure: address(function.blocks.len() + 1),
+        })),
+    ));
+    instructions.push(Instruction::ExitBlock(var(result)));
+
+    function.blocks.insert(
+        header,
+        Block {
+            instructions,
+            parent_block_index,
+            is_loop_header: false,
+        },
+    );
+    // The branch that is never taken, which is a block of its own since the
+    // backends emit the code of each branch separately.
+    function.blocks.push(Block {
+        instructions: vec![
+            Instruction::EnterBlock,
+            Instruction::ExitBlock(var(condition)),
+        ],
+        parent_block_index: Some(header),
+        is_loop_header: false,
+    });
+}
+
+// Hoists literals and lifted closures out of loops, such as the constants that
+// a tail recursive function compares its counter with and subtracts from it.
+// Inner loops come after the loops that enclose them, so going through the
+// headers backwards hoists the instructions of an inner loop all the way out
+// of the loops enclosing it.
+pub fn hoist_invariants(program: &mut Program) {
+    let mut names = FreshNames::new(program);
+
+    for (function_index, function) in program.functions.iter_mut().enumerate() {
+        let headers: Vec<usize> = (0..function.blocks.len())
+            .filter(|block_index| function.blocks[*block_index].is_loop_header)
+            .collect();
+
+        for header in headers.into_iter().rev() {
+            hoist_loop(function_index, function, header, &mut names);
+        }
+    }
+}
+
+pub struct HoistInvariants;
+
+impl Pass for HoistInvariants {
+    fn name(&self) -> &str {
+        "hoist-invariants"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        hoist_invariants(program);
+    }
+}
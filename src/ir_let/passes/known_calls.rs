@@ -0,0 +1,69 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Control, Definition, Function, Instruction, Program, Simple, Step,
+};
+use crate::ir_let::passes::manager::Pass;
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+
+// Turns calls of a variable that is bound to a closure allocated in the same
+// function into direct calls, which do not have to look up the closure on the
+// heap to find their code. Since variable names are unique, a
+// use of the variable anywhere in the function refers to that closure. Calls
+// with the wrong number of arguments are left alone, so that they still fail
+// when they are executed.
+fn convert_function(function: &mut Function) {
+    let mut closures: HashMap<Symbol, AllocClosure> = HashMap::new();
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            if let Instruction::Assignment(assignment) = instruction {
+                if let Definition::Step(Step::Simple(Simple::Fun(closure))) = &assignment.definition
+                {
+                    closures.insert(assignment.name, closure.clone());
+                }
+            }
+        }
+    }
+
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            let Instruction::Assignment(assignment) = instruction else {
+                continue;
+            };
+
+            if let Definition::Step(Step::Control(Control::Call { func, args })) =
+                &mut assignment.definition
+            {
+                let Some(closure) = closures.get(&func.var_name) else {
+                    continue;
+                };
+                if closure.arg_names.len() != args.len() {
+                    continue;
+                }
+
+                assignment.definition = Definition::Step(Step::Control(Control::CallDirect {
+                    func: func.clone(),
+                    function_index: closure.body.function_index,
+                    args: std::mem::take(args),
+                }));
+            }
+        }
+    }
+}
+
+pub fn convert_known_calls(program: &mut Program) {
+    for function in &mut program.functions {
+        convert_function(function);
+    }
+}
+
+pub struct KnownCalls;
+
+impl Pass for KnownCalls {
+    fn name(&self) -> &str {
+        "known-calls"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        convert_known_calls(program);
+    }
+}
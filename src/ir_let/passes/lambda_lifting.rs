@@ -0,0 +1,40 @@
+use crate::ir_let::let_expr::{Definition, Instruction, Program, Simple, Step};
+use crate::ir_let::passes::manager::Pass;
+
+// Every function is already a toplevel function of the program, but a closure
+// is allocated every time the definition of a function is evaluated. A closure
+// without free names is the same every time, so it is replaced by a reference
+// to the function, for which the evaluator only allocates a closure once.
+pub fn lift_lambdas(program: &mut Program) {
+    for function in &mut program.functions {
+        for block in &mut function.blocks {
+            for instruction in &mut block.instructions {
+                let Instruction::Assignment(assignment) = instruction else {
+                    continue;
+                };
+
+                if let Definition::Step(Step::Simple(Simple::Fun(closure))) = &assignment.definition
+                {
+                    if closure.free_names.is_empty() {
+                        assignment.definition = Definition::Step(Step::Simple(Simple::LiftedFun {
+                            name: closure.name,
+                            function_index: closure.body.function_index,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct LambdaLifting;
+
+impl Pass for LambdaLifting {
+    fn name(&self) -> &str {
+        "lambda-lifting"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        lift_lambdas(program);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::ir_let::let_expr::Program;
+use crate::ir_let::passes::constant_folding::ConstantFolding;
+use crate::ir_let::passes::copy_propagation::CopyPropagation;
+use crate::ir_let::passes::hoist_invariants::HoistInvariants;
+use crate::ir_let::passes::known_calls::KnownCalls;
+use crate::ir_let::passes::lambda_lifting::LambdaLifting;
+use crate::ir_let::passes::prune_captures::PruneCaptures;
+use crate::ir_let::passes::range_propagation::RangePropagation;
+use crate::ir_let::passes::reuse::ReuseTuples;
+use crate::ir_let::passes::simplify::Simplify;
+use crate::ir_let::passes::tail_recursion::TailRecursionToLoop;
+
+// A transformation of a whole program. Passes have to preserve the invariants
+// established by let-normalization (such as unique variable names).
+pub trait Pass {
+    fn name(&self) -> &str;
+    fn run(&mut self, program: &mut Program);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DumpPoint {
+    BeforePass,
+    AfterPass,
+}
+
+type Hook = Box<dyn FnMut(&str, DumpPoint, &Program)>;
+
+// Runs a sequence of passes in order, calling the registered hooks before and
+// after every pass (for example to dump the intermediate programs).
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    hooks: Vec<Hook>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn default_pipeline() -> Self {
+        let mut manager = PassManager::new();
+        manager.add_pass(ConstantFolding);
+        manager.add_pass(Simplify::new());
+        manager.add_pass(RangePropagation);
+        manager.add_pass(CopyPropagation);
+        manager.add_pass(TailRecursionToLoop);
+        manager.add_pass(HoistInvariants);
+        manager.add_pass(PruneCaptures);
+        manager.add_pass(KnownCalls);
+        manager.add_pass(LambdaLifting);
+        manager.add_pass(ReuseTuples);
+        manager
+    }
+
+    pub fn add_pass<P: Pass + 'static>(&mut self, pass: P) {
+        self.passes.push(Box::new(pass));
+    }
+
+    pub fn add_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&str, DumpPoint, &Program) + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn run(&mut self, program: &mut Program) {
+        for pass in &mut self.passes {
+            for hook in &mut self.hooks {
+                hook(pass.name(), DumpPoint::BeforePass, program);
+            }
+
+            pass.run(program);
+
+            for hook in &mut self.hooks {
+                hook(pass.name(), DumpPoint::AfterPass, program);
+            }
+        }
+    }
+}
+
+// A hook that prints the whole program before and after every pass.
+pub fn print_program(pass_name: &str, point: DumpPoint, program: &Program) {
+    match point {
+        DumpPoint::BeforePass => println!("; before {}", pass_name),
+        DumpPoint::AfterPass => println!("; after {}", pass_name),
+    }
+    println!("{}", program);
+}
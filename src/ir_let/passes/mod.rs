@@ -0,0 +1,91 @@
+use crate::ir_let::let_expr::{Control, Definition, Function, Instruction, Step};
+
+pub mod constant_folding;
+pub mod copy_propagation;
+pub mod hoist_invariants;
+pub mod known_calls;
+pub mod lambda_lifting;
+pub mod manager;
+pub mod prune_captures;
+pub mod range_propagation;
+pub mod reuse;
+pub mod simplify;
+pub mod tail_recursion;
+
+// Removes the blocks of a function that can no longer be entered from its
+// first block, renumbering the remaining blocks (and all references to them)
+// while preserving their relative order.
+pub fn remove_unreachable_blocks(function: &mut Function) {
+    let mut reachable = vec![false; function.blocks.len()];
+    let mut worklist = vec![0];
+
+    while let Some(block_index) = worklist.pop() {
+        if reachable[block_index] {
+            continue;
+        }
+        reachable[block_index] = true;
+
+        for instruction in &function.blocks[block_index].instructions {
+            worklist.extend(block_targets(instruction));
+        }
+    }
+
+    let mut new_indices = Vec::new();
+    let mut next_index = 0;
+    for is_reachable in &reachable {
+        new_indices.push(next_index);
+        if *is_reachable {
+            next_index += 1;
+        }
+    }
+
+    let blocks = std::mem::take(&mut function.blocks);
+    for (block_index, mut block) in blocks.into_iter().enumerate() {
+        if !reachable[block_index] {
+            continue;
+        }
+
+        block.parent_block_index = block.parent_block_index.map(|parent| {
+            assert!(reachable[parent], "parent of reachable block was removed");
+            new_indices[parent]
+        });
+
+        for instruction in &mut block.instructions {
+            if let Instruction::Assignment(assignment) = instruction {
+                match &mut assignment.definition {
+                    Definition::Step(Step::Control(Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    })) => {
+                        branch_success.block_index = new_indices[branch_success.block_index];
+                        branch_failure.block_index = new_indices[branch_failure.block_index];
+                    }
+                    Definition::Step(Step::Control(Control::Jump { target, .. })) => {
+                        target.block_index = new_indices[target.block_index];
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        function.blocks.push(block);
+    }
+}
+
+fn block_targets(instruction: &Instruction) -> Vec<usize> {
+    match instruction {
+        Instruction::Assignment(assignment) => match &assignment.definition {
+            Definition::Step(Step::Control(Control::If {
+                branch_success,
+                branch_failure,
+                ..
+            })) => vec![branch_success.block_index, branch_failure.block_index],
+            Definition::Step(Step::Control(Control::Jump { target, .. })) => {
+                vec![target.block_index]
+            }
+            _ => vec![],
+        },
+        _ => vec![],
+    }
+}
@@ -0,0 +1,63 @@
+use crate::ir_let::free_vars::FreeVars;
+use crate::ir_let::let_expr::{Definition, Instruction, Program, Simple, Step};
+use crate::ir_let::passes::manager::Pass;
+
+// Removes the captured variables of closures that are no longer used by the
+// function after other passes have removed code from it, such as a branch of
+// an if whose condition is known. A nested function is always defined after
+// the function that allocates its closure, so going through the functions
+// backwards also prunes the variables that an enclosing function only
+// captured to pass on to the nested function.
+pub fn prune_captures(program: &mut Program) {
+    for function_index in (0..program.functions.len()).rev() {
+        let function = &program.functions[function_index];
+        let Some(free_names) = &function.free_names else {
+            continue;
+        };
+
+        let used =
+            FreeVars::free_vars_function(&function.blocks, function.name, &function.arg_names, 0);
+        let keep: Vec<bool> = free_names.iter().map(|name| used.contains(name)).collect();
+        if keep.iter().all(|k| *k) {
+            continue;
+        }
+
+        let function = &mut program.functions[function_index];
+        let mut kept = keep.iter();
+        if let Some(free_names) = &mut function.free_names {
+            free_names.retain(|_| *kept.next().unwrap());
+        }
+
+        // The captured values are passed in the same order as the free names
+        // of the function.
+        for function in &mut program.functions {
+            for block in &mut function.blocks {
+                for instruction in &mut block.instructions {
+                    let Instruction::Assignment(assignment) = instruction else {
+                        continue;
+                    };
+                    if let Definition::Step(Step::Simple(Simple::Fun(closure))) =
+                        &mut assignment.definition
+                    {
+                        if closure.body.function_index == function_index {
+                            let mut kept = keep.iter();
+                            closure.free_names.retain(|_| *kept.next().unwrap());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct PruneCaptures;
+
+impl Pass for PruneCaptures {
+    fn name(&self) -> &str {
+        "prune-captures"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        prune_captures(program);
+    }
+}
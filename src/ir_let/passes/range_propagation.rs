@@ -0,0 +1,54 @@
+use crate::ir_let::absint::analyze;
+use crate::ir_let::absint::range::RangeValue;
+use crate::ir_let::let_expr::{Assignment, Definition, Program, Simple, Step};
+use crate::ir_let::passes::constant_folding::fold_constants;
+use crate::ir_let::passes::manager::Pass;
+use crate::ir_let::visit::VisitorMut;
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+
+struct ReplaceConstants {
+    constants: HashMap<Symbol, RangeValue>,
+}
+
+impl VisitorMut for ReplaceConstants {
+    // Only arithmetic and comparisons are replaced. Their result is only
+    // known when their operands are integers, in which case they cannot fail.
+    fn visit_assignment(&mut self, assignment: &mut Assignment) {
+        let Definition::Step(Step::Simple(Simple::BinOp { .. })) = assignment.definition else {
+            return;
+        };
+        if let Some(value) = self
+            .constants
+            .get(&assignment.name)
+            .and_then(|value| value.constant())
+        {
+            assignment.definition = Definition::Step(Step::Simple(Simple::Literal(value)));
+        }
+    }
+}
+
+// Replaces the operations whose result the range analysis proves to be
+// constant by their result, such as a comparison of a counter that only counts
+// down with a larger number, and then folds constants to take the branches of
+// the conditionals that became constant.
+pub fn propagate_ranges(program: &mut Program) {
+    let analysis = analyze::<RangeValue>(program);
+    let mut replace = ReplaceConstants {
+        constants: analysis.values,
+    };
+    replace.visit_program(program);
+    fold_constants(program);
+}
+
+pub struct RangePropagation;
+
+impl Pass for RangePropagation {
+    fn name(&self) -> &str {
+        "range-propagation"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        propagate_ranges(program);
+    }
+}
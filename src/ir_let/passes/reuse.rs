@@ -0,0 +1,273 @@
+use crate::ir_let::let_expr::{
+    Assignment, Control, Definition, Function, Instruction, Program, Simple, Step,
+    VariableReference,
+};
+use crate::ir_let::passes::manager::Pass;
+use crate::ir_let::visit::Visitor;
+use crate::lang::syntax::BinOp;
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+
+// The instructions that use each variable of a function, as the block and the
+// index of the instruction.
+#[derive(Default)]
+struct Uses {
+    uses: HashMap<Symbol, Vec<(usize, usize)>>,
+    position: (usize, usize),
+}
+
+impl Visitor for Uses {
+    fn visit_var(&mut self, var: &VariableReference) {
+        self.uses
+            .entry(var.var_name)
+            .or_default()
+            .push(self.position);
+    }
+}
+
+// A variable that can be reused: an argument whose fields are accessed, or a
+// tuple with a known number of fields that is assigned in a block.
+struct Candidate {
+    name: Symbol,
+    // None for arguments.
+    definition: Option<(usize, usize)>,
+}
+
+struct FunctionReuse<'a> {
+    function: &'a Function,
+    uses: HashMap<Symbol, Vec<(usize, usize)>>,
+    // The index of the If in the parent block that enters each block.
+    entered_at: Vec<Option<usize>>,
+    arities: HashMap<Symbol, usize>,
+    // The variables whose fields are accessed, which hold tuples unless the
+    // access fails.
+    accessed: HashSet<Symbol>,
+    definitions: HashMap<Symbol, (usize, usize)>,
+    reused: HashSet<Symbol>,
+}
+
+impl<'a> FunctionReuse<'a> {
+    fn new(function: &'a Function) -> Self {
+        let mut uses = Uses::default();
+        let mut entered_at = vec![None; function.blocks.len()];
+        let mut arities = HashMap::new();
+        let mut accessed = HashSet::new();
+        let mut definitions = HashMap::new();
+
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                uses.position = (block_index, instruction_index);
+                uses.visit_instruction(instruction);
+
+                let Instruction::Assignment(Assignment { name, definition }) = instruction else {
+                    continue;
+                };
+                definitions.insert(*name, (block_index, instruction_index));
+                match definition {
+                    Definition::Step(Step::Simple(
+                        Simple::Tuple { args } | Simple::ReuseTuple { args, .. },
+                    )) => {
+                        arities.insert(*name, args.len());
+                    }
+                    Definition::Step(Step::Simple(Simple::BinOp {
+                        op: BinOp::Get,
+                        lhs,
+                        ..
+                    })) => {
+                        accessed.insert(lhs.var_name);
+                    }
+                    Definition::Step(Step::Control(Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    })) => {
+                        entered_at[branch_success.block_index] = Some(instruction_index);
+                        entered_at[branch_failure.block_index] = Some(instruction_index);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        FunctionReuse {
+            function,
+            uses: uses.uses,
+            entered_at,
+            arities,
+            accessed,
+            definitions,
+            reused: HashSet::new(),
+        }
+    }
+
+    // The blocks from the first block of the function down to the block of
+    // the instruction, with the index of the instruction at which each of
+    // them is (or was) when the instruction runs.
+    fn path(&self, (block_index, instruction_index): (usize, usize)) -> Vec<(usize, usize)> {
+        let mut path = vec![(block_index, instruction_index)];
+        let mut current = block_index;
+        while let Some(parent) = self.function.blocks[current].parent_block_index {
+            let entered_at = self.entered_at[current].expect("nested block should be entered");
+            path.push((parent, entered_at));
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    // Whether the use can run after the instruction at the end of the path,
+    // or is that instruction itself.
+    fn is_after(
+        &self,
+        path: &[(usize, usize)],
+        (mut block_index, mut index): (usize, usize),
+    ) -> bool {
+        let (last_block, _) = path[path.len() - 1];
+        loop {
+            if let Some((_, position)) = path.iter().find(|(b, _)| *b == block_index) {
+                // A use in the other branch of a conditional on the path
+                // cannot run after it.
+                return index > *position || (index == *position && block_index == last_block);
+            }
+            index = self.entered_at[block_index].expect("nested block should be entered");
+            block_index = self.function.blocks[block_index]
+                .parent_block_index
+                .expect("first block should be on the path");
+        }
+    }
+
+    fn can_reuse(&self, candidate: &Candidate, path: &[(usize, usize)]) -> bool {
+        if self.reused.contains(&candidate.name) {
+            return false;
+        }
+
+        // A tuple assigned outside of a loop that is entered on the way to
+        // the instruction would be overwritten in every iteration, so it could
+        // be observed by the uses before the instruction in later iterations.
+        // Arguments are rebound by every jump to a loop header instead.
+        if let Some((definition_block, _)) = candidate.definition {
+            let mut below = path
+                .iter()
+                .skip_while(|(b, _)| *b != definition_block)
+                .skip(1);
+            if below.any(|(b, _)| self.function.blocks[*b].is_loop_header) {
+                return false;
+            }
+        }
+
+        self.uses
+            .get(&candidate.name)
+            .into_iter()
+            .flatten()
+            .all(|position| !self.is_after(path, *position))
+    }
+
+    // The variables in scope at the instruction that may hold a tuple with the
+    // given number of fields, the most recently assigned first.
+    fn candidates(&self, path: &[(usize, usize)], arity: usize) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = self
+            .arities
+            .iter()
+            .filter(|(_, a)| **a == arity)
+            .filter_map(|(name, _)| {
+                let (block_index, instruction_index) = self.definitions[name];
+                path.iter()
+                    .any(|(b, position)| *b == block_index && instruction_index < *position)
+                    .then_some(Candidate {
+                        name: *name,
+                        definition: Some((block_index, instruction_index)),
+                    })
+            })
+            .collect();
+
+        let depth = |block_index: usize| path.iter().position(|(b, _)| *b == block_index);
+        candidates.sort_by_key(|c| {
+            let (block_index, instruction_index) = c.definition.expect("local should be defined");
+            std::cmp::Reverse((depth(block_index), instruction_index))
+        });
+
+        candidates.extend(
+            self.function
+                .arg_names
+                .iter()
+                .filter(|name| self.accessed.contains(name))
+                .map(|name| Candidate {
+                    name: *name,
+                    definition: None,
+                }),
+        );
+        candidates
+    }
+
+    // The variable to reuse for the tuple allocated by the instruction.
+    fn choose(&mut self, position: (usize, usize), arity: usize) -> Option<Symbol> {
+        let path = self.path(position);
+        let chosen = self
+            .candidates(&path, arity)
+            .into_iter()
+            .find(|candidate| self.can_reuse(candidate, &path))?;
+        self.reused.insert(chosen.name);
+        Some(chosen.name)
+    }
+}
+
+// Functional-but-in-place updates: a tuple is allocated in place of a tuple
+// that is no longer used afterwards, such as the previous state of a loop or
+// a tuple that has just been taken apart. The tuple to reuse is chosen at
+// compile time, and the evaluator only overwrites it if it holds the only
+// reference to it, so that other references never observe the update.
+// Returns the number of tuples that are allocated by reusing another one.
+pub fn reuse_tuples(program: &mut Program) -> usize {
+    let mut count = 0;
+
+    for function in &mut program.functions {
+        let mut reuses = Vec::new();
+        let mut analysis = FunctionReuse::new(function);
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                if let Instruction::Assignment(Assignment {
+                    definition: Definition::Step(Step::Simple(Simple::Tuple { args })),
+                    ..
+                }) = instruction
+                {
+                    if args.is_empty() {
+                        continue;
+                    }
+                    let position = (block_index, instruction_index);
+                    if let Some(reused) = analysis.choose(position, args.len()) {
+                        reuses.push((position, reused));
+                    }
+                }
+            }
+        }
+
+        count += reuses.len();
+        for ((block_index, instruction_index), reused) in reuses {
+            let instruction = &mut function.blocks[block_index].instructions[instruction_index];
+            let Instruction::Assignment(Assignment { definition, .. }) = instruction else {
+                unreachable!("reused instruction should be an assignment");
+            };
+            let Definition::Step(Step::Simple(Simple::Tuple { args })) = definition else {
+                unreachable!("reused instruction should allocate a tuple");
+            };
+            *definition = Definition::Step(Step::Simple(Simple::ReuseTuple {
+                reused: VariableReference::new(reused),
+                args: std::mem::take(args),
+            }));
+        }
+    }
+
+    count
+}
+
+pub struct ReuseTuples;
+
+impl Pass for ReuseTuples {
+    fn name(&self) -> &str {
+        "reuse-tuples"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        reuse_tuples(program);
+    }
+}
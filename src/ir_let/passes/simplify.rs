@@ -0,0 +1,282 @@
+use crate::ir_let::let_expr::{Assignment, Definition, Program, Simple, Step, VariableReference};
+use crate::ir_let::passes::constant_folding::fold_constants;
+use crate::ir_let::passes::manager::Pass;
+use crate::ir_let::visit::VisitorMut;
+use crate::lang::syntax::{BinOp, Constant};
+use crate::symbol::Symbol;
+use std::collections::{HashMap, HashSet};
+
+// An operand of the left-hand side of a rule. A metavariable matches any
+// variable, and all occurrences of a metavariable in a rule have to match the
+// same variable. An integer matches a variable that is known to hold it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Var(&'static str),
+    Int(i64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Replacement {
+    Var(&'static str),
+    Literal(Constant),
+}
+
+// An algebraic identity `lhs op rhs = replacement`. The operations fail at
+// runtime on anything but integers, so a rule only applies if the variables
+// that its metavariables match are known to hold integers. Otherwise
+// simplifying `x - x` to `0` would hide the failure of a program that
+// subtracts a tuple from itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub op: BinOp,
+    pub lhs: Operand,
+    pub rhs: Operand,
+    pub replacement: Replacement,
+}
+
+impl Rule {
+    pub fn new(
+        name: &'static str,
+        op: BinOp,
+        lhs: Operand,
+        rhs: Operand,
+        replacement: Replacement,
+    ) -> Self {
+        if let Replacement::Var(metavariable) = replacement {
+            assert!(
+                [lhs, rhs].contains(&Operand::Var(metavariable)),
+                "rule {} replaces by unbound metavariable {}",
+                name,
+                metavariable
+            );
+        }
+
+        Rule {
+            name,
+            op,
+            lhs,
+            rhs,
+            replacement,
+        }
+    }
+}
+
+pub fn default_rules() -> Vec<Rule> {
+    use Operand::{Int, Var};
+
+    vec![
+        Rule::new(
+            "add-zero",
+            BinOp::Add,
+            Var("x"),
+            Int(0),
+            Replacement::Var("x"),
+        ),
+        Rule::new(
+            "zero-add",
+            BinOp::Add,
+            Int(0),
+            Var("x"),
+            Replacement::Var("x"),
+        ),
+        Rule::new(
+            "sub-zero",
+            BinOp::Sub,
+            Var("x"),
+            Int(0),
+            Replacement::Var("x"),
+        ),
+        Rule::new(
+            "sub-self",
+            BinOp::Sub,
+            Var("x"),
+            Var("x"),
+            Replacement::Literal(Constant::Int { value: 0 }),
+        ),
+        Rule::new(
+            "eq-self",
+            BinOp::Eq,
+            Var("x"),
+            Var("x"),
+            Replacement::Literal(Constant::Bool { value: true }),
+        ),
+    ]
+}
+
+// Applies the rules to every binary operation of a program, in the order in
+// which the blocks are stored. Parent blocks precede their children, so what is
+// known about a variable is known before it is used. Since all variable names
+// in a program are unique, the facts do not have to be scoped.
+struct Rewriter<'a> {
+    rules: &'a [Rule],
+    constants: HashMap<Symbol, Constant>,
+    ints: HashSet<Symbol>,
+    // The variables that copy another variable, which match the same
+    // metavariable as the variable they copy.
+    copies: HashMap<Symbol, Symbol>,
+    rewrites: HashMap<&'static str, usize>,
+}
+
+impl<'a> Rewriter<'a> {
+    fn new(rules: &'a [Rule]) -> Self {
+        Rewriter {
+            rules,
+            constants: HashMap::new(),
+            ints: HashSet::new(),
+            copies: HashMap::new(),
+            rewrites: HashMap::new(),
+        }
+    }
+
+    fn original(&self, name: Symbol) -> Symbol {
+        self.copies.get(&name).copied().unwrap_or(name)
+    }
+
+    fn bind(
+        &self,
+        operand: Operand,
+        var: &VariableReference,
+        bindings: &mut Vec<(&'static str, VariableReference)>,
+    ) -> bool {
+        match operand {
+            Operand::Int(value) => {
+                self.constants.get(&var.var_name) == Some(&Constant::Int { value })
+            }
+            Operand::Var(metavariable) => {
+                if !self.ints.contains(&var.var_name) {
+                    return false;
+                }
+                match bindings.iter().find(|(m, _)| *m == metavariable) {
+                    Some((_, bound)) => {
+                        self.original(bound.var_name) == self.original(var.var_name)
+                    }
+                    None => {
+                        bindings.push((metavariable, var.clone()));
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    fn rewrite(&mut self, definition: &Definition) -> Option<Definition> {
+        let Definition::Step(Step::Simple(Simple::BinOp { op, lhs, rhs })) = definition else {
+            return None;
+        };
+
+        for rule in self.rules {
+            let mut bindings = Vec::new();
+            if rule.op != *op
+                || !self.bind(rule.lhs, lhs, &mut bindings)
+                || !self.bind(rule.rhs, rhs, &mut bindings)
+            {
+                continue;
+            }
+
+            *self.rewrites.entry(rule.name).or_insert(0) += 1;
+            return Some(match rule.replacement {
+                Replacement::Var(metavariable) => {
+                    let (_, var) = bindings
+                        .into_iter()
+                        .find(|(m, _)| *m == metavariable)
+                        .expect("metavariable should be bound");
+                    Definition::Var(var)
+                }
+                Replacement::Literal(c) => Definition::Step(Step::Simple(Simple::Literal(c))),
+            });
+        }
+
+        None
+    }
+
+    fn learn(&mut self, name: Symbol, definition: &Definition) {
+        match definition {
+            Definition::Step(Step::Simple(Simple::Literal(c))) => {
+                self.constants.insert(name, *c);
+                if let Constant::Int { .. } = c {
+                    self.ints.insert(name);
+                }
+            }
+            // The result of an addition or subtraction that does not fail is
+            // an integer.
+            Definition::Step(Step::Simple(Simple::BinOp {
+                op: BinOp::Add | BinOp::Sub,
+                ..
+            })) => {
+                self.ints.insert(name);
+            }
+            Definition::Var(var) => {
+                self.copies.insert(name, self.original(var.var_name));
+                if let Some(c) = self.constants.get(&var.var_name).copied() {
+                    self.constants.insert(name, c);
+                }
+                if self.ints.contains(&var.var_name) {
+                    self.ints.insert(name);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl VisitorMut for Rewriter<'_> {
+    fn visit_assignment(&mut self, assignment: &mut Assignment) {
+        if let Some(definition) = self.rewrite(&assignment.definition) {
+            assignment.definition = definition;
+        }
+        self.learn(assignment.name, &assignment.definition);
+    }
+}
+
+// Applies the rules until none of them applies anymore, folding constants in
+// between. A rule can produce a constant that constant folding propagates, and
+// constant folding takes the branch of a conditional on a constant
+// (`if true then a else b` becomes `a`), which can expose more operations that
+// the rules apply to. Returns how often every rule was applied.
+pub fn simplify(program: &mut Program, rules: &[Rule]) -> HashMap<&'static str, usize> {
+    let mut total = HashMap::new();
+
+    loop {
+        let mut rewriter = Rewriter::new(rules);
+        rewriter.visit_program(program);
+        if rewriter.rewrites.is_empty() {
+            return total;
+        }
+
+        for (name, count) in rewriter.rewrites {
+            *total.entry(name).or_insert(0) += count;
+        }
+        fold_constants(program);
+    }
+}
+
+pub struct Simplify {
+    rules: Vec<Rule>,
+}
+
+impl Simplify {
+    pub fn new() -> Self {
+        Simplify::with_rules(default_rules())
+    }
+
+    pub fn with_rules(rules: Vec<Rule>) -> Self {
+        Simplify { rules }
+    }
+}
+
+impl Default for Simplify {
+    fn default() -> Self {
+        Simplify::new()
+    }
+}
+
+impl Pass for Simplify {
+    fn name(&self) -> &str {
+        "simplify"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        simplify(program, &self.rules);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::ir_let::let_expr::{
+    Control, Definition, Function, Instruction, Program, Step, TargetAddress,
+};
+use crate::ir_let::passes::manager::Pass;
+
+// Turns tail calls of a function to itself into jumps back to its first block,
+// which becomes a loop header, so that tail recursion runs as a loop without
+// leaving and re-entering the call stack frame. Since variable names are
+// unique, a call of the name of the function inside of its own body always
+// calls the function itself.
+fn convert_function(function_index: usize, function: &mut Function) {
+    let mut converted = false;
+
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            let Instruction::Assignment(assignment) = instruction else {
+                continue;
+            };
+
+            if let Definition::Step(Step::Control(Control::TailCall { func, args })) =
+                &mut assignment.definition
+            {
+                if func.var_name != function.name || args.len() != function.arg_names.len() {
+                    continue;
+                }
+
+                assignment.definition = Definition::Step(Step::Control(Control::Jump {
+                    target: TargetAddress {
+                        function_index,
+                        block_index: 0,
+                        instruction_index: 0,
+                    },
+                    args: std::mem::take(args),
+                }));
+                converted = true;
+            }
+        }
+    }
+
+    if converted {
+        function.blocks[0].is_loop_header = true;
+    }
+}
+
+pub fn convert_tail_recursion(program: &mut Program) {
+    for (function_index, function) in program.functions.iter_mut().enumerate() {
+        convert_function(function_index, function);
+    }
+}
+
+pub struct TailRecursionToLoop;
+
+impl Pass for TailRecursionToLoop {
+    fn name(&self) -> &str {
+        "tail-recursion-to-loop"
+    }
+
+    fn run(&mut self, program: &mut Program) {
+        convert_tail_recursion(program);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Control, Definition, Function, Instruction, Program, Simple, Step,
+    VariableLocation, VariableReference,
+};
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+
+// The variables that are in scope in a function, and where the interpreter
+// stores each of them. The offsets of locals follow the order in which the
+// interpreter pushes them onto their block frame, which is the order of the
+// assignments in the block.
+struct FunctionScope {
+    this_name: Symbol,
+    arguments: HashMap<Symbol, usize>,
+    free_vars: HashMap<Symbol, usize>,
+    blocks: Vec<BlockScope>,
+}
+
+struct BlockScope {
+    depth: usize,
+    offsets: HashMap<Symbol, usize>,
+    parent_block_index: Option<usize>,
+}
+
+fn offsets(names: &[Symbol]) -> HashMap<Symbol, usize> {
+    names.iter().enumerate().map(|(i, n)| (*n, i)).collect()
+}
+
+impl FunctionScope {
+    fn new(function: &Function) -> Self {
+        let mut blocks: Vec<BlockScope> = Vec::new();
+
+        // Parent blocks precede their children, so the depth of the parent
+        // is always known.
+        for block in &function.blocks {
+            let depth = match block.parent_block_index {
+                Some(parent) => blocks[parent].depth + 1,
+                None => 0,
+            };
+
+            blocks.push(BlockScope {
+                depth,
+                offsets: offsets(&block.block_names()),
+                parent_block_index: block.parent_block_index,
+            });
+        }
+
+        FunctionScope {
+            this_name: function.name,
+            arguments: offsets(&function.arg_names),
+            free_vars: offsets(
+                function
+                    .free_names
+                    .as_ref()
+                    .expect("free names should be known"),
+            ),
+            blocks,
+        }
+    }
+
+    fn lookup(&self, block_index: usize, name: Symbol) -> Option<VariableLocation> {
+        let mut current_block_index = Some(block_index);
+        while let Some(block_index) = current_block_index {
+            let block = &self.blocks[block_index];
+
+            if let Some(offset) = block.offsets.get(&name) {
+                return Some(VariableLocation::Local {
+                    block_depth: block.depth,
+                    offset: *offset,
+                });
+            }
+
+            current_block_index = block.parent_block_index;
+        }
+
+        if let Some(offset) = self.arguments.get(&name) {
+            return Some(VariableLocation::Argument(*offset));
+        }
+
+        if self.this_name == name {
+            return Some(VariableLocation::This);
+        }
+
+        if let Some(offset) = self.free_vars.get(&name) {
+            return Some(VariableLocation::Closure(*offset));
+        }
+
+        None
+    }
+
+    // Unbound variables are left unresolved, so that the interpreter can
+    // report them when they are evaluated.
+    fn resolve_var(&self, block_index: usize, var: &mut VariableReference) {
+        var.location = self.lookup(block_index, var.var_name);
+    }
+
+    fn resolve_instruction(&self, block_index: usize, instruction: &mut Instruction) {
+        let definition = match instruction {
+            Instruction::EnterBlock => return,
+            Instruction::ExitBlock(var) => return self.resolve_var(block_index, var),
+            Instruction::Assignment(assignment) => &mut assignment.definition,
+        };
+
+        match definition {
+            Definition::Var(var) => self.resolve_var(block_index, var),
+            Definition::Step(Step::Simple(simple)) => match simple {
+                Simple::Literal(_) | Simple::LiftedFun { .. } => {}
+                Simple::Fun(AllocClosure { free_names, .. }) => {
+                    for var in free_names {
+                        self.resolve_var(block_index, var);
+                    }
+                }
+                Simple::BinOp { lhs, rhs, .. } => {
+                    self.resolve_var(block_index, lhs);
+                    self.resolve_var(block_index, rhs);
+                }
+                Simple::Tuple { args } => {
+                    for arg in args {
+                        self.resolve_var(block_index, arg);
+                    }
+                }
+                Simple::ReuseTuple { reused, args } => {
+                    self.resolve_var(block_index, reused);
+                    for arg in args {
+                        self.resolve_var(block_index, arg);
+                    }
+                }
+                Simple::Set {
+                    tuple, new_value, ..
+                } => {
+                    self.resolve_var(block_index, tuple);
+                    self.resolve_var(block_index, new_value);
+                }
+            },
+            Definition::Step(Step::Control(control)) => match control {
+                Control::Call { func, args } | Control::TailCall { func, args } => {
+                    self.resolve_var(block_index, func);
+                    for arg in args {
+                        self.resolve_var(block_index, arg);
+                    }
+                }
+                Control::CallDirect { func, args, .. } => {
+                    self.resolve_var(block_index, func);
+                    for var in args {
+                        self.resolve_var(block_index, var);
+                    }
+                }
+                Control::If { condition, .. } => self.resolve_var(block_index, condition),
+                Control::Jump { args, .. } => {
+                    for arg in args {
+                        self.resolve_var(block_index, arg);
+                    }
+                }
+            },
+        }
+    }
+}
+
+// Resolves every variable reference to the location of its value in the call
+// stack frame. Passes can add and remove assignments, which changes the
+// offsets of locals, so this has to be done after all passes have run.
+pub fn resolve_variables(program: &mut Program) {
+    for function in &mut program.functions {
+        let scope = FunctionScope::new(function);
+
+        for (block_index, block) in function.blocks.iter_mut().enumerate() {
+            for instruction in &mut block.instructions {
+                scope.resolve_instruction(block_index, instruction);
+            }
+        }
+    }
+}
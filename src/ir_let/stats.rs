@@ -0,0 +1,113 @@
+use crate::ir_flat::frame_layout::compute_program_frame_layout;
+use crate::ir_let::let_expr::{Control, Definition, Instruction, Program, Step};
+use crate::symbol::Symbol;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub blocks: usize,
+    // Not counting the EnterBlock and ExitBlock instructions of the blocks.
+    pub instructions: usize,
+    // The size of the frame, in which locals whose blocks are not nested in
+    // each other share slots.
+    pub locals: usize,
+    pub captures: usize,
+    pub call_sites: usize,
+}
+
+impl FunctionStats {
+    fn add(&mut self, other: &FunctionStats) {
+        self.blocks += other.blocks;
+        self.instructions += other.instructions;
+        self.locals += other.locals;
+        self.captures += other.captures;
+        self.call_sites += other.call_sites;
+    }
+}
+
+// The size of every function of a program, for comparing programs before and
+// after optimization.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+    pub functions: Vec<(Symbol, FunctionStats)>,
+}
+
+impl ProgramStats {
+    pub fn new(program: &Program) -> Self {
+        let frame_layout = compute_program_frame_layout(program);
+
+        let functions = program
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(function_index, function)| {
+                let mut stats = FunctionStats {
+                    blocks: function.blocks.len(),
+                    locals: frame_layout.function_frame_size(function_index),
+                    captures: function.free_names.as_ref().map_or(0, |names| names.len()),
+                    ..FunctionStats::default()
+                };
+
+                for block in &function.blocks {
+                    for instruction in &block.instructions {
+                        let Instruction::Assignment(assignment) = instruction else {
+                            continue;
+                        };
+                        stats.instructions += 1;
+                        if let Definition::Step(Step::Control(
+                            Control::Call { .. }
+                            | Control::TailCall { .. }
+                            | Control::CallDirect { .. },
+                        )) = assignment.definition
+                        {
+                            stats.call_sites += 1;
+                        }
+                    }
+                }
+
+                (function.name, stats)
+            })
+            .collect();
+
+        ProgramStats { functions }
+    }
+
+    pub fn total(&self) -> FunctionStats {
+        let mut total = FunctionStats::default();
+        for (_, stats) in &self.functions {
+            total.add(stats);
+        }
+        total
+    }
+}
+
+impl fmt::Display for ProgramStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>8} {:>12} {:>8} {:>8} {:>8}  function",
+            "blocks", "instructions", "locals", "captures", "calls"
+        )?;
+
+        let total = self.total();
+        let rows = self
+            .functions
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+            .chain(std::iter::once(("total", &total)));
+        for (name, stats) in rows {
+            writeln!(
+                f,
+                "{:>8} {:>12} {:>8} {:>8} {:>8}  {}",
+                stats.blocks,
+                stats.instructions,
+                stats.locals,
+                stats.captures,
+                stats.call_sites,
+                name
+            )?;
+        }
+
+        Ok(())
+    }
+}
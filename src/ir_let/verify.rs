@@ -0,0 +1,97 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Assignment, Control, Definition, Instruction, Program, Simple, Step,
+    TargetAddress,
+};
+use crate::result::Result;
+
+fn check_address(program: &Program, address: TargetAddress) -> Result<()> {
+    let instruction = program
+        .functions
+        .get(address.function_index)
+        .and_then(|function| function.blocks.get(address.block_index))
+        .and_then(|block| block.instructions.get(address.instruction_index));
+    match instruction {
+        Some(_) => Ok(()),
+        None => Err(format!("invalid address {}", address).into()),
+    }
+}
+
+fn check_function(program: &Program, function_index: usize) -> Result<()> {
+    check_address(
+        program,
+        TargetAddress {
+            function_index,
+            block_index: 0,
+            instruction_index: 0,
+        },
+    )
+}
+
+// Execution continues with the next instruction after every instruction except
+// these, so they are the only ones that can end a block.
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::ExitBlock(_)
+            | Instruction::Assignment(Assignment {
+                definition: Definition::Step(Step::Control(
+                    Control::Jump { .. } | Control::TailCall { .. }
+                )),
+                ..
+            })
+    )
+}
+
+// Checks that every address in the program refers to an instruction, and that
+// execution cannot run past the end of a block. The interpreter relies on
+// this to look up instructions without checking for missing ones.
+pub fn verify_program(program: &Program) -> Result<()> {
+    // The program starts at the first instruction of the first function.
+    check_function(program, 0)?;
+
+    for (function_index, function) in program.functions.iter().enumerate() {
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            match block.instructions.last() {
+                Some(last) if ends_block(last) => {}
+                _ => {
+                    return Err(format!(
+                        "block {} of function {} does not end with an exit or a jump",
+                        block_index, function_index
+                    )
+                    .into())
+                }
+            }
+
+            for instruction in &block.instructions {
+                let Instruction::Assignment(Assignment {
+                    definition: Definition::Step(step),
+                    ..
+                }) = instruction
+                else {
+                    continue;
+                };
+                match step {
+                    Step::Simple(Simple::Fun(AllocClosure { body, .. })) => {
+                        check_address(program, *body)?
+                    }
+                    Step::Simple(Simple::LiftedFun { function_index, .. })
+                    | Step::Control(Control::CallDirect { function_index, .. }) => {
+                        check_function(program, *function_index)?
+                    }
+                    Step::Control(Control::If {
+                        branch_success,
+                        branch_failure,
+                        ..
+                    }) => {
+                        check_address(program, *branch_success)?;
+                        check_address(program, *branch_failure)?;
+                    }
+                    Step::Control(Control::Jump { target, .. }) => check_address(program, *target)?,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,323 @@
+use crate::ir_let::let_expr::{
+    AllocClosure, Assignment, Block, Control, Definition, Function, Instruction, Program, Simple,
+    Step, TargetAddress, VariableReference,
+};
+
+// Goes through the parts of a program in order. Every method visits the parts
+// it consists of by default, so a visitor only overrides the cases it is
+// interested in, and calls the corresponding walk function to continue into
+// the parts of an overridden case. Blocks are visited in the order in which
+// they are stored, not by following the branches of conditionals.
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_function(&mut self, function_index: usize, function: &Function) {
+        walk_function(self, function_index, function)
+    }
+
+    fn visit_block(&mut self, block_index: usize, block: &Block) {
+        walk_block(self, block_index, block)
+    }
+
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        walk_instruction(self, instruction)
+    }
+
+    fn visit_assignment(&mut self, assignment: &Assignment) {
+        walk_assignment(self, assignment)
+    }
+
+    fn visit_definition(&mut self, definition: &Definition) {
+        walk_definition(self, definition)
+    }
+
+    fn visit_simple(&mut self, simple: &Simple) {
+        walk_simple(self, simple)
+    }
+
+    fn visit_control(&mut self, control: &Control) {
+        walk_control(self, control)
+    }
+
+    fn visit_var(&mut self, _var: &VariableReference) {}
+
+    // The body of a closure, a branch of a conditional or the loop header of
+    // a jump.
+    fn visit_address(&mut self, _address: &TargetAddress) {}
+
+    // The function of a lifted closure or a direct call.
+    fn visit_function_index(&mut self, _function_index: usize) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for (function_index, function) in program.functions.iter().enumerate() {
+        visitor.visit_function(function_index, function);
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, _: usize, function: &Function) {
+    for (block_index, block) in function.blocks.iter().enumerate() {
+        visitor.visit_block(block_index, block);
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, _: usize, block: &Block) {
+    for instruction in &block.instructions {
+        visitor.visit_instruction(instruction);
+    }
+}
+
+pub fn walk_instruction<V: Visitor + ?Sized>(visitor: &mut V, instruction: &Instruction) {
+    match instruction {
+        Instruction::EnterBlock => {}
+        Instruction::ExitBlock(var) => visitor.visit_var(var),
+        Instruction::Assignment(assignment) => visitor.visit_assignment(assignment),
+    }
+}
+
+pub fn walk_assignment<V: Visitor + ?Sized>(visitor: &mut V, assignment: &Assignment) {
+    visitor.visit_definition(&assignment.definition)
+}
+
+pub fn walk_definition<V: Visitor + ?Sized>(visitor: &mut V, definition: &Definition) {
+    match definition {
+        Definition::Var(var) => visitor.visit_var(var),
+        Definition::Step(Step::Simple(simple)) => visitor.visit_simple(simple),
+        Definition::Step(Step::Control(control)) => visitor.visit_control(control),
+    }
+}
+
+pub fn walk_simple<V: Visitor + ?Sized>(visitor: &mut V, simple: &Simple) {
+    match simple {
+        Simple::Literal(_) => {}
+        Simple::Fun(AllocClosure {
+            free_names, body, ..
+        }) => {
+            for var in free_names {
+                visitor.visit_var(var);
+            }
+            visitor.visit_address(body);
+        }
+        Simple::LiftedFun { function_index, .. } => visitor.visit_function_index(*function_index),
+        Simple::BinOp { lhs, rhs, .. } => {
+            visitor.visit_var(lhs);
+            visitor.visit_var(rhs);
+        }
+        Simple::Tuple { args } => {
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Simple::ReuseTuple { reused, args } => {
+            visitor.visit_var(reused);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Simple::Set {
+            tuple, new_value, ..
+        } => {
+            visitor.visit_var(tuple);
+            visitor.visit_var(new_value);
+        }
+    }
+}
+
+pub fn walk_control<V: Visitor + ?Sized>(visitor: &mut V, control: &Control) {
+    match control {
+        Control::Call { func, args } | Control::TailCall { func, args } => {
+            visitor.visit_var(func);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Control::CallDirect {
+            func,
+            function_index,
+            args,
+        } => {
+            visitor.visit_var(func);
+            visitor.visit_function_index(*function_index);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Control::If {
+            condition,
+            branch_success,
+            branch_failure,
+        } => {
+            visitor.visit_var(condition);
+            visitor.visit_address(branch_success);
+            visitor.visit_address(branch_failure);
+        }
+        Control::Jump { target, args } => {
+            visitor.visit_address(target);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+    }
+}
+
+// Like Visitor, but with mutable access, for passes that rewrite parts of a
+// program in place.
+pub trait VisitorMut {
+    fn visit_program(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_function(&mut self, function_index: usize, function: &mut Function) {
+        walk_function_mut(self, function_index, function)
+    }
+
+    fn visit_block(&mut self, block_index: usize, block: &mut Block) {
+        walk_block_mut(self, block_index, block)
+    }
+
+    fn visit_instruction(&mut self, instruction: &mut Instruction) {
+        walk_instruction_mut(self, instruction)
+    }
+
+    fn visit_assignment(&mut self, assignment: &mut Assignment) {
+        walk_assignment_mut(self, assignment)
+    }
+
+    fn visit_definition(&mut self, definition: &mut Definition) {
+        walk_definition_mut(self, definition)
+    }
+
+    fn visit_simple(&mut self, simple: &mut Simple) {
+        walk_simple_mut(self, simple)
+    }
+
+    fn visit_control(&mut self, control: &mut Control) {
+        walk_control_mut(self, control)
+    }
+
+    fn visit_var(&mut self, _var: &mut VariableReference) {}
+
+    fn visit_address(&mut self, _address: &mut TargetAddress) {}
+
+    fn visit_function_index(&mut self, _function_index: &mut usize) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for (function_index, function) in program.functions.iter_mut().enumerate() {
+        visitor.visit_function(function_index, function);
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    _: usize,
+    function: &mut Function,
+) {
+    for (block_index, block) in function.blocks.iter_mut().enumerate() {
+        visitor.visit_block(block_index, block);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, _: usize, block: &mut Block) {
+    for instruction in &mut block.instructions {
+        visitor.visit_instruction(instruction);
+    }
+}
+
+pub fn walk_instruction_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    instruction: &mut Instruction,
+) {
+    match instruction {
+        Instruction::EnterBlock => {}
+        Instruction::ExitBlock(var) => visitor.visit_var(var),
+        Instruction::Assignment(assignment) => visitor.visit_assignment(assignment),
+    }
+}
+
+pub fn walk_assignment_mut<V: VisitorMut + ?Sized>(visitor: &mut V, assignment: &mut Assignment) {
+    visitor.visit_definition(&mut assignment.definition)
+}
+
+pub fn walk_definition_mut<V: VisitorMut + ?Sized>(visitor: &mut V, definition: &mut Definition) {
+    match definition {
+        Definition::Var(var) => visitor.visit_var(var),
+        Definition::Step(Step::Simple(simple)) => visitor.visit_simple(simple),
+        Definition::Step(Step::Control(control)) => visitor.visit_control(control),
+    }
+}
+
+pub fn walk_simple_mut<V: VisitorMut + ?Sized>(visitor: &mut V, simple: &mut Simple) {
+    match simple {
+        Simple::Literal(_) => {}
+        Simple::Fun(AllocClosure {
+            free_names, body, ..
+        }) => {
+            for var in free_names {
+                visitor.visit_var(var);
+            }
+            visitor.visit_address(body);
+        }
+        Simple::LiftedFun { function_index, .. } => visitor.visit_function_index(function_index),
+        Simple::BinOp { lhs, rhs, .. } => {
+            visitor.visit_var(lhs);
+            visitor.visit_var(rhs);
+        }
+        Simple::Tuple { args } => {
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Simple::ReuseTuple { reused, args } => {
+            visitor.visit_var(reused);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Simple::Set {
+            tuple, new_value, ..
+        } => {
+            visitor.visit_var(tuple);
+            visitor.visit_var(new_value);
+        }
+    }
+}
+
+pub fn walk_control_mut<V: VisitorMut + ?Sized>(visitor: &mut V, control: &mut Control) {
+    match control {
+        Control::Call { func, args } | Control::TailCall { func, args } => {
+            visitor.visit_var(func);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Control::CallDirect {
+            func,
+            function_index,
+            args,
+        } => {
+            visitor.visit_var(func);
+            visitor.visit_function_index(function_index);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+        Control::If {
+            condition,
+            branch_success,
+            branch_failure,
+        } => {
+            visitor.visit_var(condition);
+            visitor.visit_address(branch_success);
+            visitor.visit_address(branch_failure);
+        }
+        Control::Jump { target, args } => {
+            visitor.visit_address(target);
+            for var in args {
+                visitor.visit_var(var);
+            }
+        }
+    }
+}
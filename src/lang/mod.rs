@@ -1,2 +1,5 @@
+pub mod parser;
+pub mod prelude;
 pub mod syntax;
 pub mod test;
+pub mod visit;
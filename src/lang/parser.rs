@@ -0,0 +1,165 @@
+// A reader for programs written as s-expressions:
+//
+//   (let name definition body)
+//   (let (names ...) tuple body)
+//   (fun name (arguments ...) body)
+//   (if condition then else)
+//   (+ a b), (- a b), (== a b), (get tuple index)
+//   (tuple fields ...)
+//   (set tuple index value)
+//   (function arguments ...)
+//
+// Lines starting with `;` are comments.
+use crate::lang::syntax::{BinOp, Constant, Expr, MAX_NESTING};
+use crate::result::Result;
+
+#[derive(Debug)]
+enum SExpr {
+    Atom(String),
+    List(Vec<SExpr>),
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for line in text.lines() {
+        if line.trim_start().starts_with(';') {
+            continue;
+        }
+        let spaced = line.replace('(', " ( ").replace(')', " ) ");
+        tokens.extend(spaced.split_whitespace().map(|t| t.to_owned()));
+    }
+    tokens
+}
+
+// Lists are read with a stack of the lists that are still open, so that
+// deeply nested input cannot overflow the stack here. The nesting is limited,
+// since the compiler recurses over the expression that is read.
+fn read(tokens: &mut std::vec::IntoIter<String>) -> Result<SExpr> {
+    let mut open: Vec<Vec<SExpr>> = Vec::new();
+    for token in tokens.by_ref() {
+        let item = match token.as_str() {
+            "(" => {
+                if open.len() == MAX_NESTING {
+                    return Err(
+                        format!("program nested more than {} levels deep", MAX_NESTING).into(),
+                    );
+                }
+                open.push(Vec::new());
+                continue;
+            }
+            ")" => SExpr::List(open.pop().ok_or("unexpected `)`")?),
+            _ => SExpr::Atom(token),
+        };
+        match open.last_mut() {
+            Some(items) => items.push(item),
+            None => return Ok(item),
+        }
+    }
+
+    if open.is_empty() {
+        Err("unexpected end of program".into())
+    } else {
+        Err("missing `)`".into())
+    }
+}
+
+fn atom(s: &SExpr) -> Result<&str> {
+    match s {
+        SExpr::Atom(a) => Ok(a),
+        SExpr::List(_) => Err(format!("expected a name, found {:?}", s).into()),
+    }
+}
+
+fn boxed(s: &SExpr) -> Result<Box<Expr>> {
+    Ok(Box::new(to_expr(s)?))
+}
+
+fn to_expr(s: &SExpr) -> Result<Expr> {
+    let items = match s {
+        SExpr::Atom(a) => {
+            return Ok(match a.as_str() {
+                "true" => Expr::Literal(Constant::Bool { value: true }),
+                "false" => Expr::Literal(Constant::Bool { value: false }),
+                _ => match a.parse() {
+                    Ok(value) => Expr::Literal(Constant::Int { value }),
+                    Err(_) => Expr::Var {
+                        var_name: a.clone(),
+                    },
+                },
+            })
+        }
+        SExpr::List(items) => items,
+    };
+
+    let binop = |op| match &items[..] {
+        [_, lhs, rhs] => Ok(Expr::BinOp {
+            op,
+            lhs: boxed(lhs)?,
+            rhs: boxed(rhs)?,
+        }),
+        _ => Err(format!("expected two operands in {:?}", s).into()),
+    };
+
+    let head = match items.first() {
+        Some(SExpr::Atom(head)) => head.as_str(),
+        _ => "",
+    };
+    match (head, &items[..]) {
+        ("let", [_, SExpr::List(names), definition, body]) => Ok(Expr::LetTuple {
+            names: names
+                .iter()
+                .map(|n| atom(n).map(|n| n.to_owned()))
+                .collect::<Result<_>>()?,
+            definition: boxed(definition)?,
+            body: boxed(body)?,
+        }),
+        ("let", [_, name, definition, body]) => Ok(Expr::Let {
+            name: atom(name)?.to_owned(),
+            definition: boxed(definition)?,
+            body: boxed(body)?,
+        }),
+        ("fun", [_, name, SExpr::List(arg_names), body]) => Ok(Expr::Fun {
+            name: atom(name)?.to_owned(),
+            arg_names: arg_names
+                .iter()
+                .map(|a| atom(a).map(|a| a.to_owned()))
+                .collect::<Result<_>>()?,
+            body: boxed(body)?,
+        }),
+        ("if", [_, condition, branch_success, branch_failure]) => Ok(Expr::If {
+            condition: boxed(condition)?,
+            branch_success: boxed(branch_success)?,
+            branch_failure: boxed(branch_failure)?,
+        }),
+        ("+", _) => binop(BinOp::Add),
+        ("-", _) => binop(BinOp::Sub),
+        ("==", _) => binop(BinOp::Eq),
+        ("get", _) => binop(BinOp::Get),
+        ("tuple", [_, values @ ..]) => Ok(Expr::Tuple {
+            values: values.iter().map(to_expr).collect::<Result<_>>()?,
+        }),
+        ("set", [_, tuple, index, new_expr]) => Ok(Expr::Set {
+            tuple: boxed(tuple)?,
+            index: atom(index)?
+                .parse()
+                .map_err(|_| format!("invalid index in {:?}", s))?,
+            new_expr: boxed(new_expr)?,
+        }),
+        ("let" | "fun" | "if" | "tuple" | "set", _) => Err(format!("malformed {:?}", s).into()),
+        (_, [func, args @ ..]) => Ok(Expr::Call {
+            func: boxed(func)?,
+            args: args.iter().map(to_expr).collect::<Result<_>>()?,
+        }),
+        (_, []) => Err("empty list".into()),
+    }
+}
+
+// Parses a program written as an s-expression.
+pub fn parse_expr(text: &str) -> Result<Expr> {
+    let mut tokens = tokenize(text).into_iter();
+    let program = read(&mut tokens)?;
+    if tokens.next().is_some() {
+        return Err("unexpected text after the program".into());
+    }
+    to_expr(&program)
+}
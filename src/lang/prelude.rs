@@ -0,0 +1,74 @@
+use crate::expr;
+use crate::lang::syntax::{Expr, Module};
+
+// The natives that the prelude calls, see `Natives::register_prelude`.
+pub const PRELUDE_NATIVES: [&str; 2] = ["less", "assert"];
+
+// Lists are tuples tagged with whether they are empty: nil is (false,) and
+// cons(h, t) is (true, h, t). Options are (false, 0) and (true, v).
+fn definitions() -> Vec<(&'static str, Expr)> {
+    vec![
+        ("nil", expr! { (false,) }),
+        ("cons", expr! { fun cons(h, t) { (true, h, t) } }),
+        (
+            "is_empty",
+            expr! { fun is_empty(l) { if l[0] { false } else { true } } },
+        ),
+        ("head", expr! { fun head(l) { l[1] } }),
+        ("tail", expr! { fun tail(l) { l[2] } }),
+        (
+            "length",
+            expr! { fun length(l) { if l[0] { 1 + length(l[2]) } else { 0 } } },
+        ),
+        (
+            "map",
+            expr! { fun map(f, l) { if l[0] { (true, f(l[1]), map(f, l[2])) } else { l } } },
+        ),
+        (
+            "fold",
+            expr! { fun fold(f, acc, l) { if l[0] { fold(f, f(acc, l[1]), l[2]) } else { acc } } },
+        ),
+        ("none", expr! { (false, 0) }),
+        ("some", expr! { fun some(v) { (true, v) } }),
+        ("is_some", expr! { fun is_some(o) { o[0] } }),
+        (
+            "unwrap_or",
+            expr! { fun unwrap_or(o, d) { if o[0] { o[1] } else { d } } },
+        ),
+        (
+            "map_option",
+            expr! { fun map_option(f, o) { if o[0] { (true, f(o[1])) } else { o } } },
+        ),
+        (
+            "min",
+            expr! { fun min(a, b) { if less(b, a) { b } else { a } } },
+        ),
+        (
+            "max",
+            expr! { fun max(a, b) { if less(a, b) { b } else { a } } },
+        ),
+        (
+            "abs",
+            expr! { fun abs(x) { if less(x, 0) { 0 - x } else { x } } },
+        ),
+        (
+            "assert_eq",
+            expr! { fun assert_eq(a, b) { assert(a == b) } },
+        ),
+    ]
+}
+
+// The definitions that every program can use without importing them, unless
+// it is compiled without the prelude. A program only links the prelude if it
+// uses one of them.
+pub fn prelude() -> Module {
+    Module {
+        name: "prelude".to_owned(),
+        imports: vec![],
+        definitions: definitions()
+            .into_iter()
+            .map(|(name, definition)| (name.to_owned(), definition))
+            .collect(),
+        body: None,
+    }
+}
@@ -1,10 +1,12 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Constant {
-    Int { value: i32 },
+    Int { value: i64 },
     Bool { value: bool },
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOp {
     Add,
     Sub,
@@ -12,7 +14,13 @@ pub enum BinOp {
     Get,
 }
 
+// How deeply expressions can be nested. The compiler recurses over them, so
+// the parser and let-normalization reject deeper ones instead of running out
+// of stack.
+pub const MAX_NESTING: usize = 256;
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Literal(Constant),
     Var {
@@ -32,6 +40,12 @@ pub enum Expr {
         definition: Box<Expr>,
         body: Box<Expr>,
     },
+    // Binds the names to the first fields of the tuple, in order.
+    LetTuple {
+        names: Vec<String>,
+        definition: Box<Expr>,
+        body: Box<Expr>,
+    },
     If {
         condition: Box<Expr>,
         branch_success: Box<Expr>,
@@ -50,4 +64,33 @@ pub enum Expr {
         index: u32,
         new_expr: Box<Expr>,
     },
+    // Suspends the evaluation of the body until the resulting thunk is forced.
+    // Only the interpreter of the let intermediate language supports thunks,
+    // see `Natives::register_thunks`.
+    Delay {
+        body: Box<Expr>,
+    },
+    Force {
+        thunk: Box<Expr>,
+    },
+}
+
+// Makes the definitions of another module available as `alias.name`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Import {
+    pub module: String,
+    pub alias: String,
+}
+
+// A sequence of definitions, each of which can refer to the ones before it and
+// to the definitions of the imported modules. The main module of a program has
+// a body, whose value is the result of the program.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Module {
+    pub name: String,
+    pub imports: Vec<Import>,
+    pub definitions: Vec<(String, Expr)>,
+    pub body: Option<Expr>,
 }
@@ -0,0 +1,36 @@
+use crate::lang::syntax::Expr;
+use crate::lang::test::builder::{
+    add, call_named, fun, if_then_else, int, is_zero, let_in, sub, var,
+};
+
+// ackermann(m, n) =
+//   if m == 0 then n + 1
+//   else if n == 0 then ackermann(m - 1, 1)
+//   else ackermann(m - 1, ackermann(m, n - 1))
+fn ackermann_def() -> Expr {
+    let body = if_then_else(
+        is_zero("m"),
+        add(var("n"), int(1)),
+        if_then_else(
+            is_zero("n"),
+            call_named("ackermann", vec![sub(var("m"), int(1)), int(1)]),
+            call_named(
+                "ackermann",
+                vec![
+                    sub(var("m"), int(1)),
+                    call_named("ackermann", vec![var("m"), sub(var("n"), int(1))]),
+                ],
+            ),
+        ),
+    );
+
+    fun("ackermann", &["m", "n"], body)
+}
+
+pub fn ackermann_test(m: i64, n: i64) -> Expr {
+    let_in(
+        "ackermann",
+        ackermann_def(),
+        call_named("ackermann", vec![int(m), int(n)]),
+    )
+}
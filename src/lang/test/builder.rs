@@ -0,0 +1,380 @@
+use crate::lang::syntax::{BinOp, Constant, Expr};
+
+// Shorthands for building the example programs.
+
+pub fn int(value: i64) -> Expr {
+    Expr::Literal(Constant::Int { value })
+}
+
+pub fn bool(value: bool) -> Expr {
+    Expr::Literal(Constant::Bool { value })
+}
+
+pub fn var(name: &str) -> Expr {
+    Expr::Var {
+        var_name: name.to_owned(),
+    }
+}
+
+pub fn binop(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::BinOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }
+}
+
+pub fn add(lhs: Expr, rhs: Expr) -> Expr {
+    binop(BinOp::Add, lhs, rhs)
+}
+
+pub fn sub(lhs: Expr, rhs: Expr) -> Expr {
+    binop(BinOp::Sub, lhs, rhs)
+}
+
+pub fn get(tuple: Expr, index: i64) -> Expr {
+    binop(BinOp::Get, tuple, int(index))
+}
+
+pub fn is_zero(name: &str) -> Expr {
+    binop(BinOp::Eq, var(name), int(0))
+}
+
+pub fn call(func: Expr, args: Vec<Expr>) -> Expr {
+    Expr::Call {
+        func: Box::new(func),
+        args,
+    }
+}
+
+// A call of a function that is bound to a name.
+pub fn call_named(name: &str, args: Vec<Expr>) -> Expr {
+    call(var(name), args)
+}
+
+pub fn fun(name: &str, arg_names: &[&str], body: Expr) -> Expr {
+    Expr::Fun {
+        name: name.to_owned(),
+        arg_names: arg_names.iter().map(|a| a.to_string()).collect(),
+        body: Box::new(body),
+    }
+}
+
+pub fn let_in(name: &str, definition: Expr, body: Expr) -> Expr {
+    Expr::Let {
+        name: name.to_owned(),
+        definition: Box::new(definition),
+        body: Box::new(body),
+    }
+}
+
+pub fn let_tuple(names: &[&str], definition: Expr, body: Expr) -> Expr {
+    Expr::LetTuple {
+        names: names.iter().map(|n| n.to_string()).collect(),
+        definition: Box::new(definition),
+        body: Box::new(body),
+    }
+}
+
+pub fn delay(body: Expr) -> Expr {
+    Expr::Delay {
+        body: Box::new(body),
+    }
+}
+
+pub fn force(thunk: Expr) -> Expr {
+    Expr::Force {
+        thunk: Box::new(thunk),
+    }
+}
+
+pub fn if_then_else(condition: Expr, branch_success: Expr, branch_failure: Expr) -> Expr {
+    Expr::If {
+        condition: Box::new(condition),
+        branch_success: Box::new(branch_success),
+        branch_failure: Box::new(branch_failure),
+    }
+}
+
+pub fn tuple(values: Vec<Expr>) -> Expr {
+    Expr::Tuple { values }
+}
+
+pub fn set(tuple: Expr, index: u32, new_expr: Expr) -> Expr {
+    Expr::Set {
+        tuple: Box::new(tuple),
+        index,
+        new_expr: Box::new(new_expr),
+    }
+}
+
+// Values that can be spliced into an `expr!` with `#`.
+pub trait IntoExpr {
+    fn into_expr(self) -> Expr;
+}
+
+impl IntoExpr for Expr {
+    fn into_expr(self) -> Expr {
+        self
+    }
+}
+
+impl IntoExpr for i64 {
+    fn into_expr(self) -> Expr {
+        int(self)
+    }
+}
+
+impl IntoExpr for bool {
+    fn into_expr(self) -> Expr {
+        bool(self)
+    }
+}
+
+// Builds an `Expr` from a program written in a small infix syntax:
+//
+//   expr! {
+//       let fib_helper = fun(n, a, b) {
+//           if n == 0 { b } else { fib_helper(n - 1, a + b, a) }
+//       };
+//       fib_helper(#n, 1, 0)
+//   }
+//
+// A function bound by `let` takes the name of the binding, and other functions
+// are written as `fun name(args) { body }`. Tuples are written as `(a, b)` and
+// `(a,)`, fields are read with `t[i]` and written with `set t[i] = value`, and
+// `let (a, b) = t;` binds the fields of a tuple. `delay { e }` and `force(t)`
+// create and force a thunk. `m.x` refers to the definition `x` of the module
+// imported as `m`. `#x` and `#(...)` splice in a Rust value, see
+// `IntoExpr`. The operators `+` and `-` bind tighter than `==`, and an `if` or
+// `let` inside an operand has to be put in braces.
+#[macro_export]
+macro_rules! expr {
+    (let $name:ident = fun ($($arg:ident),* $(,)?) { $($body:tt)* } ; $($rest:tt)+) => {
+        $crate::lang::test::builder::let_in(
+            stringify!($name),
+            $crate::lang::test::builder::fun(
+                stringify!($name),
+                &[$(stringify!($arg)),*],
+                $crate::expr!($($body)*),
+            ),
+            $crate::expr!($($rest)+),
+        )
+    };
+    (let ($($name:ident),* $(,)?) = $($rest:tt)+) => {
+        $crate::expr!(@let_tuple [$($name)*] [] $($rest)+)
+    };
+    (let $name:ident = $($rest:tt)+) => {
+        $crate::expr!(@let $name [] $($rest)+)
+    };
+    (fun $name:ident ($($arg:ident),* $(,)?) { $($body:tt)* }) => {
+        $crate::lang::test::builder::fun(
+            stringify!($name),
+            &[$(stringify!($arg)),*],
+            $crate::expr!($($body)*),
+        )
+    };
+    (if $($rest:tt)+) => {
+        $crate::expr!(@if [] $($rest)+)
+    };
+    (set $tuple:ident [$index:literal] = $($value:tt)+) => {
+        $crate::lang::test::builder::set(
+            $crate::lang::test::builder::var(stringify!($tuple)),
+            $index,
+            $crate::expr!($($value)+),
+        )
+    };
+
+    // The definition of a let binding extends up to the semicolon.
+    (@let $name:ident [$($definition:tt)+] ; $($rest:tt)+) => {
+        $crate::lang::test::builder::let_in(
+            stringify!($name),
+            $crate::expr!($($definition)+),
+            $crate::expr!($($rest)+),
+        )
+    };
+    (@let $name:ident [$($definition:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@let $name [$($definition)* $next] $($rest)*)
+    };
+
+    (@let_tuple [$($name:ident)*] [$($definition:tt)+] ; $($rest:tt)+) => {
+        $crate::lang::test::builder::let_tuple(
+            &[$(stringify!($name)),*],
+            $crate::expr!($($definition)+),
+            $crate::expr!($($rest)+),
+        )
+    };
+    (@let_tuple [$($name:ident)*] [$($definition:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@let_tuple [$($name)*] [$($definition)* $next] $($rest)*)
+    };
+
+    // The condition of an if extends up to the first block.
+    (@if [$($condition:tt)+] { $($then:tt)* } else if $($rest:tt)+) => {
+        $crate::lang::test::builder::if_then_else(
+            $crate::expr!($($condition)+),
+            $crate::expr!($($then)*),
+            $crate::expr!(if $($rest)+),
+        )
+    };
+    (@if [$($condition:tt)+] { $($then:tt)* } else { $($else:tt)* }) => {
+        $crate::lang::test::builder::if_then_else(
+            $crate::expr!($($condition)+),
+            $crate::expr!($($then)*),
+            $crate::expr!($($else)*),
+        )
+    };
+    (@if [$($condition:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@if [$($condition)* $next] $($rest)*)
+    };
+
+    // Comparisons, which consist of sums.
+    (@eq [$($lhs:tt)+] == $($rhs:tt)+) => {
+        $crate::lang::test::builder::binop(
+            $crate::lang::syntax::BinOp::Eq,
+            $crate::expr!(@sum $($lhs)+),
+            $crate::expr!(@sum $($rhs)+),
+        )
+    };
+    (@eq [$($lhs:tt)+]) => {
+        $crate::expr!(@sum $($lhs)+)
+    };
+    (@eq [$($lhs:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@eq [$($lhs)* $next] $($rest)*)
+    };
+
+    // Sums are folded from the left. An operand is parsed by `@atom`, which
+    // passes the result to the rule given as its first argument.
+    (@sum $($tokens:tt)+) => {
+        $crate::expr!(@atom [@sum_rest] $($tokens)+)
+    };
+    (@sum_rest [$lhs:tt]) => {
+        $lhs
+    };
+    (@sum_rest [$lhs:tt] + $($rest:tt)+) => {
+        $crate::expr!(@atom [@combine add $lhs] $($rest)+)
+    };
+    (@sum_rest [$lhs:tt] - $($rest:tt)+) => {
+        $crate::expr!(@atom [@combine sub $lhs] $($rest)+)
+    };
+    (@combine $op:ident $lhs:tt [$rhs:tt] $($rest:tt)*) => {
+        $crate::expr!(@sum_rest [($crate::lang::test::builder::$op($lhs, $rhs))] $($rest)*)
+    };
+
+    (@atom [$($k:tt)*] true $($rest:tt)*) => {
+        $crate::expr!(@postfix [$($k)*] [($crate::lang::test::builder::bool(true))] $($rest)*)
+    };
+    (@atom [$($k:tt)*] false $($rest:tt)*) => {
+        $crate::expr!(@postfix [$($k)*] [($crate::lang::test::builder::bool(false))] $($rest)*)
+    };
+    (@atom [$($k:tt)*] $value:literal $($rest:tt)*) => {
+        $crate::expr!(@postfix [$($k)*] [($crate::lang::test::builder::int($value))] $($rest)*)
+    };
+    (@atom [$($k:tt)*] # $value:ident $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::IntoExpr::into_expr($value))]
+            $($rest)*
+        )
+    };
+    (@atom [$($k:tt)*] # ($($value:tt)*) $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::IntoExpr::into_expr($($value)*))]
+            $($rest)*
+        )
+    };
+    (@atom [$($k:tt)*] delay { $($body:tt)* } $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::delay($crate::expr!($($body)*)))]
+            $($rest)*
+        )
+    };
+    (@atom [$($k:tt)*] force ($($thunk:tt)+) $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::force($crate::expr!($($thunk)+)))]
+            $($rest)*
+        )
+    };
+    // A definition of an imported module.
+    (@atom [$($k:tt)*] $alias:ident . $name:ident $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::var(concat!(stringify!($alias), ".", stringify!($name))))]
+            $($rest)*
+        )
+    };
+    (@atom [$($k:tt)*] $name:ident $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::var(stringify!($name)))]
+            $($rest)*
+        )
+    };
+    (@atom [$($k:tt)*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::expr!(@postfix [$($k)*] [($crate::expr!(@paren [] $($inner)*))] $($rest)*)
+    };
+    (@atom [$($k:tt)*] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::expr!(@postfix [$($k)*] [($crate::expr!($($inner)*))] $($rest)*)
+    };
+
+    // Calls and field accesses after an operand.
+    (@postfix [$($k:tt)*] [$e:tt] ($($args:tt)*) $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::call($e, $crate::expr!(@list [] [] $($args)*)))]
+            $($rest)*
+        )
+    };
+    (@postfix [$($k:tt)*] [$e:tt] [$($index:tt)+] $($rest:tt)*) => {
+        $crate::expr!(
+            @postfix [$($k)*]
+            [($crate::lang::test::builder::binop(
+                $crate::lang::syntax::BinOp::Get,
+                $e,
+                $crate::expr!($($index)+),
+            ))]
+            $($rest)*
+        )
+    };
+    (@postfix [$($k:tt)*] [$e:tt] $($rest:tt)*) => {
+        $crate::expr!($($k)* [$e] $($rest)*)
+    };
+
+    // Parentheses hold a tuple if they contain a comma, and a single
+    // expression otherwise.
+    (@paren [$($seen:tt)*] , $($rest:tt)*) => {
+        $crate::lang::test::builder::tuple($crate::expr!(@list [] [] $($seen)* , $($rest)*))
+    };
+    (@paren [$($seen:tt)+]) => {
+        $crate::expr!($($seen)+)
+    };
+    (@paren []) => {
+        $crate::lang::test::builder::tuple(vec![])
+    };
+    (@paren [$($seen:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@paren [$($seen)* $next] $($rest)*)
+    };
+
+    // A comma-separated list of expressions, as a vector.
+    (@list [$($done:tt)*] [$($current:tt)+] , $($rest:tt)*) => {
+        $crate::expr!(@list [$($done)* ($crate::expr!($($current)+)),] [] $($rest)*)
+    };
+    (@list [$($done:tt)*] [$($current:tt)+]) => {
+        vec![$($done)* $crate::expr!($($current)+)]
+    };
+    (@list [$($done:tt)*] []) => {
+        vec![$($done)*]
+    };
+    (@list [$($done:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@list [$($done)*] [$($current)* $next] $($rest)*)
+    };
+
+    (@$rule:ident $($tokens:tt)*) => {
+        compile_error!(concat!("unexpected tokens in expr!: ", stringify!($($tokens)*)))
+    };
+    ($($tokens:tt)+) => {
+        $crate::expr!(@eq [] $($tokens)+)
+    };
+}
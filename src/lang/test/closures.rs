@@ -0,0 +1,54 @@
+use crate::lang::syntax::Expr;
+use crate::lang::test::builder::{
+    add, call, call_named, fun, if_then_else, int, is_zero, let_in, sub, var,
+};
+
+// Creates three nested closures for every i in 1, ..., n, each capturing the
+// variables of all enclosing scopes:
+//
+// make(x) = fun outer(y) -> fun inner(z) -> a + b + c + x + y + z
+//
+// with a, b and c equal to 1, 2 and 3. The result is the sum of make(i)(i)(i),
+// which is 6 * n + 3 * n * (n + 1) / 2.
+pub fn closure_capture_test(n: i64) -> Expr {
+    let inner = fun(
+        "inner",
+        &["z"],
+        add(
+            add(add(var("a"), var("b")), add(var("c"), var("x"))),
+            add(var("y"), var("z")),
+        ),
+    );
+    let make = fun("make", &["x"], fun("outer", &["y"], inner));
+    let make_i = call(
+        call(call_named("make", vec![var("i")]), vec![var("i")]),
+        vec![var("i")],
+    );
+    let loop_def = fun(
+        "loop",
+        &["i", "acc"],
+        if_then_else(
+            is_zero("i"),
+            var("acc"),
+            call_named("loop", vec![sub(var("i"), int(1)), add(var("acc"), make_i)]),
+        ),
+    );
+
+    let_in(
+        "a",
+        int(1),
+        let_in(
+            "b",
+            int(2),
+            let_in(
+                "c",
+                int(3),
+                let_in(
+                    "make",
+                    make,
+                    let_in("loop", loop_def, call_named("loop", vec![int(n), int(0)])),
+                ),
+            ),
+        ),
+    )
+}
@@ -0,0 +1,36 @@
+use crate::lang::syntax::Expr;
+use crate::lang::test::builder::{
+    bool, call_named, fun, if_then_else, int, is_zero, let_in, sub, var,
+};
+
+// Without recursive let bindings, odd is passed the even function that calls
+// it:
+//
+// odd(n, even) = if n == 0 then false else even(n - 1)
+// even(n) = if n == 0 then true else odd(n - 1, even)
+pub fn even_odd_test(n: i64) -> Expr {
+    let odd = fun(
+        "odd",
+        &["n", "even"],
+        if_then_else(
+            is_zero("n"),
+            bool(false),
+            call_named("even", vec![sub(var("n"), int(1))]),
+        ),
+    );
+    let even = fun(
+        "even",
+        &["n"],
+        if_then_else(
+            is_zero("n"),
+            bool(true),
+            call_named("odd", vec![sub(var("n"), int(1)), var("even")]),
+        ),
+    );
+
+    let_in(
+        "odd",
+        odd,
+        let_in("even", even, call_named("even", vec![int(n)])),
+    )
+}
@@ -0,0 +1,268 @@
+use crate::lang::syntax::{BinOp, Constant, Expr};
+
+// A small pseudo-random number generator (SplitMix64), so that programs can be
+// generated from a seed without depending on an external crate.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in 0..n, which must not be empty.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// The generated programs are well-typed with respect to these types, so that
+// they can only fail by exceeding a resource limit. Functions only take and
+// return data, which rules out programs that compute huge numbers by
+// repeatedly applying functions to functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+    Tuple(Vec<Type>),
+    Fun(Vec<Type>, Box<Type>),
+}
+
+// Generates random programs in which every variable is in scope and every
+// operation is applied to values of the right type. The programs terminate,
+// since functions cannot refer to themselves.
+#[derive(Debug)]
+pub struct ProgramGenerator {
+    rng: Rng,
+    max_depth: usize,
+    next_name: usize,
+    scope: Vec<(String, Type)>,
+}
+
+impl ProgramGenerator {
+    pub fn new(seed: u64, max_depth: usize) -> Self {
+        ProgramGenerator {
+            rng: Rng::new(seed),
+            max_depth,
+            next_name: 0,
+            scope: Vec::new(),
+        }
+    }
+
+    // A program that evaluates to data, so that its result can be compared
+    // between evaluators.
+    pub fn generate(&mut self) -> Expr {
+        let ty = self.data_type(2);
+        self.expr(&ty, self.max_depth)
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> String {
+        self.next_name += 1;
+        format!("{}{}", prefix, self.next_name)
+    }
+
+    fn data_type(&mut self, depth: usize) -> Type {
+        match self.rng.below(if depth == 0 { 2 } else { 3 }) {
+            0 => Type::Int,
+            1 => Type::Bool,
+            _ => {
+                let size = self.rng.below(4);
+                Type::Tuple((0..size).map(|_| self.data_type(depth - 1)).collect())
+            }
+        }
+    }
+
+    fn fun_type(&mut self) -> Type {
+        let arity = 1 + self.rng.below(3);
+        let arg_types = (0..arity).map(|_| self.data_type(1)).collect();
+        Type::Fun(arg_types, Box::new(self.data_type(1)))
+    }
+
+    fn int(&mut self) -> Expr {
+        let value = self.rng.below(10) as i64;
+        Expr::Literal(Constant::Int { value })
+    }
+
+    fn binop(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    fn expr(&mut self, ty: &Type, depth: usize) -> Expr {
+        if depth == 0 {
+            return self.leaf(ty);
+        }
+
+        match self.rng.below(8) {
+            0 => self.leaf(ty),
+            1 => self.let_expr(ty, depth),
+            2 => Expr::If {
+                condition: Box::new(self.expr(&Type::Bool, depth - 1)),
+                branch_success: Box::new(self.expr(ty, depth - 1)),
+                branch_failure: Box::new(self.expr(ty, depth - 1)),
+            },
+            3 => self.call(ty, depth),
+            4 => self.get(ty, depth),
+            _ => self.construct(ty, depth),
+        }
+    }
+
+    // A variable of the type if there is one, and otherwise the smallest
+    // expression of the type.
+    fn leaf(&mut self, ty: &Type) -> Expr {
+        let candidates: Vec<&String> = self
+            .scope
+            .iter()
+            .filter(|(_, t)| t == ty)
+            .map(|(name, _)| name)
+            .collect();
+        if !candidates.is_empty() && self.rng.below(2) == 0 {
+            let var_name = candidates[self.rng.below(candidates.len())].clone();
+            return Expr::Var { var_name };
+        }
+
+        self.construct(ty, 0)
+    }
+
+    fn let_expr(&mut self, ty: &Type, depth: usize) -> Expr {
+        let name = self.fresh_name("x");
+        let definition_type = if self.rng.below(3) == 0 {
+            self.fun_type()
+        } else {
+            self.data_type(2)
+        };
+        let definition = self.expr(&definition_type, depth - 1);
+
+        self.scope.push((name.clone(), definition_type));
+        let body = self.expr(ty, depth - 1);
+        self.scope.pop();
+
+        Expr::Let {
+            name,
+            definition: Box::new(definition),
+            body: Box::new(body),
+        }
+    }
+
+    fn call(&mut self, ty: &Type, depth: usize) -> Expr {
+        if matches!(ty, Type::Fun(..)) {
+            return self.construct(ty, depth);
+        }
+
+        let arity = 1 + self.rng.below(3);
+        let arg_types: Vec<Type> = (0..arity).map(|_| self.data_type(1)).collect();
+        let func = self.expr(
+            &Type::Fun(arg_types.clone(), Box::new(ty.clone())),
+            depth - 1,
+        );
+        let args = arg_types.iter().map(|t| self.expr(t, depth - 1)).collect();
+
+        Expr::Call {
+            func: Box::new(func),
+            args,
+        }
+    }
+
+    // Takes the value out of a tuple with some other fields around it.
+    fn get(&mut self, ty: &Type, depth: usize) -> Expr {
+        let mut field_types: Vec<Type> =
+            (0..self.rng.below(3)).map(|_| self.data_type(1)).collect();
+        let index = self.rng.below(field_types.len() + 1);
+        field_types.insert(index, ty.clone());
+
+        let tuple = self.expr(&Type::Tuple(field_types), depth - 1);
+        let index = Expr::Literal(Constant::Int {
+            value: index as i64,
+        });
+        Self::binop(BinOp::Get, tuple, index)
+    }
+
+    // An expression that builds a value of the type from smaller expressions.
+    fn construct(&mut self, ty: &Type, depth: usize) -> Expr {
+        let sub_depth = depth.saturating_sub(1);
+        match ty {
+            Type::Int => match self.rng.below(if depth == 0 { 1 } else { 3 }) {
+                0 => self.int(),
+                1 => Self::binop(
+                    BinOp::Add,
+                    self.expr(&Type::Int, sub_depth),
+                    self.expr(&Type::Int, sub_depth),
+                ),
+                _ => Self::binop(
+                    BinOp::Sub,
+                    self.expr(&Type::Int, sub_depth),
+                    self.expr(&Type::Int, sub_depth),
+                ),
+            },
+            Type::Bool => {
+                if depth == 0 || self.rng.below(2) == 0 {
+                    let value = self.rng.below(2) == 0;
+                    Expr::Literal(Constant::Bool { value })
+                } else {
+                    Self::binop(
+                        BinOp::Eq,
+                        self.expr(&Type::Int, sub_depth),
+                        self.expr(&Type::Int, sub_depth),
+                    )
+                }
+            }
+            // The result of a set is the empty tuple.
+            Type::Tuple(field_types)
+                if field_types.is_empty() && depth > 0 && self.rng.below(2) == 0 =>
+            {
+                let field_types: Vec<Type> = (0..1 + self.rng.below(3))
+                    .map(|_| self.data_type(1))
+                    .collect();
+                let index = self.rng.below(field_types.len());
+                let new_expr = self.expr(&field_types[index], sub_depth);
+                let tuple = self.expr(&Type::Tuple(field_types), sub_depth);
+
+                Expr::Set {
+                    tuple: Box::new(tuple),
+                    index: index as u32,
+                    new_expr: Box::new(new_expr),
+                }
+            }
+            Type::Tuple(field_types) => Expr::Tuple {
+                values: field_types
+                    .iter()
+                    .map(|t| self.expr(t, sub_depth))
+                    .collect(),
+            },
+            Type::Fun(arg_types, return_type) => {
+                let name = self.fresh_name("f");
+                let arg_names: Vec<String> =
+                    arg_types.iter().map(|_| self.fresh_name("a")).collect();
+
+                let scope_size = self.scope.len();
+                self.scope
+                    .extend(arg_names.iter().cloned().zip(arg_types.iter().cloned()));
+                let body = self.expr(return_type, sub_depth);
+                self.scope.truncate(scope_size);
+
+                Expr::Fun {
+                    name,
+                    arg_names,
+                    body: Box::new(body),
+                }
+            }
+        }
+    }
+}
+
+pub fn generate_program(seed: u64, max_depth: usize) -> Expr {
+    ProgramGenerator::new(seed, max_depth).generate()
+}
@@ -0,0 +1,122 @@
+use crate::lang::syntax::Expr;
+use crate::lang::test::builder::{
+    add, call, call_named, fun, get, if_then_else, int, is_zero, let_in, sub, tuple, var,
+};
+
+// A list is a chain of (head, tail) tuples. There is no way to test whether a
+// value is a tuple, so the functions on lists are passed the length.
+//
+// build(n, acc) = if n == 0 then acc else build(n - 1, (n, acc))
+fn build_def() -> Expr {
+    fun(
+        "build",
+        &["n", "acc"],
+        if_then_else(
+            is_zero("n"),
+            var("acc"),
+            call_named(
+                "build",
+                vec![sub(var("n"), int(1)), tuple(vec![var("n"), var("acc")])],
+            ),
+        ),
+    )
+}
+
+// sum(l, n) = if n == 0 then 0 else l!!0 + sum(l!!1, n - 1)
+fn sum_def() -> Expr {
+    fun(
+        "sum",
+        &["l", "n"],
+        if_then_else(
+            is_zero("n"),
+            int(0),
+            add(
+                get(var("l"), 0),
+                call_named("sum", vec![get(var("l"), 1), sub(var("n"), int(1))]),
+            ),
+        ),
+    )
+}
+
+// map(f, l, n) = if n == 0 then l else (f(l!!0), map(f, l!!1, n - 1))
+fn map_def() -> Expr {
+    fun(
+        "map",
+        &["f", "l", "n"],
+        if_then_else(
+            is_zero("n"),
+            var("l"),
+            tuple(vec![
+                call(var("f"), vec![get(var("l"), 0)]),
+                call_named(
+                    "map",
+                    vec![var("f"), get(var("l"), 1), sub(var("n"), int(1))],
+                ),
+            ]),
+        ),
+    )
+}
+
+// fold(f, acc, l, n) = if n == 0 then acc else fold(f, f(acc, l!!0), l!!1, n - 1)
+fn fold_def() -> Expr {
+    fun(
+        "fold",
+        &["f", "acc", "l", "n"],
+        if_then_else(
+            is_zero("n"),
+            var("acc"),
+            call_named(
+                "fold",
+                vec![
+                    var("f"),
+                    call(var("f"), vec![var("acc"), get(var("l"), 0)]),
+                    get(var("l"), 1),
+                    sub(var("n"), int(1)),
+                ],
+            ),
+        ),
+    )
+}
+
+fn empty_list() -> Expr {
+    tuple(vec![int(0), int(0)])
+}
+
+// Builds the list 1, ..., n and sums it, which is n * (n + 1) / 2.
+pub fn list_sum_test(n: i64) -> Expr {
+    let_in(
+        "build",
+        build_def(),
+        let_in(
+            "sum",
+            sum_def(),
+            call_named(
+                "sum",
+                vec![call_named("build", vec![int(n), empty_list()]), int(n)],
+            ),
+        ),
+    )
+}
+
+// Doubles every element of the list 1, ..., n with map and sums the result
+// with fold, which gives n * (n + 1).
+pub fn map_fold_test(n: i64) -> Expr {
+    let double = fun("double", &["x"], add(var("x"), var("x")));
+    let plus = fun("plus", &["a", "b"], add(var("a"), var("b")));
+    let list = call_named("build", vec![int(n), empty_list()]);
+    let doubled = call_named("map", vec![double, list, int(n)]);
+
+    let_in(
+        "build",
+        build_def(),
+        let_in(
+            "map",
+            map_def(),
+            let_in(
+                "fold",
+                fold_def(),
+                call_named("fold", vec![plus, int(0), doubled, int(n)]),
+            ),
+        ),
+    )
+}
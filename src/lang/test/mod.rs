@@ -1 +1,8 @@
+pub mod ackermann;
+pub mod builder;
+pub mod closures;
+pub mod even_odd;
 pub mod fib;
+pub mod gen;
+pub mod list;
+pub mod mutation;
@@ -0,0 +1,51 @@
+use crate::lang::syntax::Expr;
+use crate::lang::test::builder::{
+    add, call_named, fun, get, if_then_else, int, is_zero, let_in, set, sub, tuple, var,
+};
+
+// Adds 1, ..., n to a counter stored in a mutable tuple, which gives
+// n * (n + 1) / 2:
+//
+// loop(i) = if i == 0 then cell!!0 else let u = set cell[0] = cell!!0 + i in loop(i - 1)
+pub fn counter_test(n: i64) -> Expr {
+    let loop_def = fun(
+        "loop",
+        &["i"],
+        if_then_else(
+            is_zero("i"),
+            get(var("cell"), 0),
+            let_in(
+                "u",
+                set(var("cell"), 0, add(get(var("cell"), 0), var("i"))),
+                call_named("loop", vec![sub(var("i"), int(1))]),
+            ),
+        ),
+    );
+
+    let_in(
+        "cell",
+        tuple(vec![int(0)]),
+        let_in("loop", loop_def, call_named("loop", vec![int(n)])),
+    )
+}
+
+// A mutation is visible through every tuple that refers to the mutated one:
+//
+// let t = (1, 2) in let s = (t, t) in let u = set t[0] = 10 in s!!0!!0 + s!!1!!0
+//
+// evaluates to 20.
+pub fn aliasing_test() -> Expr {
+    let_in(
+        "t",
+        tuple(vec![int(1), int(2)]),
+        let_in(
+            "s",
+            tuple(vec![var("t"), var("t")]),
+            let_in(
+                "u",
+                set(var("t"), 0, int(10)),
+                add(get(get(var("s"), 0), 0), get(get(var("s"), 1), 0)),
+            ),
+        ),
+    )
+}
@@ -0,0 +1,144 @@
+use crate::lang::syntax::{Constant, Expr};
+
+// Goes through an expression and all of its subexpressions, in the order in
+// which they are evaluated. A visitor overrides the cases it is interested in,
+// and calls walk_expr to continue into the subexpressions of an overridden
+// case. Names that are bound are not visited as variables.
+pub trait ExprVisitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_literal(&mut self, _value: &Constant) {}
+
+    fn visit_var(&mut self, _var_name: &str) {}
+}
+
+pub fn walk_expr<V: ExprVisitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(value) => visitor.visit_literal(value),
+        Expr::Var { var_name } => visitor.visit_var(var_name),
+        Expr::Fun { body, .. } => visitor.visit_expr(body),
+        Expr::Call { func, args } => {
+            visitor.visit_expr(func);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::Let {
+            definition, body, ..
+        }
+        | Expr::LetTuple {
+            definition, body, ..
+        } => {
+            visitor.visit_expr(definition);
+            visitor.visit_expr(body);
+        }
+        Expr::If {
+            condition,
+            branch_success,
+            branch_failure,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(branch_success);
+            visitor.visit_expr(branch_failure);
+        }
+        Expr::BinOp { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Expr::Tuple { values } => {
+            for value in values {
+                visitor.visit_expr(value);
+            }
+        }
+        Expr::Set {
+            tuple, new_expr, ..
+        } => {
+            visitor.visit_expr(tuple);
+            visitor.visit_expr(new_expr);
+        }
+        Expr::Delay { body } => visitor.visit_expr(body),
+        Expr::Force { thunk } => visitor.visit_expr(thunk),
+    }
+}
+
+// Rebuilds an expression bottom-up. A folder overrides fold_expr for the cases
+// it rewrites, and calls fold_children to rebuild the other cases, or to fold
+// the subexpressions of a case before rewriting it.
+pub trait ExprFolder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_children(self, expr)
+    }
+}
+
+pub fn fold_children<F: ExprFolder + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let mut fold = |expr: Expr| folder.fold_expr(expr);
+
+    match expr {
+        Expr::Literal(_) | Expr::Var { .. } => expr,
+        Expr::Fun {
+            name,
+            arg_names,
+            body,
+        } => Expr::Fun {
+            name,
+            arg_names,
+            body: Box::new(fold(*body)),
+        },
+        Expr::Call { func, args } => Expr::Call {
+            func: Box::new(fold(*func)),
+            args: args.into_iter().map(&mut fold).collect(),
+        },
+        Expr::Let {
+            name,
+            definition,
+            body,
+        } => Expr::Let {
+            name,
+            definition: Box::new(fold(*definition)),
+            body: Box::new(fold(*body)),
+        },
+        Expr::LetTuple {
+            names,
+            definition,
+            body,
+        } => Expr::LetTuple {
+            names,
+            definition: Box::new(fold(*definition)),
+            body: Box::new(fold(*body)),
+        },
+        Expr::If {
+            condition,
+            branch_success,
+            branch_failure,
+        } => Expr::If {
+            condition: Box::new(fold(*condition)),
+            branch_success: Box::new(fold(*branch_success)),
+            branch_failure: Box::new(fold(*branch_failure)),
+        },
+        Expr::BinOp { op, lhs, rhs } => Expr::BinOp {
+            op,
+            lhs: Box::new(fold(*lhs)),
+            rhs: Box::new(fold(*rhs)),
+        },
+        Expr::Tuple { values } => Expr::Tuple {
+            values: values.into_iter().map(&mut fold).collect(),
+        },
+        Expr::Set {
+            tuple,
+            index,
+            new_expr,
+        } => Expr::Set {
+            tuple: Box::new(fold(*tuple)),
+            index,
+            new_expr: Box::new(fold(*new_expr)),
+        },
+        Expr::Delay { body } => Expr::Delay {
+            body: Box::new(fold(*body)),
+        },
+        Expr::Force { thunk } => Expr::Force {
+            thunk: Box::new(fold(*thunk)),
+        },
+    }
+}
@@ -0,0 +1,16 @@
+// TODO: Remove this when the implementation is reasonably complete and there
+// are no more unused parts.
+#![allow(dead_code)]
+pub mod bytecode;
+pub mod c_backend;
+pub mod disasm;
+pub mod driver;
+pub mod embed;
+pub mod ir_cps;
+pub mod ir_flat;
+pub mod ir_let;
+pub mod lang;
+pub mod result;
+pub mod symbol;
+
+pub use embed::{eval_str, EvalOptions, Value};
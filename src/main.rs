@@ -1,27 +1,408 @@
-// TODO: Remove this when the implementation is reasonably complete and there
-// are no more unused parts.
-#![allow(dead_code)]
-mod ir_flat;
-mod ir_let;
-mod lang;
-mod result;
-
-use crate::ir_let::compiler::let_normalize;
-use crate::ir_let::interpreter::simple_eval::ProgramEvaluator;
-use crate::lang::test::fib::fib_test;
+use bailey::bytecode::fusion::{fuse_superinstructions, FusionStats};
+use bailey::bytecode::vm::VirtualMachine;
+use bailey::c_backend::emitter::save_c_files;
+use bailey::disasm::Disassembler;
+use bailey::driver::{compile, CompileOptions, OptimizationLevel};
+use bailey::ir_cps::compiler::cps_convert;
+use bailey::ir_flat::frame_layout::compute_program_frame_layout;
+use bailey::ir_flat::interpreter::WordInterpreter;
+use bailey::ir_let::cfg::save_dot_files;
+use bailey::ir_let::diff::{print_diffs, ProgramDiff};
+use bailey::ir_let::interpreter::config::{EvaluatorConfig, OverflowMode};
+use bailey::ir_let::interpreter::debugger::{Breakpoint, Debugger, StopReason};
+use bailey::ir_let::interpreter::heap::{
+    ArenaHeap, CompactingHeap, DumpFormat, Heap, MarkSweepHeap, RefCountHeap,
+};
+use bailey::ir_let::interpreter::profile::ReportFormat;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::{PreparedProgram, ProgramEvaluator, StepOutcome};
+use bailey::ir_let::interpreter::trace::ConsoleTrace;
+use bailey::ir_let::let_expr::{Program, TargetAddress};
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::resolve::resolve_variables;
+use bailey::ir_let::stats::ProgramStats;
+use bailey::lang::test::fib::fib_test;
+use std::io::BufRead;
+use std::path::Path;
+
+// What to print about the heap, and the execution, after the program has run.
+#[derive(Default)]
+struct HeapReport {
+    stats: bool,
+    dump: Option<DumpFormat>,
+    // The allocations made by each function.
+    profile: bool,
+    // The instructions executed and the calls made by each function.
+    execution_profile: Option<ReportFormat>,
+    // The hit rates of the inline caches of the virtual machine.
+    cache_stats: bool,
+    // The superinstructions that were fused and executed.
+    fusion_stats: bool,
+}
+
+fn run_with_heap<H: Heap>(
+    program: Program,
+    heap: H,
+    config: EvaluatorConfig,
+    trace: bool,
+    report: &HeapReport,
+) {
+    let mut evaluator = ProgramEvaluator::with_heap_and_config(program, heap, config);
+    if trace {
+        evaluator.set_trace_sink(Box::new(ConsoleTrace));
+    }
+    if report.profile {
+        evaluator.enable_allocation_profile();
+    }
+    if report.execution_profile.is_some() {
+        evaluator.enable_execution_profile();
+    }
+    let result = evaluator.run();
+
+    match result {
+        Ok(value) => println!("{}", render(value, evaluator.heap())),
+        Err(error) => {
+            println!("error: {}", error);
+            if let Some(backtrace) = evaluator.backtrace() {
+                println!("{}", backtrace);
+            }
+        }
+    }
+    if report.stats {
+        println!("{}", evaluator.heap().stats());
+        if let Some(stats) = evaluator.deferred_stats() {
+            print!("{}", stats);
+        }
+    }
+    if let Some(format) = report.dump {
+        print!("{}", evaluator.dump_heap(format));
+    }
+    if let Some(profile) = evaluator.allocation_profile() {
+        print!("{}", profile.report(evaluator.program()));
+    }
+    if let (Some(profile), Some(format)) = (evaluator.execution_profile(), report.execution_profile)
+    {
+        print!("{}", profile.report(evaluator.program(), format));
+    }
+}
+
+// A breakpoint is either a function name or an address written as
+// function,block,instruction.
+fn parse_breakpoint(text: &str) -> Option<Breakpoint> {
+    let parts: Vec<&str> = text.split(',').collect();
+    if let [function_index, block_index, instruction_index] = parts[..] {
+        return Some(Breakpoint::Address(TargetAddress {
+            function_index: function_index.trim().parse().ok()?,
+            block_index: block_index.trim().parse().ok()?,
+            instruction_index: instruction_index.trim().parse().ok()?,
+        }));
+    }
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(Breakpoint::Function(text.to_owned()))
+    }
+}
+
+// Reads debugger commands from standard input until the program has finished
+// or the input ends.
+fn debug(program: PreparedProgram) {
+    let mut debugger = Debugger::new(ProgramEvaluator::new(program));
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line.expect("failed to read command");
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+
+        let outcome = match command {
+            "break" | "b" => {
+                match parse_breakpoint(argument.trim()) {
+                    Some(breakpoint) => debugger.add_breakpoint(breakpoint),
+                    None => println!("invalid breakpoint: {}", argument),
+                }
+                continue;
+            }
+            "delete" | "d" => {
+                match parse_breakpoint(argument.trim()) {
+                    Some(breakpoint) if debugger.remove_breakpoint(&breakpoint) => {}
+                    _ => println!("no such breakpoint: {}", argument),
+                }
+                continue;
+            }
+            // Both report the result once the program has finished.
+            "step" | "s" => debugger.step().map(|outcome| match outcome {
+                StepOutcome::Running => None,
+                StepOutcome::Finished(value) => Some(value),
+            }),
+            "continue" | "c" => debugger.continue_execution().map(|reason| match reason {
+                StopReason::Breakpoint(_) => None,
+                StopReason::Finished(value) => Some(value),
+            }),
+            "locals" | "l" => {
+                for (name, value) in debugger.locals() {
+                    println!("{} = {}", name, render(value, debugger.evaluator().heap()));
+                }
+                continue;
+            }
+            "backtrace" | "bt" => {
+                for entry in debugger.call_stack().iter().rev() {
+                    println!("{} at {}", entry.function_name, entry.address);
+                }
+                continue;
+            }
+            "quit" | "q" => return,
+            _ => {
+                println!("unknown command: {}", command);
+                continue;
+            }
+        };
+
+        match outcome {
+            Ok(None) => println!(
+                "stopped at {}: {}",
+                debugger.evaluator().program_counter(),
+                debugger.evaluator().current_instruction()
+            ),
+            Ok(Some(value)) => {
+                println!("finished: {}", render(value, debugger.evaluator().heap()));
+                return;
+            }
+            Err(error) => {
+                println!("error: {}", error);
+                if let Some(backtrace) = debugger.evaluator().backtrace() {
+                    println!("{}", backtrace);
+                }
+                return;
+            }
+        }
+    }
+}
 
 fn main() {
+    // Flags (such as -O0 or --no-flat) configure the compiler, the remaining
+    // arguments select what to do with the compiled program.
+    let mut options = CompileOptions::default();
+    let mut heap_report = HeapReport::default();
+    let mut config = EvaluatorConfig::default();
+    let mut trace = false;
+    let mut annotate = false;
+    let mut arguments = Vec::new();
+    for argument in std::env::args().skip(1) {
+        if argument == "--trace" {
+            trace = true;
+        } else if argument == "--annotate" {
+            annotate = true;
+        } else if argument == "--alloc-profile" {
+            heap_report.profile = true;
+        } else if let Some(format) = argument.strip_prefix("--profile=") {
+            let format = ReportFormat::from_name(format).expect("unknown profile format");
+            heap_report.execution_profile = Some(format);
+        } else if let Some(mode) = argument.strip_prefix("--overflow=") {
+            config.overflow = OverflowMode::from_name(mode).expect("unknown overflow mode");
+        } else if let Some(fuel) = argument.strip_prefix("--fuel=") {
+            config.max_instructions = Some(fuel.parse().expect("invalid fuel"));
+        } else if argument == "--deferred-rc" {
+            config.deferred_refcounts = true;
+        } else if argument == "--cache-stats" {
+            heap_report.cache_stats = true;
+        } else if argument == "--fusion-stats" {
+            heap_report.fusion_stats = true;
+        } else if argument == "--heap-stats" {
+            heap_report.stats = true;
+        } else if let Some(format) = argument.strip_prefix("--heap-dump=") {
+            let format = DumpFormat::from_name(format).expect("unknown heap dump format");
+            heap_report.dump = Some(format);
+        } else if !options.parse_flag(&argument) {
+            arguments.push(argument);
+        }
+    }
+    let mode = arguments.first().map(|a| a.as_str());
+
     let fib_program = fib_test(10);
-    let compiled_program = let_normalize(&fib_program).expect("expected program");
-    // println!("{}", compiled_program);
+    let compiled = compile(&fib_program, &options).expect("expected program");
+    let compiled_program = compiled.let_program;
 
-    let layout = crate::ir_flat::frame_layout::compute_program_frame_layout(&compiled_program);
+    // The let program, and the frame layout and flat program compiled from it.
+    if mode == Some("dump") {
+        println!("{}", compiled_program);
+        if let Some(flat_program) = &compiled.flat_program {
+            print!("{}", compute_program_frame_layout(&compiled_program));
+            println!("{:#?}", flat_program);
+        }
+        return;
+    }
 
-    println!("{}", compiled_program);
-    println!("{:#?}", layout);
+    if mode == Some("dot") {
+        let directory = arguments.get(1).map_or("cfg", |d| d.as_str());
+        save_dot_files(&compiled_program, Path::new(directory)).expect("failed to write dot files");
+        return;
+    }
 
-    let mut evaluator = ProgramEvaluator::new(compiled_program);
-    let result = evaluator.run();
+    // Prints the flat program or the bytecode (or both, without an argument),
+    // with the instructions of the let program next to them when annotated.
+    if mode == Some("disasm") {
+        let flat_program = compiled
+            .flat_program
+            .expect("the disassembler requires the flat IR to be generated");
+        let disassembler = if annotate {
+            Disassembler::with_source(&compiled_program)
+        } else {
+            Disassembler::new()
+        };
+        let what = arguments.get(1).map(|a| a.as_str());
+        if what != Some("bytecode") {
+            print!("{}", disassembler.flat(&flat_program));
+        }
+        if what != Some("flat") {
+            let mut bytecode = bailey::bytecode::compiler::compile_program(&flat_program);
+            if options.optimization_level >= OptimizationLevel::O1 {
+                fuse_superinstructions(&mut bytecode);
+            }
+            print!("{}", disassembler.bytecode(&bytecode));
+        }
+        return;
+    }
+
+    // The statistics of a program in the text format of the intermediate
+    // language after the passes of the optimization level, or of the built-in
+    // program.
+    if mode == Some("stats") {
+        let program = match arguments.get(1) {
+            Some(file) => {
+                let text = std::fs::read_to_string(file).expect("failed to read program");
+                let mut program = parse_program(&text).expect("failed to parse program");
+                options.optimization_level.pass_manager().run(&mut program);
+                resolve_variables(&mut program);
+                program
+            }
+            None => compiled_program,
+        };
+        print!("{}", ProgramStats::new(&program));
+        return;
+    }
+
+    // Compares two programs in the text format of the intermediate language,
+    // or shows what each pass of the optimization level changes in one.
+    if mode == Some("diff") {
+        let read = |file: &String| {
+            let text = std::fs::read_to_string(file).expect("failed to read program");
+            parse_program(&text).expect("failed to parse program")
+        };
+        match (arguments.get(1), arguments.get(2)) {
+            (Some(before), Some(after)) => {
+                print!("{}", ProgramDiff::new(&read(before), &read(after)))
+            }
+            (Some(file), None) => {
+                let mut manager = options.optimization_level.pass_manager();
+                manager.add_hook(print_diffs());
+                manager.run(&mut read(file));
+            }
+            _ => println!("usage: diff <before> [after]"),
+        }
+        return;
+    }
+
+    // A program in the text format of the intermediate language can be
+    // debugged instead of the built-in one.
+    if mode == Some("debug") {
+        let program = match arguments.get(1) {
+            Some(file) => {
+                let text = std::fs::read_to_string(file).expect("failed to read program");
+                parse_program(&text).and_then(PreparedProgram::new)
+            }
+            None => PreparedProgram::new(compiled_program),
+        };
+        match program {
+            Ok(program) => debug(program),
+            Err(error) => println!("error: {}", error),
+        }
+        return;
+    }
+
+    if mode == Some("cps") {
+        let cps_program = cps_convert(&fib_program).expect("expected program");
+        println!("{:#?}", bailey::ir_cps::interpreter::eval(&cps_program));
+        return;
+    }
+
+    if mode == Some("c") {
+        let flat_program = compiled
+            .flat_program
+            .expect("the C backend requires the flat IR to be generated");
+        let directory = arguments.get(1).map_or("c", |d| d.as_str());
+        save_c_files(&flat_program, Path::new(directory)).expect("failed to write C files");
+        return;
+    }
+
+    if mode == Some("flat") {
+        let flat_program = compiled
+            .flat_program
+            .expect("the flat interpreter requires the flat IR to be generated");
+        let mut interpreter = WordInterpreter::new(&flat_program);
+        match interpreter.run() {
+            Ok(result) => println!("{}", interpreter.render(result)),
+            Err(error) => println!("error: {}", error),
+        }
+        if heap_report.stats {
+            print!("{}", interpreter.stats());
+        }
+        return;
+    }
+
+    if mode == Some("vm") {
+        let flat_program = compiled
+            .flat_program
+            .expect("the VM requires the flat IR to be generated");
+        let mut bytecode = bailey::bytecode::compiler::compile_program(&flat_program);
+        let fused = if options.optimization_level >= OptimizationLevel::O1 {
+            fuse_superinstructions(&mut bytecode)
+        } else {
+            FusionStats::default()
+        };
+        println!("{:#?}", bytecode);
+        let mut vm = VirtualMachine::new(bytecode);
+        match vm.run() {
+            Ok(result) => println!("{:#?}", result),
+            Err(error) => println!("error: {}", error),
+        }
+        if heap_report.cache_stats {
+            print!("{}", vm.cache_stats());
+        }
+        if heap_report.fusion_stats {
+            print!("fused:\n{}executed:\n{}", fused, vm.fusion_stats());
+        }
+        return;
+    }
 
-    println!("{:#?}", result);
+    // The memory manager can be selected with the first command line argument.
+    match mode {
+        Some("arena") => run_with_heap(
+            compiled_program,
+            ArenaHeap::new(),
+            config,
+            trace,
+            &heap_report,
+        ),
+        Some("mark-sweep") => run_with_heap(
+            compiled_program,
+            MarkSweepHeap::new(),
+            config,
+            trace,
+            &heap_report,
+        ),
+        Some("compacting") => run_with_heap(
+            compiled_program,
+            CompactingHeap::new(),
+            config,
+            trace,
+            &heap_report,
+        ),
+        _ => run_with_heap(
+            compiled_program,
+            RefCountHeap::new(),
+            config,
+            trace,
+            &heap_report,
+        ),
+    }
 }
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+
+// An interned string. Symbols are cheap to copy and compare, and the string
+// they stand for can be recovered from the crate-wide interner.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(pub u32);
+
+// Interned strings are never freed, which is fine since the set of names used
+// by the compiler only grows by a bounded amount per compiled program. The
+// interner is shared by all threads, so that symbols can be sent between them
+// along with the programs that contain them. Most uses only look up strings,
+// which can happen in parallel.
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| RwLock::new(Interner::default()))
+}
+
+impl Symbol {
+    pub fn intern(string: &str) -> Symbol {
+        if let Some(symbol) = interner()
+            .read()
+            .expect("interner lock poisoned")
+            .symbols
+            .get(string)
+        {
+            return *symbol;
+        }
+
+        // Another thread may have interned the string in the meantime.
+        let mut interner = interner().write().expect("interner lock poisoned");
+        if let Some(symbol) = interner.symbols.get(string) {
+            return *symbol;
+        }
+
+        let string: &'static str = Box::leak(string.to_owned().into_boxed_str());
+        let symbol = Symbol(interner.strings.len() as u32);
+        interner.strings.push(string);
+        interner.symbols.insert(string, symbol);
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let interner = interner().read().expect("interner lock poisoned");
+        interner.strings[self.0 as usize]
+    }
+
+    // The name as it was written in the source program, without the suffix
+    // that the compiler adds to make names unique.
+    pub fn source_name(self) -> &'static str {
+        let string = self.as_str();
+        match string.rsplit_once("__") {
+            Some((base, suffix)) if suffix.chars().all(|c| c.is_ascii_digit()) => base,
+            _ => string,
+        }
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Symbols are printed as their string, since the numbers depend on the order
+// in which strings were interned.
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        Ok(Symbol::intern(&string))
+    }
+}
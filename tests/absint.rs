@@ -0,0 +1,161 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::absint::range::RangeValue;
+use bailey::ir_let::absint::{analyze, Analysis, Domain};
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Assignment, Control, Definition, Program, Simple, Step};
+use bailey::ir_let::passes::range_propagation::propagate_ranges;
+use bailey::ir_let::visit::{walk_assignment, Visitor};
+use bailey::lang::syntax::{BinOp, Constant};
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::symbol::Symbol;
+use common::compile_at;
+
+// The variables assigned by field accesses and by conditionals, in order.
+#[derive(Default)]
+struct Assignments {
+    accesses: Vec<Symbol>,
+    conditionals: Vec<Symbol>,
+}
+
+impl Visitor for Assignments {
+    fn visit_assignment(&mut self, assignment: &Assignment) {
+        match &assignment.definition {
+            Definition::Step(Step::Simple(Simple::BinOp { op: BinOp::Get, .. })) => {
+                self.accesses.push(assignment.name)
+            }
+            Definition::Step(Step::Control(Control::If { .. })) => {
+                self.conditionals.push(assignment.name)
+            }
+            _ => {}
+        }
+        walk_assignment(self, assignment);
+    }
+}
+
+fn assignments(program: &Program) -> Assignments {
+    let mut assignments = Assignments::default();
+    assignments.visit_program(program);
+    assignments
+}
+
+#[test]
+fn ranges_are_joined_over_branches() {
+    let e = expr! {
+        let f = fun(n) {
+            let t = (1, 2, 3);
+            let i = if n == 0 { 0 } else { 2 };
+            let j = i + 1;
+            t[i] + t[j]
+        };
+        f(0)
+    };
+    let program = compile_at(&e, "-O0");
+    let analysis: Analysis<RangeValue> = analyze(&program);
+
+    // `t[i]` is in range, but `t[j]` is not when `j` is 3.
+    let accesses = assignments(&program).accesses;
+    assert_eq!(accesses.len(), 2);
+    assert!(analysis.accesses_in_range.contains(&accesses[0]));
+    assert!(!analysis.accesses_in_range.contains(&accesses[1]));
+
+    let j = analysis
+        .values
+        .iter()
+        .find(|(name, _)| name.source_name() == "j")
+        .map(|(_, value)| *value);
+    assert_eq!(j, Some(RangeValue::Int { min: 1, max: 3 }));
+}
+
+#[test]
+fn constant_conditions_are_found_and_taken() {
+    let e = expr! {
+        let f = fun(n) {
+            let a = if n == 0 { 1 } else { 2 };
+            if a == 5 { 10 } else { 20 }
+        };
+        f(0)
+    };
+    let mut program = compile_at(&e, "-O0");
+    let analysis: Analysis<RangeValue> = analyze(&program);
+
+    let conditionals = assignments(&program).conditionals;
+    assert_eq!(conditionals.len(), 2);
+    assert_eq!(analysis.conditions.get(&conditionals[0]), None);
+    assert_eq!(analysis.conditions.get(&conditionals[1]), Some(&false));
+
+    propagate_ranges(&mut program);
+    assert_eq!(assignments(&program).conditionals.len(), 1);
+    assert_eq!(ProgramEvaluator::new(program).run(), Ok(Value::Int(20)));
+}
+
+#[test]
+fn loops_reach_a_fixpoint() {
+    // The counter of the loop grows without bound, so the analysis has to
+    // give up on its upper bound instead of taking a step for every value.
+    let e = expr! {
+        let count = fun(i, limit) {
+            if i == limit { i } else { count(i + 1, limit) }
+        };
+        count(0, 1000000)
+    };
+    let program = compile_at(&e, "-O2");
+    let analysis: Analysis<RangeValue> = analyze(&program);
+    // Only the block that enters the loop after the hoisted literals tests a
+    // constant.
+    for conditional in assignments(&program).conditionals {
+        let hoisted = conditional.as_str().starts_with("__hoist__");
+        assert_eq!(
+            analysis.conditions.get(&conditional),
+            hoisted.then_some(&true)
+        );
+    }
+
+    let entry = RangeValue::literal(Constant::Int { value: 0 });
+    let next = RangeValue::Int { min: 0, max: 1 };
+    assert_eq!(
+        entry.widen(&next),
+        RangeValue::Int {
+            min: 0,
+            max: i64::MAX
+        }
+    );
+    assert_eq!(next.widen(&entry), next);
+}
+
+#[test]
+fn operations_on_other_values_are_not_constant() {
+    let tuple = RangeValue::tuple(&[RangeValue::top(), RangeValue::top()]);
+    let zero = RangeValue::literal(Constant::Int { value: 0 });
+    assert_eq!(RangeValue::binop(BinOp::Eq, &tuple, &tuple).truth(), None);
+    assert_eq!(
+        RangeValue::binop(BinOp::Sub, &tuple, &zero).constant(),
+        None
+    );
+    assert!(RangeValue::index_in_range(&tuple, &zero));
+
+    let near_max = RangeValue::literal(Constant::Int { value: i64::MAX });
+    let one = RangeValue::literal(Constant::Int { value: 1 });
+    assert_eq!(
+        RangeValue::binop(BinOp::Add, &near_max, &one),
+        RangeValue::Int {
+            min: i64::MIN,
+            max: i64::MAX
+        }
+    );
+}
+
+#[test]
+fn results_are_unchanged() {
+    for e in [fib_test(10), map_fold_test(10), ackermann_test(2, 3)] {
+        let expected = ProgramEvaluator::new(compile_at(&e, "-O0")).run();
+        let mut program = compile_at(&e, "-O0");
+        propagate_ranges(&mut program);
+        assert_eq!(ProgramEvaluator::new(program).run(), expected);
+        assert_eq!(ProgramEvaluator::new(compile_at(&e, "-O2")).run(), expected);
+    }
+}
@@ -0,0 +1,34 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+
+#[test]
+fn failure_records_the_call_stack() {
+    // The recursive calls of f are not tail calls, so they stay on the stack.
+    // The calls of f from the toplevel and of bad are tail calls, which
+    // replace the frame of the caller.
+    let e = expr! {
+        let bad = fun(t) { t[5] };
+        let f = fun(n) { if n == 0 { bad((1, 2)) } else { 1 + f(n - 1) } };
+        f(2)
+    };
+    let compiled = compile(&e, &CompileOptions::default()).expect("expected program");
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+
+    assert_eq!(
+        evaluator.run(),
+        Err(RuntimeError::IndexOutOfRange { index: 5, size: 2 })
+    );
+    let backtrace = evaluator.backtrace().expect("expected a backtrace");
+    let names: Vec<&str> = backtrace
+        .entries
+        .iter()
+        .map(|entry| entry.function_name.source_name())
+        .collect();
+    assert_eq!(names, ["f", "f", "bad"]);
+    assert_eq!(
+        backtrace.entries.last().unwrap().address,
+        evaluator.program_counter()
+    );
+}
@@ -0,0 +1,102 @@
+use bailey::c_backend::emitter::save_c_files;
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::counter_test;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Compiles the program with the system C compiler and runs it, or returns None
+// if there is no C compiler.
+fn run_c(e: &Expr, name: &str) -> Option<String> {
+    let compiled = compile(e, &CompileOptions::default()).expect("expected program");
+    let flat = compiled.flat_program.expect("expected flat program");
+
+    let directory: PathBuf =
+        std::env::temp_dir().join(format!("bailey-c-{}-{}", name, std::process::id()));
+    save_c_files(&flat, &directory).expect("expected C files");
+
+    let executable = directory.join("program");
+    let status = Command::new("cc")
+        .arg("-O2")
+        .arg("-o")
+        .arg(&executable)
+        .arg(directory.join("program.c"))
+        .status()
+        .ok()?;
+    assert!(status.success(), "C compiler failed");
+
+    let output = Command::new(&executable)
+        .output()
+        .expect("expected program to run");
+    assert!(output.status.success(), "program failed");
+    std::fs::remove_dir_all(&directory).expect("expected directory to be removed");
+
+    Some(
+        String::from_utf8(output.stdout)
+            .expect("expected text")
+            .trim()
+            .to_owned(),
+    )
+}
+
+fn run_interpreter(e: &Expr) -> String {
+    let compiled = compile(e, &CompileOptions::default()).expect("expected program");
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+    let value = evaluator.run().expect("program should not fail");
+    render(value, evaluator.heap())
+}
+
+#[test]
+fn compiled_programs_agree_with_the_interpreter() {
+    let examples = [
+        ("fib", fib_test(20)),
+        ("ackermann", ackermann_test(2, 3)),
+        ("map_fold", map_fold_test(100)),
+        ("counter", counter_test(10)),
+        // Integers outside of the range of the cached ones.
+        ("large", fib_test(30)),
+    ];
+
+    for (name, e) in &examples {
+        let Some(output) = run_c(e, name) else {
+            eprintln!("skipping: no C compiler");
+            return;
+        };
+        assert_eq!(output, run_interpreter(e), "{}", name);
+    }
+}
+
+#[test]
+fn long_lists_are_freed() {
+    let e = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        let l = build(1000000, (0, 0));
+        l[0]
+    };
+    let Some(output) = run_c(&e, "long_list") else {
+        eprintln!("skipping: no C compiler");
+        return;
+    };
+    assert_eq!(output, run_interpreter(&e));
+}
+
+#[test]
+fn arithmetic_wraps_around() {
+    let e = expr! {
+        let inc = fun(n) { n + 1 };
+        let dec = fun(n) { n - 1 };
+        (inc(9223372036854775807), dec(dec(0 - 9223372036854775807)))
+    };
+    let Some(output) = run_c(&e, "overflow") else {
+        eprintln!("skipping: no C compiler");
+        return;
+    };
+    assert_eq!(output, "(-9223372036854775808, 9223372036854775807)");
+    assert_eq!(output, run_interpreter(&e));
+}
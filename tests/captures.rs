@@ -0,0 +1,49 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::Program;
+use common::compile_at;
+
+fn captured_names(program: &Program, function_name: &str) -> Vec<&'static str> {
+    let function = program
+        .functions
+        .iter()
+        .find(|f| f.name.source_name() == function_name)
+        .expect("missing function");
+    function
+        .free_names
+        .as_ref()
+        .expect("expected free names")
+        .iter()
+        .map(|name| name.source_name())
+        .collect()
+}
+
+#[test]
+fn closures_do_not_capture_variables_of_removed_branches() {
+    // The variables of g are only used in a branch that is never taken, so
+    // once that branch is removed, f no longer has to capture them for g.
+    let e = expr! {
+        let x = (1, 2);
+        let y = 3;
+        let f = fun(n) {
+            let g = fun(m) { if true { m + n } else { x[0] + y } };
+            g(n)
+        };
+        f(4)
+    };
+
+    let unoptimized = compile_at(&e, "-O0");
+    assert_eq!(captured_names(&unoptimized, "g"), ["n", "x", "y"]);
+    assert_eq!(captured_names(&unoptimized, "f"), ["x", "y"]);
+
+    let optimized = compile_at(&e, "-O1");
+    assert_eq!(captured_names(&optimized, "g"), ["n"]);
+    assert!(captured_names(&optimized, "f").is_empty());
+
+    let mut evaluator = ProgramEvaluator::new(optimized);
+    let value = evaluator.run().expect("expected result");
+    assert_eq!(render(value, evaluator.heap()), "8");
+}
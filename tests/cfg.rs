@@ -0,0 +1,156 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::cfg::{build_cfg, save_dot_files, EdgeKind, EdgeTarget, FunctionCfg};
+use bailey::ir_let::let_expr::Program;
+use bailey::lang::test::fib::fib_test;
+use common::compile_at;
+use std::collections::HashSet;
+
+fn function_index(program: &Program, prefix: &str) -> usize {
+    program
+        .functions
+        .iter()
+        .position(|function| function.name.as_str().starts_with(prefix))
+        .expect("expected function")
+}
+
+fn edges(cfg: &FunctionCfg) -> Vec<(usize, EdgeTarget, EdgeKind)> {
+    cfg.edges
+        .iter()
+        .map(|edge| (edge.source_block, edge.target, edge.kind))
+        .collect()
+}
+
+// Every statement is on a line of its own, quotes are balanced, and edges only
+// connect nodes that are declared.
+fn assert_well_formed(dot: &str) {
+    let lines: Vec<&str> = dot.lines().collect();
+    assert!(lines[0].starts_with("digraph \"") && lines[0].ends_with("\" {"));
+    assert_eq!(lines.last(), Some(&"}"));
+
+    let mut nodes = HashSet::new();
+    let mut edges = Vec::new();
+    for line in &lines[1..lines.len() - 1] {
+        let statement = line
+            .strip_prefix("    ")
+            .and_then(|line| line.strip_suffix(';'))
+            .expect("expected statement");
+        assert_eq!(statement.replace("\\\"", "").matches('"').count() % 2, 0);
+        let (head, _) = statement.split_once(" [").expect("expected attributes");
+        match head.split_once(" -> ") {
+            Some((source, target)) => edges.push((source.to_owned(), target.to_owned())),
+            None if head == "node" => {}
+            None => assert!(nodes.insert(head.to_owned()), "{} declared twice", head),
+        }
+    }
+    assert!(!edges.is_empty());
+    for (source, target) in edges {
+        assert!(nodes.contains(&source), "undeclared node {}", source);
+        assert!(nodes.contains(&target), "undeclared node {}", target);
+    }
+}
+
+#[test]
+fn branches_calls_and_exits_are_edges() {
+    let e = expr! {
+        let fib = fun(n) {
+            if n == 0 { 0 } else { if n == 1 { 1 } else { fib(n - 1) + fib(n - 2) } }
+        };
+        fib(10)
+    };
+    let program = compile_at(&e, "-O0");
+    let fib = function_index(&program, "fib");
+    let cfgs = build_cfg(&program);
+    assert_eq!(cfgs.len(), program.functions.len());
+
+    assert_eq!(
+        edges(&cfgs[0]),
+        [
+            (0, EdgeTarget::Function(Some(fib)), EdgeKind::TailCall),
+            (0, EdgeTarget::Return, EdgeKind::Exit),
+        ]
+    );
+    assert_eq!(
+        edges(&cfgs[fib]),
+        [
+            (0, EdgeTarget::Block(1), EdgeKind::BranchSuccess),
+            (0, EdgeTarget::Block(2), EdgeKind::BranchFailure),
+            (2, EdgeTarget::Block(3), EdgeKind::BranchSuccess),
+            (2, EdgeTarget::Block(4), EdgeKind::BranchFailure),
+            (4, EdgeTarget::Function(Some(fib)), EdgeKind::Call),
+            (4, EdgeTarget::Function(Some(fib)), EdgeKind::Call),
+            (0, EdgeTarget::Return, EdgeKind::Exit),
+            (1, EdgeTarget::Block(0), EdgeKind::Exit),
+            (2, EdgeTarget::Block(0), EdgeKind::Exit),
+            (3, EdgeTarget::Block(2), EdgeKind::Exit),
+            (4, EdgeTarget::Block(2), EdgeKind::Exit),
+        ]
+    );
+    assert_eq!(cfgs[fib].successors(4).count(), 3);
+
+    for cfg in &cfgs {
+        assert_well_formed(&cfg.to_dot(&program));
+    }
+}
+
+#[test]
+fn tail_recursion_becomes_a_jump() {
+    let program = compile_at(&fib_test(10), "-O2");
+    let fib_helper = function_index(&program, "fib_helper");
+    let cfg = &build_cfg(&program)[fib_helper];
+
+    // The loop header is entered from the function body, and the recursive
+    // call jumps back to it.
+    assert_eq!(
+        edges(cfg),
+        [
+            (0, EdgeTarget::Block(1), EdgeKind::BranchSuccess),
+            (0, EdgeTarget::Block(4), EdgeKind::BranchFailure),
+            (1, EdgeTarget::Block(2), EdgeKind::BranchSuccess),
+            (1, EdgeTarget::Block(3), EdgeKind::BranchFailure),
+            (3, EdgeTarget::Block(1), EdgeKind::Jump),
+            (0, EdgeTarget::Return, EdgeKind::Exit),
+            (1, EdgeTarget::Block(0), EdgeKind::Exit),
+            (2, EdgeTarget::Block(1), EdgeKind::Exit),
+            (3, EdgeTarget::Block(1), EdgeKind::Exit),
+            (4, EdgeTarget::Block(0), EdgeKind::Exit),
+        ]
+    );
+
+    let dot = cfg.to_dot(&program);
+    assert_well_formed(&dot);
+    assert!(dot.contains("    b3 -> b1 [label=\"loop\"];"));
+}
+
+#[test]
+fn dot_files_are_written_per_function() {
+    let program = compile_at(&fib_test(10), "-O0");
+    let directory = std::env::temp_dir().join(format!("bailey-cfg-{}", std::process::id()));
+    save_dot_files(&program, &directory).expect("expected dot files");
+
+    let mut names: Vec<String> = std::fs::read_dir(&directory)
+        .expect("expected directory")
+        .map(|entry| {
+            entry
+                .expect("expected entry")
+                .file_name()
+                .into_string()
+                .unwrap()
+        })
+        .collect();
+    names.sort();
+    let mut expected: Vec<String> = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, function)| format!("{}_{}.dot", i, function.name))
+        .collect();
+    expected.sort();
+    assert_eq!(names, expected);
+
+    for name in names {
+        assert_well_formed(&std::fs::read_to_string(directory.join(name)).expect("expected file"));
+    }
+    std::fs::remove_dir_all(&directory).expect("expected directory to be removed");
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_flat::compiler::compile_program;
+use bailey::ir_flat::frame_layout::coalesce_slots;
+use bailey::ir_flat::interpreter::WordInterpreter;
+use bailey::ir_flat::syntax::Program;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::counter_test;
+use common::compile_at;
+
+// The flat program before its slots are coalesced.
+fn compile_flat(e: &Expr) -> Program {
+    compile_program(&compile_at(e, "-O0"))
+}
+
+fn run(program: &Program) -> String {
+    let mut interpreter = WordInterpreter::new(program);
+    let result = interpreter.run().expect("program should not fail");
+    interpreter.render(result)
+}
+
+fn frame_sizes(program: &Program) -> Vec<usize> {
+    program.functions.iter().map(|f| f.frame_size).collect()
+}
+
+#[test]
+fn locals_that_are_not_live_together_share_a_slot() {
+    // Every intermediate result is dead once the next one is computed, so
+    // the eight locals of f fit in two slots.
+    let e = expr! {
+        let f = fun(x) {
+            let a = x + 1;
+            let b = a + 2;
+            let c = b + 3;
+            c + 4
+        };
+        f(1)
+    };
+    let mut program = compile_flat(&e);
+    assert_eq!(frame_sizes(&program), [3, 8]);
+    assert_eq!(run(&program), "11");
+
+    coalesce_slots(&mut program);
+    assert_eq!(frame_sizes(&program), [2, 2]);
+    assert_eq!(run(&program), "11");
+}
+
+#[test]
+fn coalescing_keeps_the_results() {
+    let examples = [
+        fib_test(20),
+        map_fold_test(50),
+        closure_capture_test(5),
+        counter_test(10),
+    ];
+
+    for e in &examples {
+        let mut program = compile_flat(e);
+        let before = frame_sizes(&program);
+        let result = run(&program);
+
+        coalesce_slots(&mut program);
+        let after = frame_sizes(&program);
+        assert!(after
+            .iter()
+            .zip(&before)
+            .all(|(after, before)| after <= before));
+        assert!(after.iter().sum::<usize>() < before.iter().sum::<usize>());
+        assert_eq!(run(&program), result);
+    }
+}
@@ -0,0 +1,54 @@
+// Helpers shared by the integration tests. Every test file compiles its own
+// copy of this module and uses only some of them.
+#![allow(dead_code)]
+
+use bailey::driver::{compile, CompileOptions};
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::{Heap, RefCountHeap};
+use bailey::ir_let::interpreter::heap_value::{HeapAddress, Value};
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::Program;
+use bailey::lang::syntax::Expr;
+use std::collections::HashSet;
+
+pub fn compile_at(e: &Expr, level: &str) -> Program {
+    let mut options = CompileOptions::default();
+    options.parse_flag(level);
+    compile(e, &options).expect("expected program").let_program
+}
+
+// Options that make the natives available to the program as globals.
+pub fn options_with_natives(natives: &Natives) -> CompileOptions {
+    CompileOptions {
+        globals: natives.names(),
+        ..CompileOptions::default()
+    }
+}
+
+pub fn compile_with_natives(e: &Expr, natives: &Natives) -> Program {
+    compile(e, &options_with_natives(natives))
+        .expect("expected program")
+        .let_program
+}
+
+pub fn run_with_natives(e: &Expr, natives: &Natives) -> Result<String, RuntimeError> {
+    let mut evaluator = ProgramEvaluator::new(compile_with_natives(e, natives));
+    evaluator.bind_natives(natives).expect("expected natives");
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+// The number of objects that can be reached from the value, which is all that
+// should be left on the heap once a program has returned it.
+pub fn reachable_objects(heap: &RefCountHeap, value: Value) -> usize {
+    let mut seen: HashSet<HeapAddress> = HashSet::new();
+    let mut worklist: Vec<HeapAddress> = value.address().into_iter().collect();
+    while let Some(address) = worklist.pop() {
+        if seen.insert(address) {
+            worklist.extend(heap.deref(address).references());
+        }
+    }
+    seen.len()
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::heap::{CompactingHeap, Heap, RefCountHeap};
+use bailey::ir_let::interpreter::heap_value::{HeapValue, Tuple, Value};
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::list::map_fold_test;
+use common::compile_with_natives;
+
+fn run<H: Heap>(e: &Expr, heap: H) -> (String, ProgramEvaluator<H>) {
+    let mut natives = Natives::new();
+    natives.register_weak_refs();
+    let mut evaluator = ProgramEvaluator::with_heap(compile_with_natives(e, &natives), heap);
+    evaluator.bind_natives(&natives).expect("expected natives");
+    let value = evaluator.run().expect("program should not fail");
+    (render(value, evaluator.heap()), evaluator)
+}
+
+fn tuple(heap: &mut CompactingHeap, value: i64) -> Value {
+    let heap_value = HeapValue::Tuple(Tuple::new(vec![Value::Int(value)]));
+    Value::Pointer(heap.alloc(heap_value).expect("allocation should succeed"))
+}
+
+#[test]
+fn objects_keep_their_address_when_they_move() {
+    let mut heap = CompactingHeap::new();
+    let a = tuple(&mut heap, 1);
+    let b = tuple(&mut heap, 2);
+    let c = tuple(&mut heap, 3);
+    let [Value::Pointer(a), Value::Pointer(b), Value::Pointer(c)] = [a, b, c] else {
+        unreachable!("tuples are pointers");
+    };
+    assert_eq!(heap.position(c), 2);
+
+    let mut freed = Vec::new();
+    heap.collect(&mut [a, c].into_iter(), &mut |address| freed.push(address));
+    assert_eq!(freed, [b]);
+    assert!(!heap.is_live(b));
+    assert_eq!(heap.position(c), 1);
+    assert_eq!(heap.moved_objects(), 1);
+    assert_eq!(
+        heap.deref(c).check_tuple().map(|t| t.field_values.clone()),
+        Ok(vec![Value::Int(3)])
+    );
+
+    // The handle of the freed object is reused, but not its address.
+    let Value::Pointer(d) = tuple(&mut heap, 4) else {
+        unreachable!("tuples are pointers");
+    };
+    assert_eq!(d.index, b.index);
+    assert!(!heap.is_live(b));
+    assert_eq!(heap.position(d), 2);
+}
+
+#[test]
+fn long_lived_data_survives_compaction() {
+    // The pairs die right away, so the cells of the list are moved down over
+    // them by every collection.
+    let e = expr! {
+        let build = fun(n, acc) {
+            if n == 0 { acc } else { let junk = (n, n); build(n - 1, (n, acc)) }
+        };
+        let sum = fun(list, acc) {
+            if list[0] == 0 { acc } else { sum(list[1], acc + list[0]) }
+        };
+        let list = build(3000, (0, 0));
+        (sum(list, 0), list[0], list[1][0])
+    };
+
+    let (expected, _) = run(&e, RefCountHeap::new());
+    let (output, evaluator) = run(&e, CompactingHeap::new());
+    assert_eq!(output, expected);
+    assert_eq!(output, "(4501500, 1, 2)");
+    assert!(evaluator.heap().moved_objects() > 0);
+    assert!(evaluator.heap().stats().frees > 0);
+}
+
+#[test]
+fn weak_references_follow_moved_objects() {
+    let e = expr! {
+        let churn = fun(n) { if n == 0 { 0 } else { let junk = (n, n); churn(n - 1) } };
+        let make = fun(n) { weak((n, n)) };
+        let dead = make(1);
+        let kept = (3, 4);
+        let alive = weak(kept);
+        let done = churn(3000);
+        (deref_weak(dead)[0], deref_weak(alive)[1][1], kept[0])
+    };
+
+    let (output, evaluator) = run(&e, CompactingHeap::new());
+    assert_eq!(output, "(false, 4, 3)");
+    assert!(evaluator.heap().moved_objects() > 0);
+}
+
+#[test]
+fn results_agree_with_reference_counting() {
+    let e = map_fold_test(400);
+    let (expected, _) = run(&e, RefCountHeap::new());
+    let (output, _) = run(&e, CompactingHeap::new());
+    assert_eq!(output, expected);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Program, Simple};
+use bailey::ir_let::passes::constant_folding::fold_constants;
+use bailey::ir_let::resolve::resolve_variables;
+use bailey::ir_let::visit::{walk_simple, Visitor};
+use bailey::lang::syntax::{Constant, Expr};
+use common::compile_at;
+
+#[derive(Default)]
+struct Operations {
+    binops: usize,
+    literals: Vec<Constant>,
+}
+
+impl Visitor for Operations {
+    fn visit_simple(&mut self, simple: &Simple) {
+        match simple {
+            Simple::BinOp { .. } => self.binops += 1,
+            Simple::Literal(c) => self.literals.push(*c),
+            _ => {}
+        }
+        walk_simple(self, simple);
+    }
+}
+
+fn operations(program: &Program) -> Operations {
+    let mut operations = Operations::default();
+    operations.visit_program(program);
+    operations
+}
+
+fn run(mut program: Program) -> Result<String, RuntimeError> {
+    resolve_variables(&mut program);
+    let mut evaluator = ProgramEvaluator::new(program);
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+// Compiled without optimizations, and then folded.
+fn fold(e: &Expr) -> (Program, Program) {
+    let program = compile_at(e, "-O0");
+    let mut folded = program.clone();
+    fold_constants(&mut folded);
+    (program, folded)
+}
+
+#[test]
+fn operations_on_literals_become_literals() {
+    let e = expr! {
+        let a = 2;
+        let b = 3;
+        (a + b, a - b, a == b)
+    };
+    let (program, folded) = fold(&e);
+    assert_eq!(operations(&program).binops, 3);
+
+    let operations = operations(&folded);
+    assert_eq!(operations.binops, 0);
+    for c in [
+        Constant::Int { value: 5 },
+        Constant::Int { value: -1 },
+        Constant::Bool { value: false },
+    ] {
+        assert!(operations.literals.contains(&c), "{:?} not folded", c);
+    }
+    assert_eq!(run(folded), run(program));
+}
+
+#[test]
+fn failing_operations_are_left_for_runtime() {
+    let overflow = expr! {
+        let max = 9223372036854775807;
+        let one = 1;
+        max + one
+    };
+    let (program, folded) = fold(&overflow);
+    assert_eq!(operations(&folded).binops, 1);
+    assert_eq!(run(program), Ok("-9223372036854775808".to_owned()));
+    assert_eq!(run(folded), Ok("-9223372036854775808".to_owned()));
+
+    let mismatch = expr! {
+        let one = 1;
+        let yes = true;
+        one + yes
+    };
+    let (_, folded) = fold(&mismatch);
+    assert_eq!(operations(&folded).binops, 1);
+    assert_eq!(
+        run(folded),
+        Err(RuntimeError::TypeMismatch { expected: "int" })
+    );
+}
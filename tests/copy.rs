@@ -0,0 +1,85 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use common::{compile_with_natives, reachable_objects};
+
+// Also checks that only the result is left on the heap.
+fn run(e: &Expr) -> String {
+    let mut natives = Natives::new();
+    natives.register_copy();
+    let mut evaluator = ProgramEvaluator::new(compile_with_natives(e, &natives));
+    evaluator.bind_natives(&natives).expect("expected natives");
+    let value = evaluator.run().expect("program should not fail");
+
+    let heap = evaluator.heap();
+    assert_eq!(heap.len(), reachable_objects(heap, value));
+    render(value, heap)
+}
+
+#[test]
+fn copies_are_independent_of_the_original() {
+    let e = expr! {
+        let t = (1, (2, 3));
+        let c = copy(t);
+        let inner = c[1];
+        let u = set inner[0] = 9;
+        (t, c)
+    };
+    assert_eq!(run(&e), "((1, (2, 3)), (1, (9, 3)))");
+}
+
+#[test]
+fn sharing_is_preserved() {
+    let e = expr! {
+        let s = (1,);
+        let t = (s, s);
+        let c = copy(t);
+        let shared = c[0];
+        let u = set shared[0] = 2;
+        (t, c)
+    };
+    assert_eq!(run(&e), "(((1,), (1,)), ((2,), (2,)))");
+}
+
+#[test]
+fn cycles_are_copied_as_cycles() {
+    // The cycles are broken before the program returns, so that reference
+    // counting can free them.
+    let e = expr! {
+        let t = (1, 0);
+        let u = set t[1] = t;
+        let c = copy(t);
+        let v = set c[0] = 5;
+        let result = (t[0], c[0], c[1][0], t[1][0]);
+        let w = set t[1] = 0;
+        let x = set c[1] = 0;
+        result
+    };
+    assert_eq!(run(&e), "(1, 5, 5, 1)");
+}
+
+#[test]
+fn other_values_are_not_copied() {
+    let e = expr! {
+        let f = fun(x) { x + 1 };
+        let c = copy((f, 1));
+        (copy(5), copy(true), c[0](c[1]))
+    };
+    assert_eq!(run(&e), "(5, true, 2)");
+}
+
+#[test]
+fn long_lists_are_copied() {
+    let e = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        let sum = fun(list, acc) {
+            if list[0] == 0 { acc } else { sum(list[1], acc + list[0]) }
+        };
+        sum(copy(build(100000, (0, 0))), 0)
+    };
+    assert_eq!(run(&e), "5000050000");
+}
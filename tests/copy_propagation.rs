@@ -0,0 +1,122 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Definition, Instruction, Program};
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::passes::copy_propagation::propagate_copies;
+use bailey::ir_let::resolve::resolve_variables;
+use common::compile_at;
+
+fn copies(program: &Program) -> usize {
+    program
+        .functions
+        .iter()
+        .flat_map(|function| &function.blocks)
+        .flat_map(|block| &block.instructions)
+        .filter(|instruction| {
+            matches!(
+                instruction,
+                Instruction::Assignment(assignment)
+                    if matches!(assignment.definition, Definition::Var(_))
+            )
+        })
+        .count()
+}
+
+fn run(mut program: Program) -> String {
+    resolve_variables(&mut program);
+    let mut evaluator = ProgramEvaluator::new(program);
+    let value = evaluator.run().expect("program should not fail");
+    render(value, evaluator.heap())
+}
+
+#[test]
+fn aliases_are_replaced_by_their_source() {
+    let e = expr! {
+        let f = fun(x) {
+            let a = x;
+            let b = a;
+            let g = fun(y) { b + y };
+            (a, g(b))
+        };
+        f(20)
+    };
+    let mut program = compile_at(&e, "-O0");
+    assert_eq!(copies(&program), 2);
+    let expected = run(program.clone());
+
+    propagate_copies(&mut program);
+    assert_eq!(copies(&program), 0);
+    assert_eq!(run(program), expected);
+}
+
+// f(n) keeps a copy of its argument from before the loop, which rebinds the
+// argument in every iteration, and returns the copy once n reaches 0.
+const LOOP: &str = "program
+begin function 0
+name toplevel
+arguments
+free names
+begin block 0
+no parent block
+enterblock
+f__0 = closure(f__1, (1,0,0), [n__2 ], [])
+three__3 = 3
+result__4 = f__0(three__3)
+exitblock(result__4)
+end block 0
+
+end function 0
+
+begin function 1
+name f__1
+arguments n__2
+free names
+begin block 0
+no parent block
+enterblock
+first__5 = n__2
+true__6 = true
+loop__7 = if true__6 then (1,1,0) else (1,1,0)
+exitblock(loop__7)
+end block 0
+
+begin block 1
+parent block 0
+loop header
+enterblock
+zero__8 = 0
+done__9 = n__2 == zero__8
+step__10 = if done__9 then (1,2,0) else (1,3,0)
+exitblock(step__10)
+end block 1
+
+begin block 2
+parent block 1
+enterblock
+exitblock(first__5)
+end block 2
+
+begin block 3
+parent block 1
+enterblock
+one__11 = 1
+next__12 = n__2 - one__11
+jump__13 = jump (1,1,0)(next__12)
+exitblock(jump__13)
+end block 3
+
+end function 1
+";
+
+#[test]
+fn copies_of_rebound_arguments_are_kept() {
+    let mut program = parse_program(LOOP).expect("expected program text");
+    assert_eq!(run(program.clone()), "3");
+
+    propagate_copies(&mut program);
+    assert_eq!(copies(&program), 1);
+    assert_eq!(run(program), "3");
+}
@@ -0,0 +1,99 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::debugger::{Breakpoint, Debugger, StopReason};
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+
+fn debugger(e: &Expr) -> Debugger {
+    let compiled = compile(e, &CompileOptions::default()).expect("expected program");
+    Debugger::new(ProgramEvaluator::new(compiled.let_program))
+}
+
+fn program() -> Expr {
+    expr! {
+        let f = fun(x) { let y = x + 1; (y, y) };
+        let a = f(1);
+        let b = f(10);
+        a[0] + b[1]
+    }
+}
+
+fn innermost_function(debugger: &Debugger) -> Option<&'static str> {
+    let call_stack = debugger.call_stack();
+    Some(call_stack.last()?.function_name.source_name())
+}
+
+#[test]
+fn function_breakpoints_stop_at_every_call() {
+    let mut debugger = debugger(&program());
+    debugger.add_breakpoint(Breakpoint::Function("f".to_owned()));
+    debugger.add_breakpoint(Breakpoint::Function("f".to_owned()));
+    assert_eq!(debugger.breakpoints().len(), 1);
+
+    for _ in 0..2 {
+        let StopReason::Breakpoint(address) = debugger
+            .continue_execution()
+            .expect("program should not fail")
+        else {
+            panic!("expected to stop in f");
+        };
+        let call_stack = debugger.call_stack();
+        let innermost = call_stack.last().expect("expected a function");
+        assert_eq!(innermost.function_name.source_name(), "f");
+        assert_eq!(innermost.address, address);
+        assert_eq!((address.block_index, address.instruction_index), (0, 0));
+    }
+
+    assert_eq!(
+        debugger.continue_execution(),
+        Ok(StopReason::Finished(Value::Int(13)))
+    );
+}
+
+#[test]
+fn locals_are_shown_as_they_are_assigned() {
+    let mut debugger = debugger(&program());
+    debugger.add_breakpoint(Breakpoint::Function("f".to_owned()));
+    debugger
+        .continue_execution()
+        .expect("program should not fail");
+
+    // Steps through the first call of f until it returns.
+    let mut seen = Vec::new();
+    while innermost_function(&debugger) == Some("f") {
+        for (name, value) in debugger.locals() {
+            if name.source_name() == "y" && !seen.contains(&value) {
+                seen.push(value);
+            }
+        }
+        debugger.step().expect("program should not fail");
+    }
+    assert_eq!(seen, [Value::Int(2)]);
+}
+
+#[test]
+fn address_breakpoints_can_be_removed() {
+    let mut debugger = debugger(&program());
+    debugger.add_breakpoint(Breakpoint::Function("f".to_owned()));
+    let Ok(StopReason::Breakpoint(address)) = debugger.continue_execution() else {
+        panic!("expected to stop in f");
+    };
+
+    let function = Breakpoint::Function("f".to_owned());
+    assert!(debugger.remove_breakpoint(&function));
+    assert!(!debugger.remove_breakpoint(&function));
+    debugger.add_breakpoint(Breakpoint::Address(address));
+    assert_eq!(
+        debugger.continue_execution(),
+        Ok(StopReason::Breakpoint(address))
+    );
+
+    debugger.remove_breakpoint(&Breakpoint::Address(address));
+    assert_eq!(
+        debugger.continue_execution(),
+        Ok(StopReason::Finished(Value::Int(13)))
+    );
+    assert!(debugger.locals().is_empty());
+    assert!(debugger.call_stack().is_empty());
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use bailey::driver::compile;
+use bailey::expr;
+use bailey::ir_let::interpreter::config::EvaluatorConfig;
+use bailey::ir_let::interpreter::deferred::DeferredStats;
+use bailey::ir_let::interpreter::heap::{Heap, HeapStats};
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::gen::generate_program;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::{aliasing_test, counter_test};
+use common::options_with_natives;
+
+struct Outcome {
+    output: String,
+    live_objects: usize,
+    stats: HeapStats,
+    deferred: Option<DeferredStats>,
+}
+
+fn run(e: &Expr, level: &str, deferred_refcounts: bool) -> Outcome {
+    let mut natives = Natives::new();
+    natives.register_thunks();
+    let mut options = options_with_natives(&natives);
+    options.parse_flag(level);
+    let compiled = compile(e, &options).expect("expected program");
+
+    let config = EvaluatorConfig {
+        deferred_refcounts,
+        ..EvaluatorConfig::default()
+    };
+    let mut evaluator = ProgramEvaluator::with_config(compiled.let_program, config);
+    evaluator.bind_natives(&natives).expect("expected natives");
+    let output = match evaluator.run() {
+        Ok(value) => render(value, evaluator.heap()),
+        Err(error) => format!("error: {}", error),
+    };
+
+    Outcome {
+        output,
+        live_objects: evaluator.heap().len(),
+        stats: evaluator.heap().stats().clone(),
+        deferred: evaluator.deferred_stats().cloned(),
+    }
+}
+
+#[test]
+fn deferring_does_not_change_results_or_leak() {
+    let mut examples = vec![
+        fib_test(12),
+        map_fold_test(30),
+        counter_test(10),
+        aliasing_test(),
+        closure_capture_test(5),
+        expr! {
+            let from = fun(n) { (n, delay { from(n + 1) }) };
+            let sum = fun(stream, k) {
+                if k == 0 { 0 } else { stream[0] + sum(force(stream[1]), k - 1) }
+            };
+            sum(from(0), 10)
+        },
+    ];
+    examples.extend((0..30).map(|seed| generate_program(seed, 5)));
+
+    for e in &examples {
+        for level in ["-O0", "-O2"] {
+            let immediate = run(e, level, false);
+            let deferred = run(e, level, true);
+            assert_eq!(deferred.output, immediate.output, "at {}", level);
+            assert_eq!(
+                deferred.live_objects, immediate.live_objects,
+                "at {}",
+                level
+            );
+            assert_eq!(deferred.stats.frees, immediate.stats.frees, "at {}", level);
+            assert!(immediate.deferred.is_none());
+        }
+    }
+}
+
+#[test]
+fn temporaries_cancel_out() {
+    let e = expr! {
+        let go = fun(n, acc) {
+            if n == 0 {
+                acc
+            } else {
+                let pair = (n, acc);
+                go(n - 1, pair[1] + pair[0])
+            }
+        };
+        go(10000, 0)
+    };
+
+    for level in ["-O0", "-O2"] {
+        let outcome = run(&e, level, true);
+        assert_eq!(outcome.output, "50005000");
+        assert_eq!(outcome.live_objects, 0);
+
+        // Every pair is assigned to a local and released before the next
+        // reconciliation, so neither update reaches the heap.
+        let stats = outcome.deferred.expect("expected deferred stats");
+        assert!(stats.cancelled >= 10000, "{} at {}", stats, level);
+
+        // Objects are freed at least every few hundred updates, even in a
+        // loop without calls or block exits.
+        assert!(outcome.stats.peak_objects < 300, "at {}", level);
+    }
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use bailey::ir_let::diff::{DiffLine, ProgramDiff};
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::passes::hoist_invariants::hoist_invariants;
+use bailey::ir_let::passes::tail_recursion::convert_tail_recursion;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use common::compile_at;
+
+fn changes(diff: &ProgramDiff) -> (Vec<String>, Vec<String>) {
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for function in &diff.functions {
+        for line in &function.lines {
+            match line {
+                DiffLine::Same(_) => {}
+                DiffLine::Removed(text) => removed.push(text.trim().to_owned()),
+                DiffLine::Added(text) => added.push(text.trim().to_owned()),
+            }
+        }
+    }
+    (removed, added)
+}
+
+#[test]
+fn equal_programs_have_no_diff() {
+    let program = compile_at(&fib_test(10), "-O2");
+    assert!(ProgramDiff::new(&program, &program).is_empty());
+
+    let parsed = parse_program(&program.to_string()).expect("expected program text");
+    let diff = ProgramDiff::new(&program, &parsed);
+    assert!(diff.is_empty(), "{}", diff);
+    assert_eq!(diff.to_string(), "");
+}
+
+#[test]
+fn changed_instructions_are_shown() {
+    let before = compile_at(&fib_test(10), "-O0");
+    let mut after = before.clone();
+    convert_tail_recursion(&mut after);
+
+    let diff = ProgramDiff::new(&before, &after);
+    assert_eq!(diff.functions.len(), 1);
+    assert_eq!(diff.functions[0].name.source_name(), "fib_helper");
+
+    let (removed, added) = changes(&diff);
+    assert_eq!(removed.len(), 2, "{}", diff);
+    assert_eq!(added.len(), 2, "{}", diff);
+    assert_eq!(removed[0], "block entry");
+    assert_eq!(added[0], "block entry (loop header)");
+    assert!(removed[1].contains("= tailcall fib_helper"), "{}", diff);
+    assert!(added[1].contains("= jump entry("), "{}", diff);
+
+    let text = diff.to_string();
+    assert!(text.starts_with("@@ function fib_helper"), "{}", text);
+    assert!(text.contains("\n- ") && text.contains("\n+ "), "{}", text);
+}
+
+// Hoisting inserts a block in front of the loop header, which renumbers the
+// blocks of the loop. Their labels do not depend on the numbering, so only
+// the moved instructions and the new blocks show up in the diff.
+#[test]
+fn labels_are_stable_when_blocks_are_renumbered() {
+    let mut before = compile_at(&fib_test(10), "-O0");
+    convert_tail_recursion(&mut before);
+    let mut after = before.clone();
+    hoist_invariants(&mut after);
+
+    let diff = ProgramDiff::new(&before, &after);
+    let (removed, added) = changes(&diff);
+    assert_eq!(removed.len(), 3, "{}", diff);
+    assert_eq!(removed[0], "block entry (loop header)");
+    assert!(removed[1].ends_with(" = 1"), "{}", diff);
+    assert!(removed[2].contains("= jump entry("), "{}", diff);
+    assert!(added.contains(&"block entry".to_owned()), "{}", diff);
+
+    // The exit of the loop is in a block whose number changed.
+    let unchanged = diff.functions[0]
+        .lines
+        .iter()
+        .any(|line| matches!(line, DiffLine::Same(text) if text.trim() == "exitblock(b__1)"));
+    assert!(unchanged, "{}", diff);
+}
+
+#[test]
+fn functions_are_matched_by_name() {
+    let fib = compile_at(&fib_test(10), "-O0");
+    let even_odd = compile_at(&even_odd_test(7), "-O0");
+
+    let diff = ProgramDiff::new(&fib, &even_odd);
+    for function in &diff.functions {
+        let only_in_fib = fib.functions.iter().any(|f| f.name == function.name)
+            && !even_odd.functions.iter().any(|f| f.name == function.name);
+        let only_in_even_odd = !fib.functions.iter().any(|f| f.name == function.name);
+        if only_in_fib {
+            assert!(function
+                .lines
+                .iter()
+                .all(|l| matches!(l, DiffLine::Removed(_))));
+        } else if only_in_even_odd {
+            assert!(function
+                .lines
+                .iter()
+                .all(|l| matches!(l, DiffLine::Added(_))));
+        }
+    }
+    assert!(diff.functions.len() >= even_odd.functions.len());
+}
@@ -0,0 +1,99 @@
+// Runs randomly generated programs on every execution engine and checks that
+// they agree on the result, and that the let interpreter releases everything
+// except the result.
+mod common;
+
+use bailey::bytecode::vm::{Value as VmValue, VirtualMachine};
+use bailey::driver::{compile, compile_bytecode, CompileOptions};
+use bailey::ir_cps::compiler::cps_convert;
+use bailey::ir_flat::interpreter::WordInterpreter;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::gen::generate_program;
+use common::reachable_objects;
+
+const PROGRAMS: u64 = 300;
+const MAX_DEPTH: usize = 5;
+
+// The VM has no renderer of its own. The generated programs only return data.
+fn render_vm(value: &VmValue) -> String {
+    match value {
+        VmValue::Int(value) => value.to_string(),
+        VmValue::Bool(value) => value.to_string(),
+        VmValue::Tuple(fields) => {
+            let fields: Vec<String> = fields.borrow().iter().map(render_vm).collect();
+            if fields.len() == 1 {
+                format!("({},)", fields[0])
+            } else {
+                format!("({})", fields.join(", "))
+            }
+        }
+        VmValue::Closure(_) => "<closure>".to_owned(),
+    }
+}
+
+fn check_program(seed: u64, e: &Expr) {
+    let mut outputs = Vec::new();
+
+    for flag in ["-O0", "-O1", "-O2"] {
+        let mut options = CompileOptions::default();
+        options.parse_flag(flag);
+        let compiled = compile(e, &options).expect("expected program");
+        let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+        let output = match evaluator.run() {
+            Ok(value) => {
+                let heap = evaluator.heap();
+                assert_eq!(
+                    heap.len(),
+                    reachable_objects(heap, value),
+                    "seed {}: objects left after the program at {}:\n{:#?}",
+                    seed,
+                    flag,
+                    e
+                );
+                render(value, heap)
+            }
+            Err(error) => format!("error: {}", error),
+        };
+        outputs.push((format!("let interpreter at {}", flag), output));
+
+        let flat = compiled.flat_program.expect("expected flat program");
+        let mut interpreter = WordInterpreter::new(&flat);
+        let output = match interpreter.run() {
+            Ok(word) => interpreter.render(word),
+            Err(error) => format!("error: {}", error),
+        };
+        outputs.push((format!("flat interpreter at {}", flag), output));
+
+        let bytecode = compile_bytecode(e, &options).expect("expected program");
+        let output = match VirtualMachine::new(bytecode).run() {
+            Ok(value) => render_vm(&value),
+            Err(error) => format!("error: {}", error),
+        };
+        outputs.push((format!("VM at {}", flag), output));
+    }
+
+    let cps_program = cps_convert(e).expect("expected program");
+    let output = match bailey::ir_cps::interpreter::eval(&cps_program) {
+        Ok(value) => value.to_string(),
+        Err(error) => format!("error: {}", error),
+    };
+    outputs.push(("CPS interpreter".to_owned(), output));
+
+    let (_, expected) = &outputs[0];
+    for (engine, output) in &outputs[1..] {
+        assert_eq!(
+            output, expected,
+            "seed {}: {} disagrees with the let interpreter at -O0 on:\n{:#?}",
+            seed, engine, e
+        );
+    }
+}
+
+#[test]
+fn engines_agree_on_generated_programs() {
+    for seed in 0..PROGRAMS {
+        check_program(seed, &generate_program(seed, MAX_DEPTH));
+    }
+}
@@ -0,0 +1,102 @@
+use bailey::bytecode::compiler::compile_program;
+use bailey::disasm::Disassembler;
+use bailey::driver::{compile, compile_bytecode, CompileOptions};
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::counter_test;
+use std::collections::HashSet;
+
+fn programs() -> Vec<Expr> {
+    vec![
+        fib_test(10),
+        even_odd_test(7),
+        closure_capture_test(5),
+        map_fold_test(10),
+        counter_test(10),
+    ]
+}
+
+// Every label that an instruction jumps to is defined exactly once.
+fn check_labels(text: &str) {
+    let mut defined = HashSet::new();
+    let mut used = HashSet::new();
+    for line in text.lines() {
+        if line.starts_with("function ") {
+            assert!(used.is_subset(&defined), "{}", text);
+            defined.clear();
+            used.clear();
+        } else if let Some(label) = line.strip_suffix(':') {
+            assert!(defined.insert(label.to_owned()), "{}", text);
+        } else {
+            let code = line.split(" ; ").next().unwrap();
+            for word in code.split(|c: char| !c.is_alphanumeric()) {
+                if word.starts_with('L') && word[1..].chars().all(|c| c.is_ascii_digit()) {
+                    used.insert(word.to_owned());
+                }
+            }
+        }
+    }
+    assert!(used.is_subset(&defined), "{}", text);
+}
+
+#[test]
+fn flat_programs_are_disassembled() {
+    for e in programs() {
+        let flat_program = compile(&e, &CompileOptions::default())
+            .expect("expected program")
+            .flat_program
+            .expect("expected flat program");
+        let text = Disassembler::new().flat(&flat_program);
+
+        let headers = text.lines().filter(|l| l.starts_with("function ")).count();
+        assert_eq!(headers, flat_program.functions.len());
+        assert!(text.starts_with("function 0 <toplevel>"), "{}", text);
+        assert!(!text.contains(" ; "), "{}", text);
+        check_labels(&text);
+    }
+}
+
+#[test]
+fn bytecode_is_disassembled() {
+    for e in programs() {
+        let program = compile_bytecode(&e, &CompileOptions::default()).expect("expected program");
+        let text = Disassembler::new().bytecode(&program);
+
+        let instructions = text
+            .lines()
+            .filter(|l| l.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+            .count();
+        let expected: usize = program.functions.iter().map(|f| f.code.len()).sum();
+        assert_eq!(instructions, expected);
+        check_labels(&text);
+    }
+}
+
+#[test]
+fn annotations_show_the_let_program() {
+    let compiled = compile(&fib_test(10), &CompileOptions::default()).expect("expected program");
+    let flat_program = compiled.flat_program.expect("expected flat program");
+    let disassembler = Disassembler::with_source(&compiled.let_program);
+
+    let text = disassembler.flat(&flat_program);
+    check_labels(&text);
+    // Arguments are shown with their names, and every assignment and exit of
+    // the let program appears next to the flat instructions it became.
+    assert!(text.contains("arg0<n__"), "{}", text);
+    let let_instructions: usize = compiled
+        .let_program
+        .functions
+        .iter()
+        .flat_map(|f| &f.blocks)
+        .map(|b| b.instructions.len() - 1)
+        .sum();
+    assert_eq!(text.matches(" ; ").count(), let_instructions);
+
+    let bytecode = compile_program(&flat_program);
+    let text = disassembler.bytecode(&bytecode);
+    assert!(text.contains("load arg0<n__"), "{}", text);
+    assert!(text.contains("function 1 <fib_helper"), "{}", text);
+}
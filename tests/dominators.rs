@@ -0,0 +1,174 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::ir_flat::ssa::construction::convert_to_ssa;
+use bailey::ir_flat::ssa::dominators::{reverse_postorder, DominatorTree};
+use bailey::ir_flat::ssa::loops::LoopForest;
+use bailey::ir_flat::ssa::syntax::{BasicBlock, Function, Operand, Terminator};
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::counter_test;
+
+fn jump(target: usize) -> Terminator {
+    Terminator::Jump(target)
+}
+
+fn branch(branch_success: usize, branch_failure: usize) -> Terminator {
+    Terminator::Branch {
+        condition: Operand::This,
+        branch_success,
+        branch_failure,
+    }
+}
+
+fn function(terminators: Vec<Terminator>) -> Function {
+    Function {
+        args_size: 0,
+        closure_env_size: 0,
+        values_size: 0,
+        blocks: terminators
+            .into_iter()
+            .map(|terminator| BasicBlock {
+                phis: vec![],
+                instructions: vec![],
+                terminator,
+            })
+            .collect(),
+    }
+}
+
+// 0 -> 1, the header of the outer loop, which contains a diamond 1 -> 2, 3
+// -> 4 and the inner loop 4 -> 5 -> 4. Block 5 leaves the inner loop for 6,
+// which goes back to 1 or returns in 8. Block 7 cannot be reached.
+fn nested_loops() -> Function {
+    function(vec![
+        jump(1),
+        branch(2, 3),
+        jump(4),
+        jump(4),
+        jump(5),
+        branch(4, 6),
+        branch(1, 8),
+        jump(6),
+        Terminator::Return(Operand::This),
+    ])
+}
+
+#[test]
+fn immediate_dominators() {
+    let f = nested_loops();
+    let tree = DominatorTree::new(&f);
+
+    let idoms: Vec<Option<usize>> = (0..f.blocks.len())
+        .map(|b| tree.immediate_dominator(b))
+        .collect();
+    assert_eq!(
+        idoms,
+        [
+            None,
+            Some(0),
+            Some(1),
+            Some(1),
+            Some(1),
+            Some(4),
+            Some(5),
+            None,
+            Some(6)
+        ]
+    );
+
+    assert!(tree.dominates(1, 6));
+    assert!(tree.dominates(4, 4));
+    assert!(!tree.dominates(2, 4));
+    assert!(!tree.is_reachable(7));
+    assert!(!tree.dominates(0, 7));
+    assert_eq!(tree.children(1), [2, 3, 4]);
+
+    let order = reverse_postorder(&f);
+    assert_eq!(order.len(), 8);
+    assert_eq!(order[0], 0);
+    assert!(!order.contains(&7));
+}
+
+#[test]
+fn natural_loops_are_nested() {
+    let f = nested_loops();
+    let forest = LoopForest::new(&f, &DominatorTree::new(&f));
+    assert_eq!(forest.loops.len(), 2);
+
+    let outer = &forest.loops[0];
+    assert_eq!(outer.header, 1);
+    assert_eq!(outer.latches, [6]);
+    assert_eq!(outer.blocks, [1, 2, 3, 4, 5, 6]);
+    assert_eq!(outer.parent, None);
+
+    let inner = &forest.loops[1];
+    assert_eq!(inner.header, 4);
+    assert_eq!(inner.latches, [5]);
+    assert_eq!(inner.blocks, [4, 5]);
+    assert_eq!(inner.parent, Some(0));
+
+    assert_eq!(forest.depth(0), 0);
+    assert_eq!(forest.depth(2), 1);
+    assert_eq!(forest.depth(5), 2);
+    assert_eq!(forest.innermost_loop(5), Some(1));
+    assert_eq!(forest.innermost_loop(8), None);
+    assert!(forest.is_header(4));
+}
+
+#[test]
+fn loops_of_compiled_programs() {
+    let examples = [
+        fib_test(10),
+        ackermann_test(2, 3),
+        even_odd_test(10),
+        map_fold_test(10),
+        counter_test(10),
+    ];
+
+    for e in &examples {
+        let flat = compile(e, &CompileOptions::default())
+            .expect("expected program")
+            .flat_program
+            .expect("expected flat program");
+        let ssa = convert_to_ssa(&flat);
+
+        for (flat_function, f) in flat.functions.iter().zip(&ssa.functions) {
+            let tree = DominatorTree::new(f);
+            for block_index in 0..f.blocks.len() {
+                assert!(tree.dominates(0, block_index));
+            }
+
+            // Every loop header of the flat function becomes the header of
+            // a natural loop.
+            let forest = LoopForest::new(f, &tree);
+            let headers = flat_function
+                .blocks
+                .iter()
+                .filter(|b| b.is_loop_header)
+                .count();
+            assert_eq!(forest.loops.len(), headers);
+
+            for l in &forest.loops {
+                for block_index in &l.blocks {
+                    assert!(tree.dominates(l.header, *block_index));
+                }
+                for latch in &l.latches {
+                    assert_eq!(f.blocks[*latch].terminator, jump(l.header));
+                }
+            }
+        }
+    }
+
+    // The tail recursion of fib_helper becomes a single loop.
+    let flat = compile(&fib_test(10), &CompileOptions::default())
+        .expect("expected program")
+        .flat_program
+        .expect("expected flat program");
+    let loops: usize = convert_to_ssa(&flat)
+        .functions
+        .iter()
+        .map(|f| LoopForest::new(f, &DominatorTree::new(f)).loops.len())
+        .sum();
+    assert_eq!(loops, 1);
+}
@@ -0,0 +1,174 @@
+use bailey::driver::{compile, CompileOptions, OptimizationLevel};
+use bailey::ir_let::interpreter::config::EvaluatorConfig;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::native::TypeTag;
+use bailey::lang::syntax::{BinOp, Constant, Expr, MAX_NESTING};
+use bailey::{eval_str, EvalOptions, Value};
+use std::sync::Arc;
+
+#[test]
+fn programs_are_evaluated_to_host_values() {
+    let source = "
+        ; The sum of 1 to 10, next to the pair it was computed from.
+        (let sum (fun sum (n acc) (if (== n 0) acc (sum (- n 1) (+ acc n))))
+          (tuple (sum 10 0) (tuple true) (fun id (x) x)))
+    ";
+    let value = eval_str(source, &EvalOptions::default()).expect("program should not fail");
+    assert_eq!(
+        value,
+        Value::tuple(vec![
+            Value::Int(55),
+            Value::tuple(vec![Value::Bool(true)]),
+            Value::Opaque(TypeTag::Closure),
+        ])
+    );
+    assert_eq!(value.to_string(), "(55, (true,), <closure>)");
+}
+
+#[test]
+fn every_optimization_level_gives_the_same_value() {
+    let source = "(let t (tuple 1 2) (let u (set t 0 5) (tuple (get t 0) (get t 1))))";
+    for flag in ["-O0", "-O1", "-O2", "--no-flat"] {
+        let mut options = EvalOptions::default();
+        assert!(options.compile.parse_flag(flag));
+        assert_eq!(
+            eval_str(source, &options).expect("program should not fail"),
+            Value::tuple(vec![Value::Int(5), Value::Int(2)])
+        );
+    }
+}
+
+#[test]
+fn natives_are_available_as_globals() {
+    let mut options = EvalOptions::default();
+    options.natives.register_hash();
+    let source = "(== (hash (tuple 1 2)) (hash (tuple 1 2)))";
+    assert_eq!(eval_str(source, &options).ok(), Some(Value::Bool(true)));
+}
+
+#[test]
+fn errors_of_every_stage_are_reported() {
+    let options = EvalOptions::default();
+    let error = |source: &str| {
+        eval_str(source, &options)
+            .expect_err("program should fail")
+            .to_string()
+    };
+
+    assert_eq!(error("(+ 1"), "missing `)`");
+    assert_eq!(
+        error("(missing 1)"),
+        RuntimeError::UnboundVariable("missing".to_owned()).to_string()
+    );
+    assert_eq!(
+        error("(get (tuple 1) 3)"),
+        RuntimeError::IndexOutOfRange { index: 3, size: 1 }.to_string()
+    );
+    assert_eq!(
+        error("(let t (tuple 1) (let u (set t 0 t) t))"),
+        "the result contains a cycle"
+    );
+}
+
+#[test]
+fn deeply_nested_programs_are_rejected() {
+    let options = EvalOptions::default();
+    let error = |source: &str| {
+        eval_str(source, &options)
+            .expect_err("program should fail")
+            .to_string()
+    };
+    let too_deep = format!("program nested more than {} levels deep", MAX_NESTING);
+
+    assert_eq!(error(&"(".repeat(100000)), too_deep);
+    assert_eq!(error(&"(+ 1 ".repeat(20000)), too_deep);
+    let source = format!("{}0{}", "(+ 1 ".repeat(20000), ")".repeat(20000));
+    assert_eq!(error(&source), too_deep);
+
+    let depth = MAX_NESTING - 1;
+    let source = format!("{}0{}", "(+ 1 ".repeat(depth), ")".repeat(depth));
+    assert_eq!(
+        eval_str(&source, &options).ok(),
+        Some(Value::Int(depth as i64))
+    );
+}
+
+#[test]
+fn deeply_nested_expressions_are_not_compiled() {
+    let mut e = Expr::Literal(Constant::Int { value: 0 });
+    for _ in 0..MAX_NESTING {
+        e = Expr::BinOp {
+            op: BinOp::Add,
+            lhs: Box::new(Expr::Literal(Constant::Int { value: 1 })),
+            rhs: Box::new(e),
+        };
+    }
+    assert_eq!(
+        compile(&e, &CompileOptions::default())
+            .err()
+            .map(|error| error.to_string()),
+        Some(format!(
+            "expression nested more than {} levels deep",
+            MAX_NESTING
+        ))
+    );
+}
+
+#[test]
+fn limits_of_the_evaluator_apply() {
+    let options = EvalOptions {
+        config: EvaluatorConfig {
+            max_call_depth: Some(10),
+            ..EvaluatorConfig::default()
+        },
+        compile: CompileOptions {
+            optimization_level: OptimizationLevel::O0,
+            ..CompileOptions::default()
+        },
+        ..EvalOptions::default()
+    };
+    let source = "(let deep (fun deep (n) (if (== n 0) 0 (+ 1 (deep (- n 1))))) (deep 100))";
+    assert_eq!(
+        eval_str(source, &options)
+            .expect_err("program should fail")
+            .to_string(),
+        RuntimeError::StackOverflow.to_string()
+    );
+}
+
+#[test]
+fn long_lists_are_converted() {
+    let source = "
+        (let build (fun build (n acc) (if (== n 0) acc (build (- n 1) (tuple n acc))))
+          (build 10000 0))
+    ";
+    let value = eval_str(source, &EvalOptions::default()).expect("program should not fail");
+    let text = value.to_string();
+    assert!(text.starts_with("(1, (2, (3, "));
+    assert!(text.ends_with(&format!("(10000, 0{}", ")".repeat(10000))));
+}
+
+#[test]
+fn shared_tuples_stay_shared() {
+    // Expanding every path would give 2^64 tuples.
+    let source = "
+        (let share (fun share (n acc) (if (== n 0) acc (share (- n 1) (tuple acc acc))))
+          (share 64 (tuple 1)))
+    ";
+    let mut value = eval_str(source, &EvalOptions::default()).expect("program should not fail");
+    for _ in 0..64 {
+        let Value::Tuple(fields) = &value else {
+            panic!("expected tuple");
+        };
+        let [left, right] = fields.as_slice() else {
+            panic!("expected pair");
+        };
+        match (left, right) {
+            (Value::Tuple(left), Value::Tuple(right)) => assert!(Arc::ptr_eq(left, right)),
+            _ => panic!("expected pair of tuples"),
+        }
+        let next = left.clone();
+        value = next;
+    }
+    assert_eq!(value, Value::tuple(vec![Value::Int(1)]));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::escape::analyze_escapes;
+use bailey::lang::syntax::Expr;
+use common::compile_at;
+use std::collections::BTreeSet;
+
+fn non_escaping(e: &Expr) -> BTreeSet<&'static str> {
+    let info = analyze_escapes(&compile_at(e, "-O0"));
+    info.non_escaping
+        .iter()
+        .map(|name| name.source_name())
+        .collect()
+}
+
+#[test]
+fn values_that_are_only_read_do_not_escape() {
+    let e = expr! {
+        let t = (1, 2);
+        let f = fun(x) { x + 1 };
+        let u = set t[0] = 3;
+        t[0] + t[1]
+    };
+    assert_eq!(non_escaping(&e), BTreeSet::from(["t", "f"]));
+}
+
+#[test]
+fn returned_values_escape() {
+    let e = expr! {
+        let t = (1, 2);
+        let u = t;
+        u
+    };
+    assert_eq!(non_escaping(&e), BTreeSet::new());
+}
+
+#[test]
+fn stored_captured_and_passed_values_escape() {
+    let e = expr! {
+        let stored = (1,);
+        let captured = (2,);
+        let passed = (3,);
+        let kept = (4,);
+        let outer = (stored, 0);
+        let f = fun(x) { captured[0] + x };
+        let g = fun(y) { y[0] };
+        let u = set kept[0] = passed;
+        f(g(passed)) + outer[1] + kept[0][0]
+    };
+    // The closures themselves are only called, and kept is only read and
+    // written.
+    assert_eq!(
+        non_escaping(&e),
+        BTreeSet::from(["outer", "f", "g", "kept"])
+    );
+}
+
+#[test]
+fn both_branches_of_an_if_are_followed() {
+    let e = expr! {
+        let a = (1,);
+        let b = (2,);
+        let c = if true { a } else { b };
+        c
+    };
+    assert_eq!(non_escaping(&e), BTreeSet::new());
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::{Heap, MarkSweepHeap, RefCountHeap};
+use bailey::ir_let::interpreter::heap_value::{HeapValue, HostValue, Value};
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use common::compile_with_natives;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Stands for a resource of the embedder, such as a file handle.
+struct File(i64);
+
+thread_local! {
+    // The files whose finalizer has run, in order. Tests run on their own
+    // threads, so they do not see each other's files.
+    static CLOSED: RefCell<Vec<i64>> = const { RefCell::new(Vec::new()) };
+}
+
+fn closed() -> Vec<i64> {
+    CLOSED.with(|closed| closed.borrow().clone())
+}
+
+fn close(file: File) {
+    CLOSED.with(|closed| closed.borrow_mut().push(file.0));
+}
+
+fn open(heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    let file = File(args[0].check_int()?);
+    let address = heap.alloc(HeapValue::Host(HostValue::with_finalizer(file, close)))?;
+    Ok(Value::Pointer(address))
+}
+
+fn closed_count(_heap: &mut dyn Heap, _args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Int(closed().len() as i64))
+}
+
+fn evaluator<H: Heap>(e: &Expr, heap: H) -> ProgramEvaluator<H> {
+    let mut natives = Natives::new();
+    natives.register("open", 1, open);
+    natives.register("closed", 1, closed_count);
+    let mut evaluator = ProgramEvaluator::with_heap(compile_with_natives(e, &natives), heap);
+    evaluator.bind_natives(&natives).expect("expected natives");
+    evaluator
+}
+
+#[test]
+fn unreferenced_host_values_are_finalized_when_freed() {
+    let e = expr! {
+        let use_file = fun(n) { let f = open(n); n };
+        let a = use_file(1);
+        let b = use_file(2);
+        closed(0)
+    };
+
+    let mut evaluator = evaluator(&e, RefCountHeap::new());
+    assert_eq!(evaluator.run(), Ok(Value::Int(2)));
+    assert_eq!(closed(), [1, 2]);
+}
+
+#[test]
+fn live_host_values_are_finalized_when_the_heap_is_dropped() {
+    let e = expr! { open(3) };
+
+    let mut evaluator = evaluator(&e, RefCountHeap::new());
+    let result = evaluator.run().expect("program should not fail");
+    assert!(evaluator
+        .heap()
+        .deref(
+            result
+                .check_pointer("host value")
+                .expect("expected pointer")
+        )
+        .check_host::<File>()
+        .is_ok());
+    assert!(closed().is_empty());
+
+    drop(evaluator);
+    assert_eq!(closed(), [3]);
+}
+
+#[test]
+fn collected_host_values_are_finalized() {
+    let e = expr! {
+        let go = fun(n) {
+            if n == 0 { closed(0) } else { let f = open(n); go(n - 1) }
+        };
+        go(3000)
+    };
+
+    // Each collection finalizes the files that were dropped since the last
+    // one.
+    let mut evaluator = evaluator(&e, MarkSweepHeap::new());
+    let Ok(Value::Int(closed_during_run)) = evaluator.run() else {
+        panic!("program should return an integer");
+    };
+    assert!(closed_during_run > 0);
+
+    drop(evaluator);
+    let mut files = closed();
+    files.sort();
+    assert_eq!(files, (1..=3000).collect::<Vec<_>>());
+}
+
+#[test]
+fn host_globals_can_have_finalizers() {
+    let e = expr! { (log, 1)[1] };
+    let options = CompileOptions {
+        globals: vec!["log".to_owned()],
+        ..CompileOptions::default()
+    };
+    let compiled = compile(&e, &options).expect("expected program");
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+
+    let mut host_values = HashMap::new();
+    host_values.insert("log".to_owned(), HostValue::with_finalizer(File(4), close));
+    evaluator
+        .bind_globals(&Natives::new(), host_values)
+        .expect("expected globals");
+
+    // The globals are released when the toplevel function returns.
+    assert_eq!(evaluator.run(), Ok(Value::Int(1)));
+    assert_eq!(closed(), [4]);
+}
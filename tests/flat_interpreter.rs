@@ -0,0 +1,126 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_flat::interpreter::{Word, WordInterpreter, WordStats};
+use bailey::ir_flat::syntax::Program;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::{aliasing_test, counter_test};
+
+fn compile_flat(e: &Expr, level: &str) -> Program {
+    let mut options = CompileOptions::default();
+    options.parse_flag(level);
+    compile(e, &options)
+        .expect("expected program")
+        .flat_program
+        .expect("expected flat program")
+}
+
+// Runs the program and releases the result, which should leave the heap
+// empty.
+fn run(e: &Expr, level: &str) -> (String, WordStats) {
+    let program = compile_flat(e, level);
+    let mut interpreter = WordInterpreter::new(&program);
+    let result = interpreter.run().expect("program should not fail");
+    let output = interpreter.render(result);
+    interpreter.release(result);
+    assert!(
+        interpreter.heap().is_empty(),
+        "objects left after {}",
+        output
+    );
+    (output, interpreter.stats().clone())
+}
+
+#[test]
+fn words_are_tagged() {
+    for value in [0, 1, -1, 42, i64::MAX >> 1, i64::MIN >> 1] {
+        let word = Word::small_int(value).expect("expected small integer");
+        assert_eq!(word.as_small_int(), Some(value));
+        assert_eq!(word.as_bool(), None);
+        assert_eq!(word.as_pointer(), None);
+    }
+    assert_eq!(Word::small_int(i64::MAX), None);
+    assert_eq!(Word::small_int(i64::MIN), None);
+
+    for value in [false, true] {
+        let word = Word::bool(value);
+        assert_eq!(word.as_bool(), Some(value));
+        assert_eq!(word.as_small_int(), None);
+        assert_eq!(word.as_pointer(), None);
+    }
+}
+
+#[test]
+fn results_agree_with_the_let_interpreter() {
+    let examples = [
+        fib_test(15),
+        ackermann_test(2, 3),
+        even_odd_test(11),
+        map_fold_test(20),
+        counter_test(10),
+        aliasing_test(),
+        closure_capture_test(5),
+    ];
+
+    for e in &examples {
+        for level in ["-O0", "-O2"] {
+            let mut options = CompileOptions::default();
+            options.parse_flag(level);
+            let compiled = compile(e, &options).expect("expected program");
+            let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+            let value = evaluator.run().expect("program should not fail");
+            let expected = render(value, evaluator.heap());
+
+            assert_eq!(run(e, level).0, expected);
+        }
+    }
+}
+
+#[test]
+fn arithmetic_does_not_count_references() {
+    let count = |limit: i64| {
+        expr! {
+            let count = fun(i, total, limit) {
+                if i == limit { total } else { count(i + 1, total + i, limit) }
+            };
+            count(0, 0, #limit)
+        }
+    };
+
+    // The loop only does arithmetic on integers, so the number of reference
+    // count operations does not depend on the number of iterations.
+    let (output, short) = run(&count(10), "-O2");
+    assert_eq!(output, "45");
+    let (output, long) = run(&count(10000), "-O2");
+    assert_eq!(output, "49995000");
+
+    assert!(long.instructions > short.instructions);
+    assert_eq!(long.refcount_operations, short.refcount_operations);
+    assert_eq!(long.allocations, short.allocations);
+    assert_eq!(long.boxed_ints, 0);
+}
+
+#[test]
+fn large_integers_are_boxed() {
+    // The arguments keep the additions from being folded.
+    let e = expr! {
+        let add = fun(a, b) { a + b };
+        let big = add(4611686018427387903, 1);
+        let wrapped = add(9223372036854775807, 1);
+        let back = add(big, 0 - 1);
+        (big, wrapped, back, big == back + 1)
+    };
+
+    let (output, stats) = run(&e, "-O0");
+    assert_eq!(
+        output,
+        "(4611686018427387904, -9223372036854775808, 4611686018427387903, true)"
+    );
+    assert!(stats.boxed_ints > 0);
+}
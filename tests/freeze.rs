@@ -0,0 +1,81 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::lang::syntax::Expr;
+use common::run_with_natives;
+
+fn run(e: &Expr) -> Result<String, RuntimeError> {
+    let mut natives = Natives::new();
+    natives.register_freeze();
+    natives.register_copy();
+    run_with_natives(e, &natives)
+}
+
+#[test]
+fn setting_a_field_of_a_frozen_tuple_fails() {
+    let e = expr! {
+        let t = freeze((1, 2));
+        let u = set t[0] = 3;
+        t
+    };
+    assert_eq!(run(&e), Err(RuntimeError::FrozenTuple));
+}
+
+#[test]
+fn frozen_tuples_can_still_be_read() {
+    let e = expr! {
+        let t = (1, 2);
+        let u = set t[0] = 3;
+        let f = freeze(t);
+        (f[0] + f[1], t)
+    };
+    assert_eq!(run(&e).as_deref(), Ok("(5, (3, 2))"));
+}
+
+#[test]
+fn only_the_tuple_itself_is_frozen() {
+    let e = expr! {
+        let inner = (1,);
+        let t = freeze((inner, 2));
+        let u = set inner[0] = 5;
+        t
+    };
+    assert_eq!(run(&e).as_deref(), Ok("((5,), 2)"));
+}
+
+#[test]
+fn copies_of_frozen_tuples_are_not_frozen() {
+    let e = expr! {
+        let t = freeze((1, 2));
+        let c = copy(t);
+        let u = set c[0] = 3;
+        (t, c)
+    };
+    assert_eq!(run(&e).as_deref(), Ok("((1, 2), (3, 2))"));
+}
+
+#[test]
+fn frozen_tuples_are_not_reused_in_place() {
+    // The tail call releases the frame of make, so the argument of bump
+    // holds the only reference to the frozen tuple, and its cell would
+    // otherwise be reused for the result.
+    let e = expr! {
+        let bump = fun(p) { (p[0] + 1, p[1]) };
+        let make = fun(n) { bump(freeze((n, 2))) };
+        let b = make(1);
+        let u = set b[1] = 7;
+        b
+    };
+    assert_eq!(run(&e).as_deref(), Ok("(2, 7)"));
+}
+
+#[test]
+fn only_tuples_can_be_frozen() {
+    let e = expr! { freeze(1) };
+    assert_eq!(
+        run(&e),
+        Err(RuntimeError::TypeMismatch { expected: "tuple" })
+    );
+}
@@ -0,0 +1,136 @@
+use bailey::bytecode::compiler::compile_program;
+use bailey::bytecode::fusion::{fuse_superinstructions, Fusion};
+use bailey::bytecode::syntax::{Function, Instruction, Program};
+use bailey::bytecode::vm::{Value, VirtualMachine};
+use bailey::driver::{compile, CompileOptions};
+use bailey::ir_flat::syntax::{LocalReference, Reference};
+use bailey::lang::syntax::{BinOp, Expr};
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::{list_sum_test, map_fold_test};
+use bailey::lang::test::mutation::counter_test;
+
+fn unfused_bytecode(e: &Expr) -> Program {
+    let flat_program = compile(e, &CompileOptions::default())
+        .expect("expected program")
+        .flat_program
+        .expect("expected flat program");
+    compile_program(&flat_program)
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::Int(value) => value.to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Tuple(fields) => {
+            let fields: Vec<String> = fields.borrow().iter().map(render).collect();
+            format!("({})", fields.join(", "))
+        }
+        Value::Closure(_) => "<closure>".to_owned(),
+    }
+}
+
+#[test]
+fn fused_programs_give_the_same_results() {
+    let programs = [
+        fib_test(20),
+        ackermann_test(2, 3),
+        list_sum_test(100),
+        map_fold_test(100),
+        even_odd_test(11),
+        closure_capture_test(10),
+        counter_test(100),
+    ];
+
+    for e in &programs {
+        let unfused = unfused_bytecode(e);
+        let mut fused = unfused.clone();
+        let stats = fuse_superinstructions(&mut fused);
+
+        assert!(stats.total() > 0);
+        let unfused_size: usize = unfused.functions.iter().map(|f| f.code.len()).sum();
+        let fused_size: usize = fused.functions.iter().map(|f| f.code.len()).sum();
+        assert!(fused_size < unfused_size);
+
+        let expected = render(
+            &VirtualMachine::new(unfused)
+                .run()
+                .expect("program should not fail"),
+        );
+        let mut vm = VirtualMachine::new(fused);
+        assert_eq!(
+            render(&vm.run().expect("program should not fail")),
+            expected
+        );
+        assert!(vm.fusion_stats().total() > 0);
+    }
+}
+
+// The loop of fib compares its counter with zero on every iteration, so the
+// comparison and the branch are fused.
+#[test]
+fn fusions_are_ranked_by_how_often_they_fire() {
+    let mut program = unfused_bytecode(&fib_test(30));
+    let fused = fuse_superinstructions(&mut program);
+    assert!(fused.count(Fusion::CompareBranch) > 0);
+
+    let mut vm = VirtualMachine::new(program);
+    vm.run().expect("program should not fail");
+    let executed = vm.fusion_stats();
+    assert_eq!(executed.count(Fusion::CompareBranch), 31);
+
+    let ranking = executed.ranking();
+    assert!(ranking.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    assert!(executed.to_string().contains("compare + branch: 31"));
+}
+
+fn function(code: Vec<Instruction>) -> Program {
+    Program {
+        functions: vec![Function {
+            name: "toplevel".to_owned(),
+            args_size: 0,
+            closure_env_size: 0,
+            frame_size: 1,
+            code,
+        }],
+    }
+}
+
+// A jump into the middle of a sequence keeps the sequence apart, and the jumps
+// over fused sequences are moved.
+#[test]
+fn jump_targets_are_preserved() {
+    let local = LocalReference(0);
+    let mut program = function(vec![
+        Instruction::PushInt(1),
+        Instruction::Store(local),
+        Instruction::Load(Reference::Local(local)),
+        Instruction::PushInt(2),
+        Instruction::Jump(6),
+        Instruction::Load(Reference::Local(local)),
+        Instruction::BinOp(BinOp::Add),
+        Instruction::Return,
+    ]);
+    let stats = fuse_superinstructions(&mut program);
+
+    assert_eq!(stats.count(Fusion::LoadBinOp), 0);
+    assert_eq!(stats.count(Fusion::StoreKeep), 1);
+    assert_eq!(
+        program.functions[0].code,
+        [
+            Instruction::PushInt(1),
+            Instruction::StoreKeep(local),
+            Instruction::PushInt(2),
+            Instruction::Jump(5),
+            Instruction::Load(Reference::Local(local)),
+            Instruction::BinOp(BinOp::Add),
+            Instruction::Return,
+        ]
+    );
+    assert!(matches!(
+        VirtualMachine::new(program).run(),
+        Ok(Value::Int(3))
+    ));
+}
@@ -0,0 +1,130 @@
+// Runs every program in tests/golden through both interpreters and compares the
+// result with the expected output next to it. Setting UPDATE_GOLDEN=1 writes
+// the current output to the expected files instead. The programs are written
+// as s-expressions, see `lang::parser`.
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_cps::compiler::cps_convert;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::parser::parse_expr;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::{list_sum_test, map_fold_test};
+use bailey::lang::test::mutation::{aliasing_test, counter_test};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The result of the program, or the error it failed with, which has to be the
+// same for every optimization level and both interpreters.
+fn run(e: &Expr) -> Result<String, String> {
+    let mut outputs = Vec::new();
+    for flag in ["-O0", "-O1", "-O2"] {
+        let mut options = CompileOptions::default();
+        options.parse_flag(flag);
+        let compiled = compile(e, &options).map_err(|error| error.to_string())?;
+        let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+        let output = match evaluator.run() {
+            Ok(value) => render(value, evaluator.heap()),
+            Err(error) => format!("error: {}", error),
+        };
+        outputs.push((format!("let interpreter at {}", flag), output));
+    }
+
+    let cps_program = cps_convert(e).map_err(|error| error.to_string())?;
+    let output = match bailey::ir_cps::interpreter::eval(&cps_program) {
+        Ok(value) => value.to_string(),
+        Err(error) => format!("error: {}", error),
+    };
+    outputs.push(("CPS interpreter".to_owned(), output));
+
+    let (_, expected) = &outputs[0];
+    for (engine, output) in &outputs[1..] {
+        if output != expected {
+            return Err(format!(
+                "{} printed `{}` instead of `{}`",
+                engine, output, expected
+            ));
+        }
+    }
+    Ok(outputs.swap_remove(0).1)
+}
+
+fn golden_programs() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut programs: Vec<PathBuf> = fs::read_dir(directory)
+        .expect("missing tests/golden")
+        .map(|entry| entry.expect("failed to read tests/golden").path())
+        .filter(|path| path.extension().is_some_and(|e| e == "bly"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+#[test]
+fn golden_programs_match_expected_output() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    for path in golden_programs() {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let text = fs::read_to_string(&path).expect("failed to read program");
+        let output = match parse_expr(&text)
+            .map_err(|error| error.to_string())
+            .and_then(|e| run(&e))
+        {
+            Ok(output) => output + "\n",
+            Err(error) => {
+                failures.push(format!("{}: {}", name, error));
+                continue;
+            }
+        };
+
+        let expected_path = path.with_extension("expected");
+        if update {
+            fs::write(&expected_path, &output).expect("failed to write expected output");
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == output => {}
+            Ok(expected) => failures.push(format!(
+                "{}: printed `{}` instead of `{}`",
+                name,
+                output.trim_end(),
+                expected.trim_end()
+            )),
+            Err(_) => failures.push(format!("{}: missing expected output", name)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "golden tests failed (run with UPDATE_GOLDEN=1 to accept the new output):\n{}",
+        failures.join("\n")
+    );
+}
+
+// The example programs in lang::test, which are built in Rust instead of read
+// from files.
+#[test]
+fn example_programs_give_expected_results() {
+    let programs = [
+        ("fib", fib_test(20), "6765"),
+        ("ackermann", ackermann_test(2, 3), "9"),
+        ("list_sum", list_sum_test(100), "5050"),
+        ("map_fold", map_fold_test(100), "10100"),
+        ("even", even_odd_test(10), "true"),
+        ("odd", even_odd_test(7), "false"),
+        ("closure_capture", closure_capture_test(10), "225"),
+        ("counter", counter_test(100), "5050"),
+        ("aliasing", aliasing_test(), "20"),
+        ("let_tuple", expr! { let (a, b) = (7, 2); a - b }, "5"),
+    ];
+
+    for (name, e, expected) in programs {
+        assert_eq!(run(&e).as_deref(), Ok(expected), "{}", name);
+    }
+}
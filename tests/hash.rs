@@ -0,0 +1,91 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::lang::syntax::Expr;
+use common::run_with_natives;
+
+fn run(e: &Expr) -> Result<String, RuntimeError> {
+    let mut natives = Natives::new();
+    natives.register_hash();
+    run_with_natives(e, &natives)
+}
+
+#[test]
+fn equal_structures_hash_the_same() {
+    let e = expr! {
+        let s = (1,);
+        let shared = (s, s);
+        let unshared = ((1,), (1,));
+        (
+            hash((1, (2, true))) == hash((1, (2, true))),
+            hash(shared) == hash(unshared),
+            hash(42) == hash(42)
+        )
+    };
+    assert_eq!(run(&e).as_deref(), Ok("(true, true, true)"));
+}
+
+#[test]
+fn different_structures_hash_differently() {
+    let e = expr! {
+        (
+            hash(1) == hash(true),
+            hash(0) == hash(false),
+            hash((1, 2)) == hash((2, 1)),
+            hash((1,)) == hash(1),
+            hash(((1, 2), 3)) == hash((1, (2, 3))),
+            hash(()) == hash((0,))
+        )
+    };
+    assert_eq!(
+        run(&e).as_deref(),
+        Ok("(false, false, false, false, false, false)")
+    );
+}
+
+#[test]
+fn hashes_are_the_same_in_every_run() {
+    let e = expr! { (hash((1, (true, 3))), hash(0 - 7)) };
+    let first = run(&e).expect("program should not fail");
+    assert_eq!(run(&e).as_deref(), Ok(first.as_str()));
+}
+
+#[test]
+fn cycles_and_shared_structures_are_hashed_quickly() {
+    // Visiting every path through the doubling structure would take 2^60
+    // steps.
+    let e = expr! {
+        let t = (1, 0);
+        let u = set t[1] = t;
+        let grow = fun(n, s) { if n == 0 { s } else { grow(n - 1, (s, s)) } };
+        let h = hash((t, grow(60, (1,))));
+        let v = set t[1] = 0;
+        h == h
+    };
+    assert_eq!(run(&e).as_deref(), Ok("true"));
+}
+
+#[test]
+fn long_lists_are_hashed() {
+    let e = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        hash(build(100000, 0)) == hash(build(100000, 0))
+    };
+    assert_eq!(run(&e).as_deref(), Ok("true"));
+}
+
+#[test]
+fn closures_cannot_be_hashed() {
+    let e = expr! {
+        let f = fun(x) { x };
+        hash((1, f))
+    };
+    assert_eq!(
+        run(&e),
+        Err(RuntimeError::TypeMismatch {
+            expected: "int, bool or tuple"
+        })
+    );
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Definition, Function, Instruction, Program, Simple, Step};
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::passes::hoist_invariants::hoist_invariants;
+use bailey::ir_let::passes::manager::PassManager;
+use bailey::ir_let::passes::tail_recursion::TailRecursionToLoop;
+use bailey::ir_let::resolve::resolve_variables;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::{list_sum_test, map_fold_test};
+use bailey::lang::test::mutation::counter_test;
+use common::compile_at;
+
+fn is_in_loop(function: &Function, block_index: usize) -> bool {
+    let mut current = Some(block_index);
+    while let Some(index) = current {
+        if function.blocks[index].is_loop_header {
+            return true;
+        }
+        current = function.blocks[index].parent_block_index;
+    }
+    false
+}
+
+// The literals that are evaluated in every iteration of a loop.
+fn literals_in_loops(program: &Program) -> usize {
+    let mut count = 0;
+    for function in &program.functions {
+        for (block_index, block) in function.blocks.iter().enumerate() {
+            if !is_in_loop(function, block_index) {
+                continue;
+            }
+            count += block
+                .instructions
+                .iter()
+                .filter(|instruction| {
+                    matches!(
+                        instruction,
+                        Instruction::Assignment(assignment)
+                            if matches!(
+                                assignment.definition,
+                                Definition::Step(Step::Simple(Simple::Literal(_)))
+                            )
+                    )
+                })
+                .count();
+        }
+    }
+    count
+}
+
+fn run(program: Program) -> String {
+    let mut evaluator = ProgramEvaluator::new(program);
+    let value = evaluator.run().expect("program should not fail");
+    render(value, evaluator.heap())
+}
+
+#[test]
+fn literals_are_hoisted_out_of_loops() {
+    // fib_helper compares its counter with 0 and subtracts 1 from it in every
+    // iteration.
+    let e = fib_test(20);
+    let mut program = compile_at(&e, "-O0");
+    let mut manager = PassManager::new();
+    manager.add_pass(TailRecursionToLoop);
+    manager.run(&mut program);
+    assert_eq!(literals_in_loops(&program), 2);
+
+    hoist_invariants(&mut program);
+    resolve_variables(&mut program);
+    assert_eq!(literals_in_loops(&program), 0);
+    assert_eq!(run(program), run(compile_at(&e, "-O0")));
+
+    // The loop header of fib_helper is now entered from a block that holds
+    // the literals.
+    let program = compile_at(&e, "-O2");
+    let fib_helper = &program.functions[1];
+    assert!(!fib_helper.blocks[0].is_loop_header);
+    assert!(fib_helper.blocks[1].is_loop_header);
+    assert_eq!(fib_helper.blocks[1].parent_block_index, Some(0));
+}
+
+#[test]
+fn hoisting_preserves_results() {
+    let programs = [
+        fib_test(20),
+        ackermann_test(2, 3),
+        even_odd_test(7),
+        list_sum_test(100),
+        map_fold_test(50),
+        counter_test(50),
+    ];
+
+    for e in programs {
+        let expected = run(compile_at(&e, "-O1"));
+        let hoisted = compile_at(&e, "-O2");
+        assert_eq!(literals_in_loops(&hoisted), 0);
+        assert_eq!(run(hoisted.clone()), expected);
+
+        // The preheaders survive the text format.
+        let mut parsed = parse_program(&hoisted.to_string()).expect("expected program text");
+        resolve_variables(&mut parsed);
+        assert_eq!(run(parsed), expected);
+    }
+}
@@ -0,0 +1,77 @@
+use bailey::bytecode::vm::{Value, VirtualMachine};
+use bailey::driver::{compile_bytecode, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::list::list_sum_test;
+
+fn run(e: &Expr) -> VirtualMachine {
+    let bytecode = compile_bytecode(e, &CompileOptions::default()).expect("expected program");
+    let mut vm = VirtualMachine::new(bytecode);
+    vm.run().expect("program should not fail");
+    vm
+}
+
+// The recursive calls of ackermann always go to the same function, so only
+// the first call at every call site misses.
+#[test]
+fn monomorphic_calls_hit_the_cache() {
+    let vm = run(&ackermann_test(2, 20));
+    let stats = vm.cache_stats();
+
+    assert!(stats.call_misses > 0);
+    assert!(stats.call_hits > 10 * stats.call_misses, "{}", stats);
+    assert!(stats.call_hit_rate() > 0.9);
+}
+
+#[test]
+fn field_accesses_on_tuples_of_the_same_size_hit_the_cache() {
+    let vm = run(&list_sum_test(100));
+    let stats = vm.cache_stats();
+
+    assert!(stats.field_hits > stats.field_misses, "{}", stats);
+}
+
+// A call site that alternates between two functions misses every time, and
+// still calls the right one.
+#[test]
+fn polymorphic_calls_miss_the_cache() {
+    let e = expr! {
+        let apply = fun apply(f, x) { f(x) };
+        let inc = fun inc(x) { x + 1 };
+        let dec = fun dec(x) { x - 1 };
+        (apply(inc, 1), apply(dec, 1), apply(inc, 1), apply(dec, 1))
+    };
+    let bytecode = compile_bytecode(&e, &CompileOptions::default()).expect("expected program");
+    let mut vm = VirtualMachine::new(bytecode);
+    let Value::Tuple(fields) = vm.run().expect("program should not fail") else {
+        panic!("expected tuple");
+    };
+    let fields: Vec<i64> = fields
+        .borrow()
+        .iter()
+        .map(|field| match field {
+            Value::Int(value) => *value,
+            _ => panic!("expected int"),
+        })
+        .collect();
+
+    assert_eq!(fields, [2, 0, 2, 0]);
+    assert!(vm.cache_stats().call_misses >= 4, "{}", vm.cache_stats());
+}
+
+// The cache remembers the index as well, so reading another field of a tuple
+// of the same size misses, and an index out of range is still caught.
+#[test]
+fn cached_field_accesses_check_the_index() {
+    let e = expr! {
+        let get = fun get(t, i) { t[i] };
+        get((1, 2), 1) + get((1, 2), 0) + get((1, 2), 2)
+    };
+    let bytecode = compile_bytecode(&e, &CompileOptions::default()).expect("expected program");
+    assert_eq!(
+        VirtualMachine::new(bytecode).run().map(|_| ()),
+        Err(RuntimeError::IndexOutOfRange { index: 2, size: 2 })
+    );
+}
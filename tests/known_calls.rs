@@ -0,0 +1,59 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Control, Definition, Instruction, Program, Step};
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::resolve::resolve_variables;
+
+fn direct_calls(program: &Program) -> usize {
+    program
+        .functions
+        .iter()
+        .flat_map(|function| &function.blocks)
+        .flat_map(|block| &block.instructions)
+        .filter(|instruction| {
+            matches!(
+                instruction,
+                Instruction::Assignment(assignment)
+                    if matches!(
+                        assignment.definition,
+                        Definition::Step(Step::Control(Control::CallDirect { .. }))
+                    )
+            )
+        })
+        .count()
+}
+
+#[test]
+fn calls_of_local_closures_are_direct() {
+    // The calls of add and apply become direct. The call of k cannot, since
+    // its closure is not allocated in the same function.
+    let e = expr! {
+        let x = (10, 20);
+        let add = fun(m) { m + x[1] };
+        let apply = fun(k) { k(1) };
+        let y = add(x[0]);
+        apply(add) + y
+    };
+
+    let mut options = CompileOptions::default();
+    options.parse_flag("-O1");
+    let unoptimized = compile(&e, &options).expect("expected program");
+    assert_eq!(direct_calls(&unoptimized.let_program), 0);
+
+    options.parse_flag("-O2");
+    let optimized = compile(&e, &options).expect("expected program");
+    assert_eq!(direct_calls(&optimized.let_program), 2);
+
+    // The text format keeps direct calls.
+    let mut parsed =
+        parse_program(&optimized.let_program.to_string()).expect("expected program text");
+    resolve_variables(&mut parsed);
+    assert_eq!(direct_calls(&parsed), 2);
+
+    for program in [unoptimized.let_program, optimized.let_program, parsed] {
+        let mut evaluator = ProgramEvaluator::new(program);
+        assert_eq!(evaluator.run(), Ok(Value::Int(51)));
+    }
+}
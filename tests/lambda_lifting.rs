@@ -0,0 +1,34 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::heap::Heap;
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+
+#[test]
+fn closures_without_free_variables_are_allocated_once() {
+    let e = expr! {
+        let count = fun(n, total) {
+            if n == 0 {
+                total
+            } else {
+                let inc = fun(x) { x + 1 };
+                count(n - 1, inc(total))
+            }
+        };
+        count(100, 0)
+    };
+
+    let mut closure_allocations = Vec::new();
+    for flag in ["-O1", "-O2"] {
+        let mut options = CompileOptions::default();
+        options.parse_flag(flag);
+        let compiled = compile(&e, &options).expect("expected program");
+        let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+
+        assert_eq!(evaluator.run(), Ok(Value::Int(100)));
+        assert!(evaluator.heap().is_empty());
+        closure_allocations.push(evaluator.heap().stats().closure_allocations);
+    }
+
+    assert_eq!(closure_allocations, [101, 2]);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use bailey::ir_flat::compiler::compile_program;
+use bailey::ir_flat::liveness::{analyze_function, FunctionLiveness, LiveSet};
+use bailey::ir_flat::syntax::LocalReference;
+use bailey::lang::test::fib::fib_test;
+use common::compile_at;
+
+fn slots(set: &LiveSet) -> Vec<usize> {
+    let mut slots: Vec<usize> = set.iter().map(|local| local.0).collect();
+    slots.sort();
+    slots
+}
+
+// The live locals before and after every instruction of the block.
+fn block_liveness(
+    liveness: &FunctionLiveness,
+    block_index: usize,
+) -> Vec<(Vec<usize>, Vec<usize>)> {
+    let block = &liveness.blocks[block_index];
+    block
+        .live_before
+        .iter()
+        .zip(&block.live_after)
+        .map(|(before, after)| (slots(before), slots(after)))
+        .collect()
+}
+
+#[test]
+fn locals_are_live_until_their_last_use() {
+    // At -O2 the helper is a loop: block 1 is its header, which branches to
+    // the result in block 2 or to block 3, which jumps back to the header.
+    //
+    // block 0: local0 = 0; local1 = 1; local2 = true;
+    //          local3 = if local2 then block 1 else block 4; return local3
+    // block 1: local4 = arg0 == local0; local5 = if local4 then block 2 else block 3;
+    //          local3 = local5; goto block 0
+    // block 2: local5 = arg2; goto block 1
+    // block 3: local6 = arg0 - local1; local7 = arg1 + arg2;
+    //          local8 = jump block 1(local6, local7, arg1); local5 = local8; goto block 1
+    // block 4: local3 = local2; goto block 0
+    let flat = compile_program(&compile_at(&fib_test(10), "-O2"));
+    let liveness = analyze_function(&flat.functions[1]);
+
+    assert_eq!(
+        block_liveness(&liveness, 0),
+        [
+            (vec![], vec![0]),
+            (vec![0], vec![0, 1]),
+            (vec![0, 1], vec![0, 1, 2]),
+            (vec![0, 1, 2], vec![3]),
+            (vec![3], vec![]),
+        ]
+    );
+    // The locals of the function body are assigned before the loop header is
+    // entered, so they stay live across the jump back to it.
+    assert_eq!(
+        block_liveness(&liveness, 1),
+        [
+            (vec![0, 1, 2], vec![0, 1, 2, 4]),
+            (vec![0, 1, 2, 4], vec![5]),
+            (vec![5], vec![3]),
+            (vec![3], vec![3]),
+        ]
+    );
+    assert_eq!(
+        block_liveness(&liveness, 2),
+        [(vec![], vec![5]), (vec![5], vec![5])]
+    );
+    assert_eq!(
+        block_liveness(&liveness, 3),
+        [
+            (vec![0, 1, 2], vec![0, 1, 2, 6]),
+            (vec![0, 1, 2, 6], vec![0, 1, 2, 6, 7]),
+            (vec![0, 1, 2, 6, 7], vec![8]),
+            (vec![8], vec![5]),
+            (vec![5], vec![5]),
+        ]
+    );
+    assert_eq!(
+        block_liveness(&liveness, 4),
+        [(vec![2], vec![3]), (vec![3], vec![3])]
+    );
+
+    // The comparison is the last use of local4.
+    assert!(liveness.is_live_after(1, 0, LocalReference(4)));
+    assert!(!liveness.is_live_after(1, 1, LocalReference(4)));
+}
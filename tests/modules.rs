@@ -0,0 +1,195 @@
+use bailey::bytecode::compiler::compile_program;
+use bailey::bytecode::vm::{Value as VmValue, VirtualMachine};
+use bailey::driver::{compile_modules, compile_object, CompileOptions};
+use bailey::expr;
+use bailey::ir_flat::encoding::{decode, decode_object, encode_object};
+use bailey::ir_flat::object::{link_objects, ObjectFile};
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::resolve::resolve_variables;
+use bailey::lang::syntax::{Import, Module};
+use std::collections::HashMap;
+
+fn import(module: &str, alias: &str) -> Import {
+    Import {
+        module: module.to_owned(),
+        alias: alias.to_owned(),
+    }
+}
+
+fn modules() -> Vec<Module> {
+    let math = Module {
+        name: "math".to_owned(),
+        imports: vec![],
+        definitions: vec![
+            ("ten".to_owned(), expr! { 10 }),
+            ("double".to_owned(), expr! { fun double(x) { x + x } }),
+            ("twenty".to_owned(), expr! { double(ten) }),
+        ],
+        body: None,
+    };
+    let pairs = Module {
+        name: "pairs".to_owned(),
+        imports: vec![import("math", "m")],
+        definitions: vec![(
+            "swap_double".to_owned(),
+            expr! { fun swap_double(p) { (m.double(p[1]), m.double(p[0])) } },
+        )],
+        body: None,
+    };
+    // The main module refers to math by another alias than pairs does.
+    let main = Module {
+        name: "main".to_owned(),
+        imports: vec![import("pairs", "p"), import("math", "arith")],
+        definitions: vec![("one".to_owned(), expr! { 1 })],
+        body: Some(expr! {
+            let r = p.swap_double((one, 2));
+            r[0] + r[1] + arith.twenty
+        }),
+    };
+    vec![math, pairs, main]
+}
+
+fn link_error(modules: &[Module], main: &str) -> String {
+    match compile_modules(modules, main, &CompileOptions::default()) {
+        Ok(_) => panic!("expected the program to be rejected"),
+        Err(error) => error.to_string(),
+    }
+}
+
+#[test]
+fn linked_modules_run_on_every_engine() {
+    for flag in ["-O0", "-O1", "-O2"] {
+        let mut options = CompileOptions::default();
+        options.parse_flag(flag);
+        let compiled = compile_modules(&modules(), "main", &options).expect("expected program");
+
+        let mut evaluator = ProgramEvaluator::new(compiled.let_program.clone());
+        assert_eq!(evaluator.run(), Ok(Value::Int(26)), "{}", flag);
+        assert!(evaluator.heap().is_empty(), "{}", flag);
+
+        let mut parsed = parse_program(&compiled.let_program.to_string()).expect("expected text");
+        resolve_variables(&mut parsed);
+        assert_eq!(ProgramEvaluator::new(parsed).run(), Ok(Value::Int(26)));
+
+        let flat_program = compiled.flat_program.expect("expected flat IR");
+        let bytecode = compile_program(&flat_program);
+        assert!(matches!(
+            VirtualMachine::new(bytecode).run(),
+            Ok(VmValue::Int(26))
+        ));
+    }
+}
+
+#[test]
+fn modules_that_are_not_imported_are_not_linked() {
+    let mut modules = modules();
+    modules.push(Module {
+        name: "unused".to_owned(),
+        imports: vec![import("missing", "x")],
+        definitions: vec![],
+        body: None,
+    });
+    // The unused module fails to compile on its own.
+    assert_eq!(
+        link_error(&modules, "main"),
+        "module unused imports unknown module missing"
+    );
+
+    let mut modules = self::modules();
+    modules.push(Module {
+        name: "unused".to_owned(),
+        imports: vec![],
+        definitions: vec![("z".to_owned(), expr! { 0 })],
+        body: None,
+    });
+    let compiled =
+        compile_modules(&modules, "main", &CompileOptions::default()).expect("expected program");
+    assert!(compiled
+        .let_program
+        .functions
+        .iter()
+        .all(|function| function.name.as_str() != "unused"));
+}
+
+#[test]
+fn invalid_imports_are_rejected() {
+    let mut modules = modules();
+    modules[2].body = Some(expr! { p.missing });
+    assert_eq!(link_error(&modules, "main"), "unbound variable p.missing");
+
+    assert_eq!(
+        link_error(&self::modules(), "other"),
+        "unknown module other"
+    );
+
+    let mut modules = self::modules();
+    modules[0].imports.push(import("pairs", "p"));
+    assert_eq!(
+        link_error(&modules, "main"),
+        "import cycle through module pairs"
+    );
+
+    let mut modules = self::modules();
+    modules[0]
+        .definitions
+        .push(("ten".to_owned(), expr! { 11 }));
+    assert_eq!(
+        link_error(&modules, "main"),
+        "module math defines ten more than once"
+    );
+}
+
+// Every module is compiled with only the exports of the object files of the
+// modules it imports, and the objects are linked after decoding them.
+#[test]
+fn object_files_are_linked_at_load_time() {
+    let mut interfaces = HashMap::new();
+    let mut encoded = Vec::new();
+    for module in modules() {
+        let object = compile_object(&module, &interfaces, &CompileOptions::default())
+            .expect("expected object file");
+        interfaces.insert(object.name.clone(), object.exports.clone());
+        encoded.push(encode_object(&object));
+    }
+
+    let objects: Vec<ObjectFile> = encoded
+        .iter()
+        .map(|bytes| decode_object(bytes).expect("expected object file"))
+        .collect();
+    assert_eq!(
+        objects[2].free_names,
+        ["math__twenty", "pairs__swap_double"]
+    );
+    assert!(decode(&encoded[0]).is_err());
+
+    let linked = link_objects(&objects, "main").expect("expected program");
+    assert!(matches!(
+        VirtualMachine::new(compile_program(&linked)).run(),
+        Ok(VmValue::Int(26))
+    ));
+}
+
+#[test]
+fn object_files_cannot_refer_to_globals() {
+    let options = CompileOptions {
+        globals: vec!["answer".to_owned()],
+        ..CompileOptions::default()
+    };
+    let main = Module {
+        name: "main".to_owned(),
+        imports: vec![],
+        definitions: vec![],
+        body: Some(expr! { answer }),
+    };
+    let object = compile_object(&main, &HashMap::new(), &options).expect("expected object file");
+
+    match link_objects(&[object], "main") {
+        Ok(_) => panic!("expected the link to fail"),
+        Err(error) => assert_eq!(
+            error.to_string(),
+            "module main refers to unresolved name answer"
+        ),
+    }
+}
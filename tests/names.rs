@@ -0,0 +1,130 @@
+use bailey::driver::{compile, compile_object, CompileOptions};
+use bailey::expr;
+use bailey::ir_flat::encoding::{decode, decode_object, encode, encode_object};
+use bailey::ir_flat::object::link_objects;
+use bailey::ir_flat::syntax::{Definition, Instruction, NameId, Program, Simple, Step};
+use bailey::lang::syntax::{Expr, Import, Module};
+use std::collections::HashMap;
+
+fn closure_names(program: &Program) -> Vec<Option<NameId>> {
+    let mut names = Vec::new();
+    for function in &program.functions {
+        for block in &function.blocks {
+            for instruction in &block.instructions {
+                if let Instruction::Assignment(assignment) = instruction {
+                    if let Definition::Step(Step::Simple(Simple::Fun(closure))) =
+                        &assignment.definition
+                    {
+                        names.push(closure.name);
+                    }
+                }
+            }
+        }
+    }
+    names
+}
+
+fn resolved_names(program: &Program) -> Vec<String> {
+    closure_names(program)
+        .into_iter()
+        .map(|name| program.name(name.expect("expected a name")).to_owned())
+        .collect()
+}
+
+fn program() -> Expr {
+    expr! {
+        let add = fun add(x) { fun add_x(y) { x + y } };
+        let twice = fun twice(f, x) { f(f(x)) };
+        twice(add(1), 2) - twice(add(1), 3)
+    }
+}
+
+fn compile_flat(e: &Expr, options: &CompileOptions) -> Program {
+    compile(e, options)
+        .expect("expected program")
+        .flat_program
+        .expect("expected flat program")
+}
+
+#[test]
+fn closure_names_are_interned_in_the_program() {
+    let program = compile_flat(&program(), &CompileOptions::default());
+
+    let names = resolved_names(&program);
+    for name in ["add", "add_x", "twice"] {
+        assert!(
+            names.iter().any(|n| n.starts_with(name)),
+            "{} missing from {:?}",
+            name,
+            names
+        );
+    }
+    assert_eq!(program.names.len(), names.len());
+}
+
+#[test]
+fn names_survive_encoding() {
+    let program = compile_flat(&program(), &CompileOptions::default());
+    let decoded = decode(&encode(&program)).expect("expected program");
+
+    assert_eq!(decoded, program);
+    assert_eq!(resolved_names(&decoded), resolved_names(&program));
+}
+
+#[test]
+fn stripping_debug_info_removes_the_names() {
+    let options = CompileOptions {
+        debug_info: false,
+        ..CompileOptions::default()
+    };
+    let program = compile_flat(&program(), &options);
+
+    assert!(program.names.is_empty());
+    assert!(closure_names(&program).iter().all(|name| name.is_none()));
+    assert_eq!(
+        decode(&encode(&program)).expect("expected program"),
+        program
+    );
+}
+
+// The names of every object file end up in the names of the linked program,
+// once each.
+#[test]
+fn linking_merges_the_names_of_the_objects() {
+    let library = Module {
+        name: "library".to_owned(),
+        imports: vec![],
+        definitions: vec![("increment".to_owned(), expr! { fun increment(x) { x + 1 } })],
+        body: None,
+    };
+    let main = Module {
+        name: "main".to_owned(),
+        imports: vec![Import {
+            module: "library".to_owned(),
+            alias: "library".to_owned(),
+        }],
+        definitions: vec![],
+        body: Some(expr! { library.increment(41) }),
+    };
+
+    let mut interfaces = HashMap::new();
+    let mut objects = Vec::new();
+    for module in [library, main] {
+        let object = compile_object(&module, &interfaces, &CompileOptions::default())
+            .expect("expected object file");
+        interfaces.insert(object.name.clone(), object.exports.clone());
+        objects.push(decode_object(&encode_object(&object)).expect("expected object file"));
+    }
+
+    let linked = link_objects(&objects, "main").expect("expected program");
+    let names = resolved_names(&linked);
+    for name in ["library", "main"] {
+        assert!(names.iter().any(|n| n == name), "{:?}", names);
+    }
+    assert!(names.iter().any(|n| n.contains("increment")), "{:?}", names);
+
+    let mut unique = linked.names.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), linked.names.len());
+}
@@ -0,0 +1,181 @@
+#![cfg(feature = "nan-boxing")]
+use bailey::bytecode::nan_box::NanBoxedValue;
+use bailey::bytecode::vm::{Value, ValueRepr, VirtualMachine};
+use bailey::driver::{compile_bytecode, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::gen::generate_program;
+use bailey::lang::test::list::{list_sum_test, map_fold_test};
+use bailey::lang::test::mutation::{aliasing_test, counter_test};
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::Int(value) => value.to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Tuple(fields) => {
+            let fields: Vec<String> = fields.borrow().iter().map(render).collect();
+            format!("({})", fields.join(", "))
+        }
+        Value::Closure(_) => "<closure>".to_owned(),
+    }
+}
+
+fn render_nan_boxed(value: &NanBoxedValue) -> String {
+    if let Some(value) = value.as_int() {
+        value.to_string()
+    } else if let Some(value) = value.as_bool() {
+        value.to_string()
+    } else if let Some(fields) = value.fields() {
+        let fields: Vec<String> = fields.iter().map(render_nan_boxed).collect();
+        format!("({})", fields.join(", "))
+    } else {
+        "<closure>".to_owned()
+    }
+}
+
+// Runs the program with both representations of values.
+fn run_both(e: &Expr) -> (String, String) {
+    let bytecode = compile_bytecode(e, &CompileOptions::default()).expect("expected program");
+    let value = VirtualMachine::new(bytecode.clone())
+        .run()
+        .expect("program should not fail");
+    let nan_boxed = VirtualMachine::<NanBoxedValue>::with_representation(bytecode)
+        .run()
+        .expect("program should not fail");
+    (render(&value), render_nan_boxed(&nan_boxed))
+}
+
+#[test]
+fn values_fit_in_a_word() {
+    assert_eq!(std::mem::size_of::<NanBoxedValue>(), 8);
+}
+
+#[test]
+fn representations_agree_on_example_programs() {
+    let programs = [
+        fib_test(15),
+        ackermann_test(2, 3),
+        list_sum_test(100),
+        map_fold_test(100),
+        closure_capture_test(10),
+        counter_test(100),
+        aliasing_test(),
+        expr! { (1, (true, 2), fun f(x) { x }) },
+    ];
+
+    for e in &programs {
+        let (value, nan_boxed) = run_both(e);
+        assert_eq!(value, nan_boxed);
+    }
+}
+
+#[test]
+fn representations_agree_on_generated_programs() {
+    for seed in 0..200 {
+        let (value, nan_boxed) = run_both(&generate_program(seed, 5));
+        assert_eq!(value, nan_boxed, "seed {}", seed);
+    }
+}
+
+#[test]
+fn representations_report_the_same_errors() {
+    let programs = [
+        (
+            expr! { 1 + (2, 3) },
+            RuntimeError::TypeMismatch { expected: "int" },
+        ),
+        (
+            expr! { let f = fun f(x) { x }; f(1, 2) },
+            RuntimeError::ArityMismatch {
+                expected: 1,
+                actual: 2,
+            },
+        ),
+        (
+            expr! { let t = (1, 2); let u = set t[3] = 0; t },
+            RuntimeError::IndexOutOfRange { index: 3, size: 2 },
+        ),
+        (
+            expr! { let f = 1; f(1) },
+            RuntimeError::TypeMismatch {
+                expected: "closure",
+            },
+        ),
+    ];
+    for (e, error) in programs {
+        let bytecode = compile_bytecode(&e, &CompileOptions::default()).expect("expected program");
+        let value = VirtualMachine::new(bytecode.clone()).run();
+        let nan_boxed = VirtualMachine::<NanBoxedValue>::with_representation(bytecode).run();
+        assert_eq!(value.map(|_| ()), Err(error.clone()));
+        assert_eq!(nan_boxed.map(|_| ()), Err(error));
+    }
+}
+
+// Ints that do not fit in the 48 bits of the payload move to the heap and
+// back, and arithmetic still wraps around at 64 bits.
+#[test]
+fn large_ints_are_boxed() {
+    let boundary = (1_i64 << 47) - 1;
+    let small = NanBoxedValue::int(boundary);
+    let large = NanBoxedValue::int(boundary + 1);
+    assert!(small.is_immediate_int());
+    assert!(!large.is_immediate_int());
+    assert_eq!(large.clone().as_int(), Some(boundary + 1));
+    assert!(NanBoxedValue::int(-(1 << 47)).is_immediate_int());
+    assert!(!NanBoxedValue::int(i64::MIN).is_immediate_int());
+
+    let e = expr! {
+        let max = 9223372036854775807;
+        (max + 1, (max - 140737488355327) + 140737488355327, 0 - 140737488355328)
+    };
+    let (value, nan_boxed) = run_both(&e);
+    assert_eq!(
+        nan_boxed,
+        "(-9223372036854775808, 9223372036854775807, -140737488355328)"
+    );
+    assert_eq!(value, nan_boxed);
+}
+
+#[test]
+fn floats_are_stored_unboxed() {
+    for value in [
+        0.0,
+        -1.5,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::MIN_POSITIVE,
+    ] {
+        assert_eq!(NanBoxedValue::float(value).as_float(), Some(value));
+    }
+    let nan = NanBoxedValue::float(-f64::NAN);
+    assert!(nan.as_float().is_some_and(f64::is_nan));
+    assert_eq!(nan.as_int(), None);
+    assert_eq!(NanBoxedValue::int(3).as_float(), None);
+}
+
+// The tuple that a closure captures stays alive as long as the closure, and
+// the fields of the result are shared with the tuple that was mutated.
+#[test]
+fn heap_values_are_reference_counted() {
+    let e = expr! {
+        let t = (1, 2);
+        let get = fun get(i) { t[i] };
+        let u = set t[0] = 10;
+        (get, t)
+    };
+    let bytecode = compile_bytecode(&e, &CompileOptions::default()).expect("expected program");
+    let result = VirtualMachine::<NanBoxedValue>::with_representation(bytecode)
+        .run()
+        .expect("program should not fail");
+
+    let fields = result.fields().expect("expected tuple");
+    assert!(fields[0].function_index().is_some());
+    let tuple = fields[1].clone();
+    drop(fields);
+    drop(result);
+    assert_eq!(render_nan_boxed(&tuple), "(10, 2)");
+}
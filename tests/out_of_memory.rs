@@ -0,0 +1,73 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::{DumpFormat, Heap};
+use bailey::ir_let::interpreter::simple_eval::{ProgramEvaluator, StepOutcome};
+use bailey::ir_let::let_expr::{Assignment, Definition, Instruction, Simple, Step};
+use std::collections::BTreeSet;
+
+// Every iteration sets a field, captures the list in a closure, and conses
+// onto the list, each of which needs one more object than ever before. The
+// pairs of the garbage list are freed first, and the conses reuse their
+// storage from the pool.
+fn program() -> bailey::ir_let::let_expr::Program {
+    let e = expr! {
+        let garbage = fun(n, acc) { if n == 0 { 0 } else { garbage(n - 1, (n, acc)) } };
+        let start = garbage(50, 0);
+        let t = (0,);
+        let fill = fun(n, acc) {
+            if n == 0 { acc } else {
+                let u = set t[0] = acc;
+                let f = fun(x) { (u, acc) };
+                fill(n - 1, (f, acc))
+            }
+        };
+        fill(1000, start)
+    };
+    compile(&e, &CompileOptions::default())
+        .expect("expected program")
+        .let_program
+}
+
+fn allocation_kind(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Assignment(Assignment {
+            definition: Definition::Step(Step::Simple(simple)),
+            ..
+        }) => match simple {
+            Simple::Tuple { .. } | Simple::ReuseTuple { .. } => "tuple",
+            Simple::Fun(_) => "closure",
+            Simple::Set { .. } => "set",
+            _ => "other",
+        },
+        _ => "other",
+    }
+}
+
+// Steps until the heap limit is reached, and checks that the failed
+// instruction left every object and reference count as it was.
+fn fail_at_limit(max_objects: usize) -> &'static str {
+    let mut evaluator = ProgramEvaluator::with_heap_limit(program(), max_objects);
+    loop {
+        let before = evaluator.heap().dump(DumpFormat::Json);
+        let live_objects = evaluator.heap().stats().live_objects;
+        let pooled_tuples = evaluator.heap().pooled_tuples();
+        match evaluator.step() {
+            Ok(StepOutcome::Running) => {}
+            Ok(StepOutcome::Finished(_)) => panic!("expected to run out of memory"),
+            Err(error) => {
+                assert_eq!(error, RuntimeError::OutOfMemory);
+                assert_eq!(evaluator.heap().stats().live_objects, live_objects);
+                assert_eq!(evaluator.heap().pooled_tuples(), pooled_tuples);
+                assert_eq!(evaluator.heap().dump(DumpFormat::Json), before);
+                return allocation_kind(evaluator.current_instruction());
+            }
+        }
+    }
+}
+
+#[test]
+fn failed_allocations_leave_the_heap_unchanged() {
+    let kinds: BTreeSet<&str> = (60..100).map(fail_at_limit).collect();
+    assert_eq!(kinds, BTreeSet::from(["closure", "set", "tuple"]));
+}
@@ -0,0 +1,32 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::config::{EvaluatorConfig, OverflowMode};
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+
+fn run(overflow: OverflowMode) -> Result<String, RuntimeError> {
+    // Constant folding leaves arithmetic that overflows to the evaluator.
+    let e = expr! { (9223372036854775807 + 1, 0 - 9223372036854775807 - 2) };
+    let compiled = compile(&e, &CompileOptions::default()).expect("expected program");
+    let config = EvaluatorConfig {
+        overflow,
+        ..EvaluatorConfig::default()
+    };
+    let mut evaluator = ProgramEvaluator::with_config(compiled.let_program, config);
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+#[test]
+fn overflow_modes() {
+    assert_eq!(
+        run(OverflowMode::Wrapping).as_deref(),
+        Ok("(-9223372036854775808, 9223372036854775807)")
+    );
+    assert_eq!(
+        run(OverflowMode::Saturating).as_deref(),
+        Ok("(9223372036854775807, -9223372036854775808)")
+    );
+    assert_eq!(run(OverflowMode::Checked), Err(RuntimeError::Overflow));
+}
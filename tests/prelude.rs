@@ -0,0 +1,142 @@
+use bailey::driver::{compile, compile_modules, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::{Expr, Import, Module};
+
+fn natives() -> Natives {
+    let mut natives = Natives::new();
+    natives.register_prelude();
+    natives
+}
+
+fn options() -> CompileOptions {
+    CompileOptions {
+        globals: natives().names(),
+        ..CompileOptions::default()
+    }
+}
+
+fn run_with(e: &Expr, options: &CompileOptions) -> Result<String, RuntimeError> {
+    let compiled = compile(e, options).expect("expected program");
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+    evaluator
+        .bind_natives(&natives())
+        .expect("expected natives");
+    let value = evaluator.run()?;
+    let output = render(value, evaluator.heap());
+    // The results are at most a tuple of integers and booleans.
+    let result_objects = value.address().iter().count();
+    assert_eq!(evaluator.heap().len(), result_objects, "{}", output);
+    Ok(output)
+}
+
+fn run(e: &Expr) -> Result<String, RuntimeError> {
+    let mut outputs = Vec::new();
+    for flag in ["-O0", "-O1", "-O2"] {
+        let mut options = options();
+        options.parse_flag(flag);
+        outputs.push(run_with(e, &options));
+    }
+    assert!(outputs.windows(2).all(|w| w[0] == w[1]), "{:?}", outputs);
+    outputs.swap_remove(0)
+}
+
+#[test]
+fn list_operations() {
+    let e = expr! {
+        let l = cons(1, cons(2, cons(3, nil)));
+        let doubled = map(fun double(x) { x + x }, l);
+        let sum = fold(fun add(a, b) { a + b }, 0, doubled);
+        (length(l), sum, head(tail(doubled)), is_empty(l), is_empty(nil))
+    };
+    assert_eq!(run(&e).as_deref(), Ok("(3, 12, 4, false, true)"));
+}
+
+#[test]
+fn option_helpers() {
+    let e = expr! {
+        let a = map_option(fun inc(x) { x + 1 }, some(41));
+        let b = map_option(fun inc(x) { x + 1 }, none);
+        (unwrap_or(a, 0), unwrap_or(b, 7), is_some(a), is_some(b))
+    };
+    assert_eq!(run(&e).as_deref(), Ok("(42, 7, true, false)"));
+}
+
+#[test]
+fn arithmetic_helpers() {
+    let e = expr! { (min(3, 0 - 4), max(3, 0 - 4), abs(0 - 5), abs(6)) };
+    assert_eq!(run(&e).as_deref(), Ok("(-4, 3, 5, 6)"));
+}
+
+#[test]
+fn assertions() {
+    assert_eq!(run(&expr! { assert_eq(2 + 2, 4) }).as_deref(), Ok("()"));
+    assert_eq!(
+        run(&expr! { let u = assert(1 == 2); 5 }),
+        Err(RuntimeError::Native("assertion failed".to_owned()))
+    );
+}
+
+#[test]
+fn local_definitions_hide_the_prelude() {
+    let e = expr! {
+        let length = fun length(l) { 100 };
+        length(nil)
+    };
+    assert_eq!(run(&e).as_deref(), Ok("100"));
+}
+
+#[test]
+fn programs_that_do_not_use_the_prelude_are_unaffected() {
+    let e = expr! {
+        let f = fun f(x) { x + 1 };
+        f(2)
+    };
+    let mut without_prelude = options();
+    without_prelude.parse_flag("--no-prelude");
+
+    let with = compile(&e, &options()).expect("expected program");
+    let without = compile(&e, &without_prelude).expect("expected program");
+    assert_eq!(
+        with.let_program.to_string(),
+        without.let_program.to_string()
+    );
+
+    // Without natives, only programs that use the prelude fail to compile.
+    let e = expr! { length(nil) };
+    let error = compile(&e, &CompileOptions::default()).map(|_| ());
+    assert_eq!(
+        error.map_err(|e| e.to_string()),
+        Err("the prelude requires the global less, see Natives::register_prelude".to_owned())
+    );
+    let error = compile(&e, &without_prelude).map(|_| ());
+    assert_eq!(
+        error.map_err(|e| e.to_string()),
+        Err("unbound variable length".to_owned())
+    );
+}
+
+#[test]
+fn modules_can_import_the_prelude() {
+    let main = Module {
+        name: "main".to_owned(),
+        imports: vec![Import {
+            module: "prelude".to_owned(),
+            alias: "p".to_owned(),
+        }],
+        definitions: vec![],
+        body: Some(expr! { p.max(p.length(p.cons(1, p.nil)), 0 - 1) }),
+    };
+    let compiled = compile_modules(&[main], "main", &options()).expect("expected program");
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+    evaluator
+        .bind_natives(&natives())
+        .expect("expected natives");
+    assert_eq!(
+        evaluator.run().map(|value| render(value, evaluator.heap())),
+        Ok("1".to_owned())
+    );
+}
@@ -0,0 +1,92 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::profile::ReportFormat;
+use bailey::ir_let::interpreter::simple_eval::{ProgramEvaluator, StepOutcome};
+use bailey::lang::syntax::Expr;
+
+fn evaluator(e: &Expr) -> ProgramEvaluator {
+    let compiled = compile(e, &CompileOptions::default()).expect("expected program");
+    ProgramEvaluator::new(compiled.let_program)
+}
+
+fn function_index(evaluator: &ProgramEvaluator, name: &str) -> usize {
+    evaluator
+        .program()
+        .functions
+        .iter()
+        .position(|function| function.name.source_name() == name)
+        .expect("missing function")
+}
+
+#[test]
+fn allocations_are_attributed_to_functions() {
+    let e = expr! {
+        let pairs = fun(n, acc) { if n == 0 { acc } else { pairs(n - 1, (n, acc)) } };
+        let once = fun(x) { (x,) };
+        let list = pairs(10, 0);
+        once(list)
+    };
+    let mut evaluator = evaluator(&e);
+    evaluator.enable_allocation_profile();
+    evaluator.run().expect("program should not fail");
+
+    let profile = evaluator
+        .allocation_profile()
+        .expect("expected allocation profile");
+    let pairs_index = function_index(&evaluator, "pairs");
+    let once_index = function_index(&evaluator, "once");
+    let pairs = profile.functions[pairs_index];
+    let once = profile.functions[once_index];
+    assert_eq!(pairs.allocations, 10);
+    assert_eq!(once.allocations, 1);
+    assert!(pairs.bytes > once.bytes);
+
+    // The largest volume comes first, after the header.
+    let name = |index: usize| evaluator.program().functions[index].name.as_str();
+    let report = profile.report(evaluator.program());
+    let lines: Vec<&str> = report.lines().skip(1).collect();
+    assert!(lines[0].ends_with(name(pairs_index)), "{}", report);
+    assert!(lines.iter().any(|line| line.ends_with(name(once_index))));
+}
+
+#[test]
+fn instructions_and_calls_are_counted_per_function() {
+    let e = expr! {
+        let f = fun(n) { if n == 0 { 0 } else { 1 + f(n - 1) } };
+        f(5)
+    };
+    let mut evaluator = evaluator(&e);
+    evaluator.enable_execution_profile();
+    let mut steps = 0;
+    while let StepOutcome::Running = evaluator.step().expect("program should not fail") {
+        steps += 1;
+    }
+
+    let profile = evaluator
+        .execution_profile()
+        .expect("expected execution profile");
+    let f_index = function_index(&evaluator, "f");
+    let f = profile.functions[f_index];
+    assert_eq!(f.times_called, 6);
+    assert_eq!(f.calls_made, 5);
+    assert_eq!(profile.call_edges[&(f_index, f_index)], 5);
+    let instructions: usize = profile.functions.iter().map(|f| f.instructions).sum();
+    assert_eq!(instructions, steps + 1);
+
+    let name = evaluator.program().functions[f_index].name.as_str();
+    let text = profile.report(evaluator.program(), ReportFormat::Text);
+    assert!(
+        text.contains(&format!("{}  {} -> {}", 5, name, name)),
+        "{}",
+        text
+    );
+    let json = profile.report(evaluator.program(), ReportFormat::Json);
+    assert!(
+        json.contains(&format!(
+            "{{\"caller\": \"{}\", \"callee\": \"{}\", \"calls\": 5}}",
+            name, name
+        )),
+        "{}",
+        json
+    );
+}
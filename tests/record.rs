@@ -0,0 +1,173 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::Heap;
+use bailey::ir_let::interpreter::heap_value::{HeapValue, Tuple, Value};
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::record::{RecordedCall, RecordedValue, Recording};
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use common::compile_with_natives;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+static TICKS: AtomicI64 = AtomicI64::new(0);
+
+// A different number on every call, next to a tuple of it.
+fn tick(heap: &mut dyn Heap, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let n = TICKS.fetch_add(1, Ordering::Relaxed);
+    let inner = heap.alloc(HeapValue::Tuple(Tuple::new(vec![Value::Int(n)])))?;
+    heap.inc_refcount(inner);
+    let outer = heap.alloc(HeapValue::Tuple(Tuple::new(vec![
+        Value::Int(n),
+        Value::Pointer(inner),
+    ])))?;
+    Ok(Value::Pointer(outer))
+}
+
+fn fail(_heap: &mut dyn Heap, args: &[Value]) -> Result<Value, RuntimeError> {
+    Err(RuntimeError::IndexOutOfRange {
+        index: args[0].check_int()?,
+        size: 3,
+    })
+}
+
+fn evaluator(e: &Expr) -> ProgramEvaluator {
+    let mut natives = Natives::new();
+    natives.register_prelude();
+    natives.register_freeze();
+    natives.register_nondeterministic("tick", 1, tick);
+    natives.register_nondeterministic("fail", 1, fail);
+    let mut evaluator = ProgramEvaluator::new(compile_with_natives(e, &natives));
+    evaluator.bind_natives(&natives).expect("expected natives");
+    evaluator
+}
+
+fn run(evaluator: &mut ProgramEvaluator) -> Result<String, RuntimeError> {
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+fn record(e: &Expr) -> (Result<String, RuntimeError>, Recording) {
+    let mut evaluator = evaluator(e);
+    evaluator.start_recording();
+    let result = run(&mut evaluator);
+    (
+        result,
+        evaluator.take_recording().expect("expected recording"),
+    )
+}
+
+fn replay(e: &Expr, recording: Recording) -> Result<String, RuntimeError> {
+    let mut evaluator = evaluator(e);
+    evaluator.replay(recording);
+    run(&mut evaluator)
+}
+
+#[test]
+fn replay_repeats_a_recorded_run() {
+    let e = expr! {
+        let a = tick(0);
+        let b = tick(0);
+        let later = less(a[0], b[0]);
+        (a, b, later)
+    };
+    let (result, recording) = record(&e);
+    let result = result.expect("program should not fail");
+    assert_eq!(recording.calls.len(), 2);
+
+    let reloaded = Recording::parse(&recording.to_string()).expect("expected recording");
+    assert_eq!(reloaded, recording);
+
+    // The natives now return other numbers, but not while replaying.
+    assert_ne!(run(&mut evaluator(&e)), Ok(result.clone()));
+    assert_eq!(replay(&e, reloaded), Ok(result));
+}
+
+#[test]
+fn other_natives_run_during_replay() {
+    let e = expr! {
+        let t = (1,);
+        let u = freeze(t);
+        let n = tick(0);
+        let v = set t[0] = n;
+        t
+    };
+    let (result, recording) = record(&e);
+    assert_eq!(result, Err(RuntimeError::FrozenTuple));
+    assert_eq!(
+        recording
+            .calls
+            .iter()
+            .map(|call| call.native.as_str())
+            .collect::<Vec<_>>(),
+        ["tick"]
+    );
+    assert_eq!(replay(&e, recording), Err(RuntimeError::FrozenTuple));
+}
+
+#[test]
+fn replay_fails_when_the_run_diverges() {
+    let e = expr! {
+        let a = tick(0);
+        let b = tick(0);
+        a[0] + b[0]
+    };
+    let (_, recording) = record(&e);
+
+    let mut short = recording.clone();
+    short.calls.pop();
+    assert_eq!(
+        replay(&e, short),
+        Err(RuntimeError::Native(
+            "replay has no result for tick".to_owned()
+        ))
+    );
+
+    let mut other = recording;
+    other.calls[1] = RecordedCall {
+        native: "fail".to_owned(),
+        result: Ok(RecordedValue::Int(1)),
+    };
+    assert_eq!(
+        replay(&e, other),
+        Err(RuntimeError::Native(
+            "replay diverged: recorded a call to fail, but tick was called".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn errors_are_replayed_as_they_were() {
+    let e = expr! { fail(5) };
+    let (result, recording) = record(&e);
+    let error = RuntimeError::IndexOutOfRange { index: 5, size: 3 };
+    assert_eq!(result, Err(error.clone()));
+    assert_eq!(recording.to_string(), "err fail index_out_of_range 5 3\n");
+
+    let reloaded = Recording::parse(&recording.to_string()).expect("expected recording");
+    assert_eq!(replay(&e, reloaded), Err(error));
+
+    let text = "err fail type_mismatch weak reference\n";
+    let recording = Recording::parse(text).expect("expected recording");
+    assert_eq!(recording.to_string(), text);
+    assert_eq!(
+        replay(&e, recording),
+        Err(RuntimeError::TypeMismatch {
+            expected: "weak reference"
+        })
+    );
+}
+
+#[test]
+fn long_lists_are_replayed() {
+    let e = expr! {
+        let a = tick(0);
+        a[0]
+    };
+    let text = format!("ok tick {}0{}\n", "(1, ".repeat(10000), ")".repeat(10000));
+    let recording = Recording::parse(&text).expect("expected recording");
+    assert_eq!(recording.to_string(), text);
+    assert_eq!(replay(&e, recording), Ok("1".to_owned()));
+}
@@ -0,0 +1,210 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::config::EvaluatorConfig;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::{Heap, MarkSweepHeap, RefCountHeap};
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::native::{NativeContext, Natives};
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::builder::{call_named, var};
+use common::{compile_with_natives, reachable_objects};
+
+// apply(f, x) calls f(x).
+fn apply(context: &mut dyn NativeContext, args: &[Value]) -> Result<Value, RuntimeError> {
+    context.call(args[0], &[args[1]])
+}
+
+// attempt(f, x, default) calls f(x), and evaluates to default if that fails.
+fn attempt(context: &mut dyn NativeContext, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(context.call(args[0], &[args[1]]).unwrap_or(args[2]))
+}
+
+fn natives() -> Natives {
+    let mut natives = Natives::new();
+    natives.register_sorting();
+    natives.register_generators();
+    natives.register_prelude();
+    natives.register_reentrant("apply", 2, apply);
+    natives.register_reentrant("attempt", 3, attempt);
+    natives
+}
+
+fn evaluate<H: Heap>(e: &Expr, heap: H, config: EvaluatorConfig) -> Result<String, RuntimeError> {
+    let natives = natives();
+    let program = compile_with_natives(e, &natives);
+    let mut evaluator = ProgramEvaluator::with_heap_and_config(program, heap, config);
+    evaluator.bind_natives(&natives).expect("expected natives");
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+// Also checks that only the result is left on the heap, with and without
+// deferred reference counts.
+fn run(e: &Expr) -> String {
+    let mut outputs = Vec::new();
+    for deferred_refcounts in [false, true] {
+        let natives = natives();
+        let config = EvaluatorConfig {
+            deferred_refcounts,
+            ..EvaluatorConfig::default()
+        };
+        let mut evaluator =
+            ProgramEvaluator::with_config(compile_with_natives(e, &natives), config);
+        evaluator.bind_natives(&natives).expect("expected natives");
+        let value = evaluator.run().expect("program should not fail");
+
+        let heap = evaluator.heap();
+        assert_eq!(heap.len(), reachable_objects(heap, value));
+        outputs.push(render(value, heap));
+    }
+
+    assert_eq!(outputs[0], outputs[1]);
+    outputs.remove(0)
+}
+
+#[test]
+fn natives_can_sort_with_a_guest_comparison() {
+    let e = expr! {
+        let descending = fun(a, b) { less(b, a) };
+        let by_key = fun(a, b) { less(a[0], b[0]) };
+        (
+            sort_by((3, 1, 4, 1, 5, 9, 2, 6), descending),
+            sort_by(((2, 1), (1, 2), (2, 3), (1, 4)), by_key),
+            sort_by((), by_key)
+        )
+    };
+    assert_eq!(
+        run(&e),
+        "((9, 6, 5, 4, 3, 2, 1, 1), ((1, 2), (1, 4), (2, 1), (2, 3)), ())"
+    );
+}
+
+#[test]
+fn natives_can_be_passed_as_functions() {
+    let e = expr! { sort_by((3, 1, 2), less) };
+    assert_eq!(run(&e), "(1, 2, 3)");
+}
+
+#[test]
+fn calls_nest() {
+    // The comparison sorts tuples itself, and calls back into the guest
+    // through another native.
+    let e = expr! {
+        let inc = fun(x) { x + 1 };
+        let smallest = fun(t) { sort_by(t, less)[0] };
+        let by_smallest = fun(a, b) { less(apply(smallest, a), apply(smallest, b)) };
+        (
+            sort_by(((5, 3), (2, 9), (4, 4)), by_smallest),
+            apply(inc, apply(inc, 1))
+        )
+    };
+    assert_eq!(run(&e), "(((2, 9), (5, 3), (4, 4)), 3)");
+}
+
+#[test]
+fn natives_can_handle_errors_of_the_functions_they_call() {
+    let e = expr! {
+        let first = fun(t) { (t[0], 0) };
+        let deep = fun(n) { if n == 0 { (1, 2)[5] } else { (n, deep(n - 1))[1] } };
+        (attempt(first, (7,), (0,)), attempt(first, 3, (0,)), attempt(deep, 10, 42))
+    };
+    assert_eq!(run(&e), "((7, 0), (0,), 42)");
+}
+
+#[test]
+fn errors_of_the_functions_propagate() {
+    let e = expr! {
+        let broken = fun(a, b) { a[1] };
+        sort_by((1, 2), broken)
+    };
+    assert_eq!(
+        evaluate(&e, RefCountHeap::new(), EvaluatorConfig::default()),
+        Err(RuntimeError::TypeMismatch { expected: "tuple" })
+    );
+}
+
+#[test]
+fn sorting_survives_a_comparison_that_changes_the_tuple() {
+    let e = expr! {
+        let t = ((3,), (1,), (2,));
+        let clear = fun(a, b) {
+            let u = set t[0] = 0;
+            let v = set t[1] = 0;
+            let w = set t[2] = 0;
+            less(a[0], b[0])
+        };
+        (sort_by(t, clear), t)
+    };
+    assert_eq!(run(&e), "(((1,), (2,), (3,)), (0, 0, 0))");
+}
+
+#[test]
+fn tracing_collectors_wait_for_natives_to_return() {
+    // The comparison allocates enough to trigger collections, while the
+    // fields it clears are only held by the native. They are built in a
+    // function, since the toplevel block would keep them alive.
+    let e = expr! {
+        let make = fun(n) { ((4,), (3,), (2,), (1,), (8,), (7,), (6,), (5,)) };
+        let t = make(0);
+        let churn = fun(n) { if n == 0 { 0 } else { let junk = (n, n); churn(n - 1) } };
+        let slow = fun(a, b) {
+            let u = set t[0] = 0;
+            let v = set t[3] = 0;
+            let w = set t[7] = 0;
+            let done = churn(200);
+            less(a[0], b[0])
+        };
+        sort_by(t, slow)
+    };
+    let expected = "((1,), (2,), (3,), (4,), (5,), (6,), (7,), (8,))";
+    assert_eq!(
+        evaluate(&e, MarkSweepHeap::new(), EvaluatorConfig::default()).as_deref(),
+        Ok(expected)
+    );
+}
+
+#[test]
+fn generators_cannot_yield_across_natives() {
+    let yield_a = call_named("yield", vec![var("a")]);
+    let e = expr! {
+        let compare = fun(a, b) { let y = #yield_a; less(a, b) };
+        let body = fun(v) { sort_by((2, 1), compare) };
+        let g = generator(body);
+        resume(g, 0)
+    };
+    assert_eq!(
+        evaluate(&e, RefCountHeap::new(), EvaluatorConfig::default()),
+        Err(RuntimeError::Native(
+            "cannot yield across a native function".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn generators_can_run_inside_natives() {
+    let yield_x = call_named("yield", vec![var("x")]);
+    let e = expr! {
+        let count = fun(x) { let y = #yield_x; count(x + 1) };
+        let g = generator(count);
+        let next = fun(unused) { resume(g, 0) };
+        (apply(next, 0), apply(next, 0), resume(g, 0))
+    };
+    assert_eq!(
+        evaluate(&e, RefCountHeap::new(), EvaluatorConfig::default()).as_deref(),
+        Ok("(0, 1, 2)")
+    );
+}
+
+#[test]
+fn intrinsics_cannot_be_called_from_natives() {
+    let e = expr! { apply(generator, 0) };
+    assert_eq!(
+        evaluate(&e, RefCountHeap::new(), EvaluatorConfig::default()),
+        Err(RuntimeError::Native(
+            "generator cannot be called from a native function".to_owned()
+        ))
+    );
+}
@@ -0,0 +1,168 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::heap::{Heap, RefCountHeap, TuplePool, MAX_POOLED_ARITY};
+use bailey::ir_let::interpreter::heap_value::{HeapValue, Tuple, Value};
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::fib::fib_test;
+
+// Runs the program at every optimization level and checks that only the
+// result, holding a single reference, and the objects reachable from it are
+// left on the heap.
+fn assert_heap_after_run(e: &Expr, expected_result: Option<Value>, expected_objects: usize) {
+    for flag in ["-O0", "-O1", "-O2"] {
+        let mut options = CompileOptions::default();
+        options.parse_flag(flag);
+        let compiled = compile(e, &options).expect("expected program");
+
+        let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+        let result = evaluator.run().expect("program should not fail");
+        let heap = evaluator.heap();
+
+        if let Some(expected_result) = expected_result {
+            assert_eq!(result, expected_result, "result at {}", flag);
+        }
+        if let Value::Pointer(address) = result {
+            assert_eq!(heap.refcount(address), 1, "refcount of result at {}", flag);
+        }
+        assert_eq!(heap.len(), expected_objects, "objects left at {}", flag);
+    }
+}
+
+#[test]
+fn integer_result_leaves_heap_empty() {
+    assert_heap_after_run(&fib_test(10), Some(Value::Int(55)), 0);
+}
+
+#[test]
+fn temporaries_in_non_tail_branches_are_released() {
+    let e = expr! {
+        let f = fun(n) {
+            if n == 0 {
+                0
+            } else {
+                let x = (n, n);
+                let y = f(n - 1);
+                x[0] + y
+            }
+        };
+        f(5)
+    };
+
+    assert_heap_after_run(&e, Some(Value::Int(15)), 0);
+}
+
+#[test]
+fn overwritten_fields_are_released() {
+    let e = expr! {
+        let t = (1, (2,));
+        let u = set t[1] = (3,);
+        t[0]
+    };
+
+    assert_heap_after_run(&e, Some(Value::Int(1)), 0);
+}
+
+#[test]
+fn returned_tuple_keeps_its_fields_alive() {
+    let e = expr! {
+        let t = (1, 2);
+        (t, t)
+    };
+
+    assert_heap_after_run(&e, None, 2);
+}
+
+#[test]
+fn returned_closure_keeps_its_environment_alive() {
+    let e = expr! {
+        let t = (1, 2);
+        fun g(i) { t[i] }
+    };
+
+    assert_heap_after_run(&e, None, 2);
+}
+
+#[test]
+fn tail_recursive_loop_releases_every_iteration() {
+    let e = expr! {
+        let loop = fun(n, acc) {
+            if n == 0 {
+                acc
+            } else {
+                let z = (n, acc);
+                loop(n - 1, z[1] + 1)
+            }
+        };
+        loop(100, 0)
+    };
+
+    assert_heap_after_run(&e, Some(Value::Int(100)), 0);
+}
+
+#[test]
+fn freed_pairs_are_recycled() {
+    let e = expr! {
+        let loop = fun(n, acc) {
+            if n == 0 {
+                acc
+            } else {
+                let pair = (n, acc);
+                loop(n - 1, pair[1] + 1)
+            }
+        };
+        loop(100, 0)
+    };
+    let compiled = compile(&e, &CompileOptions::default()).expect("expected program");
+
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+    assert_eq!(evaluator.run(), Ok(Value::Int(100)));
+    let heap = evaluator.heap();
+
+    // Every pair after the first one reuses the storage of the previous one.
+    assert_eq!(heap.stats().tuple_allocations, 100);
+    assert_eq!(heap.stats().recycled_tuples, 99);
+    assert_eq!(heap.pooled_tuples(), 1);
+}
+
+#[test]
+fn tuple_pools_are_segregated_by_arity() {
+    let mut pool = TuplePool::new();
+    pool.give(vec![Value::Int(1), Value::Int(2)]);
+    pool.give(vec![Value::Int(1); MAX_POOLED_ARITY + 1]);
+    pool.give(Vec::new());
+    assert_eq!(pool.len(), 1);
+
+    let (storage, recycled) = pool.take(3);
+    assert!(!recycled);
+    assert!(storage.capacity() >= 3);
+
+    let (storage, recycled) = pool.take(2);
+    assert!(recycled);
+    assert!(storage.is_empty());
+    assert!(storage.capacity() >= 2);
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn freeing_a_deep_chain_does_not_overflow_the_stack() {
+    // A list of a million cons cells, each holding the only reference to the
+    // rest of the list.
+    let mut heap = RefCountHeap::new();
+    let mut rest = Value::Int(0);
+    for i in 0..1_000_000 {
+        let cell = HeapValue::Tuple(Tuple::new(vec![Value::Int(i), rest]));
+        let address = heap.alloc(cell).expect("allocation should succeed");
+        heap.inc_refcount(address);
+        rest = Value::Pointer(address);
+    }
+    assert_eq!(heap.len(), 1_000_000);
+
+    let Value::Pointer(head) = rest else {
+        unreachable!("list should not be empty");
+    };
+    let mut freed = 0;
+    heap.dec_refcount(head, &mut |_| freed += 1);
+    assert_eq!(freed, 1_000_000);
+    assert!(heap.is_empty());
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::native::{Natives, TypeTag};
+use bailey::lang::syntax::Expr;
+use common::run_with_natives;
+
+fn run(e: &Expr) -> Result<String, RuntimeError> {
+    let mut natives = Natives::new();
+    natives.register_reflection();
+    natives.register_weak_refs();
+    natives.register_thunks();
+    run_with_natives(e, &natives)
+}
+
+fn tags(tags: &[TypeTag]) -> String {
+    let tags: Vec<String> = tags.iter().map(|tag| tag.tag().to_string()).collect();
+    format!("({})", tags.join(", "))
+}
+
+#[test]
+fn every_kind_of_value_has_a_tag() {
+    let e = expr! {
+        let f = fun(x) { x };
+        let t = (1, 2);
+        (
+            type_of(1),
+            type_of(true),
+            type_of(t),
+            type_of(f),
+            type_of(type_of),
+            type_of(weak(t)),
+            type_of(delay { 1 })
+        )
+    };
+
+    assert_eq!(
+        run(&e).as_deref(),
+        Ok(tags(&[
+            TypeTag::Int,
+            TypeTag::Bool,
+            TypeTag::Tuple,
+            TypeTag::Closure,
+            TypeTag::Native,
+            TypeTag::WeakRef,
+            TypeTag::Thunk,
+        ])
+        .as_str())
+    );
+}
+
+#[test]
+fn guest_code_can_branch_on_the_tag() {
+    let int = TypeTag::Int.tag();
+    let tuple = TypeTag::Tuple.tag();
+    let e = expr! {
+        // Sums the integers in nested tuples of two fields, and counts
+        // anything else as zero.
+        let total = fun(x) {
+            let tag = type_of(x);
+            if tag == #int {
+                x
+            } else {
+                if tag == #tuple { total(x[0]) + total(x[1]) } else { 0 }
+            }
+        };
+        let id = fun(y) { y };
+        total((1, ((2, true), (3, id))))
+    };
+
+    assert_eq!(run(&e).as_deref(), Ok("6"));
+}
+
+#[test]
+fn tags_round_trip() {
+    for (index, tag) in TypeTag::ALL.iter().enumerate() {
+        assert_eq!(tag.tag(), index as i64);
+        assert_eq!(TypeTag::from_tag(tag.tag()), Some(*tag));
+    }
+    assert_eq!(TypeTag::from_tag(-1), None);
+    assert_eq!(TypeTag::from_tag(TypeTag::ALL.len() as i64), None);
+    assert_eq!(TypeTag::Tuple.name(), "tuple");
+}
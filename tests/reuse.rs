@@ -0,0 +1,158 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::heap::{Heap, HeapStats};
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Definition, Program, Simple, Step};
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::passes::reuse::reuse_tuples;
+use bailey::ir_let::visit::{walk_definition, Visitor};
+use bailey::lang::test::list::map_fold_test;
+use common::compile_at;
+
+fn run(program: Program) -> (String, HeapStats) {
+    let mut evaluator = ProgramEvaluator::new(program);
+    let value = evaluator.run().expect("program should not fail");
+    (
+        render(value, evaluator.heap()),
+        evaluator.heap().stats().clone(),
+    )
+}
+
+// The names of the variables that are reused, in order.
+#[derive(Default)]
+struct Reused(Vec<String>);
+
+impl Visitor for Reused {
+    fn visit_definition(&mut self, definition: &Definition) {
+        if let Definition::Step(Step::Simple(Simple::ReuseTuple { reused, .. })) = definition {
+            self.0.push(reused.var_name.source_name().to_owned());
+        }
+        walk_definition(self, definition);
+    }
+}
+
+fn reused(program: &Program) -> Vec<String> {
+    let mut reused = Reused::default();
+    reused.visit_program(program);
+    reused.0
+}
+
+#[test]
+fn loop_state_is_updated_in_place() {
+    let e = expr! {
+        let step = fun(n, state) {
+            if n == 0 {
+                state[0]
+            } else {
+                let next = (state[0] + state[1], state[0]);
+                step(n - 1, next)
+            }
+        };
+        step(30, (0, 1))
+    };
+
+    let program = compile_at(&e, "-O2");
+    assert_eq!(reused(&program), ["state"]);
+
+    // The first state is passed by a tail call, so the loop holds the only
+    // reference to every state.
+    let (output, stats) = run(program);
+    assert_eq!(output, "832040");
+    assert_eq!(stats.tuple_allocations, 1);
+    assert_eq!(stats.reused_tuples, 30);
+
+    let (output, stats) = run(compile_at(&e, "-O1"));
+    assert_eq!(output, "832040");
+    assert_eq!(stats.tuple_allocations, 31);
+    assert_eq!(stats.reused_tuples, 0);
+}
+
+#[test]
+fn shared_tuples_are_not_overwritten() {
+    let e = expr! {
+        let swap = fun(p) {
+            let q = (p[1], p[0]);
+            q
+        };
+        let pair = (1, 2);
+        let swapped = swap(pair);
+        (pair, swapped)
+    };
+
+    // The argument is reused, but the caller still holds the pair.
+    let program = compile_at(&e, "-O2");
+    assert_eq!(reused(&program), ["p"]);
+    let (output, stats) = run(program);
+    assert_eq!(output, "((1, 2), (2, 1))");
+    assert_eq!(stats.reused_tuples, 0);
+}
+
+#[test]
+fn tuples_used_later_are_not_reused() {
+    let e = expr! {
+        let f = fun(n) {
+            let a = (n, n);
+            let b = (n + 1, n);
+            let c = (a[0], b[0]);
+            (a, c)
+        };
+        f(1)
+    };
+
+    // `a` is used by the last tuple, so only `b` can be reused for `c`, and
+    // nothing for the last tuple, which uses both remaining tuples.
+    let program = compile_at(&e, "-O2");
+    assert_eq!(reused(&program), ["b"]);
+    assert_eq!(run(program).0, "((1, 1), (1, 2))");
+}
+
+#[test]
+fn tuples_outside_of_a_loop_are_not_reused_inside() {
+    let e = expr! {
+        let f = fun(t) {
+            let outer = (t, t);
+            let go = fun(n, acc) {
+                if n == 0 {
+                    acc
+                } else {
+                    let pair = (outer[0], n);
+                    go(n - 1, acc + pair[0] + pair[1])
+                }
+            };
+            go(3, 0)
+        };
+        f(2)
+    };
+
+    let program = compile_at(&e, "-O2");
+    assert!(!reused(&program).contains(&"outer".to_owned()));
+    assert_eq!(run(program).0, "12");
+}
+
+#[test]
+fn results_are_unchanged() {
+    let e = map_fold_test(50);
+    let expected = run(compile_at(&e, "-O0")).0;
+
+    let mut program = compile_at(&e, "-O1");
+    reuse_tuples(&mut program);
+    assert_eq!(run(program).0, expected);
+    assert_eq!(run(compile_at(&e, "-O2")).0, expected);
+}
+
+#[test]
+fn reuse_survives_the_text_format() {
+    let e = expr! {
+        let f = fun(p) { (p[1], p[0]) };
+        f((1, 2))
+    };
+    let program = compile_at(&e, "-O2");
+    let text = program.to_string();
+    assert!(text.contains("reuse("), "{}", text);
+
+    let parsed = parse_program(&text).expect("expected program");
+    assert_eq!(parsed.to_string(), text);
+    assert_eq!(run(parsed).0, "(2, 1)");
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use bailey::driver::compile;
+use bailey::expr;
+use bailey::ir_let::interpreter::heap::{DumpFormat, Heap, RefCountHeap};
+use bailey::ir_let::interpreter::heap_value::{HeapAddress, Value};
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::simple_eval::{ProgramEvaluator, StepOutcome};
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::builder::{add, call_named, fun, get, int, let_in, tuple, var};
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use common::options_with_natives;
+use std::collections::HashSet;
+
+fn evaluator<H: Heap>(e: &Expr, level: &str, heap: H) -> ProgramEvaluator<H> {
+    let mut natives = Natives::new();
+    natives.register_generators();
+    natives.register_thunks();
+    let mut options = options_with_natives(&natives);
+    options.parse_flag(level);
+    let compiled = compile(e, &options).expect("expected program");
+    let mut evaluator = ProgramEvaluator::with_heap(compiled.let_program, heap);
+    evaluator.bind_natives(&natives).expect("expected natives");
+    evaluator
+}
+
+fn reachable<H: Heap>(evaluator: &ProgramEvaluator<H>) -> HashSet<HeapAddress> {
+    let mut seen = HashSet::new();
+    let mut worklist: Vec<HeapAddress> = evaluator.roots().collect();
+    while let Some(address) = worklist.pop() {
+        if seen.insert(address) {
+            worklist.extend(evaluator.heap().deref(address).references());
+        }
+    }
+    seen
+}
+
+// `yield` is a keyword in Rust, so this is built without `expr!`.
+fn generator_test() -> Expr {
+    let body = fun(
+        "body",
+        &["start"],
+        let_in(
+            "a",
+            call_named("yield", vec![tuple(vec![var("start"), int(1)])]),
+            let_in(
+                "b",
+                call_named("yield", vec![tuple(vec![var("a"), int(2)])]),
+                tuple(vec![var("b"), int(3)]),
+            ),
+        ),
+    );
+    let resume = |argument: Expr| call_named("resume", vec![var("counter"), argument]);
+    let_in(
+        "counter",
+        call_named("generator", vec![body]),
+        let_in(
+            "x",
+            resume(int(10)),
+            let_in(
+                "y",
+                resume(add(get(var("x"), 0), int(1))),
+                let_in(
+                    "z",
+                    resume(add(get(var("y"), 0), int(1))),
+                    tuple(vec![var("x"), var("y"), var("z")]),
+                ),
+            ),
+        ),
+    )
+}
+
+fn examples() -> Vec<Expr> {
+    vec![
+        fib_test(8),
+        map_fold_test(10),
+        closure_capture_test(4),
+        expr! {
+            let from = fun(n) { (n, delay { from(n + 1) }) };
+            let sum = fun(stream, k) {
+                if k == 0 { 0 } else { stream[0] + sum(force(stream[1]), k - 1) }
+            };
+            sum(from(0), 5)
+        },
+        generator_test(),
+    ]
+}
+
+#[test]
+fn every_live_object_is_reachable_from_the_roots() {
+    for e in &examples() {
+        for level in ["-O0", "-O2"] {
+            let mut evaluator = evaluator(e, level, RefCountHeap::new());
+            loop {
+                let live: HashSet<HeapAddress> = evaluator
+                    .heap()
+                    .objects()
+                    .iter()
+                    .map(|object| object.address)
+                    .collect();
+                assert_eq!(
+                    live,
+                    reachable(&evaluator),
+                    "at {} before {}",
+                    level,
+                    evaluator.program_counter()
+                );
+
+                let outcome = evaluator.step().expect("program should not fail");
+                if let StepOutcome::Finished(_) = outcome {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn result_is_the_only_root_after_the_program() {
+    let e = expr! { let t = (1, 2); (t, t) };
+    let mut evaluator = evaluator(&e, "-O0", RefCountHeap::new());
+    let Value::Pointer(result) = evaluator.run().expect("program should not fail") else {
+        panic!("expected tuple");
+    };
+    assert_eq!(evaluator.roots().collect::<Vec<_>>(), [result]);
+}
+
+#[test]
+fn heap_dump_marks_the_roots() {
+    let e = expr! { let t = (1, 2); let u = (3, 4); t };
+    let mut evaluator = evaluator(&e, "-O0", RefCountHeap::new());
+    evaluator.run().expect("program should not fail");
+
+    let dump = evaluator.dump_heap(DumpFormat::Json);
+    assert_eq!(dump.matches("\"root\": true").count(), 1, "{}", dump);
+    assert_eq!(dump.matches("\"root\": false").count(), 0, "{}", dump);
+
+    let dump = evaluator.dump_heap(DumpFormat::Dot);
+    assert_eq!(dump.matches("peripheries=2").count(), 1, "{}", dump);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::config::SandboxConfig;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::Heap;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::fib::fib_test;
+use common::compile_with_natives;
+
+fn evaluator(e: &Expr, sandbox: &SandboxConfig) -> Result<ProgramEvaluator, RuntimeError> {
+    let mut natives = Natives::new();
+    natives.register_prelude();
+    natives.register_sorting();
+    natives.register_hash();
+    natives.register_copy();
+    let mut evaluator = ProgramEvaluator::with_sandbox(compile_with_natives(e, &natives), sandbox);
+    evaluator.bind_natives(&natives)?;
+    Ok(evaluator)
+}
+
+fn run(e: &Expr, sandbox: &SandboxConfig) -> Result<String, RuntimeError> {
+    let mut evaluator = evaluator(e, sandbox)?;
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+fn generous() -> SandboxConfig {
+    SandboxConfig::new(1_000_000, 10_000, 1_000)
+}
+
+// The fuel that a program uses, and the objects that are left once it has
+// finished.
+fn fuel_and_objects(e: &Expr) -> (u64, usize) {
+    let mut evaluator = evaluator(e, &generous().allow("copy")).expect("expected natives");
+    evaluator.run().expect("program should not fail");
+    let used = 1_000_000 - evaluator.remaining_fuel().expect("fuel is limited");
+    (used, evaluator.heap().stats().live_objects)
+}
+
+#[test]
+fn programs_within_the_limits_run() {
+    let e = fib_test(15);
+    let mut evaluator = evaluator(&e, &generous()).expect("expected natives");
+    let value = evaluator.run().expect("program should not fail");
+    assert_eq!(render(value, evaluator.heap()), "610");
+
+    // Every run of the program uses the same amount of fuel.
+    let used = 1_000_000 - evaluator.remaining_fuel().expect("fuel is limited");
+    let sandbox = SandboxConfig {
+        fuel: used,
+        ..generous()
+    };
+    assert_eq!(run(&e, &sandbox).as_deref(), Ok("610"));
+    let sandbox = SandboxConfig {
+        fuel: used - 1,
+        ..generous()
+    };
+    assert_eq!(run(&e, &sandbox), Err(RuntimeError::OutOfFuel));
+}
+
+#[test]
+fn endless_loops_run_out_of_fuel() {
+    let e = expr! {
+        let spin = fun(n) { spin(n + 1) };
+        spin(0)
+    };
+    let mut evaluator = evaluator(&e, &generous()).expect("expected natives");
+    assert_eq!(evaluator.run(), Err(RuntimeError::OutOfFuel));
+    assert_eq!(evaluator.remaining_fuel(), Some(0));
+}
+
+#[test]
+fn functions_called_by_natives_use_fuel() {
+    let e = expr! {
+        let spin = fun(n) { if n == 0 { spin(n) } else { spin(n - 1) } };
+        let compare = fun(a, b) { spin(1) };
+        sort_by((2, 1), compare)
+    };
+    assert_eq!(
+        run(&e, &generous().allow("sort_by")),
+        Err(RuntimeError::OutOfFuel)
+    );
+}
+
+#[test]
+fn the_heap_and_the_stack_are_limited() {
+    let build = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        build(100000, 0)
+    };
+    assert_eq!(run(&build, &generous()), Err(RuntimeError::OutOfMemory));
+
+    let deep = expr! {
+        let deep = fun(n) { if n == 0 { 0 } else { 1 + deep(n - 1) } };
+        deep(100000)
+    };
+    assert_eq!(run(&deep, &generous()), Err(RuntimeError::StackOverflow));
+}
+
+#[test]
+fn natives_are_held_to_the_heap_limit() {
+    // Every copy stays alive until the recursion unwinds.
+    let e = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        let list = build(1000, 0);
+        let copies = fun(n, x) { if n == 0 { 0 } else { let y = copy(x); 1 + copies(n - 1, x) } };
+        copies(500, list)
+    };
+    let mut evaluator = evaluator(&e, &generous().allow("copy")).expect("expected natives");
+    assert_eq!(evaluator.run(), Err(RuntimeError::OutOfMemory));
+    assert!(evaluator.heap().stats().peak_objects <= 10_000);
+}
+
+#[test]
+fn natives_pay_for_their_work() {
+    // Hashing the list touches every pair, while the call is a single
+    // instruction.
+    let e = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        let list = build(1000, 0);
+        let hashes = fun(n) { if n == 0 { 0 } else { let h = hash(list); hashes(n - 1) } };
+        hashes(1000)
+    };
+    let sandbox = generous().allow("hash");
+    assert_eq!(run(&e, &sandbox), Err(RuntimeError::OutOfFuel));
+
+    // The copy is paid for by the pair, and one that runs out of fuel
+    // frees the pairs it has already copied.
+    let list = expr! {
+        let build = fun(n, acc) { if n == 0 { acc } else { build(n - 1, (n, acc)) } };
+        build(1000, 0)
+    };
+    let (list_fuel, list_objects) = fuel_and_objects(&list);
+    let copy = expr! { copy(#list) };
+    let (copy_fuel, copy_objects) = fuel_and_objects(&copy);
+    assert_eq!(copy_objects, list_objects);
+    assert!(copy_fuel - list_fuel > 3 * 1000);
+
+    // Finding the pairs costs about 6000 units of fuel, so some of them have
+    // been copied when the fuel runs out.
+    let sandbox = SandboxConfig {
+        fuel: list_fuel + 6500,
+        ..generous().allow("copy")
+    };
+    let mut evaluator = evaluator(&copy, &sandbox).expect("expected natives");
+    assert_eq!(evaluator.run(), Err(RuntimeError::OutOfFuel));
+    assert_eq!(evaluator.heap().stats().live_tuples, 1000);
+    assert!(evaluator.heap().stats().tuple_allocations > 1000);
+}
+
+#[test]
+fn only_allowed_natives_can_be_bound() {
+    let e = expr! { (hash(1) == hash(1), sort_by((2, 1), less)) };
+    assert_eq!(
+        run(&e, &generous().allow("hash").allow("sort_by")),
+        Err(RuntimeError::NativeNotAllowed("less".to_owned()))
+    );
+
+    let sandbox = generous().allow("hash").allow("sort_by").allow("less");
+    assert_eq!(run(&e, &sandbox).as_deref(), Ok("(true, (1, 2))"));
+
+    // Natives that the program does not use need not be allowed.
+    assert_eq!(run(&expr! { 1 + 2 }, &generous()).as_deref(), Ok("3"));
+}
@@ -0,0 +1,72 @@
+#![cfg(feature = "serde")]
+use bailey::driver::{compile, CompileOptions};
+use bailey::ir_flat::interpreter::WordInterpreter;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::counter_test;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+fn reload<T: Serialize + DeserializeOwned>(value: &T) -> T {
+    let json = serde_json::to_string(value).expect("expected json");
+    let reloaded: T = serde_json::from_str(&json).expect("expected reloaded value");
+    assert_eq!(
+        serde_json::to_string(&reloaded).expect("expected json"),
+        json
+    );
+    reloaded
+}
+
+fn run_let(program: bailey::ir_let::let_expr::Program) -> String {
+    let mut evaluator = ProgramEvaluator::new(program);
+    let value = evaluator.run().expect("program should not fail");
+    render(value, evaluator.heap())
+}
+
+fn run_flat(program: &bailey::ir_flat::syntax::Program) -> String {
+    let mut interpreter = WordInterpreter::new(program);
+    let result = interpreter.run().expect("program should not fail");
+    interpreter.render(result)
+}
+
+fn examples() -> Vec<Expr> {
+    vec![map_fold_test(10), closure_capture_test(5), counter_test(5)]
+}
+
+#[test]
+fn reloaded_expressions_compile_to_the_same_programs() {
+    for e in examples() {
+        let reloaded = reload(&e);
+        assert_eq!(format!("{:?}", reloaded), format!("{:?}", e));
+
+        let options = CompileOptions::default();
+        let compiled = compile(&e, &options).expect("expected program");
+        let recompiled = compile(&reloaded, &options).expect("expected program");
+        assert_eq!(
+            run_let(recompiled.let_program),
+            run_let(compiled.let_program)
+        );
+    }
+}
+
+#[test]
+fn reloaded_programs_run_the_same() {
+    for e in examples() {
+        let compiled = compile(&e, &CompileOptions::default()).expect("expected program");
+
+        let let_program = reload(&compiled.let_program);
+        assert_eq!(
+            format!("{:?}", let_program),
+            format!("{:?}", compiled.let_program)
+        );
+        assert_eq!(run_let(let_program), run_let(compiled.let_program));
+
+        let flat_program = compiled.flat_program.expect("expected flat program");
+        let reloaded = reload(&flat_program);
+        assert_eq!(reloaded, flat_program);
+        assert_eq!(run_flat(&reloaded), run_flat(&flat_program));
+    }
+}
@@ -0,0 +1,75 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::heap::MarkSweepHeap;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::{PreparedProgram, ProgramEvaluator, StepOutcome};
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::list::map_fold_test;
+
+fn prepare(e: &Expr, globals: Vec<String>) -> PreparedProgram {
+    let options = CompileOptions {
+        globals,
+        ..CompileOptions::default()
+    };
+    PreparedProgram::new(compile(e, &options).expect("expected program").let_program)
+        .expect("expected valid program")
+}
+
+#[test]
+fn evaluators_share_the_program() {
+    let program = prepare(&map_fold_test(50), Vec::new());
+    let mut first = ProgramEvaluator::new(program.clone());
+    let mut second = ProgramEvaluator::with_heap(program.clone(), MarkSweepHeap::new());
+    let third = ProgramEvaluator::new(first.prepared_program());
+    assert!(std::ptr::eq(first.program(), program.program()));
+    assert!(std::ptr::eq(second.program(), program.program()));
+    assert!(std::ptr::eq(third.program(), program.program()));
+
+    let first_value = first.run().expect("program should not fail");
+    let second_value = second.run().expect("program should not fail");
+    assert_eq!(
+        render(first_value, first.heap()),
+        render(second_value, second.heap())
+    );
+}
+
+#[test]
+fn evaluators_of_the_same_program_are_independent() {
+    // The counter lives on the heap of each evaluator, so stepping them in
+    // turn must not mix up their state.
+    let mut natives = Natives::new();
+    natives.register_hash();
+    let e = expr! {
+        let counter = (0,);
+        let count = fun(n) {
+            if n == 0 { counter[0] } else { let u = set counter[0] = counter[0] + n; count(n - 1) }
+        };
+        (count(100), hash(counter))
+    };
+    let program = prepare(&e, natives.names());
+
+    let mut evaluators: Vec<ProgramEvaluator> = (0..3)
+        .map(|_| {
+            let mut evaluator = ProgramEvaluator::new(program.clone());
+            evaluator.bind_natives(&natives).expect("expected natives");
+            evaluator
+        })
+        .collect();
+
+    let mut results = vec![None; evaluators.len()];
+    while results.iter().any(|result| result.is_none()) {
+        for (evaluator, result) in evaluators.iter_mut().zip(&mut results) {
+            if let StepOutcome::Finished(value) = evaluator.step().expect("step should not fail") {
+                *result = Some(render(value, evaluator.heap()));
+            }
+        }
+    }
+
+    for result in &results {
+        assert_eq!(result, &results[0]);
+    }
+    assert!(results[0]
+        .as_deref()
+        .is_some_and(|result| result.starts_with("(5050, ")));
+}
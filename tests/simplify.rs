@@ -0,0 +1,125 @@
+mod common;
+
+use bailey::expr;
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Control, Program, Simple};
+use bailey::ir_let::passes::simplify::{default_rules, simplify, Operand, Replacement, Rule};
+use bailey::ir_let::visit::{walk_control, walk_simple, Visitor};
+use bailey::lang::syntax::{BinOp, Constant};
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use common::compile_at;
+
+#[derive(Default)]
+struct Operations {
+    binops: usize,
+    conditionals: usize,
+}
+
+impl Visitor for Operations {
+    fn visit_simple(&mut self, simple: &Simple) {
+        if let Simple::BinOp { .. } = simple {
+            self.binops += 1;
+        }
+        walk_simple(self, simple);
+    }
+
+    fn visit_control(&mut self, control: &Control) {
+        if let Control::If { .. } = control {
+            self.conditionals += 1;
+        }
+        walk_control(self, control);
+    }
+}
+
+fn operations(program: &Program) -> Operations {
+    let mut operations = Operations::default();
+    operations.visit_program(program);
+    operations
+}
+
+#[test]
+fn identities_are_simplified_to_a_fixpoint() {
+    // The difference is 0 once `b` is known to be a copy of `a`, after which
+    // the conditional is on a constant and its branch is taken.
+    let e = expr! {
+        let f = fun(n) {
+            let a = n - 1;
+            let b = a + 0;
+            let c = b - a;
+            if c == 0 { 1 } else { 2 }
+        };
+        f(5)
+    };
+
+    let mut program = compile_at(&e, "-O0");
+    let rewrites = simplify(&mut program, &default_rules());
+    assert_eq!(rewrites.get("add-zero"), Some(&1));
+    assert_eq!(rewrites.get("sub-self"), Some(&1));
+
+    // Only `n - 1` is left, since `n` might not be an integer.
+    let operations = operations(&program);
+    assert_eq!(operations.binops, 1);
+    assert_eq!(operations.conditionals, 0);
+
+    assert_eq!(ProgramEvaluator::new(program).run(), Ok(Value::Int(1)));
+}
+
+#[test]
+fn failing_operations_are_kept() {
+    // Subtracting a tuple from itself fails, and so does adding 0 to it.
+    for e in [
+        expr! { let t = (1, 2); t - t },
+        expr! { let t = (1, 2); t + 0 },
+    ] {
+        let mut program = compile_at(&e, "-O0");
+        assert!(simplify(&mut program, &default_rules()).is_empty());
+        assert!(ProgramEvaluator::new(program).run().is_err());
+    }
+}
+
+#[test]
+fn rules_can_be_supplied() {
+    let eq_self = Rule::new(
+        "same",
+        BinOp::Eq,
+        Operand::Var("x"),
+        Operand::Var("x"),
+        Replacement::Literal(Constant::Bool { value: true }),
+    );
+    let e = expr! {
+        let f = fun(n) { let a = n + 0; let b = a; if a == b { 1 } else { 2 } };
+        f(3)
+    };
+
+    // `n + 0` stays, since the rules for addition are not supplied.
+    let mut program = compile_at(&e, "-O0");
+    let rewrites = simplify(&mut program, &[eq_self]);
+    assert_eq!(rewrites.len(), 1);
+    assert_eq!(rewrites.get("same"), Some(&1));
+    assert_eq!(operations(&program).binops, 1);
+    assert_eq!(ProgramEvaluator::new(program).run(), Ok(Value::Int(1)));
+}
+
+#[test]
+#[should_panic(expected = "unbound metavariable y")]
+fn replacements_only_use_bound_metavariables() {
+    Rule::new(
+        "bad",
+        BinOp::Add,
+        Operand::Var("x"),
+        Operand::Int(0),
+        Replacement::Var("y"),
+    );
+}
+
+#[test]
+fn results_are_unchanged() {
+    for e in [fib_test(10), map_fold_test(10)] {
+        let expected = ProgramEvaluator::new(compile_at(&e, "-O0")).run();
+        for level in ["-O1", "-O2"] {
+            assert_eq!(ProgramEvaluator::new(compile_at(&e, level)).run(), expected);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// Pins the let-normalized form and the frame layout of the example programs,
+// so that changes to name generation, block structure or layout show up in
+// review. The snapshots are in tests/snapshots, and setting
+// UPDATE_SNAPSHOTS=1 writes the current output to them instead.
+use bailey::ir_flat::frame_layout::compute_program_frame_layout;
+use bailey::ir_let::compiler::let_normalize;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::closures::closure_capture_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::{list_sum_test, map_fold_test};
+use bailey::lang::test::mutation::{aliasing_test, counter_test};
+use std::fs;
+use std::path::Path;
+
+fn programs() -> Vec<(&'static str, Expr)> {
+    vec![
+        ("fib", fib_test(20)),
+        ("ackermann", ackermann_test(2, 3)),
+        ("list_sum", list_sum_test(100)),
+        ("map_fold", map_fold_test(100)),
+        ("even_odd", even_odd_test(10)),
+        ("closure_capture", closure_capture_test(10)),
+        ("counter", counter_test(100)),
+        ("aliasing", aliasing_test()),
+    ]
+}
+
+// The first line that differs, to point at the change in a long snapshot.
+fn first_difference(expected: &str, output: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut output_lines = output.lines();
+    for number in 1.. {
+        match (expected_lines.next(), output_lines.next()) {
+            (None, None) => break,
+            (e, o) if e == o => {}
+            (e, o) => {
+                return format!(
+                    "line {}: `{}` instead of `{}`",
+                    number,
+                    o.unwrap_or("<end>"),
+                    e.unwrap_or("<end>")
+                )
+            }
+        }
+    }
+    "no difference".to_owned()
+}
+
+#[test]
+fn snapshots_match() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut failures = Vec::new();
+
+    for (name, e) in programs() {
+        let program = let_normalize(&e).expect("expected program");
+        let layout = compute_program_frame_layout(&program);
+
+        for (kind, output) in [("let", program.to_string()), ("layout", layout.to_string())] {
+            let path = directory.join(format!("{}.{}.snap", name, kind));
+            if update {
+                fs::create_dir_all(&directory).expect("failed to create tests/snapshots");
+                fs::write(&path, &output).expect("failed to write snapshot");
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(expected) if expected == output => {}
+                Ok(expected) => failures.push(format!(
+                    "{}.{}: {}",
+                    name,
+                    kind,
+                    first_difference(&expected, &output)
+                )),
+                Err(_) => failures.push(format!("{}.{}: missing snapshot", name, kind)),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "snapshots changed (run with UPDATE_SNAPSHOTS=1 to accept the new output):\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use bailey::ir_let::parser::parse_program;
+use bailey::ir_let::resolve::resolve_variables;
+use bailey::ir_let::stats::{FunctionStats, ProgramStats};
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use common::compile_at;
+
+fn function_stats(stats: &ProgramStats, name: &str) -> FunctionStats {
+    stats
+        .functions
+        .iter()
+        .find(|(function, _)| function.source_name() == name)
+        .map(|(_, stats)| *stats)
+        .expect("expected function")
+}
+
+#[test]
+fn functions_are_counted() {
+    let stats = ProgramStats::new(&compile_at(&fib_test(10), "-O0"));
+    assert_eq!(stats.functions.len(), 3);
+
+    // fib_helper tests its counter in the first block and enters one of the
+    // two branches, the second of which calls fib_helper again. A function
+    // refers to itself without capturing its closure.
+    let fib_helper = function_stats(&stats, "fib_helper");
+    assert_eq!(fib_helper.blocks, 3);
+    assert_eq!(fib_helper.call_sites, 1);
+    assert_eq!(fib_helper.captures, 0);
+
+    let fib = function_stats(&stats, "fib");
+    assert_eq!(fib.blocks, 1);
+    assert_eq!(fib.call_sites, 1);
+    assert_eq!(fib.captures, 1);
+
+    let total = stats.total();
+    let instructions: usize = stats.functions.iter().map(|(_, s)| s.instructions).sum();
+    assert_eq!(total.instructions, instructions);
+    assert_eq!(total.blocks, 5);
+
+    let text = stats.to_string();
+    assert_eq!(text.lines().count(), 5);
+    assert!(text.lines().last().unwrap().ends_with("total"), "{}", text);
+}
+
+#[test]
+fn optimizations_show_up_in_the_stats() {
+    let e = fib_test(10);
+    let unoptimized = ProgramStats::new(&compile_at(&e, "-O0"));
+    let optimized = ProgramStats::new(&compile_at(&e, "-O2"));
+
+    // The tail call of fib_helper becomes a loop.
+    let fib_helper = function_stats(&optimized, "fib_helper");
+    assert_eq!(fib_helper.call_sites, 0);
+    assert!(optimized.total().call_sites < unoptimized.total().call_sites);
+}
+
+#[test]
+fn stats_survive_the_text_format() {
+    for e in [fib_test(10), map_fold_test(10)] {
+        let program = compile_at(&e, "-O2");
+        let mut parsed = parse_program(&program.to_string()).expect("expected program text");
+        resolve_variables(&mut parsed);
+        assert_eq!(ProgramStats::new(&parsed), ProgramStats::new(&program));
+    }
+}
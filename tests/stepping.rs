@@ -0,0 +1,100 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap::Heap;
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::simple_eval::{ProgramEvaluator, StepOutcome};
+use bailey::lang::syntax::Expr;
+
+fn evaluator(e: &Expr) -> ProgramEvaluator {
+    let compiled = compile(e, &CompileOptions::default()).expect("expected program");
+    ProgramEvaluator::new(compiled.let_program)
+}
+
+#[test]
+fn stepping_gives_the_same_result_as_running() {
+    let e = expr! {
+        let f = fun(x) { (x, x + 1) };
+        let t = f(1);
+        1 + t[0] + t[1]
+    };
+    let expected = evaluator(&e).run().expect("program should not fail");
+
+    let mut stepping = evaluator(&e);
+    let mut steps = 0;
+    let result = loop {
+        assert!(!stepping.is_finished());
+        let address = stepping.program_counter();
+        let instruction = stepping.current_instruction().to_string();
+        steps += 1;
+        match stepping.step().expect("program should not fail") {
+            StepOutcome::Running => {
+                assert_ne!(stepping.program_counter(), address, "{}", instruction)
+            }
+            StepOutcome::Finished(value) => break value,
+        }
+    };
+    assert_eq!(result, expected);
+    assert_eq!(result, Value::Int(4));
+    assert!(steps > 3);
+
+    // Stepping a finished program keeps returning the result.
+    assert!(stepping.is_finished());
+    assert_eq!(stepping.step(), Ok(StepOutcome::Finished(result)));
+}
+
+#[test]
+fn the_stack_and_the_heap_can_be_inspected_between_steps() {
+    let e = expr! {
+        let f = fun(x) { let t = (x, x); 1 + t[0] };
+        1 + f(1)
+    };
+    let mut evaluator = evaluator(&e);
+    let mut deepest = Vec::new();
+    let mut most_objects = 0;
+    while let StepOutcome::Running = evaluator.step().expect("program should not fail") {
+        let names: Vec<&str> = evaluator
+            .call_stack()
+            .iter()
+            .map(|entry| entry.function_name.source_name())
+            .collect();
+        if names.len() > deepest.len() {
+            deepest = names;
+        }
+        most_objects = most_objects.max(evaluator.heap().stats().live_objects);
+        assert_eq!(
+            evaluator.stack().frames().len(),
+            evaluator.call_stack().len()
+        );
+    }
+    assert_eq!(deepest.last(), Some(&"f"));
+    assert!(deepest.len() >= 2);
+    assert!(most_objects >= 1);
+    assert_eq!(evaluator.heap().stats().live_objects, 0);
+}
+
+#[test]
+fn a_failing_step_stays_at_the_failed_instruction() {
+    let e = expr! {
+        let t = (1, 2);
+        t[5]
+    };
+    let mut evaluator = evaluator(&e);
+    let error = loop {
+        let address = evaluator.program_counter();
+        match evaluator.step() {
+            Ok(StepOutcome::Running) => {}
+            Ok(StepOutcome::Finished(_)) => panic!("expected the program to fail"),
+            Err(error) => {
+                assert_eq!(evaluator.program_counter(), address);
+                break error;
+            }
+        }
+    };
+    assert_eq!(error, RuntimeError::IndexOutOfRange { index: 5, size: 2 });
+    assert!(!evaluator.is_finished());
+    // The failed instruction has no effect, so it fails again.
+    let address = evaluator.program_counter();
+    assert_eq!(evaluator.step(), Err(error));
+    assert_eq!(evaluator.program_counter(), address);
+}
@@ -0,0 +1,119 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_flat::interpreter::WordInterpreter;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::{PreparedProgram, ProgramEvaluator};
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::symbol::Symbol;
+use bailey::{eval_str, EvalOptions};
+use std::sync::Arc;
+use std::thread;
+
+const THREADS: usize = 8;
+
+// Compiled programs are shared between threads, while every thread evaluates
+// them with its own evaluator and heap.
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn compiled_programs_can_be_shared() {
+    assert_send_sync::<bailey::ir_let::let_expr::Program>();
+    assert_send_sync::<bailey::ir_flat::syntax::Program>();
+    assert_send_sync::<PreparedProgram>();
+    assert_send_sync::<Natives>();
+    assert_send_sync::<Symbol>();
+}
+
+#[test]
+fn threads_evaluate_the_same_program() {
+    let mut natives = Natives::new();
+    natives.register_prelude();
+    natives.register_sorting();
+    let e = expr! {
+        let descending = fun(a, b) { less(b, a) };
+        let sum = fun(n, acc) { if n == 0 { acc } else { sum(n - 1, acc + n) } };
+        (sort_by((3, 1, 4, 1, 5, 9, 2, 6), descending), sum(1000, 0))
+    };
+    let options = CompileOptions {
+        globals: natives.names(),
+        ..CompileOptions::default()
+    };
+    let compiled = compile(&e, &options).expect("expected program");
+    let program = PreparedProgram::new(compiled.let_program).expect("expected valid program");
+    let natives = Arc::new(natives);
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let program = program.clone();
+            let natives = Arc::clone(&natives);
+            thread::spawn(move || {
+                let mut evaluator = ProgramEvaluator::new(program);
+                evaluator.bind_natives(&natives).expect("expected natives");
+                let value = evaluator.run().expect("program should not fail");
+                render(value, evaluator.heap())
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(
+            handle.join().expect("thread should not panic"),
+            "((9, 6, 5, 4, 3, 2, 1, 1), 500500)"
+        );
+    }
+}
+
+#[test]
+fn threads_run_the_same_flat_program() {
+    let compiled =
+        compile(&map_fold_test(100), &CompileOptions::default()).expect("expected program");
+    let program = compiled.flat_program.expect("expected flat program");
+
+    let expected = {
+        let mut interpreter = WordInterpreter::new(&program);
+        let result = interpreter.run().expect("program should not fail");
+        interpreter.render(result)
+    };
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut interpreter = WordInterpreter::new(&program);
+                    let result = interpreter.run().expect("program should not fail");
+                    interpreter.render(result)
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().expect("thread should not panic"), expected);
+        }
+    });
+}
+
+#[test]
+fn threads_compile_and_evaluate_independently() {
+    // Compiling interns fresh names, so the threads race on the interner.
+    let handles: Vec<_> = (0..THREADS as i64)
+        .map(|i| {
+            thread::spawn(move || {
+                let compiled = compile(&fib_test(10 + i), &CompileOptions::default())
+                    .expect("expected program");
+                let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+                let value = evaluator.run().expect("program should not fail");
+                let source = format!("(let x{} {} (+ x{} 1))", i, i, i);
+                let sum =
+                    eval_str(&source, &EvalOptions::default()).expect("program should not fail");
+                (render(value, evaluator.heap()), sum.to_string())
+            })
+        })
+        .collect();
+
+    let fib = [55, 89, 144, 233, 377, 610, 987, 1597];
+    for (i, handle) in handles.into_iter().enumerate() {
+        let (value, sum) = handle.join().expect("thread should not panic");
+        assert_eq!(value, fib[i].to_string());
+        assert_eq!(sum, (i + 1).to_string());
+    }
+}
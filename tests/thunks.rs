@@ -0,0 +1,86 @@
+mod common;
+
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::error::RuntimeError;
+use bailey::ir_let::interpreter::heap_value::Value;
+use bailey::ir_let::interpreter::native::Natives;
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::lang::syntax::Expr;
+use common::compile_with_natives;
+
+fn evaluator(e: &Expr) -> ProgramEvaluator {
+    let mut natives = Natives::new();
+    natives.register_thunks();
+    let mut evaluator = ProgramEvaluator::new(compile_with_natives(e, &natives));
+    evaluator.bind_natives(&natives).expect("expected natives");
+    evaluator
+}
+
+fn run(e: &Expr) -> Result<String, RuntimeError> {
+    let mut evaluator = evaluator(e);
+    let value = evaluator.run()?;
+    Ok(render(value, evaluator.heap()))
+}
+
+#[test]
+fn thunk_is_evaluated_once() {
+    let e = expr! {
+        let count = (0,);
+        let t = delay { let u = set count[0] = count[0] + 1; 20 + 1 };
+        let a = force(t);
+        let b = force(t);
+        (a + b, count[0])
+    };
+    assert_eq!(run(&e).as_deref(), Ok("(42, 1)"));
+}
+
+#[test]
+fn thunk_that_is_not_forced_is_not_evaluated() {
+    let e = expr! {
+        let t = delay { (1, 2)[5] };
+        7
+    };
+    assert_eq!(run(&e).as_deref(), Ok("7"));
+}
+
+#[test]
+fn infinite_stream() {
+    let e = expr! {
+        let from = fun(n) { (n, delay { from(n + 1) }) };
+        let sum = fun(stream, k) {
+            if k == 0 { 0 } else { stream[0] + sum(force(stream[1]), k - 1) }
+        };
+        sum(from(0), 10)
+    };
+    assert_eq!(run(&e).as_deref(), Ok("45"));
+}
+
+#[test]
+fn forcing_a_thunk_from_itself_fails() {
+    let e = expr! {
+        let cell = (0,);
+        let u = set cell[0] = delay { force(cell[0]) };
+        force(cell[0])
+    };
+    assert!(matches!(run(&e), Err(RuntimeError::Native(_))));
+}
+
+#[test]
+fn forced_thunks_are_freed() {
+    let e = expr! {
+        let t = delay { (1, (2, 3)) };
+        let pair = force(t);
+        force(t)[1][0] + pair[0]
+    };
+    let mut evaluator = evaluator(&e);
+    assert_eq!(evaluator.run(), Ok(Value::Int(3)));
+    assert!(evaluator.heap().is_empty());
+}
+
+#[test]
+fn delay_requires_the_global() {
+    let e = expr! { force(delay { 1 }) };
+    assert!(compile(&e, &CompileOptions::default()).is_err());
+}
@@ -0,0 +1,79 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::expr;
+use bailey::ir_let::interpreter::heap_value::{HeapAddress, HeapValue, Value};
+use bailey::ir_let::interpreter::simple_eval::{ProgramEvaluator, StepOutcome};
+use bailey::ir_let::interpreter::trace::TraceSink;
+use bailey::ir_let::let_expr::{Instruction, TargetAddress};
+use bailey::symbol::Symbol;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Keeps the events as text, where the test can still read them once the
+// evaluator owns the sink.
+#[derive(Debug, Default, Clone)]
+struct Events(Rc<RefCell<Vec<String>>>);
+
+impl Events {
+    fn push(&self, event: String) {
+        self.0.borrow_mut().push(event);
+    }
+
+    fn count(&self, prefix: &str) -> usize {
+        let events = self.0.borrow();
+        events.iter().filter(|e| e.starts_with(prefix)).count()
+    }
+}
+
+impl TraceSink for Events {
+    fn instruction(&mut self, address: TargetAddress, _instruction: &Instruction) {
+        self.push(format!("instruction {}", address));
+    }
+
+    fn call(&mut self, name: Symbol, _body: TargetAddress, is_tail_call: bool) {
+        let kind = if is_tail_call { "tailcall" } else { "call" };
+        self.push(format!("{} {}", kind, name.source_name()));
+    }
+
+    fn function_return(&mut self, value: Value) {
+        self.push(format!("return {:?}", value));
+    }
+
+    fn alloc(&mut self, address: HeapAddress, _heap_value: &HeapValue) {
+        self.push(format!("alloc {}", address.index));
+    }
+
+    fn free(&mut self, address: HeapAddress) {
+        self.push(format!("free {}", address.index));
+    }
+}
+
+#[test]
+fn every_event_reaches_the_sink() {
+    let e = expr! {
+        let f = fun(x) { let t = (x, x); t[0] + t[1] };
+        let a = f(1);
+        f(a)
+    };
+    let compiled = compile(&e, &CompileOptions::default()).expect("expected program");
+    let mut evaluator = ProgramEvaluator::new(compiled.let_program);
+    let events = Events::default();
+    evaluator.set_trace_sink(Box::new(events.clone()));
+
+    let mut steps = 0;
+    loop {
+        steps += 1;
+        if let StepOutcome::Finished(value) = evaluator.step().expect("program should not fail") {
+            assert_eq!(value, Value::Int(4));
+            break;
+        }
+    }
+
+    assert_eq!(events.count("instruction"), steps);
+    assert_eq!(events.count("call f"), 1);
+    assert_eq!(events.count("tailcall f"), 1);
+    assert_eq!(events.count("return Int(2)"), 1);
+    // The closure and both tuples are freed again, since the result is an
+    // int.
+    assert_eq!(events.count("alloc"), 3);
+    assert_eq!(events.count("free"), 3);
+}
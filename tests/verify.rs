@@ -0,0 +1,111 @@
+use bailey::driver::{compile, CompileOptions};
+use bailey::ir_let::interpreter::heap::Heap;
+use bailey::ir_let::interpreter::simple_eval::{PreparedProgram, ProgramEvaluator, StepOutcome};
+use bailey::ir_let::let_expr::{Control, Definition, Instruction, Program, Step};
+use bailey::ir_let::verify::verify_program;
+use bailey::lang::syntax::Expr;
+use bailey::lang::test::ackermann::ackermann_test;
+use bailey::lang::test::even_odd::even_odd_test;
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::test::mutation::counter_test;
+
+fn compile_let(e: &Expr) -> Program {
+    compile(e, &CompileOptions::default())
+        .expect("expected program")
+        .let_program
+}
+
+fn programs() -> Vec<Expr> {
+    vec![
+        fib_test(20),
+        ackermann_test(2, 3),
+        even_odd_test(7),
+        map_fold_test(50),
+        counter_test(50),
+    ]
+}
+
+#[test]
+fn compiled_programs_are_valid() {
+    for e in programs() {
+        verify_program(&compile_let(&e)).expect("expected a valid program");
+    }
+}
+
+#[test]
+fn invalid_branches_are_rejected() {
+    let mut program = compile_let(&fib_test(20));
+    let branch = program
+        .functions
+        .iter_mut()
+        .flat_map(|function| function.blocks.iter_mut())
+        .flat_map(|block| block.instructions.iter_mut())
+        .find_map(|instruction| match instruction {
+            Instruction::Assignment(assignment) => match &mut assignment.definition {
+                Definition::Step(Step::Control(Control::If { branch_failure, .. })) => {
+                    Some(branch_failure)
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .expect("expected an if");
+    branch.block_index = 100;
+
+    let error = verify_program(&program).expect_err("expected an invalid program");
+    assert!(
+        error.to_string().starts_with("invalid address"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn blocks_must_not_fall_through() {
+    let mut program = compile_let(&fib_test(20));
+    program.functions[0].blocks[0].instructions = vec![Instruction::EnterBlock];
+
+    let error = verify_program(&program).expect_err("expected an invalid program");
+    assert_eq!(
+        error.to_string(),
+        "block 0 of function 0 does not end with an exit or a jump"
+    );
+}
+
+#[test]
+fn invalid_programs_cannot_be_prepared() {
+    let mut program = compile_let(&fib_test(20));
+    program.functions[0].blocks[0].instructions = vec![Instruction::EnterBlock];
+
+    let error = PreparedProgram::new(program).expect_err("expected an invalid program");
+    assert_eq!(
+        error.to_string(),
+        "invalid program: block 0 of function 0 does not end with an exit or a jump"
+    );
+}
+
+// Running the program keeps the instructions of the current block at hand,
+// which has to give the same result as taking one step at a time.
+#[test]
+fn running_and_stepping_agree() {
+    for e in programs() {
+        let program = compile_let(&e);
+        let mut running = ProgramEvaluator::new(program.clone());
+        let result = running.run().expect("program should not fail");
+
+        let mut stepping = ProgramEvaluator::new(program);
+        let mut steps = 0;
+        let stepped = loop {
+            steps += 1;
+            if let StepOutcome::Finished(value) = stepping.step().expect("step should not fail") {
+                break value;
+            }
+        };
+
+        assert!(steps > 1);
+        assert_eq!(stepped, result);
+        assert_eq!(stepping.program_counter(), running.program_counter());
+        assert_eq!(stepping.heap().stats(), running.heap().stats());
+    }
+}
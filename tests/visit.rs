@@ -0,0 +1,143 @@
+mod common;
+
+use bailey::ir_let::interpreter::render::render;
+use bailey::ir_let::interpreter::simple_eval::ProgramEvaluator;
+use bailey::ir_let::let_expr::{Control, Program, Simple};
+use bailey::ir_let::stats::ProgramStats;
+use bailey::ir_let::visit::{walk_control, walk_simple_mut, Visitor, VisitorMut};
+use bailey::lang::syntax::{BinOp, Constant, Expr};
+use bailey::lang::test::builder::{add, int, let_in, var};
+use bailey::lang::test::fib::fib_test;
+use bailey::lang::test::list::map_fold_test;
+use bailey::lang::visit::{fold_children, ExprFolder, ExprVisitor};
+use common::compile_at;
+
+fn run(program: Program) -> String {
+    let mut evaluator = ProgramEvaluator::new(program);
+    let value = evaluator.run().expect("program should not fail");
+    render(value, evaluator.heap())
+}
+
+#[derive(Default)]
+struct Occurrences {
+    literals: usize,
+    vars: Vec<String>,
+}
+
+impl ExprVisitor for Occurrences {
+    fn visit_literal(&mut self, _value: &Constant) {
+        self.literals += 1;
+    }
+
+    fn visit_var(&mut self, var_name: &str) {
+        self.vars.push(var_name.to_owned());
+    }
+}
+
+#[test]
+fn expressions_are_visited_in_order() {
+    let e = let_in("x", int(1), add(var("x"), add(int(2), var("y"))));
+    let mut occurrences = Occurrences::default();
+    occurrences.visit_expr(&e);
+    assert_eq!(occurrences.literals, 2);
+    assert_eq!(occurrences.vars, ["x", "y"]);
+}
+
+// Adds up additions of two integer literals.
+struct FoldAdditions;
+
+impl ExprFolder for FoldAdditions {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match fold_children(self, expr) {
+            Expr::BinOp {
+                op: BinOp::Add,
+                lhs,
+                rhs,
+            } => match (*lhs, *rhs) {
+                (
+                    Expr::Literal(Constant::Int { value: a }),
+                    Expr::Literal(Constant::Int { value: b }),
+                ) => int(a + b),
+                (lhs, rhs) => add(lhs, rhs),
+            },
+            expr => expr,
+        }
+    }
+}
+
+#[test]
+fn folders_rewrite_bottom_up() {
+    let folded = FoldAdditions.fold_expr(add(int(1), add(int(2), int(3))));
+    assert!(matches!(folded, Expr::Literal(Constant::Int { value: 6 })));
+
+    // Additions that involve variables are kept, but their operands are
+    // still folded.
+    let e = let_in("x", add(int(1), int(2)), add(var("x"), int(3)));
+    let mut occurrences = Occurrences::default();
+    occurrences.visit_expr(&FoldAdditions.fold_expr(e));
+    assert_eq!(occurrences.literals, 2);
+
+    let e = fib_test(10);
+    let folded = FoldAdditions.fold_expr(e.clone());
+    assert_eq!(run(compile_at(&folded, "-O0")), run(compile_at(&e, "-O0")));
+}
+
+#[derive(Default)]
+struct CallSites {
+    calls: usize,
+    branches: usize,
+}
+
+impl Visitor for CallSites {
+    fn visit_control(&mut self, control: &Control) {
+        match control {
+            Control::Call { .. } | Control::TailCall { .. } | Control::CallDirect { .. } => {
+                self.calls += 1
+            }
+            Control::If { .. } => self.branches += 1,
+            Control::Jump { .. } => {}
+        }
+        walk_control(self, control);
+    }
+}
+
+#[test]
+fn visitors_see_every_function() {
+    for e in [fib_test(10), map_fold_test(10)] {
+        for level in ["-O0", "-O2"] {
+            let program = compile_at(&e, level);
+            let mut call_sites = CallSites::default();
+            call_sites.visit_program(&program);
+            assert_eq!(
+                call_sites.calls,
+                ProgramStats::new(&program).total().call_sites
+            );
+            assert!(call_sites.branches > 0);
+        }
+    }
+}
+
+struct ReplaceLiteral {
+    from: i64,
+    to: i64,
+}
+
+impl VisitorMut for ReplaceLiteral {
+    fn visit_simple(&mut self, simple: &mut Simple) {
+        if let Simple::Literal(Constant::Int { value }) = simple {
+            if *value == self.from {
+                *value = self.to;
+            }
+        }
+        walk_simple_mut(self, simple);
+    }
+}
+
+#[test]
+fn mutable_visitors_rewrite_in_place() {
+    let mut program = compile_at(&fib_test(10), "-O2");
+    assert_eq!(run(program.clone()), "55");
+
+    ReplaceLiteral { from: 10, to: 12 }.visit_program(&mut program);
+    assert_eq!(run(program), "144");
+}